@@ -2,6 +2,7 @@ use kcd::client::KeycloakClient;
 use kcd::plan;
 use kcd::utils::secrets::{EnvResolver, SecretResolver};
 use kcd::utils::ui::DialoguerUi;
+use kcd::workspace::WorkspaceContext;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -35,7 +36,7 @@ fn main() {
                 &[],
                 ui.clone(),
                 resolver.clone(),
-                None,
+                Arc::new(WorkspaceContext::default()),
             )
             .await
             .unwrap();
@@ -2,6 +2,7 @@ use kcd::apply;
 use kcd::client::KeycloakClient;
 use kcd::utils::secrets::{EnvResolver, SecretResolver};
 use kcd::utils::ui::DialoguerUi;
+use kcd::workspace::WorkspaceContext;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::runtime::Runtime;
@@ -59,7 +60,7 @@ fn main() {
                 false,
                 ui.clone(),
                 resolver.clone(),
-                None,
+                Arc::new(WorkspaceContext::default()),
             )
             .await
             .unwrap();
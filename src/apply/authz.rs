@@ -0,0 +1,561 @@
+use super::is_planned;
+use crate::apply::report::{ApplyReport, Status, resource_name};
+use crate::client::KeycloakClient;
+use crate::models::{
+    AuthzResourceRepresentation, AuthzScopeRepresentation, KeycloakResource, PolicyRepresentation,
+    ResourceMeta,
+};
+use crate::plan::authz::{
+    AUTHZ_DIR, PERMISSIONS_DIR, authz_client_dirs, authz_client_id, load_resource_server,
+};
+use crate::plan::load_resource_files;
+use crate::utils::secrets::SecretResolver;
+use crate::utils::ui::{SUCCESS_CREATE, SUCCESS_UPDATE};
+use crate::workspace::WorkspaceContext;
+use anyhow::{Context, Result};
+use console::style;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::task::JoinSet;
+
+/// Resolves name-based references used in the workspace to the ids of the target server.
+struct RefResolver<'a> {
+    client: &'a KeycloakClient,
+    client_uuids: &'a HashMap<String, String>,
+    realm_roles: Option<HashMap<String, String>>,
+    client_roles: HashMap<String, HashMap<String, String>>,
+    groups: HashMap<String, String>,
+}
+
+impl<'a> RefResolver<'a> {
+    fn new(client: &'a KeycloakClient, client_uuids: &'a HashMap<String, String>) -> Self {
+        Self {
+            client,
+            client_uuids,
+            realm_roles: None,
+            client_roles: HashMap::new(),
+            groups: HashMap::new(),
+        }
+    }
+
+    /// `clientId/role` names a client role when `clientId` is a client of the realm; any
+    /// other reference, slashes included, is looked up as a realm role.
+    async fn role_id(&mut self, reference: &str) -> Result<String> {
+        if let Some((client_id, role_name)) = reference
+            .split_once('/')
+            .filter(|(client_id, _)| self.client_uuids.contains_key(*client_id))
+        {
+            if !self.client_roles.contains_key(client_id) {
+                let uuid = self.client_id(client_id)?;
+                let roles =
+                    self.client.get_client_roles(&uuid).await.with_context(|| {
+                        format!("Failed to get roles of client '{}'", client_id)
+                    })?;
+                self.client_roles.insert(
+                    client_id.to_string(),
+                    roles
+                        .into_iter()
+                        .filter_map(|r| r.id.map(|id| (r.name, id)))
+                        .collect(),
+                );
+            }
+            return self.client_roles[client_id]
+                .get(role_name)
+                .cloned()
+                .with_context(|| format!("Unknown client role '{}'", reference));
+        }
+
+        if self.realm_roles.is_none() {
+            let roles = self
+                .client
+                .get_roles()
+                .await
+                .context("Failed to get realm roles")?;
+            self.realm_roles = Some(
+                roles
+                    .into_iter()
+                    .filter_map(|r| r.id.map(|id| (r.name, id)))
+                    .collect(),
+            );
+        }
+        self.realm_roles
+            .as_ref()
+            .and_then(|roles| roles.get(reference))
+            .cloned()
+            .with_context(|| format!("Unknown realm role '{}'", reference))
+    }
+
+    async fn group_id(&mut self, path: &str) -> Result<String> {
+        if let Some(id) = self.groups.get(path) {
+            return Ok(id.clone());
+        }
+        let group = self
+            .client
+            .get_group_by_path(path)
+            .await
+            .with_context(|| format!("Unknown group '{}'", path))?;
+        let id = group
+            .id
+            .with_context(|| format!("Group '{}' has no id", path))?;
+        self.groups.insert(path.to_string(), id.clone());
+        Ok(id)
+    }
+
+    fn client_id(&self, client_id: &str) -> Result<String> {
+        self.client_uuids
+            .get(client_id)
+            .cloned()
+            .with_context(|| format!("Unknown client '{}'", client_id))
+    }
+
+    /// Rewrites the role, group and client references of a policy to server ids.
+    async fn resolve_subjects(&mut self, policy: &mut PolicyRepresentation) -> Result<()> {
+        if let Some(roles) = &mut policy.roles {
+            for role in roles {
+                role.id = self.role_id(&role.id).await?;
+            }
+        }
+        if let Some(groups) = &mut policy.groups {
+            for group in groups {
+                if group.id.is_none()
+                    && let Some(path) = &group.path
+                {
+                    group.id = Some(self.group_id(path).await?);
+                }
+            }
+        }
+        if let Some(clients) = &mut policy.clients {
+            for client_id in clients {
+                *client_id = self.client_id(client_id)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Maps each name in `names` to its id, accepting values that already are known ids.
+fn resolve_names(
+    names: &mut Option<Vec<String>>,
+    known: &HashMap<String, String>,
+    kind: &str,
+    owner: &str,
+) -> Result<()> {
+    if let Some(names) = names {
+        for name in names.iter_mut() {
+            if let Some(id) = known.get(name.as_str()) {
+                *name = id.clone();
+            } else if !known.values().any(|id| id == name) {
+                anyhow::bail!("Unknown {} '{}' referenced by '{}'", kind, name, owner);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn name_index<T>(client: &KeycloakClient, parent: &str) -> Result<HashMap<String, String>>
+where
+    T: KeycloakResource + ResourceMeta + for<'de> serde::Deserialize<'de>,
+{
+    let existing = client
+        .get_child_resources::<T>(parent)
+        .await
+        .with_context(|| format!("Failed to get {}", T::LABEL))?;
+    Ok(existing
+        .into_iter()
+        .filter_map(|r| {
+            let id = r.get_id().map(|s| s.to_string());
+            r.get_identity().zip(id)
+        })
+        .collect())
+}
+
+async fn policy_index(client: &KeycloakClient, uuid: &str) -> Result<HashMap<String, String>> {
+    let existing = client
+        .get_authz_policies(uuid)
+        .await
+        .context("Failed to get authorization policies")?;
+    Ok(existing
+        .into_iter()
+        .filter_map(|p| p.name.zip(p.id))
+        .collect())
+}
+
+pub async fn apply_authz(
+    client: &KeycloakClient,
+    workspace_dir: &Path,
+    resolver: Arc<dyn SecretResolver>,
+    planned_files: Arc<Option<HashSet<PathBuf>>>,
+    realm_name: &str,
+    ws: Arc<WorkspaceContext>,
+    report: &ApplyReport,
+) -> Result<()> {
+    let client_dirs = authz_client_dirs(workspace_dir, &ws).await?;
+    let client_dirs: Vec<PathBuf> = client_dirs
+        .into_iter()
+        .filter(|dir| {
+            planned_files
+                .as_ref()
+                .as_ref()
+                .is_none_or(|plan| plan.iter().any(|p| p.starts_with(dir)))
+        })
+        .collect();
+    if client_dirs.is_empty() {
+        return Ok(());
+    }
+
+    let clients = client
+        .get_clients()
+        .await
+        .with_context(|| format!("Failed to get clients for realm '{}'", realm_name))?;
+    let client_uuids: HashMap<String, String> = clients
+        .into_iter()
+        .filter_map(|c| c.client_id.zip(c.id))
+        .collect();
+
+    for client_dir in client_dirs {
+        let mut applied = Vec::new();
+        let result = apply_client_authz(
+            client,
+            &client_dir,
+            Arc::clone(&resolver),
+            &planned_files,
+            realm_name,
+            &ws,
+            &client_uuids,
+            &mut applied,
+        )
+        .await;
+        // What was sent before a failure is reported too
+        for (path, status) in applied {
+            report.record(
+                realm_name,
+                AUTHZ_DIR,
+                &resource_name(workspace_dir, &path),
+                status,
+            );
+        }
+        if let Err(e) = result {
+            report.finish(
+                realm_name,
+                AUTHZ_DIR,
+                &resource_name(workspace_dir, &client_dir),
+                Err(e),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn apply_client_authz(
+    client: &KeycloakClient,
+    client_dir: &Path,
+    resolver: Arc<dyn SecretResolver>,
+    planned_files: &Option<HashSet<PathBuf>>,
+    realm_name: &str,
+    ws: &WorkspaceContext,
+    client_uuids: &HashMap<String, String>,
+    applied: &mut Vec<(PathBuf, Status)>,
+) -> Result<()> {
+    let settings = load_resource_server(client_dir, ws, Arc::clone(&resolver)).await?;
+    let client_id = authz_client_id(client_dir, settings.as_ref().map(|(_, s)| s))
+        .context("Failed to determine client of authorization directory")?;
+    let uuid = client_uuids.get(&client_id).cloned().with_context(|| {
+        format!(
+            "Client '{}' of authorization directory {:?} does not exist in realm '{}'",
+            client_id, client_dir, realm_name
+        )
+    })?;
+    let parent = format!("clients/{}", uuid);
+
+    // 1. Resource server settings
+    if let Some((path, settings)) = settings
+        && is_planned(planned_files, &path)
+    {
+        client
+            .update_resource_server(&uuid, &settings.settings_only())
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to update authorization settings of client '{}' in realm '{}'",
+                    client_id, realm_name
+                )
+            })?;
+        println!(
+            "  {} {}",
+            SUCCESS_UPDATE,
+            style(format!(
+                "Updated authorization settings of client {}",
+                client_id
+            ))
+            .cyan()
+        );
+        applied.push((path, Status::Updated));
+    }
+
+    // 2. Scopes
+    let scopes: Vec<(PathBuf, AuthzScopeRepresentation)> = load_resource_files(
+        &client_dir.join(AuthzScopeRepresentation::DIR_NAME),
+        ws,
+        Arc::clone(&resolver),
+    )
+    .await?
+    .into_iter()
+    .filter(|(path, _)| is_planned(planned_files, path))
+    .collect();
+    if !scopes.is_empty() {
+        let existing = name_index::<AuthzScopeRepresentation>(client, &parent).await?;
+        applied.extend(upsert_children(client, &parent, scopes, &existing, realm_name).await?);
+    }
+
+    // 3. Resources, with scope names resolved to ids
+    let resources: Vec<(PathBuf, AuthzResourceRepresentation)> = load_resource_files(
+        &client_dir.join(AuthzResourceRepresentation::DIR_NAME),
+        ws,
+        Arc::clone(&resolver),
+    )
+    .await?
+    .into_iter()
+    .filter(|(path, _)| is_planned(planned_files, path))
+    .collect();
+    if !resources.is_empty() {
+        let scope_ids = name_index::<AuthzScopeRepresentation>(client, &parent).await?;
+        let existing = name_index::<AuthzResourceRepresentation>(client, &parent).await?;
+        let mut resolved = Vec::with_capacity(resources.len());
+        for (path, mut resource) in resources {
+            let resource_name = resource.get_name();
+            for scope in resource.scopes.iter_mut().flatten() {
+                if scope.id.is_none()
+                    && let Some(name) = &scope.name
+                {
+                    scope.id = Some(scope_ids.get(name).cloned().with_context(|| {
+                        format!(
+                            "Unknown authorization scope '{}' referenced by resource '{}'",
+                            name, resource_name
+                        )
+                    })?);
+                }
+            }
+            resolved.push((path, resource));
+        }
+        applied.extend(upsert_children(client, &parent, resolved, &existing, realm_name).await?);
+    }
+
+    // 4. Policies, aggregated ones after the policies they reference
+    let policies: Vec<(PathBuf, PolicyRepresentation)> = load_resource_files(
+        &client_dir.join(PolicyRepresentation::DIR_NAME),
+        ws,
+        Arc::clone(&resolver),
+    )
+    .await?
+    .into_iter()
+    .filter(|(path, _)| is_planned(planned_files, path))
+    .collect();
+    let mut refs = RefResolver::new(client, client_uuids);
+    if !policies.is_empty() {
+        let (mut pending, simple): (Vec<_>, Vec<_>) =
+            policies.into_iter().partition(|(_, p)| p.is_aggregate());
+
+        let existing = policy_index(client, &uuid).await?;
+        let mut resolved = Vec::with_capacity(simple.len());
+        for (path, mut policy) in simple {
+            refs.resolve_subjects(&mut policy).await.with_context(|| {
+                format!(
+                    "Failed to resolve references of policy '{}'",
+                    policy.get_name()
+                )
+            })?;
+            resolved.push((path, policy));
+        }
+        applied.extend(upsert_policies(client, &uuid, resolved, &existing, realm_name).await?);
+
+        while !pending.is_empty() {
+            let existing = policy_index(client, &uuid).await?;
+            let (ready, waiting): (Vec<_>, Vec<_>) = pending.into_iter().partition(|(_, p)| {
+                p.policies.iter().flatten().all(|name| {
+                    existing.contains_key(name) || existing.values().any(|id| id == name)
+                })
+            });
+            if ready.is_empty() {
+                let names: Vec<String> = waiting.iter().map(|(_, p)| p.get_name()).collect();
+                anyhow::bail!(
+                    "Cannot resolve policies referenced by aggregated policies {} of client '{}' (missing or cyclic references)",
+                    names.join(", "),
+                    client_id
+                );
+            }
+            let mut resolved = Vec::with_capacity(ready.len());
+            for (path, mut policy) in ready {
+                let owner = policy.get_name();
+                resolve_names(&mut policy.policies, &existing, "policy", &owner)?;
+                refs.resolve_subjects(&mut policy).await?;
+                resolved.push((path, policy));
+            }
+            applied.extend(upsert_policies(client, &uuid, resolved, &existing, realm_name).await?);
+            pending = waiting;
+        }
+    }
+
+    // 5. Permissions, referencing resources, scopes and policies by name
+    let permissions: Vec<(PathBuf, PolicyRepresentation)> =
+        load_resource_files(&client_dir.join(PERMISSIONS_DIR), ws, Arc::clone(&resolver))
+            .await?
+            .into_iter()
+            .filter(|(path, _)| is_planned(planned_files, path))
+            .collect();
+    if !permissions.is_empty() {
+        let existing = policy_index(client, &uuid).await?;
+        let resource_ids = name_index::<AuthzResourceRepresentation>(client, &parent).await?;
+        let scope_ids = name_index::<AuthzScopeRepresentation>(client, &parent).await?;
+        let mut resolved = Vec::with_capacity(permissions.len());
+        for (path, mut permission) in permissions {
+            let owner = permission.get_name();
+            resolve_names(&mut permission.resources, &resource_ids, "resource", &owner)?;
+            resolve_names(&mut permission.scopes, &scope_ids, "scope", &owner)?;
+            resolve_names(&mut permission.policies, &existing, "policy", &owner)?;
+            resolved.push((path, permission));
+        }
+        applied.extend(upsert_policies(client, &uuid, resolved, &existing, realm_name).await?);
+    }
+
+    Ok(())
+}
+
+async fn upsert_children<T>(
+    client: &KeycloakClient,
+    parent: &str,
+    items: Vec<(PathBuf, T)>,
+    existing: &HashMap<String, String>,
+    realm_name: &str,
+) -> Result<Vec<(PathBuf, Status)>>
+where
+    T: KeycloakResource + ResourceMeta + serde::Serialize + Send + Sync + 'static,
+{
+    let mut set = JoinSet::new();
+    for (path, mut rep) in items {
+        let client = client.clone();
+        let parent = parent.to_string();
+        let realm_name = realm_name.to_string();
+        let identity = rep
+            .get_identity()
+            .with_context(|| format!("Failed to get identity for {} in {:?}", T::LABEL, path))?;
+        let id = existing.get(&identity).cloned();
+        set.spawn(async move {
+            if let Some(id) = id {
+                rep.set_id(Some(id.clone()));
+                client
+                    .update_child_resource(&parent, &id, &rep)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Failed to update {} '{}' in realm '{}'",
+                            T::LABEL,
+                            rep.get_name(),
+                            realm_name
+                        )
+                    })?;
+                println!(
+                    "  {} {}",
+                    SUCCESS_UPDATE,
+                    style(format!("Updated {} {}", T::LABEL, rep.get_name())).cyan()
+                );
+            } else {
+                rep.set_id(None);
+                client
+                    .create_child_resource(&parent, &rep)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Failed to create {} '{}' in realm '{}'",
+                            T::LABEL,
+                            rep.get_name(),
+                            realm_name
+                        )
+                    })?;
+                println!(
+                    "  {} {}",
+                    SUCCESS_CREATE,
+                    style(format!("Created {} {}", T::LABEL, rep.get_name())).green()
+                );
+                return Ok((path, Status::Created));
+            }
+            Ok::<_, anyhow::Error>((path, Status::Updated))
+        });
+    }
+    crate::utils::join_all_tasks(set, None).await
+}
+
+async fn upsert_policies(
+    client: &KeycloakClient,
+    uuid: &str,
+    items: Vec<(PathBuf, PolicyRepresentation)>,
+    existing: &HashMap<String, String>,
+    realm_name: &str,
+) -> Result<Vec<(PathBuf, Status)>> {
+    let mut set = JoinSet::new();
+    for (path, mut rep) in items {
+        let client = client.clone();
+        let uuid = uuid.to_string();
+        let realm_name = realm_name.to_string();
+        let id = rep.name.as_ref().and_then(|n| existing.get(n)).cloned();
+        let status = if id.is_some() {
+            Status::Updated
+        } else {
+            Status::Created
+        };
+        set.spawn(async move {
+            crate::handle_upsert! {
+                client: client,
+                realm: realm_name,
+                rep: rep,
+                id_opt: id.as_ref(),
+                id_field: id,
+                resource_name: "authorization policy",
+                update_call: |id, rep| client.update_authz_policy(&uuid, id, rep),
+                create_call: |rep| client.create_authz_policy(&uuid, rep)
+            }
+            Ok::<_, anyhow::Error>((path, status))
+        });
+    }
+    crate::utils::join_all_tasks(set, None).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_names() {
+        let known: HashMap<String, String> = [("read".to_string(), "id-read".to_string())]
+            .into_iter()
+            .collect();
+
+        let mut names = Some(vec!["read".to_string(), "id-read".to_string()]);
+        resolve_names(&mut names, &known, "scope", "perm").unwrap();
+        assert_eq!(
+            names,
+            Some(vec!["id-read".to_string(), "id-read".to_string()])
+        );
+
+        let mut unknown = Some(vec!["write".to_string()]);
+        let err = resolve_names(&mut unknown, &known, "scope", "perm").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Unknown scope 'write' referenced by 'perm'")
+        );
+
+        let mut none = None;
+        resolve_names(&mut none, &known, "scope", "perm").unwrap();
+        assert!(none.is_none());
+    }
+
+    #[test]
+    fn test_is_planned() {
+        let path = PathBuf::from("/ws/realm/authz/app/policies/p.yaml");
+        assert!(is_planned(&None, &path));
+        let plan: HashSet<PathBuf> = [path.clone()].into_iter().collect();
+        assert!(is_planned(&Some(plan.clone()), &path));
+        assert!(!is_planned(&Some(plan), Path::new("/other.yaml")));
+    }
+}
@@ -0,0 +1,196 @@
+use super::is_planned;
+use crate::apply::report::{ApplyReport, Status, resource_name};
+use crate::client::KeycloakClient;
+use crate::models::{
+    ClientPoliciesRepresentation, ClientProfileRepresentation, ClientProfilesRepresentation,
+};
+use crate::plan::client_policies::{
+    CLIENT_POLICIES_DIR, POLICIES_FILE, PROFILES_FILE, load_document,
+};
+use crate::utils::secrets::SecretResolver;
+use crate::utils::ui::SUCCESS_UPDATE;
+use crate::workspace::WorkspaceContext;
+use anyhow::{Context, Result};
+use console::style;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Applies `client-policies/profiles.yaml` and `client-policies/policies.yaml` as whole
+/// documents. Profiles go first, as policies reference them by name; profiles the file
+/// removes are only dropped once the policies have been updated, since Keycloak rejects
+/// removing a profile a policy still uses.
+pub async fn apply_client_policies(
+    client: &KeycloakClient,
+    workspace_dir: &Path,
+    resolver: Arc<dyn SecretResolver>,
+    planned_files: Arc<Option<HashSet<PathBuf>>>,
+    realm_name: &str,
+    ws: Arc<WorkspaceContext>,
+    report: &ApplyReport,
+) -> Result<()> {
+    let dir = workspace_dir.join(CLIENT_POLICIES_DIR);
+    let profiles_path = dir.join(PROFILES_FILE);
+    let policies_path = dir.join(POLICIES_FILE);
+
+    let profiles = if is_planned(&planned_files, &profiles_path) {
+        match load_document::<ClientProfilesRepresentation>(
+            &profiles_path,
+            &ws,
+            Arc::clone(&resolver),
+        )
+        .await
+        {
+            Ok(profiles) => profiles,
+            Err(e) => {
+                finish_document(report, realm_name, workspace_dir, &profiles_path, Err(e))?;
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let policies = if is_planned(&planned_files, &policies_path) {
+        match load_document::<ClientPoliciesRepresentation>(&policies_path, &ws, resolver).await {
+            Ok(policies) => policies,
+            Err(e) => {
+                finish_document(report, realm_name, workspace_dir, &policies_path, Err(e))?;
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    if let Some(profiles) = &profiles {
+        let result = async {
+            // Keep the removed profiles until the policies no longer use them
+            let removed = match &policies {
+                Some(_) => removed_profiles(client, profiles).await?,
+                None => Vec::new(),
+            };
+            let mut first = profiles.clone();
+            first.profiles.extend(removed.iter().cloned());
+            update_profiles(client, &first, realm_name).await?;
+            Ok(removed)
+        }
+        .await;
+        let removed = match result {
+            Ok(removed) => removed,
+            Err(e) => {
+                finish_document(report, realm_name, workspace_dir, &profiles_path, Err(e))?;
+                if policies.is_some() {
+                    report.record(
+                        realm_name,
+                        CLIENT_POLICIES_DIR,
+                        &resource_name(workspace_dir, &policies_path),
+                        Status::Skipped,
+                    );
+                }
+                return Ok(());
+            }
+        };
+        if removed.is_empty() {
+            finish_document(
+                report,
+                realm_name,
+                workspace_dir,
+                &profiles_path,
+                Ok(Some(Status::Updated)),
+            )?;
+        }
+        if let Some(policies) = &policies {
+            let result = update_policies(client, policies, realm_name).await;
+            finish_document(report, realm_name, workspace_dir, &policies_path, result)?;
+        }
+        if !removed.is_empty() {
+            let result = update_profiles(client, profiles, realm_name).await;
+            finish_document(report, realm_name, workspace_dir, &profiles_path, result)?;
+        }
+    } else if let Some(policies) = &policies {
+        let result = update_policies(client, policies, realm_name).await;
+        finish_document(report, realm_name, workspace_dir, &policies_path, result)?;
+    }
+
+    Ok(())
+}
+
+/// The realm profiles on the server that `profiles` leaves out.
+async fn removed_profiles(
+    client: &KeycloakClient,
+    profiles: &ClientProfilesRepresentation,
+) -> Result<Vec<ClientProfileRepresentation>> {
+    let remote = client
+        .get_client_profiles()
+        .await
+        .context("Failed to get client profiles")?
+        .unwrap_or_default();
+    Ok(remote
+        .profiles
+        .into_iter()
+        .filter(|r| !profiles.profiles.iter().any(|p| p.name == r.name))
+        .collect())
+}
+
+async fn update_profiles(
+    client: &KeycloakClient,
+    profiles: &ClientProfilesRepresentation,
+    realm_name: &str,
+) -> Result<Option<Status>> {
+    client
+        .update_client_profiles(profiles)
+        .await
+        .with_context(|| format!("Failed to update client profiles in realm '{}'", realm_name))?;
+    println!(
+        "  {} {}",
+        SUCCESS_UPDATE,
+        style(format!(
+            "Updated client profiles ({})",
+            profiles.profiles.len()
+        ))
+        .cyan()
+    );
+    Ok(Some(Status::Updated))
+}
+
+async fn update_policies(
+    client: &KeycloakClient,
+    policies: &ClientPoliciesRepresentation,
+    realm_name: &str,
+) -> Result<Option<Status>> {
+    client
+        .update_client_policies(policies)
+        .await
+        .with_context(|| format!("Failed to update client policies in realm '{}'", realm_name))?;
+    println!(
+        "  {} {}",
+        SUCCESS_UPDATE,
+        style(format!(
+            "Updated client policies ({})",
+            policies.policies.len()
+        ))
+        .cyan()
+    );
+    Ok(Some(Status::Updated))
+}
+
+/// Records the outcome of a document, unless it does not exist.
+fn finish_document(
+    report: &ApplyReport,
+    realm_name: &str,
+    workspace_dir: &Path,
+    path: &Path,
+    result: Result<Option<Status>>,
+) -> Result<()> {
+    let result = match result {
+        Ok(None) => return Ok(()),
+        Ok(Some(status)) => Ok(status),
+        Err(e) => Err(e),
+    };
+    report.finish(
+        realm_name,
+        CLIENT_POLICIES_DIR,
+        &resource_name(workspace_dir, path),
+        result,
+    )
+}
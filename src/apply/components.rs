@@ -1,3 +1,4 @@
+use crate::apply::report::{ApplyReport, Status, resource_name};
 use crate::client::KeycloakClient;
 use crate::models::{ComponentRepresentation, KeycloakResource};
 use crate::utils::secrets::{SecretResolver, substitute_secrets};
@@ -40,6 +41,7 @@ pub fn build_component_indices(
 }
 
 use crate::utils::yaml::{is_overlay_file, load_yaml_with_overlay};
+use crate::workspace::WorkspaceContext;
 
 pub async fn process_component_file(
     path: PathBuf,
@@ -48,9 +50,9 @@ pub async fn process_component_file(
     by_details: Arc<HashMap<ComponentKey, ComponentRepresentation>>,
     resolver: Arc<dyn SecretResolver>,
     realm_name: String,
-    profile: Option<String>,
-) -> Result<()> {
-    let mut val = load_yaml_with_overlay(&path, profile.as_deref()).await?;
+    ws: Arc<WorkspaceContext>,
+) -> Result<Status> {
+    let mut val = load_yaml_with_overlay(&path, &ws).await?;
     substitute_secrets(&mut val, Arc::clone(&resolver)).await?;
     let mut component_rep: ComponentRepresentation = serde_json::from_value(val)?;
 
@@ -74,6 +76,11 @@ pub async fn process_component_file(
         by_details.get(&key)
     };
 
+    let status = if existing.is_some_and(|e| e.id.is_some()) {
+        Status::Updated
+    } else {
+        Status::Created
+    };
     crate::handle_upsert! {
         client: client,
         realm: realm_name,
@@ -84,9 +91,10 @@ pub async fn process_component_file(
         update_call: |id, rep| client.update_component(id, rep),
         create_call: |rep| client.create_component(rep)
     }
-    Ok(())
+    Ok(status)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn apply_components_or_keys(
     client: &KeycloakClient,
     workspace_dir: &std::path::Path,
@@ -94,7 +102,8 @@ pub async fn apply_components_or_keys(
     resolver: Arc<dyn SecretResolver>,
     planned_files: Arc<Option<HashSet<PathBuf>>>,
     realm_name: &str,
-    profile: Option<String>,
+    ws: Arc<WorkspaceContext>,
+    report: &ApplyReport,
 ) -> Result<()> {
     let components_dir = workspace_dir.join(dir_name);
     if !async_fs::try_exists(&components_dir).await? {
@@ -123,8 +132,8 @@ pub async fn apply_components_or_keys(
         if path.extension().is_none_or(|ext| ext != "yaml") {
             continue;
         }
-        // Skip overlay files themselves
-        if is_overlay_file(&path, profile.as_deref()) {
+        // Skip overlay files themselves and files kcd.yaml excludes
+        if is_overlay_file(&path, &ws) || !ws.filter.is_managed(&path) {
             continue;
         }
 
@@ -133,18 +142,22 @@ pub async fn apply_components_or_keys(
         let by_details = Arc::clone(&by_details);
         let resolver = Arc::clone(&resolver);
         let realm_name = realm_name.to_string();
-        let profile = profile.clone();
+        let ws = Arc::clone(&ws);
+        let report = report.clone();
+        let resource = resource_name(workspace_dir, &path);
+        let kind = dir_name.to_string();
         set.spawn(async move {
-            process_component_file(
+            let result = process_component_file(
                 path,
                 client,
                 by_identity,
                 by_details,
                 resolver,
-                realm_name,
-                profile,
+                realm_name.clone(),
+                ws,
             )
-            .await
+            .await;
+            report.finish(&realm_name, &kind, &resource, result)
         });
     }
     crate::utils::join_all_tasks(set, None).await?;
@@ -238,7 +251,8 @@ mod tests {
             Arc::clone(&resolver) as Arc<dyn SecretResolver>,
             Arc::new(None),
             "test",
-            None,
+            Arc::new(WorkspaceContext::default()),
+            &ApplyReport::default(),
         )
         .await;
         assert!(res.is_err());
@@ -262,7 +276,8 @@ mod tests {
             Arc::clone(&resolver) as Arc<dyn SecretResolver>,
             Arc::new(None),
             "test",
-            None,
+            Arc::new(WorkspaceContext::default()),
+            &ApplyReport::default(),
         )
         .await;
         assert!(res.is_err());
@@ -272,6 +287,25 @@ mod tests {
                 .contains("Failed to create component")
         );
 
+        // 3. Failures are recorded instead when keeping going
+        call_count.store(0, std::sync::atomic::Ordering::SeqCst);
+        let report = ApplyReport::new(true);
+        apply_components_or_keys(
+            &client,
+            temp.path(),
+            "components",
+            Arc::clone(&resolver) as Arc<dyn SecretResolver>,
+            Arc::new(None),
+            "test",
+            Arc::new(WorkspaceContext::default()),
+            &report,
+        )
+        .await?;
+        let entries = report.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].resource, "components/new");
+        assert_eq!(entries[0].status, Status::Failed);
+
         Ok(())
     }
 }
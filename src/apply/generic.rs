@@ -1,16 +1,23 @@
+use crate::apply::report::{ApplyReport, Status, resource_name};
 use crate::client::KeycloakClient;
-use crate::models::{KeycloakResource, ResourceMeta};
+use crate::models::{AttributeStyle, KeycloakResource, ResourceMeta};
+use crate::state::{self, Ownership};
 use crate::utils::secrets::{SecretResolver, substitute_secrets};
 pub use crate::utils::ui::{SUCCESS_CREATE, SUCCESS_UPDATE};
 use crate::utils::ui::{Ui, create_progress_bar};
 use crate::utils::yaml::{is_overlay_file, load_yaml_with_overlay};
+use crate::workspace::WorkspaceContext;
 use anyhow::{Context, Result};
+use serde_json::Value;
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tokio::fs as async_fs;
 use tokio::task::JoinSet;
 
+/// Creates or updates the resources of `T` from their files, marking each with its
+/// [`Ownership`]: in its attributes, or in the state file of the workspace when the type
+/// has none.
 #[allow(clippy::too_many_arguments)]
 pub async fn apply_resources<T>(
     client: &KeycloakClient,
@@ -18,9 +25,10 @@ pub async fn apply_resources<T>(
     resolver: Arc<dyn SecretResolver>,
     planned_files: Arc<Option<HashSet<PathBuf>>>,
     realm_name: &str,
-    profile: Option<String>,
+    ws: Arc<WorkspaceContext>,
     review: bool,
     ui: Arc<dyn Ui>,
+    report: &ApplyReport,
 ) -> Result<()>
 where
     T: KeycloakResource
@@ -43,18 +51,29 @@ where
         .await
         .with_context(|| format!("Failed to get {} for realm '{}'", T::LABEL, realm_name))?;
 
-    let existing_map: HashMap<String, String> = existing_resources
+    let root = workspace_dir
+        .parent()
+        .unwrap_or(workspace_dir)
+        .to_path_buf();
+    let state = state::load(&root).await?;
+    let existing_map: HashMap<String, (String, Option<Ownership>)> = existing_resources
         .into_iter()
         .filter_map(|r| {
             let identity = r.get_identity();
             let id = r.get_id();
             match (identity, id) {
-                (Some(identity), Some(id)) => Some((identity, id.to_string())),
+                (Some(identity), Some(id)) => {
+                    let owner = state.owner(realm_name, &r);
+                    Some((identity, (id.to_string(), owner)))
+                }
                 _ => None,
             }
         })
         .collect();
     let existing_map = Arc::new(existing_map);
+    let workspace_id = state::workspace_id(ws.workspace_id.as_deref(), &root);
+    // What was applied, for the state file and the last-applied representations
+    let applied: Arc<Mutex<Vec<(String, Ownership, Value)>>> = Arc::default();
 
     let mut entries = async_fs::read_dir(&resources_dir).await?;
     let mut files = Vec::new();
@@ -70,8 +89,8 @@ where
         if path.extension().is_none_or(|ext| ext != "yaml") {
             continue;
         }
-        // Skip overlay files themselves
-        if is_overlay_file(&path, profile.as_deref()) {
+        // Skip overlay files themselves and files kcd.yaml excludes
+        if is_overlay_file(&path, &ws) || !ws.filter.is_managed(&path) {
             continue;
         }
         files.push(path);
@@ -89,78 +108,127 @@ where
         let existing_map = Arc::clone(&existing_map);
         let resolver = Arc::clone(&resolver);
         let realm_name = realm_name.to_string();
-        let profile = profile.clone();
+        let ws = Arc::clone(&ws);
         let ui = Arc::clone(&ui);
         let pb = pb.clone();
+        let report = report.clone();
+        let resource = resource_name(workspace_dir, &path);
+        let workspace_id = workspace_id.clone();
+        let applied = Arc::clone(&applied);
 
         set.spawn(async move {
-            let mut val = load_yaml_with_overlay(&path, profile.as_deref()).await?;
-            substitute_secrets(&mut val, Arc::clone(&resolver)).await?;
-            let mut rep: T = serde_json::from_value(val)
-                .with_context(|| format!("Failed to deserialize YAML file: {:?}", path))?;
+            let result = async {
+                let mut val = load_yaml_with_overlay(&path, &ws).await?;
+                substitute_secrets(&mut val, Arc::clone(&resolver)).await?;
+                let mut rep: T = serde_json::from_value(val)
+                    .with_context(|| format!("Failed to deserialize YAML file: {:?}", path))?;
 
-            let identity = rep.get_identity().with_context(|| {
-                format!("Failed to get identity for {} in {:?}", T::LABEL, path)
-            })?;
+                let identity = rep.get_identity().with_context(|| {
+                    format!("Failed to get identity for {} in {:?}", T::LABEL, path)
+                })?;
+                rep.clear_local_fields();
+                let value = state::applied_value(&rep, &ws.secrets)?;
 
-            let id_opt = existing_map.get(&identity);
+                let existing = existing_map.get(&identity);
+                let id_opt = existing.map(|(id, _)| id);
+                // Resources keep the workspace that created them, if any
+                let ownership = Ownership {
+                    managed_by: match existing {
+                        Some((_, owner)) => owner.as_ref().and_then(|o| o.managed_by.clone()),
+                        None => Some(workspace_id),
+                    },
+                    last_applied: state::hash_value(&value),
+                };
+                state::stamp(&mut rep, &ownership)?;
 
-            if review {
-                let action = if id_opt.is_some() { "update" } else { "create" };
-                let proceed = ui.confirm(
-                    &format!(
-                        "Do you want to {} {} '{}'?",
-                        action,
-                        T::LABEL,
-                        rep.get_name()
-                    ),
-                    true,
-                )?;
-                if !proceed {
-                    pb.inc(1);
-                    return Ok::<(), anyhow::Error>(());
+                if review {
+                    let action = if id_opt.is_some() { "update" } else { "create" };
+                    let proceed = ui.confirm(
+                        &format!(
+                            "Do you want to {} {} '{}'?",
+                            action,
+                            T::LABEL,
+                            rep.get_name()
+                        ),
+                        true,
+                    )?;
+                    if !proceed {
+                        return Ok((Status::Skipped, identity, Ownership::default(), value));
+                    }
                 }
-            }
 
-            if let Some(id) = id_opt {
-                rep.set_id(Some(id.clone()));
-                client.update_resource(id, &rep).await.with_context(|| {
-                    format!(
-                        "Failed to update {} '{}' in realm '{}'",
+                if let Some(id) = id_opt {
+                    rep.set_id(Some(id.clone()));
+                    client.update_resource(id, &rep).await.with_context(|| {
+                        format!(
+                            "Failed to update {} '{}' in realm '{}'",
+                            T::LABEL,
+                            rep.get_name(),
+                            realm_name
+                        )
+                    })?;
+                    pb.println(format!(
+                        "  {} Updated {} {}",
+                        SUCCESS_UPDATE,
                         T::LABEL,
-                        rep.get_name(),
-                        realm_name
-                    )
-                })?;
-                pb.println(format!(
-                    "  {} Updated {} {}",
-                    SUCCESS_UPDATE,
-                    T::LABEL,
-                    rep.get_name()
-                ));
-            } else {
-                rep.set_id(None);
-                client.create_resource(&rep).await.with_context(|| {
-                    format!(
-                        "Failed to create {} '{}' in realm '{}'",
+                        rep.get_name()
+                    ));
+                    Ok((Status::Updated, identity, ownership, value))
+                } else {
+                    rep.set_id(None);
+                    client.create_resource(&rep).await.with_context(|| {
+                        format!(
+                            "Failed to create {} '{}' in realm '{}'",
+                            T::LABEL,
+                            rep.get_name(),
+                            realm_name
+                        )
+                    })?;
+                    pb.println(format!(
+                        "  {} Created {} {}",
+                        SUCCESS_CREATE,
                         T::LABEL,
-                        rep.get_name(),
-                        realm_name
-                    )
-                })?;
-                pb.println(format!(
-                    "  {} Created {} {}",
-                    SUCCESS_CREATE,
-                    T::LABEL,
-                    rep.get_name()
-                ));
+                        rep.get_name()
+                    ));
+                    Ok((Status::Created, identity, ownership, value))
+                }
             }
+            .await;
             pb.inc(1);
-            Ok::<(), anyhow::Error>(())
+            let result = result.map(|(status, identity, ownership, value)| {
+                if status != Status::Skipped {
+                    applied
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push((identity, ownership, value));
+                }
+                status
+            });
+            report.finish(&realm_name, dir_name, &resource, result)
         });
     }
 
-    crate::utils::join_all_tasks(set, None).await?;
+    let result = crate::utils::join_all_tasks(set, None).await;
+    // Record what was applied even when some resources failed
+    let applied = std::mem::take(&mut *applied.lock().unwrap_or_else(|e| e.into_inner()));
+    if !applied.is_empty() {
+        if T::ATTRIBUTES == AttributeStyle::None {
+            state::update(&root, |state| {
+                for (identity, ownership, _) in &applied {
+                    state.insert(realm_name, dir_name, identity, ownership.clone());
+                }
+            })
+            .await?;
+        }
+        // The base of the three-way diffs `plan` shows
+        state::update_last_applied(&root, realm_name, dir_name, |last_applied| {
+            for (identity, _, value) in applied {
+                last_applied.insert(identity, value);
+            }
+        })
+        .await?;
+    }
+    result?;
     pb.finish_with_message(format!("Applied {}", T::LABEL));
     Ok(())
 }
@@ -1,6 +1,11 @@
+pub mod authz;
+pub mod client_policies;
 pub mod components;
 pub mod generic;
+pub mod organizations;
 pub mod realm;
+pub mod report;
+pub mod service_accounts;
 
 #[cfg(test)]
 pub mod test_utils;
@@ -55,21 +60,26 @@ macro_rules! handle_upsert {
     };
 }
 
+use crate::apply::report::{ApplyReport, ResourcesFailed, Status};
 use crate::client::KeycloakClient;
+use crate::graph::{DependencyGraph, Unit};
+use crate::lock::LocalLock;
 use crate::models::{
     AuthenticationFlowRepresentation, ClientRepresentation, ClientScopeRepresentation,
     GroupRepresentation, IdentityProviderRepresentation, RequiredActionProviderRepresentation,
     RoleRepresentation, UserRepresentation,
 };
 use crate::utils::secrets::SecretResolver;
-pub use crate::utils::ui::{ACTION, SUCCESS_CREATE, SUCCESS_UPDATE, Ui, WARN};
-use anyhow::Result;
+use crate::utils::selection::Selection;
+pub use crate::utils::ui::{ACTION, MEMO, SUCCESS_CREATE, SUCCESS_UPDATE, Ui, WARN};
+use crate::utils::yaml::realm_files;
+use crate::workspace::WorkspaceContext;
+use anyhow::{Context, Result};
 use console::style;
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::fs as async_fs;
-use tokio::task::JoinSet;
 
 #[allow(clippy::too_many_arguments)]
 pub async fn run(
@@ -80,11 +90,47 @@ pub async fn run(
     review: bool,
     ui: Arc<dyn Ui>,
     resolver: Arc<dyn SecretResolver>,
-    profile: Option<String>,
+    ws: Arc<WorkspaceContext>,
+) -> Result<()> {
+    run_with_selection(
+        client,
+        workspace_dir,
+        realms_to_apply,
+        yes,
+        review,
+        ui,
+        resolver,
+        ws,
+        &Selection::default(),
+        &ApplyReport::default(),
+    )
+    .await
+}
+
+/// Like [`run`], limited to the resources of `selection`. Planned files outside the
+/// selection stay in `.kcdplan` for a later apply. Every resource is recorded in
+/// `report`, which is printed at the end; failures make the run fail once every realm
+/// is done when the report keeps going.
+/// The workspace is locked for the whole run, and so is each realm on the server when
+/// `lock.server` is set in `kcd.yaml`.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_with_selection(
+    client: &KeycloakClient,
+    workspace_dir: PathBuf,
+    realms_to_apply: &[String],
+    yes: bool,
+    review: bool,
+    ui: Arc<dyn Ui>,
+    resolver: Arc<dyn SecretResolver>,
+    ws: Arc<WorkspaceContext>,
+    selection: &Selection,
+    report: &ApplyReport,
 ) -> Result<()> {
     if !workspace_dir.exists() {
         anyhow::bail!("Input directory {:?} does not exist", workspace_dir);
     }
+    crate::plan::check_server_version(client, &workspace_dir).await?;
+    let _lock = LocalLock::acquire(&workspace_dir, &ws.lock).await?;
 
     // Check for .kcdplan
     let plan_path = workspace_dir.join(".kcdplan");
@@ -126,7 +172,9 @@ pub async fn run(
         let mut dirs = Vec::new();
         let mut entries = async_fs::read_dir(&workspace_dir).await?;
         while let Some(entry) = entries.next_entry().await? {
-            if entry.file_type().await?.is_dir() {
+            if entry.file_type().await?.is_dir()
+                && !entry.file_name().to_string_lossy().starts_with('.')
+            {
                 dirs.push(entry.file_name().to_string_lossy().to_string());
             }
         }
@@ -144,16 +192,22 @@ pub async fn run(
         return Ok(());
     }
 
+    let snapshot_dir = crate::snapshot::new_snapshot_dir(&workspace_dir);
     let mut set = tokio::task::JoinSet::new();
+    let mut selections = Vec::new();
 
     for realm_name in realms {
         let mut realm_client = client.clone();
         realm_client.set_target_realm(realm_name.clone());
         let realm_dir = workspace_dir.join(&realm_name);
+        let selection = selection.for_realm(&realm_dir, &ws).await?;
+        selections.push((realm_dir.clone(), selection.clone()));
         let resolver = Arc::clone(&resolver);
         let planned_files = Arc::clone(&planned_files);
-        let profile = profile.clone();
+        let ws = Arc::clone(&ws);
         let ui = Arc::clone(&ui);
+        let snapshot_dir = snapshot_dir.clone();
+        let report = report.clone();
 
         set.spawn(async move {
             println!(
@@ -164,30 +218,108 @@ pub async fn run(
                     .bold()
             );
 
-            apply_single_realm(
+            let lock_settings = ws.lock.clone();
+            let result = crate::lock::with_server_lock(
                 &realm_client,
-                realm_dir,
-                resolver,
-                planned_files,
                 &realm_name,
-                profile,
-                review,
-                ui,
+                &lock_settings,
+                apply_single_realm(
+                    &realm_client,
+                    realm_dir,
+                    resolver,
+                    planned_files,
+                    &realm_name,
+                    ws,
+                    review,
+                    ui,
+                    &selection,
+                    &snapshot_dir,
+                    &report,
+                ),
             )
-            .await
+            .await;
+            match result {
+                Err(e) if report.keep_going() => {
+                    report.fail(&realm_name, "realm", "*", &e);
+                    Ok(())
+                }
+                result => result,
+            }
         });
     }
 
-    crate::utils::join_all_tasks(set, None).await?;
+    let result = crate::utils::join_all_tasks(set, None).await;
+    report.print();
+    crate::snapshot::retain(&workspace_dir, &ws.snapshots).await?;
+    result?;
+    if report.failures() > 0 {
+        anyhow::bail!(
+            "{} resource(s) failed to apply ({})",
+            report.failures(),
+            report.summary()
+        );
+    }
 
-    // Success - remove plan
+    // Success - remove the applied files from the plan
     if plan_path.exists() {
-        let _ = async_fs::remove_file(plan_path).await;
+        let remaining: Vec<PathBuf> = match &*planned_files {
+            Some(plan) if !selection.is_all() => {
+                let mut remaining: Vec<PathBuf> = plan
+                    .iter()
+                    .filter(|path| {
+                        !selections.iter().any(|(realm_dir, selection)| {
+                            path.starts_with(realm_dir) && selection.includes(realm_dir, path)
+                        })
+                    })
+                    .cloned()
+                    .collect();
+                remaining.sort();
+                remaining
+            }
+            _ => Vec::new(),
+        };
+        if remaining.is_empty() {
+            let _ = async_fs::remove_file(plan_path).await;
+        } else {
+            async_fs::write(&plan_path, serde_json::to_string_pretty(&remaining)?).await?;
+        }
     }
 
     Ok(())
 }
 
+/// Whether `path` is part of the plan (everything is when there is no plan).
+fn is_planned(planned_files: &Option<HashSet<PathBuf>>, path: &std::path::Path) -> bool {
+    planned_files
+        .as_ref()
+        .is_none_or(|plan| plan.contains(path))
+}
+
+/// The plans applying the files of `unit` one batch after the other (see
+/// [`DependencyGraph::batches`]), or `planned_files` alone when its files do not refer to
+/// each other.
+fn batched_plans(
+    graph: &DependencyGraph,
+    unit: Unit,
+    realm_dir: &Path,
+    planned_files: &Arc<Option<HashSet<PathBuf>>>,
+) -> Vec<Arc<Option<HashSet<PathBuf>>>> {
+    let batches = graph.batches(unit);
+    if batches.len() < 2 {
+        return vec![Arc::clone(planned_files)];
+    }
+    batches
+        .into_iter()
+        .map(|batch| {
+            let paths = batch
+                .iter()
+                .map(|file| realm_dir.join(format!("{}.yaml", file)))
+                .collect();
+            Arc::new(Some(paths))
+        })
+        .collect()
+}
+
 #[allow(clippy::too_many_arguments)]
 async fn apply_single_realm(
     client: &KeycloakClient,
@@ -195,183 +327,182 @@ async fn apply_single_realm(
     resolver: Arc<dyn SecretResolver>,
     planned_files: Arc<Option<HashSet<PathBuf>>>,
     realm_name: &str,
-    profile: Option<String>,
+    ws: Arc<WorkspaceContext>,
     review: bool,
     ui: Arc<dyn Ui>,
+    selection: &Selection,
+    snapshot_dir: &Path,
+    report: &ApplyReport,
 ) -> Result<()> {
-    // Stage 0: Realms
-    realm::apply_realm(
+    // Narrow the plan down to the selected files
+    let planned_files = if selection.is_all() || !async_fs::try_exists(&workspace_dir).await? {
+        planned_files
+    } else {
+        let selected: HashSet<PathBuf> = realm_files(&workspace_dir, &ws)
+            .await?
+            .into_iter()
+            .filter(|path| selection.includes(&workspace_dir, path))
+            .filter(|path| is_planned(&planned_files, path))
+            .collect();
+        Arc::new(Some(selected))
+    };
+
+    let graph = DependencyGraph::build(&workspace_dir, &ws, selection, &planned_files)
+        .await
+        .with_context(|| format!("Failed to order the resources of realm '{}'", realm_name))?;
+
+    // Record what is about to change so that `kcd rollback` can restore it
+    let snapshot = crate::snapshot::capture(
         client,
         &workspace_dir,
-        Arc::clone(&resolver),
-        Arc::clone(&planned_files),
         realm_name,
-        profile.clone(),
+        &ws,
+        Arc::clone(&resolver),
+        selection,
+        &planned_files,
     )
-    .await?;
-
-    // Stage 1: Identity Providers, Roles
-    {
-        let mut set = JoinSet::new();
-        let client1 = client.clone();
-        let dir1 = workspace_dir.clone();
-        let res1 = Arc::clone(&resolver);
-        let plan1 = Arc::clone(&planned_files);
-        let rn1 = realm_name.to_string();
-        let p1 = profile.clone();
-        let ui1 = Arc::clone(&ui);
-        set.spawn(async move {
-            generic::apply_resources::<IdentityProviderRepresentation>(
-                &client1, &dir1, res1, plan1, &rn1, p1, review, ui1,
-            )
-            .await
-        });
-
-        let client2 = client.clone();
-        let dir2 = workspace_dir.clone();
-        let res2 = Arc::clone(&resolver);
-        let plan2 = Arc::clone(&planned_files);
-        let rn2 = realm_name.to_string();
-        let p2 = profile.clone();
-        let ui2 = Arc::clone(&ui);
-        set.spawn(async move {
-            generic::apply_resources::<RoleRepresentation>(
-                &client2, &dir2, res2, plan2, &rn2, p2, review, ui2,
-            )
-            .await
-        });
-        crate::utils::join_all_tasks(set, None).await?;
+    .await
+    .with_context(|| format!("Failed to snapshot realm '{}'", realm_name))?;
+    if !snapshot.resources.is_empty() {
+        let path = crate::snapshot::save(snapshot_dir, &snapshot).await?;
+        println!(
+            "  {} {}",
+            MEMO,
+            style(format!("Snapshot saved to {:?}", path)).dim()
+        );
     }
 
-    // Stage 2: Clients, Client Scopes, Authentication Flows, Required Actions, Groups
-    {
-        let mut set = JoinSet::new();
-
-        let client_cl = client.clone();
-        let dir_cl = workspace_dir.clone();
-        let res_cl = Arc::clone(&resolver);
-        let plan_cl = Arc::clone(&planned_files);
-        let rn_cl = realm_name.to_string();
-        let p_cl = profile.clone();
-        let ui_cl = Arc::clone(&ui);
-        set.spawn(async move {
-            generic::apply_resources::<ClientRepresentation>(
-                &client_cl, &dir_cl, res_cl, plan_cl, &rn_cl, p_cl, review, ui_cl,
-            )
-            .await
-        });
-
-        let client_sc = client.clone();
-        let dir_sc = workspace_dir.clone();
-        let res_sc = Arc::clone(&resolver);
-        let plan_sc = Arc::clone(&planned_files);
-        let rn_sc = realm_name.to_string();
-        let p_sc = profile.clone();
-        let ui_sc = Arc::clone(&ui);
-        set.spawn(async move {
-            generic::apply_resources::<ClientScopeRepresentation>(
-                &client_sc, &dir_sc, res_sc, plan_sc, &rn_sc, p_sc, review, ui_sc,
-            )
-            .await
-        });
-
-        let client_fl = client.clone();
-        let dir_fl = workspace_dir.clone();
-        let res_fl = Arc::clone(&resolver);
-        let plan_fl = Arc::clone(&planned_files);
-        let rn_fl = realm_name.to_string();
-        let p_fl = profile.clone();
-        let ui_fl = Arc::clone(&ui);
-        set.spawn(async move {
-            generic::apply_resources::<AuthenticationFlowRepresentation>(
-                &client_fl, &dir_fl, res_fl, plan_fl, &rn_fl, p_fl, review, ui_fl,
-            )
-            .await
-        });
-
-        let client_ra = client.clone();
-        let dir_ra = workspace_dir.clone();
-        let res_ra = Arc::clone(&resolver);
-        let plan_ra = Arc::clone(&planned_files);
-        let rn_ra = realm_name.to_string();
-        let p_ra = profile.clone();
-        let ui_ra = Arc::clone(&ui);
-        set.spawn(async move {
-            generic::apply_resources::<RequiredActionProviderRepresentation>(
-                &client_ra, &dir_ra, res_ra, plan_ra, &rn_ra, p_ra, review, ui_ra,
-            )
-            .await
-        });
-
-        let client_gr = client.clone();
-        let dir_gr = workspace_dir.clone();
-        let res_gr = Arc::clone(&resolver);
-        let plan_gr = Arc::clone(&planned_files);
-        let rn_gr = realm_name.to_string();
-        let p_gr = profile.clone();
-        let ui_gr = Arc::clone(&ui);
-        set.spawn(async move {
-            generic::apply_resources::<GroupRepresentation>(
-                &client_gr, &dir_gr, res_gr, plan_gr, &rn_gr, p_gr, review, ui_gr,
-            )
-            .await
-        });
+    let incomplete = graph
+        .execute(
+            client.max_concurrent_requests(),
+            report.keep_going(),
+            |unit| {
+                let client = client.clone();
+                let dir = workspace_dir.clone();
+                let res = Arc::clone(&resolver);
+                let plan = Arc::clone(&planned_files);
+                let batches = batched_plans(&graph, unit, &workspace_dir, &planned_files);
+                let rn = realm_name.to_string();
+                let p = Arc::clone(&ws);
+                let ui = Arc::clone(&ui);
+                let report = report.clone();
+                async move {
+                    let result = match unit {
+                        Unit::Realm => {
+                            realm::apply_realm(&client, &dir, res, plan, &rn, p, &report).await
+                        }
+                        Unit::IdentityProviders => {
+                            generic::apply_resources::<IdentityProviderRepresentation>(
+                                &client, &dir, res, plan, &rn, p, review, ui, &report,
+                            )
+                            .await
+                        }
+                        Unit::Roles => {
+                            // Composite roles after the roles they include
+                            for plan in batches {
+                                generic::apply_resources::<RoleRepresentation>(
+                                    &client,
+                                    &dir,
+                                    Arc::clone(&res),
+                                    plan,
+                                    &rn,
+                                    Arc::clone(&p),
+                                    review,
+                                    Arc::clone(&ui),
+                                    &report,
+                                )
+                                .await?;
+                                report.check(&rn, unit.name())?;
+                            }
+                            Ok(())
+                        }
+                        Unit::ClientScopes => {
+                            generic::apply_resources::<ClientScopeRepresentation>(
+                                &client, &dir, res, plan, &rn, p, review, ui, &report,
+                            )
+                            .await
+                        }
+                        Unit::Clients => {
+                            generic::apply_resources::<ClientRepresentation>(
+                                &client, &dir, res, plan, &rn, p, review, ui, &report,
+                            )
+                            .await
+                        }
+                        Unit::AuthenticationFlows => {
+                            generic::apply_resources::<AuthenticationFlowRepresentation>(
+                                &client, &dir, res, plan, &rn, p, review, ui, &report,
+                            )
+                            .await
+                        }
+                        Unit::RequiredActions => {
+                            generic::apply_resources::<RequiredActionProviderRepresentation>(
+                                &client, &dir, res, plan, &rn, p, review, ui, &report,
+                            )
+                            .await
+                        }
+                        Unit::Groups => {
+                            generic::apply_resources::<GroupRepresentation>(
+                                &client, &dir, res, plan, &rn, p, review, ui, &report,
+                            )
+                            .await
+                        }
+                        Unit::Users => {
+                            generic::apply_resources::<UserRepresentation>(
+                                &client, &dir, res, plan, &rn, p, review, ui, &report,
+                            )
+                            .await
+                        }
+                        Unit::Components | Unit::Keys => {
+                            components::apply_components_or_keys(
+                                &client,
+                                &dir,
+                                unit.resource_type(),
+                                res,
+                                plan,
+                                &rn,
+                                p,
+                                &report,
+                            )
+                            .await
+                        }
+                        Unit::Authz => {
+                            authz::apply_authz(&client, &dir, res, plan, &rn, p, &report).await
+                        }
+                        Unit::ClientPolicies => {
+                            client_policies::apply_client_policies(
+                                &client, &dir, res, plan, &rn, p, &report,
+                            )
+                            .await
+                        }
+                        Unit::ServiceAccounts => {
+                            service_accounts::apply_service_accounts(
+                                &client, &dir, res, plan, &rn, p, &report,
+                            )
+                            .await
+                        }
+                        Unit::Organizations => {
+                            organizations::apply_organizations(
+                                &client, &dir, res, plan, &rn, p, &report,
+                            )
+                            .await
+                        }
+                    };
+                    result?;
+                    // Resources that failed while keeping going fail the unit as well
+                    report.check(&rn, unit.name())
+                }
+            },
+        )
+        .await?;
 
-        crate::utils::join_all_tasks(set, None).await?;
+    for (unit, e) in &incomplete.failed {
+        // Failed resources are in the report already
+        if e.downcast_ref::<ResourcesFailed>().is_none() {
+            report.fail(realm_name, unit.name(), "*", e);
+        }
     }
-
-    // Stage 3: Users, Components, Keys
-    {
-        let mut set = JoinSet::new();
-
-        let client_us = client.clone();
-        let dir_us = workspace_dir.clone();
-        let res_us = Arc::clone(&resolver);
-        let plan_us = Arc::clone(&planned_files);
-        let rn_us = realm_name.to_string();
-        let p_us = profile.clone();
-        let ui_us = Arc::clone(&ui);
-        set.spawn(async move {
-            generic::apply_resources::<UserRepresentation>(
-                &client_us, &dir_us, res_us, plan_us, &rn_us, p_us, review, ui_us,
-            )
-            .await
-        });
-
-        let client_co = client.clone();
-        let dir_co = workspace_dir.clone();
-        let res_co = Arc::clone(&resolver);
-        let plan_co = Arc::clone(&planned_files);
-        let rn_co = realm_name.to_string();
-        let p_co = profile.clone();
-        set.spawn(async move {
-            components::apply_components_or_keys(
-                &client_co,
-                &dir_co,
-                "components",
-                res_co,
-                plan_co,
-                &rn_co,
-                p_co,
-            )
-            .await
-        });
-
-        let client_ke = client.clone();
-        let dir_ke = workspace_dir.clone();
-        let res_ke = Arc::clone(&resolver);
-        let plan_ke = Arc::clone(&planned_files);
-        let rn_ke = realm_name.to_string();
-        let p_ke = profile.clone();
-        set.spawn(async move {
-            components::apply_components_or_keys(
-                &client_ke, &dir_ke, "keys", res_ke, plan_ke, &rn_ke, p_ke,
-            )
-            .await
-        });
-
-        crate::utils::join_all_tasks(set, None).await?;
+    for unit in &incomplete.skipped {
+        report.record(realm_name, unit.name(), "*", Status::Skipped);
     }
-
     Ok(())
 }
@@ -0,0 +1,266 @@
+use super::is_planned;
+use crate::apply::report::{ApplyReport, Status, resource_name};
+use crate::client::KeycloakClient;
+use crate::models::{KeycloakResource, OrganizationRepresentation, ResourceMeta};
+use crate::plan::load_resource_files;
+use crate::utils::secrets::SecretResolver;
+use crate::utils::ui::{SUCCESS_DELETE, SUCCESS_UPDATE, WARN};
+use crate::workspace::WorkspaceContext;
+use anyhow::{Context, Result};
+use console::style;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs as async_fs;
+
+pub async fn apply_organizations(
+    client: &KeycloakClient,
+    workspace_dir: &Path,
+    resolver: Arc<dyn SecretResolver>,
+    planned_files: Arc<Option<HashSet<PathBuf>>>,
+    realm_name: &str,
+    ws: Arc<WorkspaceContext>,
+    report: &ApplyReport,
+) -> Result<()> {
+    let orgs_dir = workspace_dir.join(OrganizationRepresentation::DIR_NAME);
+    if !async_fs::try_exists(&orgs_dir).await? {
+        return Ok(());
+    }
+
+    let orgs: Vec<(PathBuf, OrganizationRepresentation)> =
+        load_resource_files(&orgs_dir, &ws, resolver)
+            .await?
+            .into_iter()
+            .filter(|(path, _)| is_planned(&planned_files, path))
+            .collect();
+    if orgs.is_empty() {
+        return Ok(());
+    }
+
+    let Some(existing) = client.get_organizations().await.with_context(|| {
+        format!(
+            "Failed to get {} for realm '{}'",
+            OrganizationRepresentation::LABEL,
+            realm_name
+        )
+    })?
+    else {
+        println!(
+            "{} {}",
+            WARN,
+            style(format!(
+                "Organizations are not supported by this server (Keycloak 25+ required), skipping {} organization(s) in realm '{}'",
+                orgs.len(),
+                realm_name
+            ))
+            .yellow()
+        );
+        return Ok(());
+    };
+    let mut existing_map = organization_ids(existing);
+
+    for (path, org) in orgs {
+        let result = async {
+            let identity = org.get_identity().with_context(|| {
+                format!(
+                    "Failed to get identity for {} in {:?}",
+                    OrganizationRepresentation::LABEL,
+                    path
+                )
+            })?;
+
+            let mut rep = org.for_api();
+            let id_opt = existing_map.get(&identity).cloned();
+            let created = id_opt.is_none();
+            crate::handle_upsert!(
+                client: client,
+                realm: realm_name,
+                rep: rep,
+                id_opt: id_opt.as_ref(),
+                id_field: id,
+                resource_name: "organization",
+                update_call: |id, r| client.update_resource(id, r),
+                create_call: |r| client.create_resource(r)
+            );
+
+            if created {
+                let refreshed = client
+                    .get_organizations()
+                    .await?
+                    .context("Organizations endpoint disappeared while applying")?;
+                existing_map = organization_ids(refreshed);
+            }
+            let org_id = existing_map.get(&identity).cloned().with_context(|| {
+                format!(
+                    "Organization '{}' not found in realm '{}' after creation",
+                    identity, realm_name
+                )
+            })?;
+
+            if let Some(members) = &org.members {
+                reconcile_members(client, &org_id, &identity, members).await?;
+            }
+            if let Some(idps) = &org.identity_providers {
+                reconcile_identity_providers(client, &org_id, &identity, idps).await?;
+            }
+            Ok(if created {
+                Status::Created
+            } else {
+                Status::Updated
+            })
+        }
+        .await;
+        report.finish(
+            realm_name,
+            OrganizationRepresentation::DIR_NAME,
+            &resource_name(workspace_dir, &path),
+            result,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn organization_ids(orgs: Vec<OrganizationRepresentation>) -> HashMap<String, String> {
+    orgs.into_iter()
+        .filter_map(|o| o.get_identity().zip(o.id))
+        .collect()
+}
+
+async fn reconcile_members(
+    client: &KeycloakClient,
+    org_id: &str,
+    org_name: &str,
+    desired: &[String],
+) -> Result<()> {
+    let current: HashMap<String, String> = client
+        .get_organization_members(org_id)
+        .await
+        .with_context(|| format!("Failed to get members of organization '{}'", org_name))?
+        .into_iter()
+        .filter_map(|u| u.username.zip(u.id))
+        .collect();
+    let desired: BTreeSet<&str> = desired.iter().map(String::as_str).collect();
+
+    for username in &desired {
+        if current.contains_key(*username) {
+            continue;
+        }
+        let user = client
+            .get_user_by_username(username)
+            .await?
+            .with_context(|| {
+                format!(
+                    "Unknown user '{}' referenced by organization '{}'",
+                    username, org_name
+                )
+            })?;
+        let user_id = user.id.context("User has no id")?;
+        client
+            .add_organization_member(org_id, &user_id)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to add member '{}' to organization '{}'",
+                    username, org_name
+                )
+            })?;
+        println!(
+            "  {} {}",
+            SUCCESS_UPDATE,
+            style(format!(
+                "Added member {} to organization {}",
+                username, org_name
+            ))
+            .cyan()
+        );
+    }
+
+    for (username, user_id) in &current {
+        if desired.contains(username.as_str()) {
+            continue;
+        }
+        client
+            .remove_organization_member(org_id, user_id)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to remove member '{}' from organization '{}'",
+                    username, org_name
+                )
+            })?;
+        println!(
+            "  {} {}",
+            SUCCESS_DELETE,
+            style(format!(
+                "Removed member {} from organization {}",
+                username, org_name
+            ))
+            .yellow()
+        );
+    }
+    Ok(())
+}
+
+async fn reconcile_identity_providers(
+    client: &KeycloakClient,
+    org_id: &str,
+    org_name: &str,
+    desired: &[String],
+) -> Result<()> {
+    let current: BTreeSet<String> = client
+        .get_organization_identity_providers(org_id)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to get identity providers of organization '{}'",
+                org_name
+            )
+        })?
+        .into_iter()
+        .filter_map(|i| i.alias)
+        .collect();
+    let desired: BTreeSet<String> = desired.iter().cloned().collect();
+
+    for alias in desired.difference(&current) {
+        client
+            .link_organization_identity_provider(org_id, alias)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to link identity provider '{}' to organization '{}'",
+                    alias, org_name
+                )
+            })?;
+        println!(
+            "  {} {}",
+            SUCCESS_UPDATE,
+            style(format!(
+                "Linked identity provider {} to organization {}",
+                alias, org_name
+            ))
+            .cyan()
+        );
+    }
+    for alias in current.difference(&desired) {
+        client
+            .unlink_organization_identity_provider(org_id, alias)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to unlink identity provider '{}' from organization '{}'",
+                    alias, org_name
+                )
+            })?;
+        println!(
+            "  {} {}",
+            SUCCESS_DELETE,
+            style(format!(
+                "Unlinked identity provider {} from organization {}",
+                alias, org_name
+            ))
+            .yellow()
+        );
+    }
+    Ok(())
+}
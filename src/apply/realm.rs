@@ -1,8 +1,12 @@
+use crate::apply::report::{ApplyReport, Status};
 use crate::client::KeycloakClient;
+use crate::lock::strip_server_lock;
 use crate::models::RealmRepresentation;
+use crate::state;
 use crate::utils::secrets::{SecretResolver, substitute_secrets};
 use crate::utils::ui::SUCCESS_UPDATE;
 use crate::utils::yaml::load_yaml_with_overlay;
+use crate::workspace::WorkspaceContext;
 use anyhow::{Context, Result};
 use console::style;
 use std::collections::HashSet;
@@ -16,7 +20,8 @@ pub async fn apply_realm(
     resolver: Arc<dyn SecretResolver>,
     planned_files: Arc<Option<HashSet<PathBuf>>>,
     realm_name: &str,
-    profile: Option<String>,
+    ws: Arc<WorkspaceContext>,
+    report: &ApplyReport,
 ) -> Result<()> {
     // 1. Apply Realm
     let realm_path = workspace_dir.join("realm.yaml");
@@ -25,19 +30,33 @@ pub async fn apply_realm(
     {
         return Ok(());
     }
-    if async_fs::try_exists(&realm_path).await? {
-        let mut val = load_yaml_with_overlay(&realm_path, profile.as_deref()).await?;
-        substitute_secrets(&mut val, Arc::clone(&resolver)).await?;
-        let realm_rep: RealmRepresentation = serde_json::from_value(val)?;
-        client
-            .update_realm(&realm_rep)
-            .await
-            .with_context(|| format!("Failed to update realm '{}'", realm_name))?;
-        println!(
-            "  {} {}",
-            SUCCESS_UPDATE,
-            style("Updated realm configuration").cyan()
-        );
+    if async_fs::try_exists(&realm_path).await? && ws.filter.is_managed(&realm_path) {
+        let result = async {
+            let mut val = load_yaml_with_overlay(&realm_path, &ws).await?;
+            substitute_secrets(&mut val, Arc::clone(&resolver)).await?;
+            let mut realm_rep: RealmRepresentation = serde_json::from_value(val)?;
+            // Sending the lock back would replace the one this run holds
+            strip_server_lock(&mut realm_rep);
+            client
+                .update_realm(&realm_rep)
+                .await
+                .with_context(|| format!("Failed to update realm '{}'", realm_name))?;
+            // The base of the three-way diff `plan` shows
+            let value = state::applied_value(&realm_rep, &ws.secrets)?;
+            let root = workspace_dir.parent().unwrap_or(workspace_dir);
+            state::update_last_applied(root, realm_name, "realm", |last_applied| {
+                last_applied.insert(realm_name.to_string(), value);
+            })
+            .await?;
+            println!(
+                "  {} {}",
+                SUCCESS_UPDATE,
+                style("Updated realm configuration").cyan()
+            );
+            Ok(Status::Updated)
+        }
+        .await;
+        report.finish(realm_name, "realm", "realm", result)?;
     }
     Ok(())
 }
@@ -0,0 +1,295 @@
+use crate::utils::ui::{ERROR, MEMO, SUCCESS_CREATE, SUCCESS_DELETE, SUCCESS_UPDATE, WARN};
+use anyhow::{Context, Result};
+use console::style;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Created,
+    Updated,
+    Deleted,
+    Skipped,
+    Failed,
+}
+
+impl Status {
+    const ALL: [Status; 5] = [
+        Status::Created,
+        Status::Updated,
+        Status::Deleted,
+        Status::Skipped,
+        Status::Failed,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Status::Created => "created",
+            Status::Updated => "updated",
+            Status::Deleted => "deleted",
+            Status::Skipped => "skipped",
+            Status::Failed => "failed",
+        }
+    }
+}
+
+/// The error of a unit whose resources failed while keeping going: the failures are in
+/// the report already, this only keeps the units waiting for it from starting.
+#[derive(Debug)]
+pub struct ResourcesFailed {
+    pub kind: String,
+    pub count: usize,
+}
+
+impl std::fmt::Display for ResourcesFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} failed to apply", self.count, self.kind)
+    }
+}
+
+impl std::error::Error for ResourcesFailed {}
+
+/// What happened to one resource during an `apply`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReportEntry {
+    pub realm: String,
+    /// The unit that applied the resource (`clients`, `service-accounts`, ...).
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The file of the resource relative to the realm, without `.yaml` (the syntax of
+    /// `--target`), or `*` when a whole unit failed or was skipped.
+    pub resource: String,
+    pub status: Status,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Collects the outcome of every resource of an `apply`. Clones share the same entries,
+/// so that every task of the run can record into it.
+#[derive(Debug, Clone, Default)]
+pub struct ApplyReport {
+    keep_going: bool,
+    entries: Arc<Mutex<Vec<ReportEntry>>>,
+}
+
+impl ApplyReport {
+    /// With `keep_going`, failed resources are recorded and the run goes on with the
+    /// resources that do not depend on them.
+    pub fn new(keep_going: bool) -> Self {
+        Self {
+            keep_going,
+            ..Default::default()
+        }
+    }
+
+    pub fn keep_going(&self) -> bool {
+        self.keep_going
+    }
+
+    pub fn record(&self, realm: &str, kind: &str, resource: &str, status: Status) {
+        self.push(ReportEntry {
+            realm: realm.to_string(),
+            kind: kind.to_string(),
+            resource: resource.to_string(),
+            status,
+            error: None,
+        });
+    }
+
+    pub fn fail(&self, realm: &str, kind: &str, resource: &str, error: &anyhow::Error) {
+        self.push(ReportEntry {
+            realm: realm.to_string(),
+            kind: kind.to_string(),
+            resource: resource.to_string(),
+            status: Status::Failed,
+            error: Some(format!("{:#}", error)),
+        });
+    }
+
+    /// Records the outcome of applying one resource. A failure is returned as an error
+    /// unless the report keeps going.
+    pub fn finish(
+        &self,
+        realm: &str,
+        kind: &str,
+        resource: &str,
+        result: Result<Status>,
+    ) -> Result<()> {
+        match result {
+            Ok(status) => {
+                self.record(realm, kind, resource, status);
+                Ok(())
+            }
+            Err(e) => {
+                self.fail(realm, kind, resource, &e);
+                if self.keep_going { Ok(()) } else { Err(e) }
+            }
+        }
+    }
+
+    /// Fails with [`ResourcesFailed`] when resources of `kind` in `realm` failed, so that
+    /// a unit that kept going still counts as failed.
+    pub fn check(&self, realm: &str, kind: &str) -> Result<()> {
+        let count = self
+            .entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|e| e.realm == realm && e.kind == kind && e.status == Status::Failed)
+            .count();
+        if count > 0 {
+            return Err(ResourcesFailed {
+                kind: kind.to_string(),
+                count,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    fn push(&self, entry: ReportEntry) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(entry);
+    }
+
+    /// The entries, by realm, in the order they were recorded.
+    pub fn entries(&self) -> Vec<ReportEntry> {
+        let mut entries = self
+            .entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        entries.sort_by(|a, b| a.realm.cmp(&b.realm));
+        entries
+    }
+
+    pub fn count(&self, status: Status) -> usize {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|e| e.status == status)
+            .count()
+    }
+
+    pub fn failures(&self) -> usize {
+        self.count(Status::Failed)
+    }
+
+    /// `3 created, 1 updated, 0 deleted, 0 skipped, 1 failed`.
+    pub fn summary(&self) -> String {
+        Status::ALL
+            .iter()
+            .map(|status| format!("{} {}", self.count(*status), status.name()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn print(&self) {
+        let entries = self.entries();
+        if entries.is_empty() {
+            return;
+        }
+        println!("\n{} {}", MEMO, style("Apply report").cyan().bold());
+        for entry in &entries {
+            let (emoji, status) = match entry.status {
+                Status::Created => (SUCCESS_CREATE, style(entry.status.name()).green()),
+                Status::Updated => (SUCCESS_UPDATE, style(entry.status.name()).cyan()),
+                Status::Deleted => (SUCCESS_DELETE, style(entry.status.name()).red()),
+                Status::Skipped => (WARN, style(entry.status.name()).yellow()),
+                Status::Failed => (ERROR, style(entry.status.name()).red().bold()),
+            };
+            let resource = if entry.resource == "*" {
+                format!("{} (all)", entry.kind)
+            } else {
+                entry.resource.clone()
+            };
+            println!("  {} {:<8} {}/{}", emoji, status, entry.realm, resource);
+            if let Some(error) = &entry.error {
+                println!("      {}", style(error).red());
+            }
+        }
+        println!("  {}", self.summary());
+    }
+
+    /// Writes the report as JSON: a count per status and the entries.
+    pub async fn write_json(&self, path: &Path) -> Result<()> {
+        let summary: BTreeMap<&str, usize> = Status::ALL
+            .iter()
+            .map(|status| (status.name(), self.count(*status)))
+            .collect();
+        let json = serde_json::json!({
+            "summary": summary,
+            "resources": self.entries(),
+        });
+        tokio::fs::write(path, serde_json::to_string_pretty(&json)? + "\n")
+            .await
+            .with_context(|| format!("Failed to write apply report to {:?}", path))
+    }
+}
+
+/// The name of the resource in `path` for the report: its path relative to the realm
+/// directory, without `.yaml`.
+pub fn resource_name(realm_dir: &Path, path: &Path) -> String {
+    crate::graph::relative_segments(realm_dir, path)
+        .map(|segments| segments.join("/"))
+        .unwrap_or_else(|| path.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let report = ApplyReport::new(false);
+        report.record("master", "roles", "roles/admin", Status::Created);
+        assert!(
+            report
+                .finish(
+                    "master",
+                    "clients",
+                    "clients/app",
+                    Err(anyhow::anyhow!("boom"))
+                )
+                .is_err()
+        );
+        let shared = report.clone();
+        shared.record("alpha", "realm", "realm", Status::Updated);
+
+        let entries = report.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].realm, "alpha");
+        assert_eq!(entries[2].error.as_deref(), Some("boom"));
+        assert_eq!(report.failures(), 1);
+        assert_eq!(
+            report.summary(),
+            "1 created, 1 updated, 0 deleted, 0 skipped, 1 failed"
+        );
+
+        let keep_going = ApplyReport::new(true);
+        assert!(
+            keep_going
+                .finish("master", "users", "users/bob", Err(anyhow::anyhow!("boom")))
+                .is_ok()
+        );
+        assert_eq!(keep_going.failures(), 1);
+        let err = keep_going.check("master", "users").unwrap_err();
+        assert!(err.downcast_ref::<ResourcesFailed>().is_some());
+        assert!(keep_going.check("master", "roles").is_ok());
+        assert!(keep_going.check("alpha", "users").is_ok());
+
+        assert_eq!(
+            resource_name(
+                Path::new("/ws/master"),
+                Path::new("/ws/master/clients/app.yaml")
+            ),
+            "clients/app"
+        );
+    }
+}
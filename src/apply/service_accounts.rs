@@ -0,0 +1,367 @@
+use super::is_planned;
+use crate::apply::report::{ApplyReport, Status, resource_name};
+use crate::client::KeycloakClient;
+use crate::models::{
+    ClientRepresentation, KeycloakResource, RoleRepresentation, ServiceAccountRepresentation,
+};
+use crate::plan::load_resource_files;
+use crate::plan::service_accounts::{current_service_account, default_roles_name};
+use crate::utils::secrets::SecretResolver;
+use crate::utils::ui::SUCCESS_UPDATE;
+use crate::workspace::WorkspaceContext;
+use anyhow::{Context, Result};
+use console::style;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs as async_fs;
+
+/// Reconciles the `serviceAccount` block of client files: realm roles, client roles and
+/// groups of each client's service account user. Runs once roles, clients and groups exist.
+pub async fn apply_service_accounts(
+    client: &KeycloakClient,
+    workspace_dir: &Path,
+    resolver: Arc<dyn SecretResolver>,
+    planned_files: Arc<Option<HashSet<PathBuf>>>,
+    realm_name: &str,
+    ws: Arc<WorkspaceContext>,
+    report: &ApplyReport,
+) -> Result<()> {
+    let clients_dir = workspace_dir.join(ClientRepresentation::DIR_NAME);
+    if !async_fs::try_exists(&clients_dir).await? {
+        return Ok(());
+    }
+    let locals: Vec<(PathBuf, String, ServiceAccountRepresentation)> =
+        load_resource_files::<ClientRepresentation>(&clients_dir, &ws, resolver)
+            .await?
+            .into_iter()
+            .filter(|(path, _)| is_planned(&planned_files, path))
+            .filter_map(|(path, c)| {
+                let name = c.get_name();
+                c.service_account.map(|sa| (path, name, sa))
+            })
+            .collect();
+    if locals.is_empty() {
+        return Ok(());
+    }
+
+    let client_uuids: HashMap<String, String> = client
+        .get_clients()
+        .await
+        .with_context(|| format!("Failed to get clients for realm '{}'", realm_name))?
+        .into_iter()
+        .filter_map(|c| c.client_id.zip(c.id))
+        .collect();
+    let realm_roles: HashMap<String, RoleRepresentation> = client
+        .get_roles()
+        .await
+        .with_context(|| format!("Failed to get roles for realm '{}'", realm_name))?
+        .into_iter()
+        .map(|r| (r.name.clone(), r))
+        .collect();
+
+    for (path, client_id, desired) in locals {
+        let result = async {
+            let uuid = client_uuids.get(&client_id).with_context(|| {
+                format!(
+                    "Client '{}' does not exist in realm '{}'",
+                    client_id, realm_name
+                )
+            })?;
+            let user = client
+                .get_service_account_user(uuid)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to get service account user of client '{}' (is serviceAccountsEnabled set?)",
+                        client_id
+                    )
+                })?;
+            let user_id = user.id.context("Service account user has no id")?;
+            let current = current_service_account(client, &user_id, realm_name)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to get service account roles of client '{}'",
+                        client_id
+                    )
+                })?;
+
+            if let Some(roles) = &desired.realm_roles {
+                reconcile_realm_roles(
+                    client,
+                    &user_id,
+                    &client_id,
+                    roles,
+                    current.realm_roles.as_deref().unwrap_or_default(),
+                    &realm_roles,
+                    realm_name,
+                )
+                .await?;
+            }
+            if let Some(desired_client_roles) = &desired.client_roles {
+                let current_client_roles = current.client_roles.clone().unwrap_or_default();
+                let role_clients: BTreeSet<&String> = desired_client_roles
+                    .keys()
+                    .chain(current_client_roles.keys())
+                    .collect();
+                for role_client in role_clients {
+                    let role_client_uuid = client_uuids.get(role_client).with_context(|| {
+                        format!(
+                            "Unknown client '{}' referenced by service account of client '{}'",
+                            role_client, client_id
+                        )
+                    })?;
+                    reconcile_client_roles(
+                        client,
+                        &user_id,
+                        &client_id,
+                        role_client,
+                        role_client_uuid,
+                        desired_client_roles
+                            .get(role_client)
+                            .map(Vec::as_slice)
+                            .unwrap_or_default(),
+                        current_client_roles
+                            .get(role_client)
+                            .map(Vec::as_slice)
+                            .unwrap_or_default(),
+                    )
+                    .await?;
+                }
+            }
+            if let Some(groups) = &desired.groups {
+                reconcile_groups(client, &user_id, &client_id, groups).await?;
+            }
+            Ok(Status::Updated)
+        }
+        .await;
+        report.finish(
+            realm_name,
+            "service-accounts",
+            &resource_name(workspace_dir, &path),
+            result,
+        )?;
+    }
+    Ok(())
+}
+
+/// Splits desired vs current names into (to add, to remove).
+fn role_delta<'a>(desired: &'a [String], current: &'a [String]) -> (Vec<&'a str>, Vec<&'a str>) {
+    let desired: BTreeSet<&str> = desired.iter().map(String::as_str).collect();
+    let current: BTreeSet<&str> = current.iter().map(String::as_str).collect();
+    (
+        desired.difference(&current).copied().collect(),
+        current.difference(&desired).copied().collect(),
+    )
+}
+
+fn lookup_roles(
+    names: &[&str],
+    known: &HashMap<String, RoleRepresentation>,
+    kind: &str,
+    owner: &str,
+) -> Result<Vec<RoleRepresentation>> {
+    names
+        .iter()
+        .map(|name| {
+            known.get(*name).cloned().with_context(|| {
+                format!(
+                    "Unknown {} '{}' referenced by service account of client '{}'",
+                    kind, name, owner
+                )
+            })
+        })
+        .collect()
+}
+
+async fn reconcile_realm_roles(
+    client: &KeycloakClient,
+    user_id: &str,
+    client_id: &str,
+    desired: &[String],
+    current: &[String],
+    known: &HashMap<String, RoleRepresentation>,
+    realm_name: &str,
+) -> Result<()> {
+    let (to_add, to_remove) = role_delta(desired, current);
+    let default_roles = default_roles_name(realm_name);
+    let to_remove: Vec<&str> = to_remove
+        .into_iter()
+        .filter(|r| *r != default_roles)
+        .collect();
+
+    if !to_add.is_empty() {
+        let roles = lookup_roles(&to_add, known, "realm role", client_id)?;
+        client
+            .add_user_realm_roles(user_id, &roles)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to assign realm roles to service account of client '{}'",
+                    client_id
+                )
+            })?;
+    }
+    if !to_remove.is_empty() {
+        let roles = lookup_roles(&to_remove, known, "realm role", client_id)?;
+        client
+            .remove_user_realm_roles(user_id, &roles)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to unassign realm roles from service account of client '{}'",
+                    client_id
+                )
+            })?;
+    }
+    report(client_id, "realm roles", &to_add, &to_remove);
+    Ok(())
+}
+
+async fn reconcile_client_roles(
+    client: &KeycloakClient,
+    user_id: &str,
+    client_id: &str,
+    role_client: &str,
+    role_client_uuid: &str,
+    desired: &[String],
+    current: &[String],
+) -> Result<()> {
+    let (to_add, to_remove) = role_delta(desired, current);
+    if to_add.is_empty() && to_remove.is_empty() {
+        return Ok(());
+    }
+    let known: HashMap<String, RoleRepresentation> = client
+        .get_client_roles(role_client_uuid)
+        .await
+        .with_context(|| format!("Failed to get roles of client '{}'", role_client))?
+        .into_iter()
+        .map(|r| (r.name.clone(), r))
+        .collect();
+    let kind = format!("role of client {}", role_client);
+
+    if !to_add.is_empty() {
+        let roles = lookup_roles(&to_add, &known, &kind, client_id)?;
+        client
+            .add_user_client_roles(user_id, role_client_uuid, &roles)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to assign roles of client '{}' to service account of client '{}'",
+                    role_client, client_id
+                )
+            })?;
+    }
+    if !to_remove.is_empty() {
+        let roles = lookup_roles(&to_remove, &known, &kind, client_id)?;
+        client
+            .remove_user_client_roles(user_id, role_client_uuid, &roles)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to unassign roles of client '{}' from service account of client '{}'",
+                    role_client, client_id
+                )
+            })?;
+    }
+    report(
+        client_id,
+        &format!("{} roles", role_client),
+        &to_add,
+        &to_remove,
+    );
+    Ok(())
+}
+
+async fn reconcile_groups(
+    client: &KeycloakClient,
+    user_id: &str,
+    client_id: &str,
+    desired: &[String],
+) -> Result<()> {
+    let current: HashMap<String, String> = client
+        .get_user_groups(user_id)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to get groups of service account of client '{}'",
+                client_id
+            )
+        })?
+        .into_iter()
+        .filter_map(|g| g.path.zip(g.id))
+        .collect();
+    let current_paths: Vec<String> = current.keys().cloned().collect();
+    let (to_add, to_remove) = role_delta(desired, &current_paths);
+
+    for path in &to_add {
+        let group = client.get_group_by_path(path).await.with_context(|| {
+            format!(
+                "Unknown group '{}' referenced by service account of client '{}'",
+                path, client_id
+            )
+        })?;
+        let group_id = group.id.context("Group has no id")?;
+        client
+            .add_user_to_group(user_id, &group_id)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to add service account of client '{}' to group '{}'",
+                    client_id, path
+                )
+            })?;
+    }
+    for path in &to_remove {
+        client
+            .remove_user_from_group(user_id, &current[*path])
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to remove service account of client '{}' from group '{}'",
+                    client_id, path
+                )
+            })?;
+    }
+    report(client_id, "groups", &to_add, &to_remove);
+    Ok(())
+}
+
+fn report(client_id: &str, what: &str, added: &[&str], removed: &[&str]) {
+    if added.is_empty() && removed.is_empty() {
+        return;
+    }
+    let mut changes = Vec::new();
+    if !added.is_empty() {
+        changes.push(format!("+{}", added.join(", +")));
+    }
+    if !removed.is_empty() {
+        changes.push(format!("-{}", removed.join(", -")));
+    }
+    println!(
+        "  {} {}",
+        SUCCESS_UPDATE,
+        style(format!(
+            "Updated service account {} of client {} ({})",
+            what,
+            client_id,
+            changes.join(", ")
+        ))
+        .cyan()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_delta() {
+        let desired = vec!["a".to_string(), "b".to_string()];
+        let current = vec!["b".to_string(), "c".to_string()];
+        let (add, remove) = role_delta(&desired, &current);
+        assert_eq!(add, vec!["a"]);
+        assert_eq!(remove, vec!["c"]);
+    }
+}
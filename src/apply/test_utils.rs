@@ -204,6 +204,7 @@ pub async fn start_mock_server() -> Result<(String, Arc<std::sync::atomic::Atomi
                 public_client: None,
                 bearer_only: None,
                 service_accounts_enabled: None,
+                service_account: None,
                 extra: Default::default(),
             })
             .post(mock_handler!(StatusCode::CREATED)),
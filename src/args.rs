@@ -1,4 +1,8 @@
-use clap::{Parser, Subcommand};
+use crate::graph::GraphFormat;
+use crate::render::RenderFormat;
+use crate::utils::secrets::sink::SecretsSink;
+use crate::utils::selection::Selection;
+use clap::{Args, Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
@@ -8,11 +12,17 @@ pub struct Cli {
     pub command: Commands,
 
     /// Keycloak Server URL
-    #[arg(long, env = "KEYCLOAK_URL", required_unless_present = "profile")]
+    #[arg(long, env = "KEYCLOAK_URL")]
     pub server: Option<String>,
 
     /// Keycloak Realms to consider. If empty, all realms are considered.
-    #[arg(long, env = "KEYCLOAK_REALMS", value_delimiter = ',')]
+    #[arg(
+        long,
+        alias = "realm",
+        env = "KEYCLOAK_REALMS",
+        value_delimiter = ',',
+        global = true
+    )]
     pub realms: Vec<String>,
 
     /// Keycloak Admin User
@@ -23,18 +33,22 @@ pub struct Cli {
     #[arg(skip)]
     pub password: Option<String>,
 
-    /// Keycloak Client ID (for client credentials grant)
-    #[arg(long, env = "KEYCLOAK_CLIENT_ID", default_value = "admin-cli")]
-    pub client_id: String,
+    /// Keycloak Client ID (for client credentials grant) [default: admin-cli]
+    #[arg(long, env = "KEYCLOAK_CLIENT_ID")]
+    pub client_id: Option<String>,
 
     /// Keycloak Client Secret (for client credentials grant)
     #[arg(skip)]
     pub client_secret: Option<String>,
 
     /// Profile name to load from profiles/ directory
-    #[arg(long, short = 'p')]
+    #[arg(long, short = 'p', env = "KCD_PROFILE", global = true)]
     pub profile: Option<String>,
 
+    /// Maximum number of concurrent requests to Keycloak
+    #[arg(long, env = "KCD_CONCURRENCY", global = true)]
+    pub concurrency: Option<usize>,
+
     /// HashiCorp Vault URL
     #[arg(long, env = "VAULT_ADDR")]
     pub vault_addr: Option<String>,
@@ -42,6 +56,10 @@ pub struct Cli {
     /// HashiCorp Vault Token
     #[arg(long, env = "VAULT_TOKEN")]
     pub vault_token: Option<String>,
+
+    /// HashiCorp Vault Enterprise namespace
+    #[arg(long, env = "VAULT_NAMESPACE")]
+    pub vault_namespace: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -55,6 +73,18 @@ pub enum Commands {
         /// Skip confirmation prompt when overwriting local files
         #[arg(long, short = 'y', default_value = "false")]
         yes: bool,
+
+        /// Where to store extracted secrets: `dotenv` (the .secrets file) or
+        /// `vault:<mount>/<path>`, where `<realm>` in the path is replaced by the realm name
+        #[arg(long, default_value = "dotenv")]
+        secrets_sink: SecretsSink,
+
+        /// Remove `KEYCLOAK_*` entries of .secrets that no workspace file references anymore
+        #[arg(long, default_value = "false")]
+        prune_secrets: bool,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
     },
     /// Validate the local Keycloak configuration files
     Validate {
@@ -75,6 +105,17 @@ pub enum Commands {
         /// Ask for confirmation before applying each resource
         #[arg(long, short = 'r', default_value = "false")]
         review: bool,
+
+        /// Go on with the resources that do not depend on failed ones, and fail at the end
+        #[arg(long, default_value = "false")]
+        keep_going: bool,
+
+        /// Also write the apply report to this file, as JSON
+        #[arg(long, value_name = "FILE")]
+        report: Option<PathBuf>,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
     },
     /// Plan the application of the local Keycloak configuration
     Plan {
@@ -89,12 +130,58 @@ pub enum Commands {
         /// Ask interactively whether to include each change in the plan
         #[arg(long, short = 'i', default_value = "false")]
         interactive: bool,
+
+        /// Compare every field returned by the server, in order, instead of only the
+        /// fields set locally
+        #[arg(long)]
+        strict: bool,
+
+        /// Print the order in which apply would process the local files (`text`, or `dot`
+        /// for Graphviz) instead of planning; needs no server
+        #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "text")]
+        graph: Option<GraphFormat>,
+
+        /// Also write the planned changes to this file, as JSON
+        #[arg(long, value_name = "FILE")]
+        report: Option<PathBuf>,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
+    },
+    /// Render the effective configuration of a profile (overlays, variables and secrets applied)
+    Render {
+        /// Workspace directory containing configuration files
+        #[arg(long, short = 'w', default_value = "workspace")]
+        workspace: PathBuf,
+
+        /// Only render this file or directory of each realm, e.g. `clients/my-app`
+        #[arg(long)]
+        resource: Option<String>,
+
+        /// Resolve and show secret values instead of masking them
+        #[arg(long, default_value = "false")]
+        reveal: bool,
+
+        /// Output format (default: `output` in kcd.yaml, then yaml)
+        #[arg(long, value_enum)]
+        format: Option<RenderFormat>,
+
+        /// Diff the effective configuration of two profiles instead of printing it
+        #[arg(long, num_args = 2, value_names = ["FROM", "TO"])]
+        diff_profiles: Option<Vec<String>>,
     },
     /// Check for drift between local configuration and server
     Drift {
         /// Workspace directory containing configuration files
         #[arg(long, short = 'w', default_value = "workspace")]
         workspace: PathBuf,
+
+        /// Also write the drifted resources to this file, as JSON
+        #[arg(long, value_name = "FILE")]
+        report: Option<PathBuf>,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
     },
     /// Interactive CLI mode to generate local configuration
     Cli {
@@ -112,4 +199,83 @@ pub enum Commands {
         #[arg(long, short = 'y', default_value = "false")]
         yes: bool,
     },
+    /// Restore the resources changed by an apply from its snapshot
+    Rollback {
+        /// Workspace directory containing configuration files
+        #[arg(long, short = 'w', default_value = "workspace")]
+        workspace: PathBuf,
+
+        /// Snapshot to restore, as listed in `.kcd/snapshots` (default: the latest)
+        #[arg(long)]
+        snapshot: Option<String>,
+
+        /// Skip confirmation prompt
+        #[arg(long, short = 'y', default_value = "false")]
+        yes: bool,
+    },
+    /// Delete the server resources the workspace created that no longer have a local file
+    Prune {
+        /// Workspace directory containing configuration files
+        #[arg(long, short = 'w', default_value = "workspace")]
+        workspace: PathBuf,
+
+        /// Skip confirmation prompt
+        #[arg(long, short = 'y', default_value = "false")]
+        yes: bool,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
+    },
+    /// Watch the workspace and apply the changed files as they are saved
+    Watch {
+        /// Workspace directory containing configuration files
+        #[arg(long, short = 'w', default_value = "workspace")]
+        workspace: PathBuf,
+
+        /// Milliseconds without changes to wait before planning them
+        #[arg(long, default_value = "500")]
+        debounce: u64,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
+    },
+    /// Remove a stale apply lock of the workspace (and of the realms on the server when
+    /// `lock.server` is set in kcd.yaml)
+    ForceUnlock {
+        /// Workspace directory containing configuration files
+        #[arg(long, short = 'w', default_value = "workspace")]
+        workspace: PathBuf,
+
+        /// Skip confirmation prompt
+        #[arg(long, short = 'y', default_value = "false")]
+        yes: bool,
+    },
+}
+
+/// Restricts a command to some resources of each realm.
+#[derive(Args, Debug, Clone, Default)]
+pub struct SelectionArgs {
+    /// Only these resource types, e.g. `clients,roles` (`realm` is realm.yaml)
+    #[arg(long, value_delimiter = ',')]
+    pub only: Vec<String>,
+
+    /// Leave out these resource types, e.g. `users`
+    #[arg(long, value_delimiter = ',')]
+    pub exclude: Vec<String>,
+
+    /// Only this resource, as a path in the realm directory such as `clients/my-app` or
+    /// `roles/app-*`; can be repeated
+    #[arg(long = "target", value_name = "PATH")]
+    pub targets: Vec<String>,
+
+    /// Also select the resources the selected ones depend on (client scopes, roles,
+    /// groups, ...)
+    #[arg(long, default_value = "false")]
+    pub with_deps: bool,
+}
+
+impl SelectionArgs {
+    pub fn to_selection(&self) -> anyhow::Result<Selection> {
+        Selection::new(&self.only, &self.exclude, &self.targets, self.with_deps)
+    }
 }
@@ -69,6 +69,10 @@ pub async fn run(workspace_dir: PathBuf, yes: bool, realms_to_clean: &[String])
             );
             let mut entries = fs::read_dir(&workspace_dir).await?;
             while let Some(entry) = entries.next_entry().await? {
+                // Snapshots outlive the exported configuration
+                if entry.file_name() == crate::snapshot::STATE_DIR {
+                    continue;
+                }
                 let path = entry.path();
                 let file_type = entry.file_type().await?;
                 set.spawn(async move {
@@ -38,6 +38,7 @@ pub async fn create_client_yaml(
         public_client: Some(is_public),
         bearer_only: Some(false),
         service_accounts_enabled: Some(!is_public),
+        service_account: None,
         extra: HashMap::new(),
     };
 
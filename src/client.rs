@@ -1,13 +1,18 @@
 use crate::models::{
-    AuthenticationFlowRepresentation, ClientRepresentation, ClientScopeRepresentation,
-    ComponentRepresentation, GroupRepresentation, IdentityProviderRepresentation, KeycloakResource,
-    RealmRepresentation, RequiredActionProviderRepresentation, RoleRepresentation,
+    AuthenticationFlowRepresentation, ClientPoliciesRepresentation, ClientProfilesRepresentation,
+    ClientRepresentation, ClientScopeRepresentation, ComponentRepresentation, GroupRepresentation,
+    IdentityProviderRepresentation, KeycloakResource, MappingsRepresentation,
+    OrganizationRepresentation, PolicyRepresentation, RealmRepresentation,
+    RequiredActionProviderRepresentation, ResourceServerRepresentation, RoleRepresentation,
     UserRepresentation,
 };
+use crate::server::{Capability, ServerVersion, strip_unsupported_fields};
 use anyhow::{Context, Result};
 use log::{debug, info};
 use reqwest::{Client, Response};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
 #[derive(Clone)]
 pub struct KeycloakClient {
@@ -15,6 +20,10 @@ pub struct KeycloakClient {
     base_url: String,
     pub target_realm: String, // The realm we are managing
     token: Option<String>,
+    server_version: Option<ServerVersion>,
+    /// Caps the requests in flight across all clones of this client.
+    request_limit: Option<Arc<Semaphore>>,
+    max_concurrent_requests: Option<usize>,
 }
 
 impl KeycloakClient {
@@ -26,6 +35,26 @@ impl KeycloakClient {
             base_url,
             target_realm,
             token: None,
+            server_version: None,
+            request_limit: None,
+            max_concurrent_requests: None,
+        }
+    }
+
+    /// Limits the number of concurrent requests (shared by clones made afterwards).
+    pub fn set_max_concurrent_requests(&mut self, max: usize) {
+        self.request_limit = Some(Arc::new(Semaphore::new(max.max(1))));
+        self.max_concurrent_requests = Some(max.max(1));
+    }
+
+    pub fn max_concurrent_requests(&self) -> Option<usize> {
+        self.max_concurrent_requests
+    }
+
+    async fn request_permit(&self) -> Option<OwnedSemaphorePermit> {
+        match &self.request_limit {
+            Some(limit) => Arc::clone(limit).acquire_owned().await.ok(),
+            None => None,
         }
     }
 
@@ -60,7 +89,13 @@ impl KeycloakClient {
     pub async fn get_resources<T: KeycloakResource + for<'a> Deserialize<'a>>(
         &self,
     ) -> Result<Vec<T>> {
-        self.get(&self.resource_url::<T>()).await
+        let mut url = self.resource_url::<T>();
+        // Role and group lists are brief by default, without the attributes kcd keeps
+        // its ownership markers in
+        if matches!(T::API_PATH, "roles" | "groups") {
+            url.push_str("?briefRepresentation=false");
+        }
+        self.get(&url).await
     }
 
     pub async fn get_resource<T: KeycloakResource + for<'a> Deserialize<'a>>(
@@ -71,7 +106,8 @@ impl KeycloakClient {
     }
 
     pub async fn create_resource<T: KeycloakResource + Serialize>(&self, res: &T) -> Result<()> {
-        self.post(&self.resource_url::<T>(), res).await
+        let body = self.supported_body::<T>(res)?;
+        self.post(&self.resource_url::<T>(), &body).await
     }
 
     pub async fn update_resource<T: KeycloakResource + Serialize>(
@@ -79,7 +115,18 @@ impl KeycloakClient {
         id: &str,
         res: &T,
     ) -> Result<()> {
-        self.put(&self.object_url::<T>(id), res).await
+        let body = self.supported_body::<T>(res)?;
+        self.put(&self.object_url::<T>(id), &body).await
+    }
+
+    /// Serializes `res` without the fields the server version does not know about.
+    fn supported_body<T: KeycloakResource + Serialize>(
+        &self,
+        res: &T,
+    ) -> Result<serde_json::Value> {
+        let mut body = serde_json::to_value(res)?;
+        strip_unsupported_fields(T::API_PATH, self.server_version, &mut body);
+        Ok(body)
     }
 
     pub async fn delete_resource<T: KeycloakResource>(&self, id: &str) -> Result<()> {
@@ -293,6 +340,7 @@ impl KeycloakClient {
 
     async fn get<T: for<'a> Deserialize<'a>>(&self, url: &str) -> Result<T> {
         let token = self.get_token()?;
+        let _permit = self.request_permit().await;
         debug!("GET {}", redact_url(url));
         let response = self
             .client
@@ -309,6 +357,7 @@ impl KeycloakClient {
 
     async fn post<T: Serialize>(&self, url: &str, body: &T) -> Result<()> {
         let token = self.get_token()?;
+        let _permit = self.request_permit().await;
         debug!("POST {}", redact_url(url));
         let response = self
             .client
@@ -325,6 +374,7 @@ impl KeycloakClient {
 
     async fn put<T: Serialize>(&self, url: &str, body: &T) -> Result<()> {
         let token = self.get_token()?;
+        let _permit = self.request_permit().await;
         debug!("PUT {}", redact_url(url));
         let response = self
             .client
@@ -341,6 +391,7 @@ impl KeycloakClient {
 
     async fn delete(&self, url: &str) -> Result<()> {
         let token = self.get_token()?;
+        let _permit = self.request_permit().await;
         debug!("DELETE {}", redact_url(url));
         let response = self
             .client
@@ -410,6 +461,41 @@ impl KeycloakClient {
         Ok(())
     }
 
+    /// Reads the server version from `/admin/serverinfo` and remembers it for
+    /// capability checks.
+    pub async fn detect_server_version(&mut self) -> Result<ServerVersion> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ServerInfo {
+            system_info: SystemInfo,
+        }
+        #[derive(Deserialize)]
+        struct SystemInfo {
+            version: String,
+        }
+
+        let url = format!("{}/admin/serverinfo", self.base_url);
+        let info: ServerInfo = self.get(&url).await?;
+        let version: ServerVersion = info.system_info.version.parse()?;
+        info!("Detected Keycloak server version {}", version);
+        self.server_version = Some(version);
+        Ok(version)
+    }
+
+    /// The detected server version, if `detect_server_version` succeeded.
+    pub fn server_version(&self) -> Option<ServerVersion> {
+        self.server_version
+    }
+
+    pub fn set_server_version(&mut self, version: ServerVersion) {
+        self.server_version = Some(version);
+    }
+
+    /// Whether the server supports `capability`. Assumes it does when the version is unknown.
+    pub fn supports(&self, capability: Capability) -> bool {
+        capability.is_supported_by(self.server_version)
+    }
+
     pub fn get_token(&self) -> Result<&str> {
         self.token.as_deref().context("Not authenticated")
     }
@@ -454,6 +540,391 @@ impl KeycloakClient {
     }
 }
 
+impl KeycloakClient {
+    fn resource_server_url(&self, client_uuid: &str) -> String {
+        format!(
+            "{}/clients/{}/authz/resource-server",
+            self.realm_admin_url(),
+            client_uuid
+        )
+    }
+
+    fn child_resource_url<T: KeycloakResource>(&self, parent: &str) -> String {
+        format!("{}/{}/{}", self.realm_admin_url(), parent, T::API_PATH)
+    }
+
+    fn child_object_url<T: KeycloakResource>(&self, parent: &str, id: &str) -> String {
+        format!(
+            "{}/{}/{}",
+            self.realm_admin_url(),
+            parent,
+            T::object_path(id)
+        )
+    }
+
+    /// Lists resources nested under `parent` (e.g. `clients/{id}`).
+    pub async fn get_child_resources<T: KeycloakResource + for<'a> Deserialize<'a>>(
+        &self,
+        parent: &str,
+    ) -> Result<Vec<T>> {
+        self.get(&self.child_resource_url::<T>(parent)).await
+    }
+
+    pub async fn create_child_resource<T: KeycloakResource + Serialize>(
+        &self,
+        parent: &str,
+        res: &T,
+    ) -> Result<()> {
+        let body = self.supported_body::<T>(res)?;
+        self.post(&self.child_resource_url::<T>(parent), &body)
+            .await
+    }
+
+    pub async fn update_child_resource<T: KeycloakResource + Serialize>(
+        &self,
+        parent: &str,
+        id: &str,
+        res: &T,
+    ) -> Result<()> {
+        let body = self.supported_body::<T>(res)?;
+        self.put(&self.child_object_url::<T>(parent, id), &body)
+            .await
+    }
+
+    pub async fn delete_child_resource<T: KeycloakResource>(
+        &self,
+        parent: &str,
+        id: &str,
+    ) -> Result<()> {
+        self.delete(&self.child_object_url::<T>(parent, id)).await
+    }
+
+    /// Fetches the resource server export (settings, resources, scopes and policies with name-based references).
+    pub async fn export_resource_server(
+        &self,
+        client_uuid: &str,
+    ) -> Result<ResourceServerRepresentation> {
+        let url = self.resource_server_url(client_uuid) + "/settings";
+        self.get(&url).await
+    }
+
+    pub async fn update_resource_server(
+        &self,
+        client_uuid: &str,
+        rep: &ResourceServerRepresentation,
+    ) -> Result<()> {
+        self.put(&self.resource_server_url(client_uuid), rep).await
+    }
+
+    /// Lists all policies and permissions of a client resource server.
+    pub async fn get_authz_policies(&self, client_uuid: &str) -> Result<Vec<PolicyRepresentation>> {
+        let url = self.resource_server_url(client_uuid) + "/policy?first=0&max=-1";
+        self.get(&url).await
+    }
+
+    pub async fn create_authz_policy(
+        &self,
+        client_uuid: &str,
+        rep: &PolicyRepresentation,
+    ) -> Result<()> {
+        let policy_type = rep.type_.as_deref().context("Policy type is required")?;
+        let url = format!(
+            "{}/policy/{}",
+            self.resource_server_url(client_uuid),
+            policy_type
+        );
+        self.post(&url, rep).await
+    }
+
+    pub async fn update_authz_policy(
+        &self,
+        client_uuid: &str,
+        id: &str,
+        rep: &PolicyRepresentation,
+    ) -> Result<()> {
+        let policy_type = rep.type_.as_deref().context("Policy type is required")?;
+        let url = format!(
+            "{}/policy/{}/{}",
+            self.resource_server_url(client_uuid),
+            policy_type,
+            id
+        );
+        self.put(&url, rep).await
+    }
+
+    pub async fn get_client_roles(&self, client_uuid: &str) -> Result<Vec<RoleRepresentation>> {
+        self.get_child_resources(&format!("clients/{}", client_uuid))
+            .await
+    }
+
+    pub async fn get_group_by_path(&self, path: &str) -> Result<GroupRepresentation> {
+        let url = format!(
+            "{}/group-by-path/{}",
+            self.realm_admin_url(),
+            path.trim_start_matches('/')
+        );
+        self.get(&url).await
+    }
+}
+
+impl KeycloakClient {
+    fn organization_url(&self, org_id: &str) -> String {
+        format!("{}/organizations/{}", self.realm_admin_url(), org_id)
+    }
+
+    /// Like `get`, but yields `None` when the endpoint answers 404 (e.g. a feature
+    /// that the server does not expose).
+    async fn get_optional<T: for<'a> Deserialize<'a>>(&self, url: &str) -> Result<Option<T>> {
+        let token = self.get_token()?;
+        let _permit = self.request_permit().await;
+        debug!("GET {}", redact_url(url));
+        let response = self
+            .client
+            .get(url)
+            .bearer_auth(token)
+            .send()
+            .await
+            .with_context(|| format!("Failed to send GET request to {}", redact_url(url)))?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = Self::check_response(response, "GET request failed").await?;
+
+        response
+            .json()
+            .await
+            .map(Some)
+            .context("Failed to parse response")
+    }
+
+    /// Lists the organizations of the realm, or `None` when the server does not
+    /// support organizations (Keycloak < 25).
+    pub async fn get_organizations(&self) -> Result<Option<Vec<OrganizationRepresentation>>> {
+        if !self.supports(Capability::Organizations) {
+            return Ok(None);
+        }
+        let url = format!(
+            "{}/organizations?briefRepresentation=false&first=0&max=-1",
+            self.realm_admin_url()
+        );
+        let Some(orgs) = self.get_optional::<Vec<serde_json::Value>>(&url).await? else {
+            return Ok(None);
+        };
+        orgs.into_iter()
+            .map(|mut org| {
+                // Members and identity providers are reconciled through their own
+                // endpoints and represented by name in the workspace.
+                if let Some(obj) = org.as_object_mut() {
+                    obj.remove("members");
+                    obj.remove("identityProviders");
+                }
+                serde_json::from_value(org).context("Failed to parse organization")
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(Some)
+    }
+
+    pub async fn get_organization_members(&self, org_id: &str) -> Result<Vec<UserRepresentation>> {
+        let url = self.organization_url(org_id) + "/members?first=0&max=-1";
+        self.get(&url).await
+    }
+
+    pub async fn add_organization_member(&self, org_id: &str, user_id: &str) -> Result<()> {
+        let url = self.organization_url(org_id) + "/members";
+        self.post(&url, &user_id).await
+    }
+
+    pub async fn remove_organization_member(&self, org_id: &str, user_id: &str) -> Result<()> {
+        let url = format!("{}/members/{}", self.organization_url(org_id), user_id);
+        self.delete(&url).await
+    }
+
+    pub async fn get_organization_identity_providers(
+        &self,
+        org_id: &str,
+    ) -> Result<Vec<IdentityProviderRepresentation>> {
+        let url = self.organization_url(org_id) + "/identity-providers";
+        self.get(&url).await
+    }
+
+    pub async fn link_organization_identity_provider(
+        &self,
+        org_id: &str,
+        alias: &str,
+    ) -> Result<()> {
+        let url = self.organization_url(org_id) + "/identity-providers";
+        self.post(&url, &alias).await
+    }
+
+    pub async fn unlink_organization_identity_provider(
+        &self,
+        org_id: &str,
+        alias: &str,
+    ) -> Result<()> {
+        let url = format!(
+            "{}/identity-providers/{}",
+            self.organization_url(org_id),
+            alias
+        );
+        self.delete(&url).await
+    }
+
+    pub async fn get_user_by_username(&self, username: &str) -> Result<Option<UserRepresentation>> {
+        let url = format!("{}/users", self.realm_admin_url());
+        let token = self.get_token()?;
+        let _permit = self.request_permit().await;
+        debug!("GET {}", redact_url(&url));
+        let response = self
+            .client
+            .get(&url)
+            .query(&[("username", username), ("exact", "true")])
+            .bearer_auth(token)
+            .send()
+            .await
+            .with_context(|| format!("Failed to send GET request to {}", redact_url(&url)))?;
+        let response = Self::check_response(response, "GET request failed").await?;
+        let users: Vec<UserRepresentation> =
+            response.json().await.context("Failed to parse response")?;
+        Ok(users
+            .into_iter()
+            .find(|u| u.username.as_deref() == Some(username)))
+    }
+}
+
+impl KeycloakClient {
+    /// Fetches the realm client profiles (without the built-in global profiles), or
+    /// `None` when the server does not expose client policies.
+    pub async fn get_client_profiles(&self) -> Result<Option<ClientProfilesRepresentation>> {
+        if !self.supports(Capability::ClientPolicies) {
+            return Ok(None);
+        }
+        let url =
+            self.realm_admin_url() + "/client-policies/profiles?include-global-profiles=false";
+        self.get_optional(&url).await
+    }
+
+    /// Replaces the realm client profiles document.
+    pub async fn update_client_profiles(&self, rep: &ClientProfilesRepresentation) -> Result<()> {
+        let url = self.realm_admin_url() + "/client-policies/profiles";
+        self.put(&url, rep).await
+    }
+
+    /// Fetches the realm client policies (without the built-in global policies), or
+    /// `None` when the server does not expose client policies.
+    pub async fn get_client_policies(&self) -> Result<Option<ClientPoliciesRepresentation>> {
+        if !self.supports(Capability::ClientPolicies) {
+            return Ok(None);
+        }
+        let url =
+            self.realm_admin_url() + "/client-policies/policies?include-global-policies=false";
+        self.get_optional(&url).await
+    }
+
+    /// Replaces the realm client policies document.
+    pub async fn update_client_policies(&self, rep: &ClientPoliciesRepresentation) -> Result<()> {
+        let url = self.realm_admin_url() + "/client-policies/policies";
+        self.put(&url, rep).await
+    }
+}
+
+impl KeycloakClient {
+    fn user_url(&self, user_id: &str) -> String {
+        format!("{}/users/{}", self.realm_admin_url(), user_id)
+    }
+
+    /// The hidden `service-account-<clientId>` user of a client.
+    pub async fn get_service_account_user(&self, client_uuid: &str) -> Result<UserRepresentation> {
+        let url = format!(
+            "{}/clients/{}/service-account-user",
+            self.realm_admin_url(),
+            client_uuid
+        );
+        self.get(&url).await
+    }
+
+    pub async fn get_user_role_mappings(&self, user_id: &str) -> Result<MappingsRepresentation> {
+        self.get(&(self.user_url(user_id) + "/role-mappings")).await
+    }
+
+    pub async fn add_user_realm_roles(
+        &self,
+        user_id: &str,
+        roles: &[RoleRepresentation],
+    ) -> Result<()> {
+        let url = self.user_url(user_id) + "/role-mappings/realm";
+        self.post(&url, &roles).await
+    }
+
+    pub async fn remove_user_realm_roles(
+        &self,
+        user_id: &str,
+        roles: &[RoleRepresentation],
+    ) -> Result<()> {
+        let url = self.user_url(user_id) + "/role-mappings/realm";
+        self.delete_with_body(&url, &roles).await
+    }
+
+    pub async fn add_user_client_roles(
+        &self,
+        user_id: &str,
+        client_uuid: &str,
+        roles: &[RoleRepresentation],
+    ) -> Result<()> {
+        let url = format!(
+            "{}/role-mappings/clients/{}",
+            self.user_url(user_id),
+            client_uuid
+        );
+        self.post(&url, &roles).await
+    }
+
+    pub async fn remove_user_client_roles(
+        &self,
+        user_id: &str,
+        client_uuid: &str,
+        roles: &[RoleRepresentation],
+    ) -> Result<()> {
+        let url = format!(
+            "{}/role-mappings/clients/{}",
+            self.user_url(user_id),
+            client_uuid
+        );
+        self.delete_with_body(&url, &roles).await
+    }
+
+    pub async fn get_user_groups(&self, user_id: &str) -> Result<Vec<GroupRepresentation>> {
+        self.get(&(self.user_url(user_id) + "/groups")).await
+    }
+
+    pub async fn add_user_to_group(&self, user_id: &str, group_id: &str) -> Result<()> {
+        let url = format!("{}/groups/{}", self.user_url(user_id), group_id);
+        self.put(&url, &()).await
+    }
+
+    pub async fn remove_user_from_group(&self, user_id: &str, group_id: &str) -> Result<()> {
+        let url = format!("{}/groups/{}", self.user_url(user_id), group_id);
+        self.delete(&url).await
+    }
+
+    async fn delete_with_body<T: Serialize>(&self, url: &str, body: &T) -> Result<()> {
+        let token = self.get_token()?;
+        let _permit = self.request_permit().await;
+        debug!("DELETE {}", redact_url(url));
+        let response = self
+            .client
+            .delete(url)
+            .bearer_auth(token)
+            .json(body)
+            .send()
+            .await
+            .with_context(|| format!("Failed to send DELETE request to {}", redact_url(url)))?;
+
+        Self::check_response(response, "DELETE request failed").await?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,1008 @@
+use crate::utils::selection::Selection;
+use crate::utils::ui::{SEARCH, WARN};
+use crate::utils::yaml::{load_yaml_with_overlay, realm_files};
+use crate::workspace::WorkspaceContext;
+use anyhow::{Context, Result};
+use console::style;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+
+/// A step of `apply`: one resource type of a realm, applied by one function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Unit {
+    Realm,
+    IdentityProviders,
+    Roles,
+    ClientScopes,
+    Clients,
+    AuthenticationFlows,
+    RequiredActions,
+    Groups,
+    Users,
+    Components,
+    Keys,
+    Authz,
+    ClientPolicies,
+    ServiceAccounts,
+    Organizations,
+}
+
+impl Unit {
+    pub const ALL: [Unit; 15] = [
+        Unit::Realm,
+        Unit::IdentityProviders,
+        Unit::Roles,
+        Unit::ClientScopes,
+        Unit::Clients,
+        Unit::AuthenticationFlows,
+        Unit::RequiredActions,
+        Unit::Groups,
+        Unit::Users,
+        Unit::Components,
+        Unit::Keys,
+        Unit::Authz,
+        Unit::ClientPolicies,
+        Unit::ServiceAccounts,
+        Unit::Organizations,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Unit::ServiceAccounts => "service-accounts",
+            unit => unit.resource_type(),
+        }
+    }
+
+    /// The resource type (see [`crate::utils::selection::RESOURCE_TYPES`]) whose files the
+    /// unit applies. Service accounts are declared in client files.
+    pub fn resource_type(self) -> &'static str {
+        match self {
+            Unit::Realm => "realm",
+            Unit::IdentityProviders => "identity-providers",
+            Unit::Roles => "roles",
+            Unit::ClientScopes => "client-scopes",
+            Unit::Clients | Unit::ServiceAccounts => "clients",
+            Unit::AuthenticationFlows => "authentication-flows",
+            Unit::RequiredActions => "required-actions",
+            Unit::Groups => "groups",
+            Unit::Users => "users",
+            Unit::Components => "components",
+            Unit::Keys => "keys",
+            Unit::Authz => "authz",
+            Unit::ClientPolicies => "client-policies",
+            Unit::Organizations => "organizations",
+        }
+    }
+
+    fn of_type(kind: &str) -> Option<Unit> {
+        Unit::ALL
+            .into_iter()
+            .find(|unit| *unit != Unit::ServiceAccounts && unit.resource_type() == kind)
+    }
+
+    /// Units that always wait for others, whatever the files say.
+    fn implied_dependencies(self) -> &'static [Unit] {
+        match self {
+            Unit::Authz | Unit::ServiceAccounts => &[Unit::Clients],
+            Unit::ClientPolicies => &[Unit::Clients, Unit::ClientScopes],
+            _ => &[],
+        }
+    }
+}
+
+/// A `(resource type, name)` pair naming a resource of a realm.
+pub type Reference = (String, String);
+
+/// The name other resources use to refer to a resource of `kind`.
+pub(crate) fn identity(kind: &str, value: &Value) -> Option<String> {
+    let key = match kind {
+        "clients" => "clientId",
+        "identity-providers" | "authentication-flows" => "alias",
+        "users" => "username",
+        _ => "name",
+    };
+    value.get(key)?.as_str().map(str::to_string)
+}
+
+/// The identity provider each `identity-provider-redirector` config of the realm
+/// document (its `authenticatorConfig` list, as in realm exports) redirects to, by
+/// config alias.
+pub fn redirects(realm: &Value) -> HashMap<String, String> {
+    realm
+        .get("authenticatorConfig")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|config| {
+            let alias = config.get("alias")?.as_str()?;
+            let provider = config.get("config")?.get("defaultProvider")?.as_str()?;
+            Some((alias.to_string(), provider.to_string()))
+        })
+        .collect()
+}
+
+/// Every resource the document at `relative` (its path segments in the realm, without
+/// `.yaml`) refers to, together with the unit that needs the reference to exist.
+/// `redirects` are the [`redirects`] of the realm.
+pub fn references(
+    relative: &[String],
+    value: &Value,
+    redirects: &HashMap<String, String>,
+) -> Vec<(Unit, Reference)> {
+    let mut refs = Vec::new();
+    let strings = |value: Option<&Value>| -> Vec<String> {
+        value
+            .and_then(Value::as_array)
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let mut add = |unit: Unit, kind: &str, name: &str| {
+        refs.push((unit, (kind.to_string(), name.to_string())))
+    };
+    // Roles, groups (by their top-level group) and client roles granted to a principal.
+    let grants = |unit: Unit, holder: &Value, add: &mut dyn FnMut(Unit, &str, &str)| {
+        for role in strings(holder.get("realmRoles")) {
+            add(unit, "roles", &role);
+        }
+        for group in strings(holder.get("groups")) {
+            if let Some(top) = group.trim_start_matches('/').split('/').next() {
+                add(unit, "groups", top);
+            }
+        }
+        if let Some(client_roles) = holder.get("clientRoles").and_then(Value::as_object) {
+            for client in client_roles.keys() {
+                add(unit, "clients", client);
+            }
+        }
+    };
+
+    match relative.first().map(String::as_str) {
+        Some("clients") => {
+            for scope in strings(value.get("defaultClientScopes"))
+                .into_iter()
+                .chain(strings(value.get("optionalClientScopes")))
+            {
+                add(Unit::Clients, "client-scopes", &scope);
+            }
+            if let Some(service_account) = value.get("serviceAccount") {
+                grants(Unit::ServiceAccounts, service_account, &mut add);
+            }
+        }
+        Some("users") => grants(Unit::Users, value, &mut add),
+        Some("groups") => {
+            // Subgroups are declared, and applied, with their top-level group
+            let mut groups = vec![value];
+            while let Some(group) = groups.pop() {
+                grants(Unit::Groups, group, &mut add);
+                if let Some(children) = group.get("subGroups").and_then(Value::as_array) {
+                    groups.extend(children);
+                }
+            }
+        }
+        Some("roles") => {
+            if let Some(composites) = value.get("composites") {
+                for role in strings(composites.get("realm")) {
+                    add(Unit::Roles, "roles", &role);
+                }
+                if let Some(client) = composites.get("client").and_then(Value::as_object) {
+                    for client in client.keys() {
+                        add(Unit::Roles, "clients", client);
+                    }
+                }
+            }
+        }
+        Some("identity-providers") => {
+            for key in ["firstBrokerLoginFlowAlias", "postBrokerLoginFlowAlias"] {
+                if let Some(flow) = value.get(key).and_then(Value::as_str) {
+                    add(Unit::IdentityProviders, "authentication-flows", flow);
+                }
+            }
+        }
+        Some("authentication-flows") => {
+            let executions = value
+                .get("authenticationExecutions")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten();
+            for execution in executions {
+                if execution.get("authenticator").and_then(Value::as_str)
+                    == Some("identity-provider-redirector")
+                    && let Some(idp) = execution
+                        .get("authenticatorConfig")
+                        .and_then(Value::as_str)
+                        .and_then(|alias| redirects.get(alias))
+                {
+                    add(Unit::AuthenticationFlows, "identity-providers", idp);
+                }
+            }
+        }
+        Some("organizations") => {
+            for idp in strings(value.get("identityProviders")) {
+                add(Unit::Organizations, "identity-providers", &idp);
+            }
+            for member in strings(value.get("members")) {
+                add(Unit::Organizations, "users", &member);
+            }
+        }
+        Some("authz") => {
+            if let Some(client) = relative.get(1) {
+                add(Unit::Authz, "clients", client);
+            }
+        }
+        Some(_) if relative.len() == 1 => {
+            for key in [
+                "browserFlow",
+                "registrationFlow",
+                "directGrantFlow",
+                "resetCredentialsFlow",
+                "clientAuthenticationFlow",
+                "dockerAuthenticationFlow",
+                "firstBrokerLoginFlow",
+            ] {
+                if let Some(flow) = value.get(key).and_then(Value::as_str) {
+                    add(Unit::Realm, "authentication-flows", flow);
+                }
+            }
+        }
+        _ => {}
+    }
+    refs
+}
+
+/// Segments of `path` below `realm_dir`, without the `.yaml` extension.
+pub(crate) fn relative_segments(realm_dir: &Path, path: &Path) -> Option<Vec<String>> {
+    let relative = path.strip_prefix(realm_dir).ok()?;
+    let mut segments: Vec<String> = relative
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => s.to_str().map(str::to_string),
+            _ => None,
+        })
+        .collect();
+    if let Some(last) = segments.last_mut()
+        && let Some(stem) = last.strip_suffix(".yaml")
+    {
+        *last = stem.to_string();
+    }
+    (!segments.is_empty()).then_some(segments)
+}
+
+/// The units [`DependencyGraph::execute`] did not complete when keeping going.
+#[derive(Debug, Default)]
+pub struct Incomplete {
+    pub failed: Vec<(Unit, anyhow::Error)>,
+    /// Units that waited for a failed unit.
+    pub skipped: Vec<Unit>,
+}
+
+/// The order in which the units of a realm are applied. A unit waits for the units
+/// holding resources it refers to (a client for its client scopes, a user for its groups,
+/// an identity provider for its login flows, ...); everything else runs in parallel.
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    units: BTreeSet<Unit>,
+    /// The units each unit waits for, with the reference that made it wait, if any.
+    dependencies: BTreeMap<Unit, BTreeMap<Unit, Option<String>>>,
+    /// The pending files of each unit (relative paths, like `roles/admin`), with the
+    /// files of the same unit they refer to (the roles of a composite role).
+    files: BTreeMap<Unit, BTreeMap<String, BTreeSet<String>>>,
+}
+
+impl DependencyGraph {
+    /// The graph of the selected units of the realm in `realm_dir`. Only references
+    /// between files that are going to be applied (`planned_files`, everything when
+    /// `None`) count: anything else already exists or is left alone. The realm comes
+    /// first, so its own references do not count either. References between files of the
+    /// same unit order the files within it, see [`DependencyGraph::batches`].
+    pub async fn build(
+        realm_dir: &Path,
+        ws: &WorkspaceContext,
+        selection: &Selection,
+        planned_files: &Option<HashSet<PathBuf>>,
+    ) -> Result<Self> {
+        let mut graph = DependencyGraph::default();
+        for unit in Unit::ALL {
+            if selection.includes_type(unit.resource_type()) {
+                graph.units.insert(unit);
+            }
+        }
+        for unit in Unit::ALL {
+            if unit != Unit::Realm {
+                graph.add_dependency(unit, Unit::Realm, None);
+            }
+            for dependency in unit.implied_dependencies() {
+                graph.add_dependency(unit, *dependency, None);
+            }
+        }
+
+        if fs::try_exists(realm_dir).await? {
+            let mut documents = Vec::new();
+            let mut by_identity: HashMap<Reference, String> = HashMap::new();
+            let mut redirects = HashMap::new();
+            for path in realm_files(realm_dir, ws).await? {
+                let pending = selection.includes(realm_dir, &path)
+                    && planned_files
+                        .as_ref()
+                        .is_none_or(|plan| plan.contains(&path));
+                let Some(relative) = relative_segments(realm_dir, &path) else {
+                    continue;
+                };
+                // The realm document holds the configs flows refer to, pending or not
+                if !pending && relative.len() != 1 {
+                    continue;
+                }
+                let value = load_yaml_with_overlay(&path, ws).await?;
+                if relative.len() == 1 {
+                    redirects.extend(self::redirects(&value));
+                }
+                if !pending {
+                    continue;
+                }
+                if relative.len() == 2
+                    && let Some(name) = identity(&relative[0], &value)
+                {
+                    by_identity.insert((relative[0].clone(), name), relative.join("/"));
+                }
+                if relative.len() == 2
+                    && let Some(unit) = Unit::of_type(&relative[0])
+                    && graph.units.contains(&unit)
+                {
+                    let files = graph.files.entry(unit).or_default();
+                    files.entry(relative.join("/")).or_default();
+                }
+                documents.push((relative, value));
+            }
+            // A redirector works without its identity provider, so that wait gives way to
+            // the login flows of identity providers
+            let mut redirectors = Vec::new();
+            for (relative, value) in &documents {
+                for (unit, reference) in references(relative, value, &redirects) {
+                    let (Some(target), Some(dependency)) =
+                        (by_identity.get(&reference), Unit::of_type(&reference.0))
+                    else {
+                        continue;
+                    };
+                    if unit == Unit::Realm {
+                        continue;
+                    }
+                    if unit != dependency {
+                        let reason = format!("{} -> {}", relative.join("/"), target);
+                        if (unit, dependency)
+                            == (Unit::AuthenticationFlows, Unit::IdentityProviders)
+                        {
+                            redirectors.push(reason);
+                            continue;
+                        }
+                        graph.add_dependency(unit, dependency, Some(reason));
+                    } else if let Some(targets) = graph
+                        .files
+                        .get_mut(&unit)
+                        .and_then(|files| files.get_mut(&relative.join("/")))
+                    {
+                        targets.insert(target.clone());
+                    }
+                }
+            }
+            for reason in redirectors {
+                if !graph.waits_for(Unit::IdentityProviders, Unit::AuthenticationFlows) {
+                    graph.add_dependency(
+                        Unit::AuthenticationFlows,
+                        Unit::IdentityProviders,
+                        Some(reason),
+                    );
+                }
+            }
+        }
+
+        graph.check_cycles()?;
+        Ok(graph)
+    }
+
+    /// Records that `unit` waits for `dependency`; ignored unless both are selected.
+    fn add_dependency(&mut self, unit: Unit, dependency: Unit, reason: Option<String>) {
+        if !self.units.contains(&unit) || !self.units.contains(&dependency) {
+            return;
+        }
+        let reasons = self.dependencies.entry(unit).or_default();
+        match reasons.get(&dependency) {
+            Some(Some(_)) => {}
+            _ => {
+                reasons.insert(dependency, reason);
+            }
+        }
+    }
+
+    /// Whether `unit` waits for `other`, directly or through other units.
+    fn waits_for(&self, unit: Unit, other: Unit) -> bool {
+        let mut pending = vec![unit];
+        let mut seen = BTreeSet::new();
+        while let Some(unit) = pending.pop() {
+            for dependency in self.dependencies(unit) {
+                if dependency == other {
+                    return true;
+                }
+                if seen.insert(dependency) {
+                    pending.push(dependency);
+                }
+            }
+        }
+        false
+    }
+
+    pub fn units(&self) -> impl Iterator<Item = Unit> + '_ {
+        self.units.iter().copied()
+    }
+
+    /// The units `unit` waits for.
+    pub fn dependencies(&self, unit: Unit) -> impl Iterator<Item = Unit> + '_ {
+        self.dependencies
+            .get(&unit)
+            .into_iter()
+            .flat_map(|deps| deps.keys().copied())
+    }
+
+    /// The units grouped in steps: every unit of a step only waits for units of earlier
+    /// steps. Units left out are part of a cycle.
+    pub fn steps(&self) -> Vec<Vec<Unit>> {
+        let mut done: BTreeSet<Unit> = BTreeSet::new();
+        let mut steps = Vec::new();
+        loop {
+            let step: Vec<Unit> = self
+                .units()
+                .filter(|unit| !done.contains(unit))
+                .filter(|unit| self.dependencies(*unit).all(|dep| done.contains(&dep)))
+                .collect();
+            if step.is_empty() {
+                return steps;
+            }
+            done.extend(step.iter().copied());
+            steps.push(step);
+        }
+    }
+
+    /// The pending files of `unit` grouped in batches: every file of a batch only refers
+    /// to files of the unit in earlier batches. Files left out are part of a cycle.
+    pub fn batches(&self, unit: Unit) -> Vec<Vec<String>> {
+        let Some(files) = self.files.get(&unit) else {
+            return Vec::new();
+        };
+        let mut done: BTreeSet<&String> = BTreeSet::new();
+        let mut batches = Vec::new();
+        loop {
+            let batch: Vec<&String> = files
+                .iter()
+                .filter(|(file, _)| !done.contains(file))
+                .filter(|(_, targets)| targets.iter().all(|target| done.contains(target)))
+                .map(|(file, _)| file)
+                .collect();
+            if batch.is_empty() {
+                return batches;
+            }
+            done.extend(batch.iter().copied());
+            batches.push(batch.into_iter().cloned().collect());
+        }
+    }
+
+    fn check_cycles(&self) -> Result<()> {
+        self.check_unit_cycles()?;
+        for (unit, files) in &self.files {
+            let ordered: BTreeSet<String> = self.batches(*unit).into_iter().flatten().collect();
+            let Some(start) = files.keys().find(|file| !ordered.contains(*file)) else {
+                continue;
+            };
+            let mut path = vec![start];
+            loop {
+                let last = path[path.len() - 1];
+                let next = files[last]
+                    .iter()
+                    .find(|target| !ordered.contains(*target))
+                    .expect("unordered files refer to unordered files");
+                if let Some(position) = path.iter().position(|file| *file == next) {
+                    let names: Vec<&str> = path[position..]
+                        .iter()
+                        .copied()
+                        .chain(std::iter::once(next))
+                        .map(String::as_str)
+                        .collect();
+                    anyhow::bail!("Dependency cycle between {}", names.join(" -> "));
+                }
+                path.push(next);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_unit_cycles(&self) -> Result<()> {
+        let ordered: BTreeSet<Unit> = self.steps().into_iter().flatten().collect();
+        let Some(start) = self.units().find(|unit| !ordered.contains(unit)) else {
+            return Ok(());
+        };
+        // Every unit outside the steps waits for another one outside them, so following
+        // those dependencies from `start` runs into a cycle.
+        let mut path = vec![start];
+        loop {
+            let last = path[path.len() - 1];
+            let next = self
+                .dependencies(last)
+                .find(|dep| !ordered.contains(dep))
+                .expect("unordered units depend on unordered units");
+            if let Some(position) = path.iter().position(|unit| *unit == next) {
+                let cycle = &path[position..];
+                let reasons: Vec<String> = cycle
+                    .iter()
+                    .zip(cycle.iter().cycle().skip(1))
+                    .filter_map(|(unit, dep)| self.dependencies[unit][dep].clone())
+                    .collect();
+                let names: Vec<&str> = cycle
+                    .iter()
+                    .chain(std::iter::once(&next))
+                    .map(|unit| unit.name())
+                    .collect();
+                anyhow::bail!(
+                    "Dependency cycle between {} ({})",
+                    names.join(" -> "),
+                    reasons.join(", ")
+                );
+            }
+            path.push(next);
+        }
+    }
+
+    /// Runs `start(unit)` for every unit as soon as the units it waits for are done, with
+    /// at most `limit` units at a time. Stops at the first error, unless `keep_going` is
+    /// set: failed units are then returned, and the units waiting for them never start.
+    pub async fn execute<F, Fut>(
+        &self,
+        limit: Option<usize>,
+        keep_going: bool,
+        mut start: F,
+    ) -> Result<Incomplete>
+    where
+        F: FnMut(Unit) -> Fut,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let limit = limit.unwrap_or(usize::MAX).max(1);
+        let mut waiting: BTreeMap<Unit, usize> = self
+            .units()
+            .map(|unit| (unit, self.dependencies(unit).count()))
+            .collect();
+        let mut ready: VecDeque<Unit> = waiting
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(unit, _)| *unit)
+            .collect();
+        let mut started = BTreeSet::new();
+        let mut incomplete = Incomplete::default();
+        let mut set = tokio::task::JoinSet::new();
+        loop {
+            while set.len() < limit
+                && let Some(unit) = ready.pop_front()
+            {
+                started.insert(unit);
+                let task = start(unit);
+                set.spawn(async move { (unit, task.await) });
+            }
+            let Some(joined) = set.join_next().await else {
+                incomplete.skipped = self.units().filter(|u| !started.contains(u)).collect();
+                return Ok(incomplete);
+            };
+            let (done, result) = joined.context("Task panicked")?;
+            if let Err(e) = result {
+                if !keep_going {
+                    return Err(e);
+                }
+                incomplete.failed.push((done, e));
+                continue;
+            }
+            for unit in self.units() {
+                if self.dependencies(unit).any(|dep| dep == done)
+                    && let Some(count) = waiting.get_mut(&unit)
+                {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push_back(unit);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The graph in Graphviz DOT format, with edges pointing from a unit to the units
+    /// that wait for it.
+    pub fn to_dot(&self, realm_name: &str) -> String {
+        let mut out = format!("digraph {:?} {{\n    rankdir=LR;\n", realm_name);
+        for unit in self.units() {
+            out.push_str(&format!("    {:?};\n", unit.name()));
+        }
+        for (unit, deps) in &self.dependencies {
+            for (dep, reason) in deps {
+                out.push_str(&format!("    {:?} -> {:?}", dep.name(), unit.name()));
+                if let Some(reason) = reason {
+                    out.push_str(&format!(" [label={:?}]", reason));
+                }
+                out.push_str(";\n");
+            }
+        }
+        for (unit, files) in &self.files {
+            for (file, targets) in files {
+                for target in targets {
+                    let reason = format!("{} -> {}", file, target);
+                    out.push_str(&format!(
+                        "    {:?} -> {:?} [label={:?}];\n",
+                        unit.name(),
+                        unit.name(),
+                        reason
+                    ));
+                }
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// The steps of the graph, one line each, followed by the references that order them.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for (index, step) in self.steps().iter().enumerate() {
+            let names: Vec<&str> = step.iter().map(|unit| unit.name()).collect();
+            out.push_str(&format!("  {}. {}\n", index + 1, names.join(", ")));
+            for unit in step {
+                for (dep, reason) in self.dependencies.get(unit).into_iter().flatten() {
+                    if let Some(reason) = reason {
+                        out.push_str(&format!(
+                            "       {} after {} ({})\n",
+                            unit.name(),
+                            dep.name(),
+                            reason
+                        ));
+                    }
+                }
+                for (file, targets) in self.files.get(unit).into_iter().flatten() {
+                    for target in targets {
+                        out.push_str(&format!("       {} after {}\n", file, target));
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GraphFormat {
+    #[default]
+    Text,
+    Dot,
+}
+
+/// Prints the apply order of every realm of the workspace, offline.
+pub async fn run(
+    workspace_dir: &Path,
+    realms: &[String],
+    ws: &WorkspaceContext,
+    selection: &Selection,
+    format: GraphFormat,
+) -> Result<()> {
+    if !fs::try_exists(workspace_dir).await? {
+        anyhow::bail!("Input directory {:?} does not exist", workspace_dir);
+    }
+    let realms = if realms.is_empty() {
+        let mut dirs = Vec::new();
+        let mut entries = fs::read_dir(workspace_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir()
+                && fs::try_exists(entry.path().join("realm.yaml")).await?
+            {
+                dirs.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        dirs.sort();
+        dirs
+    } else {
+        realms.to_vec()
+    };
+    if realms.is_empty() {
+        eprintln!(
+            "{} {}",
+            WARN,
+            style(format!("No realms found in {:?}", workspace_dir)).yellow()
+        );
+        return Ok(());
+    }
+
+    for realm_name in realms {
+        let realm_dir = workspace_dir.join(&realm_name);
+        let selection = selection.for_realm(&realm_dir, ws).await?;
+        let graph = DependencyGraph::build(&realm_dir, ws, &selection, &None)
+            .await
+            .with_context(|| format!("Failed to order the resources of realm '{}'", realm_name))?;
+        match format {
+            GraphFormat::Dot => print!("{}", graph.to_dot(&realm_name)),
+            GraphFormat::Text => {
+                println!(
+                    "\n{} {}",
+                    SEARCH,
+                    style(format!("Apply order for realm: {}", realm_name))
+                        .cyan()
+                        .bold()
+                );
+                print!("{}", graph.to_text());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_dependency_graph() {
+        let dir = tempdir().unwrap();
+        let realm = dir.path().join("master");
+        for sub in [
+            "clients",
+            "client-scopes",
+            "groups",
+            "users",
+            "identity-providers",
+        ] {
+            std::fs::create_dir_all(realm.join(sub)).unwrap();
+        }
+        let write = |path: &str, content: &str| std::fs::write(realm.join(path), content).unwrap();
+        write("realm.yaml", "realm: master\nbrowserFlow: browser\n");
+        write(
+            "clients/app.yaml",
+            "clientId: app\ndefaultClientScopes: [app-scope]\n",
+        );
+        write("client-scopes/app-scope.yaml", "name: app-scope\n");
+        write(
+            "groups/staff.yaml",
+            "name: staff\nclientRoles:\n  app: [reader]\n",
+        );
+        write("users/alice.yaml", "username: alice\ngroups: [/staff]\n");
+        write(
+            "identity-providers/google.yaml",
+            "alias: google\nfirstBrokerLoginFlowAlias: missing-flow\n",
+        );
+
+        let graph = DependencyGraph::build(
+            &realm,
+            &WorkspaceContext::default(),
+            &Selection::default(),
+            &None,
+        )
+        .await
+        .unwrap();
+        let deps = |unit: Unit| graph.dependencies(unit).collect::<Vec<_>>();
+        assert_eq!(deps(Unit::Realm), vec![]);
+        assert_eq!(deps(Unit::Clients), vec![Unit::Realm, Unit::ClientScopes]);
+        assert_eq!(deps(Unit::Groups), vec![Unit::Realm, Unit::Clients]);
+        assert_eq!(deps(Unit::Users), vec![Unit::Realm, Unit::Groups]);
+        // Flows that are not in the workspace are not waited for.
+        assert_eq!(deps(Unit::IdentityProviders), vec![Unit::Realm]);
+
+        let steps = graph.steps();
+        assert_eq!(steps[0], vec![Unit::Realm]);
+        let step_of = |unit: Unit| steps.iter().position(|s| s.contains(&unit)).unwrap();
+        assert!(step_of(Unit::ClientScopes) < step_of(Unit::Clients));
+        assert!(step_of(Unit::Clients) < step_of(Unit::Groups));
+        assert!(step_of(Unit::Groups) < step_of(Unit::Users));
+        assert_eq!(step_of(Unit::Components), 1);
+
+        let dot = graph.to_dot("master");
+        assert!(dot.contains("\"groups\" -> \"users\" [label=\"users/alice -> groups/staff\"];"));
+        assert!(
+            graph
+                .to_text()
+                .contains("clients after client-scopes (clients/app -> client-scopes/app-scope)")
+        );
+
+        // Only selected units are part of the graph.
+        let only = Selection::new(&["users".into(), "groups".into()], &[], &[], false).unwrap();
+        let graph = DependencyGraph::build(&realm, &WorkspaceContext::default(), &only, &None)
+            .await
+            .unwrap();
+        assert_eq!(
+            graph.units().collect::<Vec<_>>(),
+            vec![Unit::Groups, Unit::Users]
+        );
+
+        // Units run after the ones they wait for.
+        let graph = DependencyGraph::build(
+            &realm,
+            &WorkspaceContext::default(),
+            &Selection::default(),
+            &None,
+        )
+        .await
+        .unwrap();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        graph
+            .execute(Some(2), false, |unit| {
+                let order = Arc::clone(&order);
+                async move {
+                    order.lock().unwrap().push(unit);
+                    Ok(())
+                }
+            })
+            .await
+            .unwrap();
+        let order = order.lock().unwrap().clone();
+        assert_eq!(order.len(), Unit::ALL.len());
+        let position = |unit: Unit| order.iter().position(|u| *u == unit).unwrap();
+        assert!(position(Unit::Realm) == 0);
+        assert!(position(Unit::Groups) < position(Unit::Users));
+        assert!(position(Unit::Clients) < position(Unit::ServiceAccounts));
+
+        // When keeping going, only the units waiting for a failed one are left out.
+        let incomplete = graph
+            .execute(None, true, |unit| async move {
+                match unit {
+                    Unit::Clients => anyhow::bail!("boom"),
+                    _ => Ok(()),
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(incomplete.failed.len(), 1);
+        assert_eq!(incomplete.failed[0].0, Unit::Clients);
+        assert!(incomplete.skipped.contains(&Unit::Groups));
+        assert!(incomplete.skipped.contains(&Unit::Users));
+        assert!(!incomplete.skipped.contains(&Unit::IdentityProviders));
+    }
+
+    #[tokio::test]
+    async fn test_composite_roles() {
+        let dir = tempdir().unwrap();
+        let realm = dir.path().join("master");
+        std::fs::create_dir_all(realm.join("roles")).unwrap();
+        let write = |path: &str, content: &str| std::fs::write(realm.join(path), content).unwrap();
+        write("roles/base.yaml", "name: base\n");
+        write(
+            "roles/editor.yaml",
+            "name: editor\ncomposite: true\ncomposites:\n  realm: [base]\n",
+        );
+        write(
+            "roles/admin.yaml",
+            "name: admin\ncomposite: true\ncomposites:\n  realm: [editor, base, missing]\n",
+        );
+        write("roles/other.yaml", "name: other\n");
+
+        let ws = WorkspaceContext::default();
+        let graph = DependencyGraph::build(&realm, &ws, &Selection::default(), &None)
+            .await
+            .unwrap();
+        assert_eq!(
+            graph.batches(Unit::Roles),
+            vec![
+                vec!["roles/base".to_string(), "roles/other".to_string()],
+                vec!["roles/editor".to_string()],
+                vec!["roles/admin".to_string()],
+            ]
+        );
+        assert!(graph.to_text().contains("roles/admin after roles/editor"));
+
+        // Roles already applied are not waited for.
+        let planned: HashSet<PathBuf> = [realm.join("roles/admin.yaml")].into_iter().collect();
+        let graph = DependencyGraph::build(&realm, &ws, &Selection::default(), &Some(planned))
+            .await
+            .unwrap();
+        assert_eq!(
+            graph.batches(Unit::Roles),
+            vec![vec!["roles/admin".to_string()]]
+        );
+
+        write(
+            "roles/base.yaml",
+            "name: base\ncomposite: true\ncomposites:\n  realm: [admin]\n",
+        );
+        let err = DependencyGraph::build(&realm, &ws, &Selection::default(), &None)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("Dependency cycle between roles/admin -> roles/base -> roles/admin"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subgroups_and_redirectors() {
+        let dir = tempdir().unwrap();
+        let realm = dir.path().join("master");
+        for sub in [
+            "authentication-flows",
+            "groups",
+            "identity-providers",
+            "roles",
+        ] {
+            std::fs::create_dir_all(realm.join(sub)).unwrap();
+        }
+        let write = |path: &str, content: &str| std::fs::write(realm.join(path), content).unwrap();
+        write(
+            "realm.yaml",
+            "realm: master\nauthenticatorConfig:\n  - alias: to-google\n    config:\n      defaultProvider: google\n",
+        );
+        write(
+            "authentication-flows/redirect.yaml",
+            "alias: redirect\nauthenticationExecutions:\n  - authenticator: identity-provider-redirector\n    authenticatorConfig: to-google\n",
+        );
+        write("identity-providers/google.yaml", "alias: google\n");
+        write(
+            "groups/staff.yaml",
+            "name: staff\nsubGroups:\n  - name: dev\n    realmRoles: [developer]\n",
+        );
+        write("roles/developer.yaml", "name: developer\n");
+
+        let ws = WorkspaceContext::default();
+        let graph = DependencyGraph::build(&realm, &ws, &Selection::default(), &None)
+            .await
+            .unwrap();
+        let deps =
+            |graph: &DependencyGraph, unit: Unit| graph.dependencies(unit).collect::<Vec<_>>();
+        assert_eq!(deps(&graph, Unit::Groups), vec![Unit::Realm, Unit::Roles]);
+        assert_eq!(
+            deps(&graph, Unit::AuthenticationFlows),
+            vec![Unit::Realm, Unit::IdentityProviders]
+        );
+        assert!(graph.to_text().contains(
+            "authentication-flows after identity-providers (authentication-flows/redirect -> identity-providers/google)"
+        ));
+
+        // The login flows of identity providers come first
+        write(
+            "identity-providers/google.yaml",
+            "alias: google\nfirstBrokerLoginFlowAlias: redirect\n",
+        );
+        let graph = DependencyGraph::build(&realm, &ws, &Selection::default(), &None)
+            .await
+            .unwrap();
+        assert_eq!(deps(&graph, Unit::AuthenticationFlows), vec![Unit::Realm]);
+        assert_eq!(
+            deps(&graph, Unit::IdentityProviders),
+            vec![Unit::Realm, Unit::AuthenticationFlows]
+        );
+    }
+
+    #[test]
+    fn test_dependency_cycle() {
+        let mut graph = DependencyGraph {
+            units: [Unit::Realm, Unit::Roles, Unit::Clients, Unit::Users]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        graph.add_dependency(Unit::Roles, Unit::Realm, None);
+        graph.add_dependency(
+            Unit::Roles,
+            Unit::Clients,
+            Some("roles/a -> clients/b".into()),
+        );
+        graph.add_dependency(
+            Unit::Clients,
+            Unit::Roles,
+            Some("clients/b -> roles/a".into()),
+        );
+        graph.add_dependency(Unit::Users, Unit::Roles, None);
+        let err = graph.check_cycles().unwrap_err().to_string();
+        assert!(err.contains("roles -> clients -> roles"), "{}", err);
+        assert!(
+            err.contains("roles/a -> clients/b, clients/b -> roles/a"),
+            "{}",
+            err
+        );
+    }
+}
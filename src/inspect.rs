@@ -1,27 +1,124 @@
 use crate::client::KeycloakClient;
 use crate::models::{
-    AuthenticationFlowRepresentation, ClientRepresentation, ClientScopeRepresentation,
-    ComponentRepresentation, GroupRepresentation, IdentityProviderRepresentation, KeycloakResource,
-    RequiredActionProviderRepresentation, ResourceMeta, RoleRepresentation, UserRepresentation,
+    AuthenticationFlowRepresentation, AuthzResourceRepresentation, AuthzScopeRepresentation,
+    ClientRepresentation, ClientScopeRepresentation, ComponentRepresentation, GroupRepresentation,
+    IdentityProviderRepresentation, KeycloakResource, OrganizationRepresentation,
+    PolicyRepresentation, RequiredActionProviderRepresentation, ResourceMeta, RoleRepresentation,
+    ServiceAccountRepresentation, UserRepresentation,
 };
-use crate::utils::to_sorted_yaml_with_secrets;
+use crate::plan::client_policies::{
+    CLIENT_POLICIES_DIR, POLICIES_FILE, PROFILES_FILE, SECRET_PREFIX as CLIENT_POLICY_SECRET_PREFIX,
+};
+use crate::plan::service_accounts::current_service_account;
+use crate::utils::dotenv::DotenvFile;
+use crate::utils::secrets::obfuscate_string;
+use crate::utils::secrets::rules::SecretRules;
+use crate::utils::secrets::sink::SecretsSink;
+use crate::utils::secrets::template::{self, Segment};
+use crate::utils::secrets::vault::VaultResolver;
+use crate::utils::selection::Selection;
+use crate::utils::to_sorted_yaml_with_secret_refs;
 use crate::utils::ui::{CHECK, SEARCH, SUCCESS, WARN};
+use crate::utils::yaml::is_overlay_file;
+use crate::workspace::WorkspaceContext;
 use anyhow::{Context, Result};
 use console::style;
 use dialoguer::{Confirm, theme::ColorfulTheme};
 use sanitize_filename::sanitize;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::fs;
 use tokio::sync::Mutex;
 
+/// Collects the secrets extracted while exporting, per realm, and writes the references
+/// expected by the configured [`SecretsSink`] in their place.
+struct SecretCollector {
+    sink: SecretsSink,
+    rules: SecretRules,
+    secrets: Mutex<BTreeMap<String, BTreeMap<String, String>>>,
+}
+
+impl SecretCollector {
+    fn to_yaml<T: serde::Serialize>(
+        &self,
+        value: &T,
+        realm_name: &str,
+        resource: &str,
+        prefix: &str,
+        secrets: &mut BTreeMap<String, String>,
+    ) -> Result<String> {
+        to_sorted_yaml_with_secret_refs(value, resource, prefix, secrets, &self.rules, &|name| {
+            self.sink.reference(realm_name, name)
+        })
+    }
+
+    async fn add(&self, realm_name: &str, secrets: BTreeMap<String, String>) {
+        if secrets.is_empty() {
+            return;
+        }
+        self.secrets
+            .lock()
+            .await
+            .entry(realm_name.to_string())
+            .or_default()
+            .extend(secrets);
+    }
+}
+
 pub async fn run(
     client: &KeycloakClient,
     workspace_dir: PathBuf,
     realms_to_inspect: &[String],
     yes: bool,
 ) -> Result<()> {
+    let options = InspectOptions {
+        yes,
+        ..Default::default()
+    };
+    let ws = WorkspaceContext::for_workspace(&workspace_dir);
+    run_with_options(
+        client,
+        workspace_dir,
+        realms_to_inspect,
+        &options,
+        &ws,
+        None,
+    )
+    .await
+}
+
+/// How `inspect` writes what it exports.
+#[derive(Debug, Clone, Default)]
+pub struct InspectOptions {
+    /// Overwrite changed files without asking.
+    pub yes: bool,
+    /// Where extracted secrets are stored.
+    pub secrets_sink: SecretsSink,
+    /// Remove `KEYCLOAK_*` entries of `.secrets` that no workspace file references.
+    pub prune_secrets: bool,
+    /// Resources to export.
+    pub selection: Selection,
+}
+
+/// Like [`run`], with explicit options and the secret rules of `ws`. `vault` is required
+/// by the Vault secrets sink.
+pub async fn run_with_options(
+    client: &KeycloakClient,
+    workspace_dir: PathBuf,
+    realms_to_inspect: &[String],
+    options: &InspectOptions,
+    ws: &WorkspaceContext,
+    vault: Option<&VaultResolver>,
+) -> Result<()> {
+    let sink = &options.secrets_sink;
+    let yes = options.yes;
+    if matches!(sink, SecretsSink::Vault { .. }) && vault.is_none() {
+        anyhow::bail!(
+            "The Vault secrets sink requires Vault to be configured (VAULT_ADDR and a token or auth method)"
+        );
+    }
+
     if !fs::try_exists(&workspace_dir)
         .await
         .context("Failed to check output directory")?
@@ -41,8 +138,14 @@ pub async fn run(
         realms_to_inspect.to_vec()
     };
 
-    let all_secrets = Arc::new(Mutex::new(BTreeMap::new()));
+    let all_secrets = Arc::new(SecretCollector {
+        sink: sink.clone(),
+        rules: ws.secrets.clone(),
+        secrets: Mutex::new(BTreeMap::new()),
+    });
     let prompt_mutex = Arc::new(Mutex::new(()));
+    let selection = Arc::new(options.selection.clone());
+    let ws = Arc::new(ws.clone());
 
     let mut set = tokio::task::JoinSet::new();
 
@@ -53,6 +156,8 @@ pub async fn run(
         let all_secrets = Arc::clone(&all_secrets);
         let prompt_mutex = Arc::clone(&prompt_mutex);
         let realm_name_owned = realm_name.clone();
+        let selection = Arc::clone(&selection);
+        let ws = Arc::clone(&ws);
 
         set.spawn(async move {
             {
@@ -72,6 +177,8 @@ pub async fn run(
                 all_secrets,
                 yes,
                 prompt_mutex,
+                selection,
+                ws,
             )
             .await
         });
@@ -79,44 +186,157 @@ pub async fn run(
 
     crate::utils::join_all_tasks(set, Some("Task panicked")).await?;
 
-    let secrets_lock = all_secrets.lock().await;
-    if !secrets_lock.is_empty() {
-        let env_path = workspace_dir.join(".secrets");
-        let mut env_content = String::new();
-        for (key, value) in secrets_lock.iter() {
-            env_content.push_str(&format!("{}={}\n", key, value));
+    let secrets_by_realm = all_secrets.secrets.lock().await;
+    if let (SecretsSink::Vault { mount, .. }, Some(vault)) = (sink, vault) {
+        for (realm_name, secrets) in secrets_by_realm.iter() {
+            let path = sink.vault_path(realm_name).unwrap_or_default();
+            vault
+                .write_secret(mount, &path, secrets)
+                .await
+                .with_context(|| format!("Failed to export secrets of realm '{}'", realm_name))?;
+            println!(
+                "{} {}",
+                CHECK,
+                style(format!(
+                    "Exported {} secrets to vault:{}/{}",
+                    secrets.len(),
+                    mount,
+                    path
+                ))
+                .green()
+            );
         }
+        return Ok(());
+    }
 
-        let mut existing_env = String::new();
-        if fs::try_exists(&env_path).await.unwrap_or(false) {
-            #[allow(clippy::collapsible_if)]
-            if let Ok(content) = fs::read_to_string(&env_path).await {
-                existing_env = content;
-                if !existing_env.ends_with('\n') && !existing_env.is_empty() {
-                    existing_env.push('\n');
-                }
-            }
-        }
+    let secrets: BTreeMap<String, String> = secrets_by_realm
+        .values()
+        .flatten()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    if secrets.is_empty() && !options.prune_secrets {
+        return Ok(());
+    }
+    export_dotenv_secrets(
+        &workspace_dir,
+        &secrets,
+        options.prune_secrets,
+        yes,
+        prompt_mutex,
+    )
+    .await
+}
 
-        let new_content = format!("{}{}", existing_env, env_content);
-        write_if_changed_with_mutex(
-            &env_path,
-            &new_content,
-            yes,
-            Arc::clone(&prompt_mutex),
-            true,
-        )
-        .await?;
+/// Merges `secrets` into the workspace `.secrets` file by key, keeping comments and
+/// unrelated entries, and prints a masked summary of the changes.
+async fn export_dotenv_secrets(
+    workspace_dir: &Path,
+    secrets: &BTreeMap<String, String>,
+    prune: bool,
+    yes: bool,
+    prompt_mutex: Arc<Mutex<()>>,
+) -> Result<()> {
+    let env_path = workspace_dir.join(".secrets");
+    let existing = if fs::try_exists(&env_path).await.unwrap_or(false) {
+        fs::read_to_string(&env_path)
+            .await
+            .with_context(|| format!("Failed to read {:?}", env_path))?
+    } else {
+        String::new()
+    };
+
+    let mut env_file = DotenvFile::parse(&existing);
+    let mut summary = env_file.merge(secrets);
+    if prune {
+        let referenced = referenced_names(workspace_dir).await?;
+        summary.removed =
+            env_file.prune(|key| !key.starts_with("KEYCLOAK_") || referenced.contains(key));
+    }
+    if summary.is_empty() {
+        return Ok(());
+    }
+
+    {
+        let _lock = prompt_mutex.lock().await;
         println!(
-            "{} {}",
-            CHECK,
-            style("Exported secrets to .secrets").green()
+            "\n{} {}",
+            SEARCH,
+            style("Secrets changes in .secrets:").bold()
         );
+        for key in &summary.added {
+            let masked = obfuscate_string(secrets.get(key).map_or("", String::as_str));
+            println!("  {} {} = {}", style("+").green(), key, style(masked).dim());
+        }
+        for key in &summary.changed {
+            let masked = obfuscate_string(secrets.get(key).map_or("", String::as_str));
+            println!(
+                "  {} {} = {}",
+                style("~").yellow(),
+                key,
+                style(masked).dim()
+            );
+        }
+        for key in &summary.removed {
+            println!("  {} {}", style("-").red(), key);
+        }
     }
 
+    write_if_changed_with_mutex(&env_path, &env_file.render(), yes, prompt_mutex, true).await?;
+    println!(
+        "{} {}",
+        CHECK,
+        style(format!(
+            "Exported secrets to .secrets ({} added, {} changed, {} removed)",
+            summary.added.len(),
+            summary.changed.len(),
+            summary.removed.len()
+        ))
+        .green()
+    );
+
     Ok(())
 }
 
+/// Names of every `${...}` reference in the YAML files of the workspace.
+async fn referenced_names(workspace_dir: &Path) -> Result<HashSet<String>> {
+    let mut names = HashSet::new();
+    let mut dirs = vec![workspace_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = fs::read_dir(&dir)
+            .await
+            .with_context(|| format!("Failed to read directory {:?}", dir))?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "yaml") {
+                let content = fs::read_to_string(&path)
+                    .await
+                    .with_context(|| format!("Failed to read {:?}", path))?;
+                names.extend(template::parse(&content).into_iter().filter_map(|segment| {
+                    match segment {
+                        Segment::Reference { name, .. } => Some(name.to_string()),
+                        Segment::Text(_) => None,
+                    }
+                }));
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// The file of the resource `name` in `dir`. A name ending in `.<profile>` would be read
+/// as an overlay of that profile, so its dots are replaced.
+pub(crate) fn resource_path(dir: &Path, name: &str, ws: &WorkspaceContext) -> PathBuf {
+    let file_name = sanitize(name);
+    let path = dir.join(format!("{}.yaml", file_name));
+    if is_overlay_file(&path, ws) {
+        dir.join(format!("{}.yaml", file_name.replace('.', "_")))
+    } else {
+        path
+    }
+}
+
 async fn write_if_changed_with_mutex(
     path: &Path,
     content: &str,
@@ -181,13 +401,16 @@ async fn write_if_changed_with_mutex(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn inspect_resources<T>(
     client: &KeycloakClient,
     realm_name: &str,
     target_dir: Arc<PathBuf>,
-    all_secrets: Arc<Mutex<BTreeMap<String, String>>>,
+    all_secrets: Arc<SecretCollector>,
     yes: bool,
     prompt_mutex: Arc<Mutex<()>>,
+    selection: &Selection,
+    ws: &WorkspaceContext,
 ) -> Result<()>
 where
     T: KeycloakResource
@@ -203,6 +426,49 @@ where
         .await
         .with_context(|| format!("Failed to fetch {} for realm '{}'", T::LABEL, realm_name))?;
 
+    write_resources(
+        resources,
+        realm_name,
+        target_dir,
+        all_secrets,
+        yes,
+        prompt_mutex,
+        selection,
+        ws,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn write_resources<T>(
+    resources: Vec<T>,
+    realm_name: &str,
+    target_dir: Arc<PathBuf>,
+    all_secrets: Arc<SecretCollector>,
+    yes: bool,
+    prompt_mutex: Arc<Mutex<()>>,
+    selection: &Selection,
+    ws: &WorkspaceContext,
+) -> Result<()>
+where
+    T: KeycloakResource
+        + ResourceMeta
+        + serde::Serialize
+        + for<'de> serde::Deserialize<'de>
+        + Send
+        + Sync
+        + 'static,
+{
+    let realm_dir = target_dir.parent().unwrap_or(Path::new(""));
+    let resources: Vec<T> = resources
+        .into_iter()
+        .filter(|res| {
+            selection.includes(
+                realm_dir,
+                &resource_path(&target_dir, &res.get_filename(), ws),
+            )
+        })
+        .collect();
     if !fs::try_exists(&*target_dir)
         .await
         .with_context(|| format!("Failed to check {} directory", T::LABEL))?
@@ -218,15 +484,26 @@ where
         let all_secrets = Arc::clone(&all_secrets);
         let realm_name = realm_name.to_string();
         let prompt_mutex = Arc::clone(&prompt_mutex);
+        let path = resource_path(&target_dir, &res.get_filename(), ws);
         set.spawn(async move {
-            let filename = format!("{}.yaml", sanitize(res.get_filename()));
-            let path = target_dir.join(filename);
+            // The ownership markers belong to the server, not to the workspace
+            let res = crate::state::without_markers(res)?;
             let mut local_secrets = BTreeMap::new();
             let prefix = format!("realm_{}_{}", realm_name, T::SECRET_PREFIX);
-            let yaml = to_sorted_yaml_with_secrets(&res, &prefix, &mut local_secrets).context(
-                format!("Failed to serialize {} {}", T::LABEL, res.get_name()),
-            )?;
-            all_secrets.lock().await.extend(local_secrets);
+            let yaml = all_secrets
+                .to_yaml(
+                    &res,
+                    &realm_name,
+                    T::SECRET_PREFIX,
+                    &prefix,
+                    &mut local_secrets,
+                )
+                .context(format!(
+                    "Failed to serialize {} {}",
+                    T::LABEL,
+                    res.get_name()
+                ))?;
+            all_secrets.add(&realm_name, local_secrets).await;
             write_if_changed_with_mutex(&path, &yaml, yes, prompt_mutex, true).await
         });
     }
@@ -251,13 +528,16 @@ where
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn inspect_realm(
     client: &KeycloakClient,
     realm_name: &str,
     workspace_dir: PathBuf,
-    all_secrets: Arc<Mutex<BTreeMap<String, String>>>,
+    all_secrets: Arc<SecretCollector>,
     yes: bool,
     prompt_mutex: Arc<Mutex<()>>,
+    selection: Arc<Selection>,
+    ws: Arc<WorkspaceContext>,
 ) -> Result<()> {
     if !fs::try_exists(&workspace_dir)
         .await
@@ -272,19 +552,27 @@ async fn inspect_realm(
     let workspace_dir = Arc::new(workspace_dir);
 
     // Fetch realm configuration in parallel
-    {
+    if selection.includes(&workspace_dir, &workspace_dir.join("realm.yaml")) {
         let client = client.clone();
         let realm_name = realm_name.to_string();
         let workspace_dir = Arc::clone(&workspace_dir);
         let all_secrets = Arc::clone(&all_secrets);
         let prompt_mutex = Arc::clone(&prompt_mutex);
         set.spawn(async move {
-            let realm = client.get_realm().await.context("Failed to fetch realm")?;
+            let mut realm = client.get_realm().await.context("Failed to fetch realm")?;
+            crate::lock::strip_server_lock(&mut realm);
             let mut local_secrets = BTreeMap::new();
             let realm_prefix = format!("realm_{}", realm_name);
-            let realm_yaml = to_sorted_yaml_with_secrets(&realm, &realm_prefix, &mut local_secrets)
+            let realm_yaml = all_secrets
+                .to_yaml(
+                    &realm,
+                    &realm_name,
+                    "realm",
+                    &realm_prefix,
+                    &mut local_secrets,
+                )
                 .context("Failed to serialize realm")?;
-            all_secrets.lock().await.extend(local_secrets);
+            all_secrets.add(&realm_name, local_secrets).await;
 
             let realm_path = workspace_dir.join("realm.yaml");
             write_if_changed_with_mutex(
@@ -308,15 +596,28 @@ async fn inspect_realm(
     }
 
     // Fetch resources in parallel
-    spawn_inspect::<ClientRepresentation>(
-        &mut set,
-        client,
-        realm_name,
-        &workspace_dir,
-        &all_secrets,
-        yes,
-        &prompt_mutex,
-    );
+    if selection.includes_type(ClientRepresentation::DIR_NAME) {
+        let client = client.clone();
+        let realm_name = realm_name.to_string();
+        let target_dir = Arc::new(workspace_dir.join(ClientRepresentation::DIR_NAME));
+        let all_secrets = Arc::clone(&all_secrets);
+        let prompt_mutex = Arc::clone(&prompt_mutex);
+        let selection = Arc::clone(&selection);
+        let ws = Arc::clone(&ws);
+        set.spawn(async move {
+            inspect_clients(
+                &client,
+                &realm_name,
+                target_dir,
+                all_secrets,
+                yes,
+                prompt_mutex,
+                &selection,
+                &ws,
+            )
+            .await
+        });
+    }
     spawn_inspect::<RoleRepresentation>(
         &mut set,
         client,
@@ -325,6 +626,8 @@ async fn inspect_realm(
         &all_secrets,
         yes,
         &prompt_mutex,
+        &selection,
+        &ws,
     );
     spawn_inspect::<ClientScopeRepresentation>(
         &mut set,
@@ -334,6 +637,8 @@ async fn inspect_realm(
         &all_secrets,
         yes,
         &prompt_mutex,
+        &selection,
+        &ws,
     );
     spawn_inspect::<IdentityProviderRepresentation>(
         &mut set,
@@ -343,6 +648,8 @@ async fn inspect_realm(
         &all_secrets,
         yes,
         &prompt_mutex,
+        &selection,
+        &ws,
     );
     spawn_inspect::<GroupRepresentation>(
         &mut set,
@@ -352,6 +659,8 @@ async fn inspect_realm(
         &all_secrets,
         yes,
         &prompt_mutex,
+        &selection,
+        &ws,
     );
     spawn_inspect::<UserRepresentation>(
         &mut set,
@@ -361,6 +670,8 @@ async fn inspect_realm(
         &all_secrets,
         yes,
         &prompt_mutex,
+        &selection,
+        &ws,
     );
     spawn_inspect::<AuthenticationFlowRepresentation>(
         &mut set,
@@ -370,6 +681,8 @@ async fn inspect_realm(
         &all_secrets,
         yes,
         &prompt_mutex,
+        &selection,
+        &ws,
     );
     spawn_inspect::<RequiredActionProviderRepresentation>(
         &mut set,
@@ -379,6 +692,8 @@ async fn inspect_realm(
         &all_secrets,
         yes,
         &prompt_mutex,
+        &selection,
+        &ws,
     );
     spawn_inspect::<ComponentRepresentation>(
         &mut set,
@@ -388,21 +703,464 @@ async fn inspect_realm(
         &all_secrets,
         yes,
         &prompt_mutex,
+        &selection,
+        &ws,
     );
 
+    if selection.includes_type("authz") {
+        let client = client.clone();
+        let realm_name = realm_name.to_string();
+        let target_dir = workspace_dir.join("authz");
+        let all_secrets = Arc::clone(&all_secrets);
+        let prompt_mutex = Arc::clone(&prompt_mutex);
+        let selection = Arc::clone(&selection);
+        let ws = Arc::clone(&ws);
+        set.spawn(async move {
+            inspect_authz(
+                &client,
+                &realm_name,
+                target_dir,
+                all_secrets,
+                yes,
+                prompt_mutex,
+                &selection,
+                &ws,
+            )
+            .await
+        });
+    }
+
+    if selection.includes_type(OrganizationRepresentation::DIR_NAME) {
+        let client = client.clone();
+        let realm_name = realm_name.to_string();
+        let target_dir = workspace_dir.join(OrganizationRepresentation::DIR_NAME);
+        let all_secrets = Arc::clone(&all_secrets);
+        let prompt_mutex = Arc::clone(&prompt_mutex);
+        let selection = Arc::clone(&selection);
+        let ws = Arc::clone(&ws);
+        set.spawn(async move {
+            inspect_organizations(
+                &client,
+                &realm_name,
+                target_dir,
+                all_secrets,
+                yes,
+                prompt_mutex,
+                &selection,
+                &ws,
+            )
+            .await
+        });
+    }
+
+    if selection.includes_type(CLIENT_POLICIES_DIR) {
+        let client = client.clone();
+        let realm_name = realm_name.to_string();
+        let target_dir = workspace_dir.join(CLIENT_POLICIES_DIR);
+        let all_secrets = Arc::clone(&all_secrets);
+        let prompt_mutex = Arc::clone(&prompt_mutex);
+        let selection = Arc::clone(&selection);
+        set.spawn(async move {
+            inspect_client_policies(
+                &client,
+                &realm_name,
+                target_dir,
+                all_secrets,
+                yes,
+                prompt_mutex,
+                &selection,
+            )
+            .await
+        });
+    }
+
     crate::utils::join_all_tasks(set, Some("Task panicked")).await?;
 
     Ok(())
 }
 
+/// Exports clients, filling the `serviceAccount` block of clients with service accounts
+/// enabled from the roles and groups of their service account user.
+#[allow(clippy::too_many_arguments)]
+async fn inspect_clients(
+    client: &KeycloakClient,
+    realm_name: &str,
+    target_dir: Arc<PathBuf>,
+    all_secrets: Arc<SecretCollector>,
+    yes: bool,
+    prompt_mutex: Arc<Mutex<()>>,
+    selection: &Selection,
+    ws: &WorkspaceContext,
+) -> Result<()> {
+    let mut clients = client
+        .get_clients()
+        .await
+        .with_context(|| format!("Failed to fetch clients for realm '{}'", realm_name))?;
+
+    for rep in clients
+        .iter_mut()
+        .filter(|c| c.service_accounts_enabled == Some(true))
+    {
+        let Some(uuid) = rep.id.clone() else {
+            continue;
+        };
+        let user = client
+            .get_service_account_user(&uuid)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to fetch service account user of client '{}'",
+                    rep.get_name()
+                )
+            })?;
+        let user_id = user.id.context("Service account user has no id")?;
+        let mut sa = current_service_account(client, &user_id, realm_name).await?;
+        // Only export the assignments that are actually set.
+        sa.realm_roles = sa.realm_roles.filter(|r| !r.is_empty());
+        sa.client_roles = sa.client_roles.filter(|r| !r.is_empty());
+        sa.groups = sa.groups.filter(|g| !g.is_empty());
+        if sa != ServiceAccountRepresentation::default() {
+            rep.service_account = Some(sa);
+        }
+    }
+
+    write_resources(
+        clients,
+        realm_name,
+        target_dir,
+        all_secrets,
+        yes,
+        prompt_mutex,
+        selection,
+        ws,
+    )
+    .await
+}
+
+/// Exports the realm (non-global) client profiles and client policies documents.
+async fn inspect_client_policies(
+    client: &KeycloakClient,
+    realm_name: &str,
+    target_dir: PathBuf,
+    all_secrets: Arc<SecretCollector>,
+    yes: bool,
+    prompt_mutex: Arc<Mutex<()>>,
+    selection: &Selection,
+) -> Result<()> {
+    let (profiles, policies) =
+        tokio::try_join!(client.get_client_profiles(), client.get_client_policies()).with_context(
+            || format!("Failed to fetch client policies for realm '{}'", realm_name),
+        )?;
+    let profiles = profiles.unwrap_or_default();
+    let policies = policies.unwrap_or_default();
+    if profiles.profiles.is_empty() && policies.policies.is_empty() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(&target_dir)
+        .await
+        .context("Failed to create client-policies directory")?;
+    let prefix = format!("realm_{}_{}", realm_name, CLIENT_POLICY_SECRET_PREFIX);
+    let mut local_secrets = BTreeMap::new();
+    let mut files = Vec::new();
+    let realm_dir = target_dir.parent().unwrap_or(Path::new(""));
+    if !profiles.profiles.is_empty()
+        && selection.includes(realm_dir, &target_dir.join(PROFILES_FILE))
+    {
+        files.push((
+            target_dir.join(PROFILES_FILE),
+            all_secrets.to_yaml(
+                &profiles,
+                realm_name,
+                CLIENT_POLICY_SECRET_PREFIX,
+                &prefix,
+                &mut local_secrets,
+            )?,
+        ));
+    }
+    if !policies.policies.is_empty()
+        && selection.includes(realm_dir, &target_dir.join(POLICIES_FILE))
+    {
+        files.push((
+            target_dir.join(POLICIES_FILE),
+            all_secrets.to_yaml(
+                &policies,
+                realm_name,
+                CLIENT_POLICY_SECRET_PREFIX,
+                &prefix,
+                &mut local_secrets,
+            )?,
+        ));
+    }
+    all_secrets.add(realm_name, local_secrets).await;
+    for (path, yaml) in files {
+        write_if_changed_with_mutex(&path, &yaml, yes, Arc::clone(&prompt_mutex), true).await?;
+    }
+
+    let _lock = prompt_mutex.lock().await;
+    println!(
+        "  {} {}",
+        SUCCESS,
+        style("Exported client policies and profiles to client-policies/").green()
+    );
+    Ok(())
+}
+
+/// Exports organizations together with their member usernames and linked identity
+/// provider aliases. Servers without organization support (Keycloak < 25) are skipped.
+#[allow(clippy::too_many_arguments)]
+async fn inspect_organizations(
+    client: &KeycloakClient,
+    realm_name: &str,
+    target_dir: PathBuf,
+    all_secrets: Arc<SecretCollector>,
+    yes: bool,
+    prompt_mutex: Arc<Mutex<()>>,
+    selection: &Selection,
+    ws: &WorkspaceContext,
+) -> Result<()> {
+    let Some(orgs) = client
+        .get_organizations()
+        .await
+        .with_context(|| format!("Failed to fetch organizations for realm '{}'", realm_name))?
+    else {
+        let _lock = prompt_mutex.lock().await;
+        println!(
+            "  {} {}",
+            WARN,
+            style("Organizations are not supported by this server, skipping").yellow()
+        );
+        return Ok(());
+    };
+    let realm_dir = target_dir.parent().unwrap_or(Path::new(""));
+    let orgs: Vec<OrganizationRepresentation> = orgs
+        .into_iter()
+        .filter(|org| {
+            selection.includes(
+                realm_dir,
+                &resource_path(&target_dir, &org.get_filename(), ws),
+            )
+        })
+        .collect();
+    if orgs.is_empty() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(&target_dir)
+        .await
+        .context("Failed to create organizations directory")?;
+
+    let prefix = format!(
+        "realm_{}_{}",
+        realm_name,
+        OrganizationRepresentation::SECRET_PREFIX
+    );
+    for mut org in orgs {
+        let org_id = org.id.clone().context("Organization has no id")?;
+        let members: Vec<String> = client
+            .get_organization_members(&org_id)
+            .await
+            .with_context(|| format!("Failed to fetch members of organization '{}'", org_id))?
+            .into_iter()
+            .filter_map(|u| u.username)
+            .collect();
+        let idps: Vec<String> = client
+            .get_organization_identity_providers(&org_id)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to fetch identity providers of organization '{}'",
+                    org_id
+                )
+            })?
+            .into_iter()
+            .filter_map(|i| i.alias)
+            .collect();
+        org.members = (!members.is_empty()).then_some(members);
+        org.identity_providers = (!idps.is_empty()).then_some(idps);
+        org.normalize();
+
+        let path = resource_path(&target_dir, &org.get_filename(), ws);
+        let mut local_secrets = BTreeMap::new();
+        let yaml = all_secrets
+            .to_yaml(
+                &org,
+                realm_name,
+                OrganizationRepresentation::SECRET_PREFIX,
+                &prefix,
+                &mut local_secrets,
+            )
+            .with_context(|| format!("Failed to serialize organization {}", org.get_name()))?;
+        all_secrets.add(realm_name, local_secrets).await;
+        write_if_changed_with_mutex(&path, &yaml, yes, Arc::clone(&prompt_mutex), true).await?;
+    }
+
+    let _lock = prompt_mutex.lock().await;
+    println!(
+        "  {} {}",
+        SUCCESS,
+        style("Exported organizations to organizations/").green()
+    );
+    Ok(())
+}
+
+/// Exports the authorization services (resource server settings, resources, scopes,
+/// policies and permissions) of every client that has them enabled into `authz/<clientId>/`.
+#[allow(clippy::too_many_arguments)]
+async fn inspect_authz(
+    client: &KeycloakClient,
+    realm_name: &str,
+    target_dir: PathBuf,
+    all_secrets: Arc<SecretCollector>,
+    yes: bool,
+    prompt_mutex: Arc<Mutex<()>>,
+    selection: &Selection,
+    ws: &WorkspaceContext,
+) -> Result<()> {
+    let clients = client
+        .get_clients()
+        .await
+        .with_context(|| format!("Failed to fetch clients for realm '{}'", realm_name))?;
+
+    let authz_clients: Vec<ClientRepresentation> = clients
+        .into_iter()
+        .filter(|c| {
+            c.extra
+                .get("authorizationServicesEnabled")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false)
+        })
+        .collect();
+
+    if authz_clients.is_empty() {
+        return Ok(());
+    }
+
+    for authz_client in authz_clients {
+        let (Some(uuid), Some(client_id)) = (&authz_client.id, &authz_client.client_id) else {
+            continue;
+        };
+        let export = client.export_resource_server(uuid).await.with_context(|| {
+            format!(
+                "Failed to fetch authorization settings of client '{}' in realm '{}'",
+                client_id, realm_name
+            )
+        })?;
+
+        let client_dir = target_dir.join(sanitize(client_id));
+        if !selection.includes(target_dir.parent().unwrap_or(Path::new("")), &client_dir) {
+            continue;
+        }
+        let prefix = format!("realm_{}_authz_{}", realm_name, client_id);
+        let mut local_secrets = BTreeMap::new();
+        let mut files: Vec<(PathBuf, String)> = Vec::new();
+
+        let mut settings = export.settings_only();
+        settings.name = Some(client_id.clone());
+        files.push((
+            client_dir.join("resource-server.yaml"),
+            all_secrets.to_yaml(&settings, realm_name, "authz", &prefix, &mut local_secrets)?,
+        ));
+
+        for scope in export.scopes.iter().flatten() {
+            files.push((
+                resource_path(&client_dir.join("scopes"), &scope.get_filename(), ws),
+                all_secrets.to_yaml(
+                    scope,
+                    realm_name,
+                    AuthzScopeRepresentation::SECRET_PREFIX,
+                    &prefix,
+                    &mut local_secrets,
+                )?,
+            ));
+        }
+        for resource in export.resources.iter().flatten() {
+            let mut resource = resource.clone();
+            strip_resource_server_owner(&mut resource, client_id);
+            files.push((
+                resource_path(&client_dir.join("resources"), &resource.get_filename(), ws),
+                all_secrets.to_yaml(
+                    &resource,
+                    realm_name,
+                    AuthzResourceRepresentation::SECRET_PREFIX,
+                    &prefix,
+                    &mut local_secrets,
+                )?,
+            ));
+        }
+        for policy in export.policies.iter().flatten() {
+            let policy = policy.clone().from_export();
+            let sub_dir = if policy.is_permission() {
+                "permissions"
+            } else {
+                "policies"
+            };
+            files.push((
+                resource_path(&client_dir.join(sub_dir), &policy.get_filename(), ws),
+                all_secrets.to_yaml(
+                    &policy,
+                    realm_name,
+                    PolicyRepresentation::SECRET_PREFIX,
+                    &prefix,
+                    &mut local_secrets,
+                )?,
+            ));
+        }
+        all_secrets.add(realm_name, local_secrets).await;
+
+        for (path, yaml) in files {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .await
+                    .with_context(|| format!("Failed to create directory {:?}", parent))?;
+            }
+            write_if_changed_with_mutex(&path, &yaml, yes, Arc::clone(&prompt_mutex), true).await?;
+        }
+
+        let _lock = prompt_mutex.lock().await;
+        println!(
+            "  {} {}",
+            SUCCESS,
+            style(format!(
+                "Exported authorization settings of client {} to authz/{}/",
+                client_id,
+                sanitize(client_id)
+            ))
+            .green()
+        );
+    }
+
+    Ok(())
+}
+
+/// Resources owned by the resource server itself are exported with an `owner` pointing at
+/// the client; it is implied on creation, so it is dropped to keep the YAML portable.
+pub(crate) fn strip_resource_server_owner(
+    resource: &mut AuthzResourceRepresentation,
+    client_id: &str,
+) {
+    let owned_by_client = resource
+        .extra
+        .get("owner")
+        .and_then(|o| o.get("name"))
+        .and_then(|n| n.as_str())
+        .is_some_and(|n| n == client_id);
+    if owned_by_client {
+        resource.extra.remove("owner");
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn spawn_inspect<T>(
     set: &mut tokio::task::JoinSet<Result<()>>,
     client: &KeycloakClient,
     realm_name: &str,
     workspace_dir: &Arc<PathBuf>,
-    all_secrets: &Arc<Mutex<BTreeMap<String, String>>>,
+    all_secrets: &Arc<SecretCollector>,
     yes: bool,
     prompt_mutex: &Arc<Mutex<()>>,
+    selection: &Arc<Selection>,
+    ws: &Arc<WorkspaceContext>,
 ) where
     T: KeycloakResource
         + ResourceMeta
@@ -412,11 +1170,16 @@ fn spawn_inspect<T>(
         + Sync
         + 'static,
 {
+    if !selection.includes_type(T::DIR_NAME) {
+        return;
+    }
     let client = client.clone();
     let realm_name = realm_name.to_string();
     let target_dir = Arc::new(workspace_dir.join(T::DIR_NAME));
     let all_secrets = Arc::clone(all_secrets);
     let prompt_mutex = Arc::clone(prompt_mutex);
+    let selection = Arc::clone(selection);
+    let ws = Arc::clone(ws);
 
     set.spawn(async move {
         inspect_resources::<T>(
@@ -426,6 +1189,8 @@ fn spawn_inspect<T>(
             all_secrets,
             yes,
             prompt_mutex,
+            &selection,
+            &ws,
         )
         .await
     });
@@ -3,11 +3,20 @@ pub mod args;
 pub mod clean;
 pub mod cli;
 pub mod client;
+pub mod graph;
 pub mod inspect;
+pub mod lock;
 pub mod models;
 pub mod plan;
+pub mod prune;
+pub mod render;
+pub mod server;
+pub mod snapshot;
+pub mod state;
 pub mod utils;
 pub mod validate;
+pub mod watch;
+pub mod workspace;
 
 use anyhow::{Context, Result};
 use args::{Cli, Commands};
@@ -15,8 +24,18 @@ use client::KeycloakClient;
 use console::{Emoji, style};
 use std::collections::HashMap;
 use std::sync::Arc;
-use utils::secrets::vault::VaultResolver;
-use utils::secrets::{CompositeResolver, EnvResolver, SecretResolver};
+use utils::filter::{FileFilter, PathPattern};
+use utils::hooks::{Hooks, run_hooks};
+use utils::secrets::exec::ExecResolver;
+use utils::secrets::file::FileResolver;
+use utils::secrets::rules::SecretRules;
+use utils::secrets::sink::SecretsSink;
+use utils::secrets::sops::SopsResolver;
+use utils::secrets::template::VarsResolver;
+use utils::secrets::vault::{VaultAuth, VaultConfig, VaultResolver};
+use utils::secrets::{CompositeResolver, EnvResolver, SecretBackend, SecretResolver};
+use utils::selection::Selection;
+use workspace::WorkspaceContext;
 
 static ACTION: Emoji<'_, '_> = Emoji("🚀 ", ">> ");
 static SEARCH: Emoji<'_, '_> = Emoji("🔍 ", "> ");
@@ -31,40 +50,206 @@ pub struct Profile {
     pub secrets_file: Option<String>,
     pub vault_addr: Option<String>,
     pub vault_token: Option<String>,
+    pub vault_namespace: Option<String>,
+    /// KV secrets engine version of Vault mounts (1 or 2, default 2).
+    pub vault_kv_version: Option<u8>,
+    /// Vault login method (`approle`, `kubernetes` or `jwt`) used instead of a static token.
+    pub vault_auth: Option<VaultAuth>,
+    /// Secret backends to enable, in resolution order. Defaults to `[vault, env]`.
+    pub secret_backends: Option<Vec<SecretBackend>>,
+    /// Non-secret variables available to `${NAME}` references; environment variables
+    /// of the same name take precedence.
+    pub vars: Option<HashMap<String, String>>,
+    /// Parent profile whose settings and resource overlays this profile builds on.
+    pub extends: Option<String>,
+    /// Realms to work on when `--realms` is not given.
+    pub realms: Option<Vec<String>>,
+    /// Maximum number of concurrent requests to Keycloak.
+    pub concurrency: Option<usize>,
+    /// Overlay layers of resource files, from the root of `extends` to this profile
+    /// (e.g. `eu` then `eu-prod`).
+    #[serde(skip)]
+    pub overlays: Vec<String>,
 }
 
+/// Loads `profiles/<name>.yaml`, merged onto the profiles it `extends`, with its overlay
+/// chain in [`Profile::overlays`].
 pub async fn load_profile(workspace: &std::path::Path, name: &str) -> Result<Profile> {
+    let (value, chain) = load_profile_layers(workspace, name, &mut Vec::new())?;
+    let mut profile: Profile = serde_json::from_value(value)
+        .with_context(|| format!("Failed to parse profile '{}'", name))?;
+    profile.overlays = chain;
+    Ok(profile)
+}
+
+fn load_profile_layers(
+    workspace: &std::path::Path,
+    name: &str,
+    seen: &mut Vec<String>,
+) -> Result<(serde_json::Value, Vec<String>)> {
+    if seen.iter().any(|s| s == name) {
+        anyhow::bail!(
+            "Profile inheritance cycle: {} -> {}",
+            seen.join(" -> "),
+            name
+        );
+    }
+    seen.push(name.to_string());
+
     let profile_path = workspace.join("profiles").join(format!("{}.yaml", name));
     let content = std::fs::read_to_string(&profile_path)
         .with_context(|| format!("Failed to read profile file: {:?}", profile_path))?;
-    let profile: Profile = serde_yaml::from_str(&content)
+    let value: serde_json::Value = serde_yaml::from_str(&content)
         .with_context(|| format!("Failed to parse profile file: {:?}", profile_path))?;
-    Ok(profile)
+
+    match value.get("extends").and_then(|e| e.as_str()) {
+        Some(parent) => {
+            let (mut merged, mut chain) = load_profile_layers(workspace, parent, seen)
+                .with_context(|| {
+                    format!("Failed to load profile '{}' extended by '{}'", parent, name)
+                })?;
+            utils::yaml::deep_merge(&mut merged, &value)
+                .with_context(|| format!("Failed to merge profile file: {:?}", profile_path))?;
+            chain.push(name.to_string());
+            Ok((merged, chain))
+        }
+        None => Ok((value, vec![name.to_string()])),
+    }
+}
+
+/// Workspace-wide settings read from an optional `kcd.yaml` at the workspace root.
+///
+/// Settings that can also come from elsewhere follow the precedence
+/// CLI flag > environment variable > profile > `kcd.yaml` > built-in default.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceConfig {
+    /// Oldest Keycloak version the workspace is written for; checked before planning.
+    pub minimum_server_version: Option<server::ServerVersion>,
+    /// Rules overriding which fields are treated as secrets.
+    #[serde(default)]
+    pub secrets: SecretRules,
+    /// Realms to work on when neither `--realms` nor the profile names any.
+    #[serde(default)]
+    pub realms: Vec<String>,
+    /// Profile used when `--profile` is not given.
+    pub profile: Option<String>,
+    /// Maximum number of concurrent requests to Keycloak.
+    pub concurrency: Option<usize>,
+    /// Default `render` output format.
+    pub output: Option<render::RenderFormat>,
+    /// Workspace files no command reads, e.g. `*/users/test-*`.
+    #[serde(default)]
+    pub ignore: Vec<PathPattern>,
+    /// Resource types (`realm`, `clients`, `roles`, ...) that `plan` and `apply` manage.
+    pub managed: Option<Vec<String>>,
+    /// Resources `plan` and `apply` never touch, e.g. `master/clients/admin-cli`.
+    #[serde(default)]
+    pub protected: Vec<PathPattern>,
+    /// Commands run before and after `plan` and `apply`.
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// How `plan` compares remote and local resources.
+    #[serde(default)]
+    pub diff: plan::normalize::DiffRules,
+    /// How `apply` locks the workspace and its realms.
+    #[serde(default)]
+    pub lock: lock::LockSettings,
+    /// How many snapshots `apply` and `prune` keep.
+    #[serde(default)]
+    pub snapshots: snapshot::SnapshotSettings,
+    /// Id `apply` marks the resources it creates with (default: the name of the
+    /// workspace directory).
+    pub workspace_id: Option<String>,
+}
+
+impl WorkspaceConfig {
+    /// Fills in the settings `cli` and `profile` leave open.
+    pub fn apply_defaults(&self, cli: &mut Cli, profile: Option<&Profile>) {
+        if cli.realms.is_empty() {
+            cli.realms = profile
+                .and_then(|p| p.realms.clone())
+                .unwrap_or_else(|| self.realms.clone());
+        }
+        cli.concurrency = cli
+            .concurrency
+            .or_else(|| profile.and_then(|p| p.concurrency))
+            .or(self.concurrency);
+    }
+
+    /// The context commands work on the workspace with, for the profile `name` as loaded
+    /// into `profile`.
+    pub fn context(
+        &self,
+        workspace: &std::path::Path,
+        name: Option<&str>,
+        profile: Option<&Profile>,
+    ) -> WorkspaceContext {
+        WorkspaceContext {
+            profile: name.map(str::to_string),
+            overlays: profile.map(|p| p.overlays.clone()).unwrap_or_default(),
+            profiles: utils::yaml::workspace_profiles(workspace),
+            filter: FileFilter {
+                root: workspace.to_path_buf(),
+                ignore: self.ignore.clone(),
+                managed: self.managed.clone(),
+                protected: self.protected.clone(),
+            },
+            secrets: self.secrets.clone(),
+            diff: self.diff.clone(),
+            lock: self.lock.clone(),
+            snapshots: self.snapshots.clone(),
+            workspace_id: self.workspace_id.clone(),
+        }
+    }
 }
 
+pub async fn load_workspace_config(workspace: &std::path::Path) -> Result<WorkspaceConfig> {
+    let config_path = workspace.join("kcd.yaml");
+    if !config_path.exists() {
+        return Ok(WorkspaceConfig::default());
+    }
+    let content = std::fs::read_to_string(&config_path)
+        .with_context(|| format!("Failed to read workspace config: {:?}", config_path))?;
+    let config: WorkspaceConfig = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse workspace config: {:?}", config_path))?;
+    Ok(config)
+}
+
+/// Connects to Keycloak. Connection settings are taken from the command line or the
+/// environment first, then from the profile.
 pub async fn init_client(cli: &Cli, profile: Option<&Profile>) -> Result<KeycloakClient> {
-    let server = profile
-        .map(|p| p.server_url.clone())
-        .or_else(|| cli.server.clone())
+    let server = cli
+        .server
+        .clone()
+        .or_else(|| profile.map(|p| p.server_url.clone()))
         .context("Keycloak server URL not provided (neither via --server nor --profile)")?;
 
-    let client_id = profile
-        .and_then(|p| p.client_id.clone())
-        .unwrap_or_else(|| cli.client_id.clone());
+    let client_id = cli
+        .client_id
+        .clone()
+        .or_else(|| profile.and_then(|p| p.client_id.clone()))
+        .unwrap_or_else(|| "admin-cli".to_string());
 
-    let client_secret = profile
-        .and_then(|p| p.client_secret.clone())
-        .or_else(|| cli.client_secret.clone());
+    let client_secret = cli
+        .client_secret
+        .clone()
+        .or_else(|| profile.and_then(|p| p.client_secret.clone()));
 
-    let user = profile
-        .and_then(|p| p.user.clone())
-        .or_else(|| cli.user.clone());
+    let user = cli
+        .user
+        .clone()
+        .or_else(|| profile.and_then(|p| p.user.clone()));
 
-    let password = profile
-        .and_then(|p| p.password.clone())
-        .or_else(|| cli.password.clone());
+    let password = cli
+        .password
+        .clone()
+        .or_else(|| profile.and_then(|p| p.password.clone()));
 
     let mut client = KeycloakClient::new(server);
+    if let Some(max) = cli.concurrency {
+        client.set_max_concurrent_requests(max);
+    }
     client
         .login(
             &client_id,
@@ -74,15 +259,43 @@ pub async fn init_client(cli: &Cli, profile: Option<&Profile>) -> Result<Keycloa
         )
         .await
         .context("Login failed")?;
+    // Older servers or restricted admin accounts may hide serverinfo; carry on without
+    // version-specific behaviour in that case.
+    if let Err(e) = client.detect_server_version().await {
+        log::warn!("Could not detect the Keycloak server version: {:#}", e);
+    }
     Ok(client)
 }
 
-pub async fn init_secrets(
-    cli: &Cli,
-    workspace: &std::path::Path,
-    profile: Option<&Profile>,
-) -> Result<Arc<dyn SecretResolver>> {
-    // Load secrets from profile-specific secrets file or default .secrets
+/// Builds the Vault client from the CLI/environment (falling back to the profile), or
+/// returns `None` when no address or credentials are configured.
+pub fn init_vault(cli: &Cli, profile: Option<&Profile>) -> Result<Option<VaultResolver>> {
+    let Some(addr) = cli
+        .vault_addr
+        .clone()
+        .or_else(|| profile.and_then(|p| p.vault_addr.clone()))
+    else {
+        return Ok(None);
+    };
+    let mut config = VaultConfig::new(&addr);
+    config.token = cli
+        .vault_token
+        .clone()
+        .or_else(|| profile.and_then(|p| p.vault_token.clone()));
+    config.auth = profile.and_then(|p| p.vault_auth.clone());
+    if config.token.is_none() && config.auth.is_none() {
+        return Ok(None);
+    }
+    config.namespace = cli
+        .vault_namespace
+        .clone()
+        .or_else(|| profile.and_then(|p| p.vault_namespace.clone()));
+    config.kv_version = profile.and_then(|p| p.vault_kv_version).unwrap_or(2);
+    VaultResolver::from_config(config).map(Some)
+}
+
+/// Loads the profile-specific secrets file (or the default `.secrets`) into the environment.
+fn load_secrets_file(workspace: &std::path::Path, profile: Option<&Profile>) {
     let secrets_file = profile
         .and_then(|p| p.secrets_file.as_deref())
         .unwrap_or(".secrets");
@@ -91,24 +304,44 @@ pub async fn init_secrets(
     if env_path.exists() {
         dotenvy::from_path(&env_path).ok();
     }
+}
 
-    let mut resolvers: Vec<Box<dyn SecretResolver>> = Vec::new();
+fn init_vars(profile: Option<&Profile>) -> VarsResolver {
+    VarsResolver::new(profile.and_then(|p| p.vars.clone()).unwrap_or_default())
+}
+
+pub async fn init_secrets(
+    cli: &Cli,
+    workspace: &std::path::Path,
+    profile: Option<&Profile>,
+) -> Result<Arc<dyn SecretResolver>> {
+    load_secrets_file(workspace, profile);
 
-    let vault_addr = profile
-        .and_then(|p| p.vault_addr.clone())
-        .or_else(|| cli.vault_addr.clone());
+    let mut resolvers: Vec<Box<dyn SecretResolver>> = Vec::new();
 
-    let vault_token = profile
-        .and_then(|p| p.vault_token.clone())
-        .or_else(|| cli.vault_token.clone());
+    let configured_backends = profile.and_then(|p| p.secret_backends.as_deref());
+    let backends = configured_backends.unwrap_or(SecretBackend::DEFAULT);
 
-    if let (Some(addr), Some(token)) = (vault_addr, vault_token) {
-        resolvers.push(Box::new(VaultResolver::new(&addr, &token)?));
+    for backend in backends {
+        match backend {
+            SecretBackend::Env => resolvers.push(Box::new(EnvResolver::new(
+                std::env::vars().collect::<HashMap<String, String>>(),
+            ))),
+            SecretBackend::Vault => match init_vault(cli, profile)? {
+                Some(vault) => resolvers.push(Box::new(vault)),
+                // Vault is optional unless the profile asks for it explicitly.
+                None if configured_backends.is_some() => anyhow::bail!(
+                    "The vault secret backend requires vault_addr and either vault_token or vault_auth"
+                ),
+                None => {}
+            },
+            SecretBackend::File => resolvers.push(Box::new(FileResolver::new(workspace))),
+            SecretBackend::Sops => resolvers.push(Box::new(SopsResolver::new(workspace))),
+            SecretBackend::Exec => resolvers.push(Box::new(ExecResolver::new(workspace))),
+        }
     }
-
-    resolvers.push(Box::new(EnvResolver::new(
-        std::env::vars().collect::<HashMap<String, String>>(),
-    )));
+    // Plain variables come last so they never shadow a secret.
+    resolvers.push(Box::new(init_vars(profile)));
 
     Ok(Arc::new(CompositeResolver::new(resolvers)))
 }
@@ -117,8 +350,13 @@ async fn handle_inspect(
     cli: &Cli,
     profile: Option<&Profile>,
     workspace: &std::path::Path,
-    yes: bool,
+    ws: &WorkspaceContext,
+    options: &inspect::InspectOptions,
 ) -> Result<()> {
+    let vault = match options.secrets_sink {
+        SecretsSink::Vault { .. } => init_vault(cli, profile)?,
+        SecretsSink::Dotenv => None,
+    };
     let client = init_client(cli, profile).await?;
     println!(
         "{} {}",
@@ -130,11 +368,24 @@ async fn handle_inspect(
         .cyan()
         .bold()
     );
-    inspect::run(&client, workspace.to_path_buf(), &cli.realms, yes).await?;
+    inspect::run_with_options(
+        &client,
+        workspace.to_path_buf(),
+        &cli.realms,
+        options,
+        ws,
+        vault.as_ref(),
+    )
+    .await?;
     Ok(())
 }
 
-async fn handle_validate(cli: &Cli, workspace: &std::path::Path) -> Result<()> {
+async fn handle_validate(
+    cli: &Cli,
+    profile: Option<&Profile>,
+    workspace: &std::path::Path,
+    ws: &WorkspaceContext,
+) -> Result<()> {
     println!(
         "{} {}",
         SEARCH,
@@ -145,19 +396,37 @@ async fn handle_validate(cli: &Cli, workspace: &std::path::Path) -> Result<()> {
         .cyan()
         .bold()
     );
-    validate::run(workspace.to_path_buf(), &cli.realms).await?;
+    load_secrets_file(workspace, profile);
+    let vars = init_vars(profile);
+    validate::run_with_vars(workspace.to_path_buf(), &cli.realms, ws, &|name| {
+        vars.is_defined(name)
+    })
+    .await?;
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_apply(
     cli: &Cli,
     profile: Option<&Profile>,
     workspace: &std::path::Path,
+    ws: &Arc<WorkspaceContext>,
     yes: bool,
     review: bool,
+    keep_going: bool,
+    report_file: Option<&std::path::Path>,
+    selection: &Selection,
+    hooks: &Hooks,
 ) -> Result<()> {
     let client = init_client(cli, profile).await?;
     let resolver = init_secrets(cli, workspace, profile).await?;
+    run_hooks(
+        "pre_apply",
+        &hooks.pre_apply,
+        workspace,
+        cli.profile.as_deref(),
+    )
+    .await?;
     println!(
         "{} {}",
         ACTION,
@@ -168,7 +437,8 @@ async fn handle_apply(
         .cyan()
         .bold()
     );
-    apply::run(
+    let report = apply::report::ApplyReport::new(keep_going);
+    let result = apply::run_with_selection(
         &client,
         workspace.to_path_buf(),
         &cli.realms,
@@ -176,21 +446,46 @@ async fn handle_apply(
         review,
         Arc::new(crate::utils::ui::DialoguerUi::new()),
         resolver,
-        cli.profile.clone(),
+        Arc::clone(ws),
+        selection,
+        &report,
+    )
+    .await;
+    if let Some(path) = report_file {
+        report.write_json(path).await?;
+    }
+    result?;
+    run_hooks(
+        "post_apply",
+        &hooks.post_apply,
+        workspace,
+        cli.profile.as_deref(),
     )
     .await?;
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_plan(
     cli: &Cli,
     profile: Option<&Profile>,
     workspace: &std::path::Path,
+    ws: &Arc<WorkspaceContext>,
     changes_only: bool,
     interactive: bool,
+    selection: &Selection,
+    report_file: Option<&std::path::Path>,
+    hooks: &Hooks,
 ) -> Result<()> {
     let client = init_client(cli, profile).await?;
     let resolver = init_secrets(cli, workspace, profile).await?;
+    run_hooks(
+        "pre_plan",
+        &hooks.pre_plan,
+        workspace,
+        cli.profile.as_deref(),
+    )
+    .await?;
     println!(
         "{} {}",
         SEARCH,
@@ -201,15 +496,28 @@ async fn handle_plan(
         .cyan()
         .bold()
     );
-    plan::run(
-        &client,
-        workspace.to_path_buf(),
+    let options = plan::PlanOptions {
         changes_only,
         interactive,
+        drift: false,
+    };
+    plan::run_with_options(
+        &client,
+        workspace.to_path_buf(),
+        options,
         &cli.realms,
         Arc::new(crate::utils::ui::DialoguerUi::new()),
         resolver,
-        cli.profile.clone(),
+        Arc::clone(ws),
+        selection,
+        report_file,
+    )
+    .await?;
+    run_hooks(
+        "post_plan",
+        &hooks.post_plan,
+        workspace,
+        cli.profile.as_deref(),
     )
     .await?;
     Ok(())
@@ -219,6 +527,9 @@ async fn handle_drift(
     cli: &Cli,
     profile: Option<&Profile>,
     workspace: &std::path::Path,
+    ws: &Arc<WorkspaceContext>,
+    selection: &Selection,
+    report_file: Option<&std::path::Path>,
 ) -> Result<()> {
     let client = init_client(cli, profile).await?;
     let resolver = init_secrets(cli, workspace, profile).await?;
@@ -232,15 +543,21 @@ async fn handle_drift(
         .cyan()
         .bold()
     );
-    plan::run(
+    let options = plan::PlanOptions {
+        changes_only: true,
+        interactive: false,
+        drift: true,
+    };
+    plan::run_with_options(
         &client,
         workspace.to_path_buf(),
-        true,
-        false,
+        options,
         &cli.realms,
         Arc::new(crate::utils::ui::DialoguerUi::new()),
         resolver,
-        cli.profile.clone(),
+        Arc::clone(ws),
+        selection,
+        report_file,
     )
     .await?;
     Ok(())
@@ -255,6 +572,178 @@ async fn handle_cli(workspace: &std::path::Path) -> Result<()> {
     Ok(())
 }
 
+/// Secrets are only resolved with `--reveal`; otherwise references are kept as written.
+async fn render_resolver(
+    cli: &Cli,
+    workspace: &std::path::Path,
+    profile: Option<&Profile>,
+    reveal: bool,
+) -> Result<Arc<dyn SecretResolver>> {
+    if reveal {
+        init_secrets(cli, workspace, profile).await
+    } else {
+        Ok(render::masked_resolver(Box::new(init_vars(profile))))
+    }
+}
+
+async fn handle_render(
+    cli: &Cli,
+    profile: Option<&Profile>,
+    workspace: &std::path::Path,
+    ws: &WorkspaceContext,
+    options: &render::RenderOptions,
+    diff_profiles: Option<&[String]>,
+) -> Result<()> {
+    match diff_profiles {
+        Some([from, to]) => {
+            let from_profile = load_profile(workspace, from).await?;
+            let to_profile = load_profile(workspace, to).await?;
+            let from_resolver =
+                render_resolver(cli, workspace, Some(&from_profile), options.reveal).await?;
+            let to_resolver =
+                render_resolver(cli, workspace, Some(&to_profile), options.reveal).await?;
+            let from_ws = WorkspaceContext {
+                profile: Some(from.clone()),
+                overlays: from_profile.overlays.clone(),
+                ..ws.clone()
+            };
+            let to_ws = WorkspaceContext {
+                profile: Some(to.clone()),
+                overlays: to_profile.overlays.clone(),
+                ..ws.clone()
+            };
+            render::diff_profiles(
+                workspace,
+                &cli.realms,
+                (&from_ws, from_resolver),
+                (&to_ws, to_resolver),
+                options,
+            )
+            .await?;
+        }
+        Some(_) => anyhow::bail!("--diff-profiles expects exactly two profile names"),
+        None => {
+            let resolver = render_resolver(cli, workspace, profile, options.reveal).await?;
+            render::run(workspace, &cli.realms, ws, resolver, options).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn handle_rollback(
+    cli: &Cli,
+    profile: Option<&Profile>,
+    workspace: &std::path::Path,
+    ws: &WorkspaceContext,
+    snapshot: Option<&str>,
+    yes: bool,
+) -> Result<()> {
+    let client = init_client(cli, profile).await?;
+    snapshot::rollback(
+        &client,
+        workspace,
+        &cli.realms,
+        snapshot,
+        yes,
+        Arc::new(crate::utils::ui::DialoguerUi::new()),
+        ws,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn handle_prune(
+    cli: &Cli,
+    profile: Option<&Profile>,
+    workspace: &std::path::Path,
+    ws: &WorkspaceContext,
+    yes: bool,
+    selection: &Selection,
+) -> Result<()> {
+    let client = init_client(cli, profile).await?;
+    let realms = if cli.realms.is_empty() {
+        workspace_realms(workspace).await?
+    } else {
+        cli.realms.clone()
+    };
+    prune::run(
+        &client,
+        workspace,
+        &realms,
+        yes,
+        Arc::new(crate::utils::ui::DialoguerUi::new()),
+        ws,
+        selection,
+    )
+    .await
+}
+
+async fn handle_watch(
+    cli: &Cli,
+    profile: Option<&Profile>,
+    workspace: &std::path::Path,
+    ws: &Arc<WorkspaceContext>,
+    debounce: std::time::Duration,
+    selection: &Selection,
+) -> Result<()> {
+    let client = init_client(cli, profile).await?;
+    let resolver = init_secrets(cli, workspace, profile).await?;
+    watch::run(
+        &client,
+        workspace,
+        &cli.realms,
+        Arc::new(crate::utils::ui::DialoguerUi::new()),
+        resolver,
+        Arc::clone(ws),
+        selection,
+        debounce,
+    )
+    .await
+}
+
+async fn handle_force_unlock(
+    cli: &Cli,
+    profile: Option<&Profile>,
+    workspace: &std::path::Path,
+    ws: &WorkspaceContext,
+    yes: bool,
+) -> Result<()> {
+    // Server-side locks are only looked up when they are in use
+    let client = if ws.lock.server {
+        Some(init_client(cli, profile).await?)
+    } else {
+        None
+    };
+    let realms = if cli.realms.is_empty() && client.is_some() {
+        workspace_realms(workspace).await?
+    } else {
+        cli.realms.clone()
+    };
+    lock::force_unlock(
+        workspace,
+        client.as_ref(),
+        &realms,
+        yes,
+        Arc::new(crate::utils::ui::DialoguerUi::new()),
+    )
+    .await
+}
+
+/// The realm directories of the workspace (those with a `realm.yaml`).
+async fn workspace_realms(workspace: &std::path::Path) -> Result<Vec<String>> {
+    let mut realms = Vec::new();
+    let mut entries = tokio::fs::read_dir(workspace).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir()
+            && tokio::fs::try_exists(entry.path().join("realm.yaml")).await?
+        {
+            realms.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    realms.sort();
+    Ok(realms)
+}
+
 async fn handle_clean(cli: &Cli, workspace: &std::path::Path, yes: bool) -> Result<()> {
     println!(
         "{} {}",
@@ -270,53 +759,144 @@ async fn handle_clean(cli: &Cli, workspace: &std::path::Path, yes: bool) -> Resu
     Ok(())
 }
 
-pub async fn run_app(cli: Cli) -> Result<()> {
+pub async fn run_app(mut cli: Cli) -> Result<()> {
     let workspace = match &cli.command {
         Commands::Inspect { workspace, .. } => workspace,
         Commands::Validate { workspace } => workspace,
         Commands::Apply { workspace, .. } => workspace,
         Commands::Plan { workspace, .. } => workspace,
-        Commands::Drift { workspace } => workspace,
+        Commands::Render { workspace, .. } => workspace,
+        Commands::Drift { workspace, .. } => workspace,
         Commands::Cli { workspace } => workspace,
         Commands::Clean { workspace, .. } => workspace,
-    };
+        Commands::Rollback { workspace, .. } => workspace,
+        Commands::Prune { workspace, .. } => workspace,
+        Commands::Watch { workspace, .. } => workspace,
+        Commands::ForceUnlock { workspace, .. } => workspace,
+    }
+    .clone();
 
+    let config = load_workspace_config(&workspace).await?;
+    if cli.profile.is_none() {
+        cli.profile = config.profile.clone();
+    }
     let profile = if let Some(p) = &cli.profile {
-        Some(load_profile(workspace, p).await?)
+        Some(load_profile(&workspace, p).await?)
     } else {
         None
     };
+    config.apply_defaults(&mut cli, profile.as_ref());
+    let mut ws = config.context(&workspace, cli.profile.as_deref(), profile.as_ref());
+    utils::secrets::set_debug_rules(ws.secrets.clone());
+    if let Commands::Plan { strict: true, .. } = cli.command {
+        ws.diff.strict = true;
+    }
+    let ws = Arc::new(ws);
 
     match &cli.command {
-        Commands::Inspect { workspace, yes } => {
-            handle_inspect(&cli, profile.as_ref(), workspace, *yes).await?;
+        Commands::Inspect {
+            workspace,
+            yes,
+            secrets_sink,
+            prune_secrets,
+            selection,
+        } => {
+            let options = inspect::InspectOptions {
+                yes: *yes,
+                secrets_sink: secrets_sink.clone(),
+                prune_secrets: *prune_secrets,
+                selection: selection.to_selection()?,
+            };
+            handle_inspect(&cli, profile.as_ref(), workspace, &ws, &options).await?;
         }
         Commands::Validate { workspace } => {
-            handle_validate(&cli, workspace).await?;
+            handle_validate(&cli, profile.as_ref(), workspace, &ws).await?;
         }
         Commands::Apply {
             workspace,
             yes,
             review,
+            keep_going,
+            report,
+            selection,
         } => {
-            handle_apply(&cli, profile.as_ref(), workspace, *yes, *review).await?;
+            handle_apply(
+                &cli,
+                profile.as_ref(),
+                workspace,
+                &ws,
+                *yes,
+                *review,
+                *keep_going,
+                report.as_deref(),
+                &selection.to_selection()?,
+                &config.hooks,
+            )
+            .await?;
         }
         Commands::Plan {
             workspace,
             changes_only,
             interactive,
+            strict: _,
+            graph,
+            report,
+            selection,
         } => {
+            let selection = selection.to_selection()?;
+            if let Some(format) = graph {
+                graph::run(workspace, &cli.realms, &ws, &selection, *format).await?;
+                return Ok(());
+            }
             handle_plan(
                 &cli,
                 profile.as_ref(),
                 workspace,
+                &ws,
                 *changes_only,
                 *interactive,
+                &selection,
+                report.as_deref(),
+                &config.hooks,
+            )
+            .await?;
+        }
+        Commands::Render {
+            workspace,
+            resource,
+            reveal,
+            format,
+            diff_profiles,
+        } => {
+            let options = render::RenderOptions {
+                resource: resource.clone(),
+                reveal: *reveal,
+                format: format.or(config.output).unwrap_or_default(),
+            };
+            handle_render(
+                &cli,
+                profile.as_ref(),
+                workspace,
+                &ws,
+                &options,
+                diff_profiles.as_deref(),
             )
             .await?;
         }
-        Commands::Drift { workspace } => {
-            handle_drift(&cli, profile.as_ref(), workspace).await?;
+        Commands::Drift {
+            workspace,
+            report,
+            selection,
+        } => {
+            handle_drift(
+                &cli,
+                profile.as_ref(),
+                workspace,
+                &ws,
+                &selection.to_selection()?,
+                report.as_deref(),
+            )
+            .await?;
         }
         Commands::Cli { workspace } => {
             handle_cli(workspace).await?;
@@ -324,6 +904,54 @@ pub async fn run_app(cli: Cli) -> Result<()> {
         Commands::Clean { workspace, yes } => {
             handle_clean(&cli, workspace, *yes).await?;
         }
+        Commands::Rollback {
+            workspace,
+            snapshot,
+            yes,
+        } => {
+            handle_rollback(
+                &cli,
+                profile.as_ref(),
+                workspace,
+                &ws,
+                snapshot.as_deref(),
+                *yes,
+            )
+            .await?;
+        }
+        Commands::Prune {
+            workspace,
+            yes,
+            selection,
+        } => {
+            handle_prune(
+                &cli,
+                profile.as_ref(),
+                workspace,
+                &ws,
+                *yes,
+                &selection.to_selection()?,
+            )
+            .await?;
+        }
+        Commands::Watch {
+            workspace,
+            debounce,
+            selection,
+        } => {
+            handle_watch(
+                &cli,
+                profile.as_ref(),
+                workspace,
+                &ws,
+                std::time::Duration::from_millis(*debounce),
+                &selection.to_selection()?,
+            )
+            .await?;
+        }
+        Commands::ForceUnlock { workspace, yes } => {
+            handle_force_unlock(&cli, profile.as_ref(), workspace, &ws, *yes).await?;
+        }
     }
 
     Ok(())
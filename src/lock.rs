@@ -0,0 +1,478 @@
+use crate::client::KeycloakClient;
+use crate::models::RealmRepresentation;
+use crate::snapshot::STATE_DIR;
+use crate::utils::ui::{SUCCESS_DELETE, Ui, WARN};
+use anyhow::{Context, Result};
+use console::style;
+use jiff::{SignedDuration, Timestamp};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+
+/// Realm attribute holding the server-side lock.
+pub const SERVER_LOCK_ATTRIBUTE: &str = "kcd.lock";
+
+const DEFAULT_TTL: SignedDuration = SignedDuration::from_mins(30);
+
+/// How `apply` locks the workspace and the realms it applies (`lock:` in `kcd.yaml`).
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct LockSettings {
+    /// Also lock each realm on the server, through the `kcd.lock` realm attribute.
+    #[serde(default)]
+    pub server: bool,
+    /// How long a lock is held before it counts as stale, e.g. `45m` (default: `30m`).
+    pub ttl: Option<String>,
+}
+
+impl LockSettings {
+    pub fn ttl(&self) -> Result<SignedDuration> {
+        match &self.ttl {
+            Some(ttl) => ttl
+                .parse()
+                .with_context(|| format!("Invalid lock ttl '{}', expected e.g. '30m'", ttl)),
+            None => Ok(DEFAULT_TTL),
+        }
+    }
+}
+
+/// Who holds a lock, and until when.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LockInfo {
+    /// Tells locks apart, so that a lock is only released by the run that took it.
+    pub id: String,
+    pub owner: String,
+    pub host: String,
+    pub pid: u32,
+    pub acquired: String,
+    pub expires: String,
+}
+
+impl LockInfo {
+    pub fn new(ttl: SignedDuration) -> Result<Self> {
+        let now = Timestamp::now();
+        let owner = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        let host = std::env::var("HOSTNAME")
+            .or_else(|_| std::env::var("COMPUTERNAME"))
+            .or_else(|_| std::fs::read_to_string("/etc/hostname").map(|h| h.trim().to_string()))
+            .unwrap_or_else(|_| "unknown".to_string());
+        let pid = std::process::id();
+        Ok(Self {
+            id: format!("{}@{}:{}:{}", owner, host, pid, now.as_nanosecond()),
+            owner,
+            host,
+            pid,
+            acquired: now.to_string(),
+            expires: now
+                .checked_add(ttl)
+                .context("Lock ttl is out of range")?
+                .to_string(),
+        })
+    }
+
+    /// Whether the lock has expired; unreadable expiry dates count as expired.
+    pub fn is_expired(&self) -> bool {
+        match self.expires.parse::<Timestamp>() {
+            Ok(expires) => expires <= Timestamp::now(),
+            Err(_) => true,
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "held by {} on {} (pid {}) since {}, expires {}",
+            self.owner, self.host, self.pid, self.acquired, self.expires
+        )
+    }
+}
+
+pub fn lock_file(workspace_dir: &Path) -> PathBuf {
+    workspace_dir.join(STATE_DIR).join("apply.lock")
+}
+
+async fn read_lock_file(path: &Path) -> Result<Option<LockInfo>> {
+    match fs::read_to_string(path).await {
+        Ok(content) => Ok(serde_json::from_str(&content).ok()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read lock file {:?}", path)),
+    }
+}
+
+/// The lock file of a workspace, removed when dropped.
+#[derive(Debug)]
+pub struct LocalLock {
+    path: PathBuf,
+    info: LockInfo,
+}
+
+impl LocalLock {
+    /// Takes the lock of the workspace, replacing an expired one. Fails when another
+    /// run holds it.
+    pub async fn acquire(workspace_dir: &Path, settings: &LockSettings) -> Result<Self> {
+        let path = lock_file(workspace_dir);
+        crate::snapshot::create_state_dir(workspace_dir).await?;
+        let info = LockInfo::new(settings.ttl()?)?;
+        let content = serde_json::to_string_pretty(&info)?;
+        loop {
+            let created = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await;
+            match created {
+                Ok(mut file) => {
+                    use tokio::io::AsyncWriteExt;
+                    file.write_all(content.as_bytes()).await?;
+                    file.flush().await?;
+                    return Ok(Self { path, info });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    // A lock file that cannot be read yet may be being written
+                    let held = match fs::read_to_string(&path).await {
+                        Ok(content) => serde_json::from_str::<LockInfo>(&content).ok(),
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                        Err(e) => {
+                            return Err(e)
+                                .with_context(|| format!("Failed to read lock file {:?}", path));
+                        }
+                    };
+                    match held {
+                        Some(held) if held.is_expired() => {
+                            println!(
+                                "{} {}",
+                                WARN,
+                                style(format!(
+                                    "Replacing expired workspace lock {}",
+                                    held.describe()
+                                ))
+                                .yellow()
+                            );
+                            // Only the run holding the takeover file may remove the expired
+                            // lock, so racing runs cannot remove a lock another one just took
+                            let takeover = path.with_extension("lock.takeover");
+                            match fs::OpenOptions::new()
+                                .write(true)
+                                .create_new(true)
+                                .open(&takeover)
+                                .await
+                            {
+                                Ok(_) => {}
+                                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                                    anyhow::bail!(
+                                        "Workspace is locked by another apply ({}). Run `kcd force-unlock` if it is stale.",
+                                        held.describe()
+                                    )
+                                }
+                                Err(e) => {
+                                    return Err(e).with_context(|| {
+                                        format!("Failed to create lock file {:?}", takeover)
+                                    });
+                                }
+                            }
+                            let current = read_lock_file(&path).await;
+                            let removed = match &current {
+                                Ok(Some(current)) if current.id == held.id => {
+                                    fs::remove_file(&path).await.map_err(anyhow::Error::from)
+                                }
+                                _ => Ok(()),
+                            };
+                            let _ = fs::remove_file(&takeover).await;
+                            removed.with_context(|| {
+                                format!("Failed to remove lock file {:?}", path)
+                            })?;
+                            if let Some(current) = current?
+                                && current.id != held.id
+                            {
+                                anyhow::bail!(
+                                    "Workspace is locked by another apply ({}). Run `kcd force-unlock` if it is stale.",
+                                    current.describe()
+                                );
+                            }
+                        }
+                        Some(held) => anyhow::bail!(
+                            "Workspace is locked by another apply ({}). Run `kcd force-unlock` if it is stale.",
+                            held.describe()
+                        ),
+                        None => anyhow::bail!(
+                            "Workspace is locked by another apply ({:?}). Run `kcd force-unlock` if it is stale.",
+                            path
+                        ),
+                    }
+                }
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to create lock file {:?}", path));
+                }
+            }
+        }
+    }
+
+    pub fn info(&self) -> &LockInfo {
+        &self.info
+    }
+}
+
+impl Drop for LocalLock {
+    fn drop(&mut self) {
+        // Only remove the file if it is still ours
+        let ours = std::fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<LockInfo>(&content).ok())
+            .is_some_and(|held| held.id == self.info.id);
+        if ours {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// The server-side lock of the realm the client targets, if any.
+pub async fn server_lock(client: &KeycloakClient) -> Result<Option<LockInfo>> {
+    let realm = client.get_realm().await?;
+    Ok(realm
+        .extra
+        .get("attributes")
+        .and_then(|attributes| attributes.get(SERVER_LOCK_ATTRIBUTE))
+        .and_then(Value::as_str)
+        .filter(|value| !value.is_empty())
+        .and_then(|value| serde_json::from_str(value).ok()))
+}
+
+/// Drops the lock attribute from `realm`: it belongs to the run holding the lock, never
+/// to the workspace, so it is neither exported, diffed nor sent back.
+pub fn strip_server_lock(realm: &mut RealmRepresentation) {
+    if let Some(attributes) = realm
+        .extra
+        .get_mut("attributes")
+        .and_then(Value::as_object_mut)
+    {
+        attributes.remove(SERVER_LOCK_ATTRIBUTE);
+    }
+}
+
+/// Sets the lock attribute of the realm; `null` removes it.
+async fn write_server_lock(client: &KeycloakClient, realm_name: &str, value: Value) -> Result<()> {
+    let rep = RealmRepresentation {
+        realm: realm_name.to_string(),
+        enabled: None,
+        display_name: None,
+        extra: [(
+            "attributes".to_string(),
+            serde_json::json!({ SERVER_LOCK_ATTRIBUTE: value }),
+        )]
+        .into_iter()
+        .collect(),
+    };
+    client.update_realm(&rep).await
+}
+
+/// Takes the server-side lock of the realm the client targets, replacing an expired one.
+/// Realm attributes cannot be updated atomically, so the lock is read back to detect a
+/// concurrent run that wrote its own at the same time.
+pub async fn acquire_server_lock(
+    client: &KeycloakClient,
+    realm_name: &str,
+    settings: &LockSettings,
+) -> Result<LockInfo> {
+    if let Some(held) = server_lock(client).await? {
+        if !held.is_expired() {
+            anyhow::bail!(
+                "Realm '{}' is locked by another apply ({}). Run `kcd force-unlock` if it is stale.",
+                realm_name,
+                held.describe()
+            );
+        }
+        println!(
+            "{} {}",
+            WARN,
+            style(format!(
+                "Replacing expired lock of realm '{}' {}",
+                realm_name,
+                held.describe()
+            ))
+            .yellow()
+        );
+    }
+    let info = LockInfo::new(settings.ttl()?)?;
+    write_server_lock(client, realm_name, serde_json::to_string(&info)?.into())
+        .await
+        .with_context(|| format!("Failed to lock realm '{}'", realm_name))?;
+    match server_lock(client).await? {
+        Some(held) if held.id == info.id => Ok(info),
+        Some(held) => anyhow::bail!(
+            "Realm '{}' was locked by another apply at the same time ({})",
+            realm_name,
+            held.describe()
+        ),
+        None => anyhow::bail!("Failed to lock realm '{}'", realm_name),
+    }
+}
+
+/// Runs `work` while holding the server-side lock of the realm the client targets, when
+/// `settings.server` is set.
+pub async fn with_server_lock<T>(
+    client: &KeycloakClient,
+    realm_name: &str,
+    settings: &LockSettings,
+    work: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    if !settings.server {
+        return work.await;
+    }
+    let lock = acquire_server_lock(client, realm_name, settings).await?;
+    let result = work.await;
+    let released = release_server_lock(client, realm_name, &lock).await;
+    let value = result?;
+    released?;
+    Ok(value)
+}
+
+/// Releases the server-side lock of the realm, if it is still the one taken as `info`.
+pub async fn release_server_lock(
+    client: &KeycloakClient,
+    realm_name: &str,
+    info: &LockInfo,
+) -> Result<()> {
+    if server_lock(client)
+        .await?
+        .is_some_and(|held| held.id == info.id)
+    {
+        write_server_lock(client, realm_name, Value::Null)
+            .await
+            .with_context(|| format!("Failed to unlock realm '{}'", realm_name))?;
+    }
+    Ok(())
+}
+
+/// Removes the workspace lock and, when `client` is given, the server-side locks of
+/// `realms`, whoever holds them.
+pub async fn force_unlock(
+    workspace_dir: &Path,
+    client: Option<&KeycloakClient>,
+    realms: &[String],
+    yes: bool,
+    ui: Arc<dyn Ui>,
+) -> Result<()> {
+    let path = lock_file(workspace_dir);
+    let local = read_lock_file(&path).await?;
+    let local_exists = fs::try_exists(&path).await?;
+    let mut servers = Vec::new();
+    if let Some(client) = client {
+        for realm_name in realms {
+            let mut realm_client = client.clone();
+            realm_client.set_target_realm(realm_name.clone());
+            if let Some(held) = server_lock(&realm_client).await? {
+                servers.push((realm_name.clone(), realm_client, held));
+            }
+        }
+    }
+
+    if !local_exists && servers.is_empty() {
+        println!("{} {}", WARN, style("No locks found").yellow());
+        return Ok(());
+    }
+    if local_exists {
+        match &local {
+            Some(held) => println!("  Workspace lock {}", held.describe()),
+            None => println!("  Workspace lock {:?} (unreadable)", path),
+        }
+    }
+    for (realm_name, _, held) in &servers {
+        println!("  Lock of realm '{}' {}", realm_name, held.describe());
+    }
+    if !yes && !ui.confirm("Remove these locks?", false)? {
+        println!("{} {}", WARN, style("Aborted.").yellow());
+        return Ok(());
+    }
+
+    if local_exists {
+        fs::remove_file(&path)
+            .await
+            .with_context(|| format!("Failed to remove lock file {:?}", path))?;
+        let _ = fs::remove_file(path.with_extension("lock.takeover")).await;
+        println!("  {} Removed workspace lock", SUCCESS_DELETE);
+    }
+    for (realm_name, realm_client, _) in servers {
+        write_server_lock(&realm_client, &realm_name, Value::Null)
+            .await
+            .with_context(|| format!("Failed to unlock realm '{}'", realm_name))?;
+        println!(
+            "  {} Removed lock of realm '{}'",
+            SUCCESS_DELETE, realm_name
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_local_lock() {
+        let dir = tempdir().unwrap();
+        let lock = LocalLock::acquire(dir.path(), &LockSettings::default())
+            .await
+            .unwrap();
+        let err = LocalLock::acquire(dir.path(), &LockSettings::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Workspace is locked"));
+        drop(lock);
+        assert!(!lock_file(dir.path()).exists());
+
+        // Expired locks are taken over
+        let mut stale = LockInfo::new(SignedDuration::from_mins(1)).unwrap();
+        stale.expires = "2000-01-01T00:00:00Z".to_string();
+        std::fs::write(
+            lock_file(dir.path()),
+            serde_json::to_string(&stale).unwrap(),
+        )
+        .unwrap();
+        assert!(stale.is_expired());
+        let lock = LocalLock::acquire(dir.path(), &LockSettings::default())
+            .await
+            .unwrap();
+        assert_ne!(lock.info().id, stale.id);
+        assert!(!lock.info().is_expired());
+        drop(lock);
+
+        // Runs racing for an expired lock end up with a single holder
+        std::fs::write(
+            lock_file(dir.path()),
+            serde_json::to_string(&stale).unwrap(),
+        )
+        .unwrap();
+        let mut set = tokio::task::JoinSet::new();
+        for _ in 0..8 {
+            let dir = dir.path().to_path_buf();
+            set.spawn(async move { LocalLock::acquire(&dir, &LockSettings::default()).await });
+        }
+        let locks: Vec<_> = set.join_all().await.into_iter().flatten().collect();
+        assert_eq!(locks.len(), 1);
+        let held = read_lock_file(&lock_file(dir.path())).await.unwrap();
+        assert_eq!(held.unwrap().id, locks[0].info().id);
+    }
+
+    #[test]
+    fn test_lock_settings() {
+        assert_eq!(LockSettings::default().ttl().unwrap(), DEFAULT_TTL);
+        let settings = LockSettings {
+            server: true,
+            ttl: Some("1h 30m".to_string()),
+        };
+        assert_eq!(settings.ttl().unwrap(), SignedDuration::from_mins(90));
+        assert!(
+            LockSettings {
+                server: false,
+                ttl: Some("soon".to_string()),
+            }
+            .ttl()
+            .is_err()
+        );
+    }
+}
@@ -1,6 +1,7 @@
+use crate::utils::secrets::obfuscate_map;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 pub trait ToOptionString {
     fn to_option_string(&self) -> Option<&str>;
@@ -53,11 +54,28 @@ pub trait KeycloakResource {
         false
     }
     fn clear_metadata(&mut self) {}
+    /// Drops kcd-only fields that are reconciled separately from the resource itself
+    /// (and must therefore be neither sent to the server nor diffed with it).
+    fn clear_local_fields(&mut self) {}
 }
 
 pub trait ResourceMeta {
     const LABEL: &'static str;
     const SECRET_PREFIX: &'static str;
+    /// The shape of the `attributes` of the representation, if it has any kcd can use
+    /// for its own markers.
+    const ATTRIBUTES: AttributeStyle = AttributeStyle::None;
+}
+
+/// How a representation stores its `attributes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeStyle {
+    /// No attributes kcd can write to.
+    None,
+    /// `name: value` (clients, client scopes).
+    Single,
+    /// `name: [values]` (roles, groups).
+    Multi,
 }
 
 macro_rules! impl_keycloak_resource {
@@ -72,6 +90,7 @@ macro_rules! impl_keycloak_resource {
         $(, clear_metadata = |$clear_self:ident| $clear_expr:block)?
         $(, get_filename = |$filename_self:ident| $filename_expr:expr)?
         $(, object_path = |$obj_id:ident| $obj_path_expr:expr)?
+        $(, clear_local_fields = |$local_self:ident| $local_expr:block)?
     ) => {
         impl KeycloakResource for $type {
             const API_PATH: &'static str = $api_path;
@@ -94,37 +113,26 @@ macro_rules! impl_keycloak_resource {
             $(fn clear_metadata(&mut $clear_self) $clear_expr)?
             $(fn get_filename(&$filename_self) -> String { $filename_expr })?
             $(fn object_path($obj_id: &str) -> String { $obj_path_expr })?
+            $(fn clear_local_fields(&mut $local_self) $local_expr)?
         }
     };
 }
 
 macro_rules! impl_resource_meta {
-    ($type:ty, label = $label:expr, secret_prefix = $secret_prefix:expr) => {
+    (
+        $type:ty,
+        label = $label:expr,
+        secret_prefix = $secret_prefix:expr
+        $(, attributes = $attributes:expr)?
+    ) => {
         impl ResourceMeta for $type {
             const LABEL: &'static str = $label;
             const SECRET_PREFIX: &'static str = $secret_prefix;
+            $(const ATTRIBUTES: AttributeStyle = $attributes;)?
         }
     };
 }
 
-fn obfuscate_config<T>(
-    config: &Option<HashMap<String, T>>,
-    prefix: &str,
-) -> Option<HashMap<String, T>>
-where
-    T: From<&'static str> + Clone,
-{
-    let mut obfuscated_config = config.clone();
-    if let Some(cfg) = &mut obfuscated_config {
-        for (key, val) in cfg.iter_mut() {
-            if crate::utils::secrets::is_secret_key(key, prefix) {
-                *val = T::from("********");
-            }
-        }
-    }
-    obfuscated_config
-}
-
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RealmRepresentation {
     pub realm: String,
@@ -144,6 +152,12 @@ impl_keycloak_resource!(
     name = |self| self.realm.clone()
 );
 
+impl_resource_meta!(
+    RealmRepresentation,
+    label = "realm",
+    secret_prefix = "realm"
+);
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct IdentityProviderRepresentation {
     #[serde(rename = "internalId", skip_serializing_if = "Option::is_none")]
@@ -195,7 +209,8 @@ pub struct IdentityProviderRepresentation {
 
 impl std::fmt::Debug for IdentityProviderRepresentation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let obfuscated_config = obfuscate_config(&self.config, "idp");
+        let obfuscated_config = obfuscate_map(self.config.as_ref(), "idp", Some("config"));
+        let obfuscated_extra = obfuscate_map(Some(&self.extra), "idp", None);
 
         f.debug_struct("IdentityProviderRepresentation")
             .field("internal_id", &self.internal_id)
@@ -224,7 +239,7 @@ impl std::fmt::Debug for IdentityProviderRepresentation {
             )
             .field("display_name", &self.display_name)
             .field("config", &obfuscated_config)
-            .field("extra", &self.extra)
+            .field("extra", &obfuscated_extra)
             .finish()
     }
 }
@@ -275,10 +290,41 @@ pub struct ClientRepresentation {
         skip_serializing_if = "Option::is_none"
     )]
     pub service_accounts_enabled: Option<bool>,
+    /// Role and group assignments of the client's service account user (kcd-only).
+    #[serde(rename = "serviceAccount", skip_serializing_if = "Option::is_none")]
+    pub service_account: Option<ServiceAccountRepresentation>,
     #[serde(flatten)]
     pub extra: HashMap<String, Value>,
 }
 
+/// Roles and groups of a client's `service-account-<clientId>` user. Each list is only
+/// reconciled when present; realm roles are names, client roles are keyed by clientId and
+/// groups are paths.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ServiceAccountRepresentation {
+    #[serde(rename = "realmRoles", skip_serializing_if = "Option::is_none")]
+    pub realm_roles: Option<Vec<String>>,
+    #[serde(rename = "clientRoles", skip_serializing_if = "Option::is_none")]
+    pub client_roles: Option<BTreeMap<String, Vec<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<String>>,
+}
+
+impl ServiceAccountRepresentation {
+    /// Sorts every list so that comparisons are order-independent.
+    pub fn normalize(&mut self) {
+        if let Some(roles) = &mut self.realm_roles {
+            roles.sort();
+        }
+        for roles in self.client_roles.iter_mut().flat_map(|m| m.values_mut()) {
+            roles.sort();
+        }
+        if let Some(groups) = &mut self.groups {
+            groups.sort();
+        }
+    }
+}
+
 impl_keycloak_resource!(
     ClientRepresentation,
     api_path = "clients",
@@ -292,13 +338,17 @@ impl_keycloak_resource!(
     has_id = |self| self.id.is_some(),
     clear_metadata = |self| {
         self.id = None;
+    },
+    clear_local_fields = |self| {
+        self.service_account = None;
     }
 );
 
 impl_resource_meta!(
     ClientRepresentation,
     label = "clients",
-    secret_prefix = "client"
+    secret_prefix = "client",
+    attributes = AttributeStyle::Single
 );
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -332,7 +382,12 @@ impl_keycloak_resource!(
     object_path = |id| format!("roles-by-id/{}", id)
 );
 
-impl_resource_meta!(RoleRepresentation, label = "roles", secret_prefix = "role");
+impl_resource_meta!(
+    RoleRepresentation,
+    label = "roles",
+    secret_prefix = "role",
+    attributes = AttributeStyle::Multi
+);
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ClientScopeRepresentation {
@@ -365,7 +420,8 @@ impl_keycloak_resource!(
 impl_resource_meta!(
     ClientScopeRepresentation,
     label = "client scopes",
-    secret_prefix = "client_scope"
+    secret_prefix = "client_scope",
+    attributes = AttributeStyle::Single
 );
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -410,7 +466,8 @@ impl_keycloak_resource!(
 impl_resource_meta!(
     GroupRepresentation,
     label = "groups",
-    secret_prefix = "group"
+    secret_prefix = "group",
+    attributes = AttributeStyle::Multi
 );
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -429,12 +486,13 @@ pub struct CredentialRepresentation {
 
 impl std::fmt::Debug for CredentialRepresentation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let obfuscated_extra = obfuscate_map(Some(&self.extra), "user", Some("credentials"));
         f.debug_struct("CredentialRepresentation")
             .field("id", &self.id)
             .field("type", &self.type_)
             .field("value", &self.value.as_ref().map(|_| "********"))
             .field("temporary", &self.temporary)
-            .field("extra", &self.extra)
+            .field("extra", &obfuscated_extra)
             .finish()
     }
 }
@@ -605,7 +663,8 @@ pub struct ComponentRepresentation {
 
 impl std::fmt::Debug for ComponentRepresentation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let obfuscated_config = obfuscate_config(&self.config, "component");
+        let obfuscated_config = obfuscate_map(self.config.as_ref(), "component", Some("config"));
+        let obfuscated_extra = obfuscate_map(Some(&self.extra), "component", None);
 
         f.debug_struct("ComponentRepresentation")
             .field("id", &self.id)
@@ -615,7 +674,7 @@ impl std::fmt::Debug for ComponentRepresentation {
             .field("parent_id", &self.parent_id)
             .field("sub_type", &self.sub_type)
             .field("config", &obfuscated_config)
-            .field("extra", &self.extra)
+            .field("extra", &obfuscated_extra)
             .finish()
     }
 }
@@ -643,6 +702,434 @@ impl_resource_meta!(
     secret_prefix = "component"
 );
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ResourceServerRepresentation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "clientId", skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(
+        rename = "allowRemoteResourceManagement",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub allow_remote_resource_management: Option<bool>,
+    #[serde(
+        rename = "policyEnforcementMode",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub policy_enforcement_mode: Option<String>,
+    #[serde(rename = "decisionStrategy", skip_serializing_if = "Option::is_none")]
+    pub decision_strategy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<Vec<AuthzResourceRepresentation>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<AuthzScopeRepresentation>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policies: Option<Vec<PolicyRepresentation>>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl ResourceServerRepresentation {
+    /// Strips the nested resources/scopes/policies and server-generated
+    /// identifiers, leaving only the settings managed in `resource-server.yaml`.
+    pub fn settings_only(&self) -> Self {
+        let mut settings = self.clone();
+        settings.id = None;
+        settings.client_id = None;
+        settings.resources = None;
+        settings.scopes = None;
+        settings.policies = None;
+        settings.extra.remove("authorizationSchema");
+        settings
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuthzScopeRepresentation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(rename = "displayName", skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(rename = "iconUri", skip_serializing_if = "Option::is_none")]
+    pub icon_uri: Option<String>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl_keycloak_resource!(
+    AuthzScopeRepresentation,
+    api_path = "authz/resource-server/scope",
+    dir_name = "scopes",
+    id_field = id,
+    identity = |self| self.name.clone(),
+    name = |self| self.name.clone().unwrap_or_else(|| "unknown".to_string()),
+    has_id = |self| self.id.is_some(),
+    clear_metadata = |self| {
+        self.id = None;
+    }
+);
+
+impl_resource_meta!(
+    AuthzScopeRepresentation,
+    label = "authorization scopes",
+    secret_prefix = "authz_scope"
+);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuthzResourceRepresentation {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(rename = "displayName", skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uris: Option<Vec<String>>,
+    #[serde(rename = "ownerManagedAccess", skip_serializing_if = "Option::is_none")]
+    pub owner_managed_access: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<AuthzScopeRepresentation>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attributes: Option<HashMap<String, Vec<String>>>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl_keycloak_resource!(
+    AuthzResourceRepresentation,
+    api_path = "authz/resource-server/resource",
+    dir_name = "resources",
+    id_field = id,
+    identity = |self| self.name.clone(),
+    name = |self| self.name.clone().unwrap_or_else(|| "unknown".to_string()),
+    has_id = |self| self.id.is_some(),
+    clear_metadata = |self| {
+        self.id = None;
+        if let Some(scopes) = &mut self.scopes {
+            for scope in scopes {
+                scope.id = None;
+            }
+        }
+    }
+);
+
+impl_resource_meta!(
+    AuthzResourceRepresentation,
+    label = "authorization resources",
+    secret_prefix = "authz_resource"
+);
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RolePolicyDefinition {
+    /// Role name in the workspace (`client-id/role` for client roles), role id on the server.
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GroupPolicyDefinition {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(rename = "extendChildren", skip_serializing_if = "Option::is_none")]
+    pub extend_children: Option<bool>,
+}
+
+/// Authorization policy or permission (permissions are policies of type `resource` or `scope`).
+///
+/// Cross-references (`resources`, `scopes`, `policies`, `roles`, `groups`, `clients`)
+/// are kept by name in the workspace and resolved to ids when applied.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PolicyRepresentation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logic: Option<String>,
+    #[serde(rename = "decisionStrategy", skip_serializing_if = "Option::is_none")]
+    pub decision_strategy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policies: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<RolePolicyDefinition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<GroupPolicyDefinition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clients: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<HashMap<String, String>>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// Mapping of exported time policy config keys to their typed representation fields.
+const TIME_POLICY_FIELDS: &[(&str, &str)] = &[
+    ("nbf", "notBefore"),
+    ("noa", "notOnOrAfter"),
+    ("dayMonth", "dayMonth"),
+    ("dayMonthEnd", "dayMonthEnd"),
+    ("month", "month"),
+    ("monthEnd", "monthEnd"),
+    ("year", "year"),
+    ("yearEnd", "yearEnd"),
+    ("hour", "hour"),
+    ("hourEnd", "hourEnd"),
+    ("minute", "minute"),
+    ("minuteEnd", "minuteEnd"),
+];
+
+impl PolicyRepresentation {
+    /// Returns true for permissions (`resource` and `scope` typed policies).
+    pub fn is_permission(&self) -> bool {
+        matches!(self.type_.as_deref(), Some("resource") | Some("scope"))
+    }
+
+    /// Returns true for aggregated policies, which must be applied after the policies they reference.
+    pub fn is_aggregate(&self) -> bool {
+        self.type_.as_deref() == Some("aggregate")
+    }
+
+    /// Converts a policy from the resource server export format, where cross-references
+    /// are JSON-encoded name lists inside `config`, into the typed name-based form.
+    pub fn from_export(mut self) -> Self {
+        let Some(mut config) = self.config.take() else {
+            return self;
+        };
+
+        fn take_json<T: serde::de::DeserializeOwned>(
+            config: &mut HashMap<String, String>,
+            key: &str,
+        ) -> Option<T> {
+            config
+                .remove(key)
+                .and_then(|raw| serde_json::from_str(&raw).ok())
+        }
+
+        self.resources = take_json(&mut config, "resources").or(self.resources);
+        self.scopes = take_json(&mut config, "scopes").or(self.scopes);
+        self.policies = take_json(&mut config, "applyPolicies").or(self.policies);
+        self.roles = take_json(&mut config, "roles").or(self.roles);
+        self.groups = take_json(&mut config, "groups").or(self.groups);
+        self.clients = take_json(&mut config, "clients").or(self.clients);
+
+        if let Some(claim) = config.remove("groupsClaim") {
+            self.extra
+                .insert("groupsClaim".to_string(), Value::String(claim));
+        }
+        if let Some(resource_type) = config.remove("defaultResourceType") {
+            self.extra
+                .insert("resourceType".to_string(), Value::String(resource_type));
+        }
+        if self.type_.as_deref() == Some("time") {
+            for (config_key, field) in TIME_POLICY_FIELDS {
+                if let Some(v) = config.remove(*config_key) {
+                    self.extra.insert(field.to_string(), Value::String(v));
+                }
+            }
+        }
+
+        if !config.is_empty() {
+            self.config = Some(config);
+        }
+        self
+    }
+}
+
+impl_keycloak_resource!(
+    PolicyRepresentation,
+    api_path = "authz/resource-server/policy",
+    dir_name = "policies",
+    id_field = id,
+    identity = |self| self.name.clone(),
+    name = |self| self.name.clone().unwrap_or_else(|| "unknown".to_string()),
+    has_id = |self| self.id.is_some(),
+    clear_metadata = |self| {
+        self.id = None;
+    }
+);
+
+impl_resource_meta!(
+    PolicyRepresentation,
+    label = "authorization policies",
+    secret_prefix = "authz_policy"
+);
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OrganizationDomainRepresentation {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verified: Option<bool>,
+}
+
+/// An organization (Keycloak 25+).
+///
+/// `members` (usernames) and `identityProviders` (aliases) are managed by kcd through the
+/// dedicated sub-resources; when omitted they are left untouched on the server.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrganizationRepresentation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "redirectUrl", skip_serializing_if = "Option::is_none")]
+    pub redirect_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domains: Option<Vec<OrganizationDomainRepresentation>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attributes: Option<HashMap<String, Vec<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub members: Option<Vec<String>>,
+    #[serde(rename = "identityProviders", skip_serializing_if = "Option::is_none")]
+    pub identity_providers: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl OrganizationRepresentation {
+    /// The representation sent to the organizations endpoint, without the
+    /// kcd-managed membership and identity provider links.
+    pub fn for_api(&self) -> Self {
+        let mut rep = self.clone();
+        rep.members = None;
+        rep.identity_providers = None;
+        rep
+    }
+
+    /// Sorts domains, members and identity providers so that diffs are order-independent.
+    pub fn normalize(&mut self) {
+        if let Some(domains) = &mut self.domains {
+            domains.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        if let Some(members) = &mut self.members {
+            members.sort();
+        }
+        if let Some(idps) = &mut self.identity_providers {
+            idps.sort();
+        }
+    }
+}
+
+impl_keycloak_resource!(
+    OrganizationRepresentation,
+    api_path = "organizations",
+    dir_name = "organizations",
+    id_field = id,
+    identity = |self| self.name.clone(),
+    name = |self| self.name.clone().unwrap_or_else(|| "unknown".to_string()),
+    has_id = |self| self.id.is_some(),
+    clear_metadata = |self| {
+        self.id = None;
+    }
+);
+
+impl_resource_meta!(
+    OrganizationRepresentation,
+    label = "organizations",
+    secret_prefix = "organization"
+);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClientProfileExecutorRepresentation {
+    pub executor: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub configuration: Option<Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClientProfileRepresentation {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub executors: Option<Vec<ClientProfileExecutorRepresentation>>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The document behind `client-policies/profiles`. Global (built-in) profiles are
+/// read-only and never written back.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ClientProfilesRepresentation {
+    #[serde(default)]
+    pub profiles: Vec<ClientProfileRepresentation>,
+    #[serde(rename = "globalProfiles", skip_serializing)]
+    pub global_profiles: Option<Vec<ClientProfileRepresentation>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClientPolicyConditionRepresentation {
+    pub condition: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub configuration: Option<Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClientPolicyRepresentation {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conditions: Option<Vec<ClientPolicyConditionRepresentation>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profiles: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The document behind `client-policies/policies`. Global policies are read-only.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ClientPoliciesRepresentation {
+    #[serde(default)]
+    pub policies: Vec<ClientPolicyRepresentation>,
+    #[serde(rename = "globalPolicies", skip_serializing)]
+    pub global_policies: Option<Vec<ClientPolicyRepresentation>>,
+}
+
+/// Role mappings of a user (`users/{id}/role-mappings`).
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MappingsRepresentation {
+    #[serde(rename = "realmMappings", skip_serializing_if = "Option::is_none")]
+    pub realm_mappings: Option<Vec<RoleRepresentation>>,
+    #[serde(rename = "clientMappings", skip_serializing_if = "Option::is_none")]
+    pub client_mappings: Option<HashMap<String, ClientMappingsRepresentation>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClientMappingsRepresentation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    #[serde(default)]
+    pub mappings: Vec<RoleRepresentation>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct KeyMetadataRepresentation {
     #[serde(rename = "providerId")]
@@ -753,6 +1240,7 @@ mod tests {
             public_client: Some(true),
             bearer_only: None,
             service_accounts_enabled: None,
+            service_account: None,
             extra: HashMap::new(),
         };
 
@@ -908,4 +1396,77 @@ mod tests {
         os2.set_from_option_string(None);
         assert_eq!(os2, Some("new".to_string()));
     }
+    #[test]
+    fn test_policy_from_export() {
+        let exported: PolicyRepresentation = serde_json::from_value(serde_json::json!({
+            "name": "office-hours",
+            "type": "time",
+            "config": {
+                "roles": "[{\"id\":\"admin\",\"required\":true}]",
+                "applyPolicies": "[\"p1\",\"p2\"]",
+                "resources": "[\"orders\"]",
+                "nbf": "2026-01-01 00:00:00"
+            }
+        }))
+        .unwrap();
+
+        let policy = exported.from_export();
+        let roles = policy.roles.clone().unwrap();
+        assert_eq!(roles[0].id, "admin");
+        assert_eq!(roles[0].required, Some(true));
+        assert_eq!(
+            policy.policies,
+            Some(vec!["p1".to_string(), "p2".to_string()])
+        );
+        assert_eq!(policy.resources, Some(vec!["orders".to_string()]));
+        assert_eq!(policy.extra["notBefore"], "2026-01-01 00:00:00");
+        assert!(policy.config.is_none());
+    }
+
+    #[test]
+    fn test_resource_server_settings_only() {
+        let server: ResourceServerRepresentation = serde_json::from_value(serde_json::json!({
+            "id": "id",
+            "clientId": "uuid",
+            "name": "my-app",
+            "policyEnforcementMode": "ENFORCING",
+            "scopes": [{"name": "view"}],
+            "resources": [],
+            "policies": [],
+            "authorizationSchema": {}
+        }))
+        .unwrap();
+
+        let settings = server.settings_only();
+        assert!(settings.id.is_none());
+        assert!(settings.client_id.is_none());
+        assert!(settings.scopes.is_none());
+        assert!(settings.resources.is_none());
+        assert!(settings.policies.is_none());
+        assert!(settings.extra.is_empty());
+        assert_eq!(settings.name, Some("my-app".to_string()));
+        assert_eq!(
+            settings.policy_enforcement_mode,
+            Some("ENFORCING".to_string())
+        );
+    }
+
+    #[test]
+    fn test_client_service_account_is_local_only() {
+        let mut client: ClientRepresentation = serde_json::from_value(serde_json::json!({
+            "clientId": "worker",
+            "serviceAccount": {"realmRoles": ["b", "a"], "groups": ["/g"]}
+        }))
+        .unwrap();
+
+        let mut sa = client.service_account.clone().unwrap();
+        sa.normalize();
+        assert_eq!(sa.realm_roles, Some(vec!["a".to_string(), "b".to_string()]));
+        assert!(sa.client_roles.is_none());
+
+        client.clear_local_fields();
+        let json_val = serde_json::to_value(&client).unwrap();
+        assert!(json_val.get("serviceAccount").is_none());
+        assert_eq!(json_val["clientId"], "worker");
+    }
 }
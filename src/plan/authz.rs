@@ -0,0 +1,233 @@
+use crate::inspect::strip_resource_server_owner;
+use crate::models::{
+    AuthzResourceRepresentation, AuthzScopeRepresentation, KeycloakResource, PolicyRepresentation,
+    ResourceMeta, ResourceServerRepresentation,
+};
+use crate::utils::secrets::{SecretResolver, substitute_secrets};
+use crate::utils::ui::SPARKLE;
+use crate::utils::yaml::load_yaml_with_overlay;
+use crate::workspace::WorkspaceContext;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs as async_fs;
+
+use super::{PlanContext, PlanSummary, load_resource_files, record_change};
+
+pub const AUTHZ_DIR: &str = "authz";
+pub const RESOURCE_SERVER_FILE: &str = "resource-server.yaml";
+pub const PERMISSIONS_DIR: &str = "permissions";
+
+/// Loads `resource-server.yaml` from an `authz/<clientId>/` directory, if present.
+pub async fn load_resource_server(
+    client_dir: &Path,
+    ws: &WorkspaceContext,
+    resolver: Arc<dyn SecretResolver>,
+) -> Result<Option<(PathBuf, ResourceServerRepresentation)>> {
+    let path = client_dir.join(RESOURCE_SERVER_FILE);
+    if !async_fs::try_exists(&path).await? || !ws.filter.is_managed(&path) {
+        return Ok(None);
+    }
+    let mut val = load_yaml_with_overlay(&path, ws).await?;
+    substitute_secrets(&mut val, resolver).await?;
+    let settings: ResourceServerRepresentation = serde_json::from_value(val)
+        .with_context(|| format!("Failed to deserialize YAML file {:?}", path))?;
+    Ok(Some((path, settings)))
+}
+
+/// The clientId owning an `authz/<clientId>/` directory: the `name` declared in
+/// `resource-server.yaml`, falling back to the directory name.
+pub fn authz_client_id(
+    client_dir: &Path,
+    settings: Option<&ResourceServerRepresentation>,
+) -> Option<String> {
+    settings.and_then(|s| s.name.clone()).or_else(|| {
+        client_dir
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(|s| s.to_string())
+    })
+}
+
+/// Lists the `authz/<clientId>/` directories of a realm workspace.
+pub async fn authz_client_dirs(
+    workspace_dir: &Path,
+    ws: &WorkspaceContext,
+) -> Result<Vec<PathBuf>> {
+    let authz_dir = workspace_dir.join(AUTHZ_DIR);
+    let mut dirs = Vec::new();
+    if !async_fs::try_exists(&authz_dir).await? {
+        return Ok(dirs);
+    }
+    let mut entries = async_fs::read_dir(&authz_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() && ws.filter.is_managed(&entry.path()) {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+pub async fn plan_authz(ctx: &PlanContext<'_>) -> Result<(Vec<PathBuf>, PlanSummary)> {
+    let mut changed_files = Vec::new();
+    let mut summary = PlanSummary::default();
+
+    if !ctx.selection.includes_type(AUTHZ_DIR) {
+        return Ok((changed_files, summary));
+    }
+    let client_dirs = authz_client_dirs(ctx.workspace_dir, &ctx.ws).await?;
+    if client_dirs.is_empty() {
+        return Ok((changed_files, summary));
+    }
+
+    let clients = ctx
+        .client
+        .get_clients()
+        .await
+        .with_context(|| format!("Failed to get clients for realm '{}'", ctx.realm_name))?;
+    let uuids: HashMap<String, String> = clients
+        .into_iter()
+        .filter_map(|c| c.client_id.zip(c.id))
+        .collect();
+
+    for client_dir in client_dirs {
+        let settings =
+            load_resource_server(&client_dir, &ctx.ws, Arc::clone(&ctx.resolver)).await?;
+        let client_id = authz_client_id(&client_dir, settings.as_ref().map(|(_, s)| s))
+            .context("Failed to determine client of authorization directory")?;
+
+        let remote = match uuids.get(&client_id) {
+            Some(uuid) => Some(ctx.client.export_resource_server(uuid).await.with_context(
+                || {
+                    format!(
+                        "Failed to get authorization settings of client '{}' in realm '{}'",
+                        client_id, ctx.realm_name
+                    )
+                },
+            )?),
+            None => None,
+        };
+
+        if let Some((path, local)) = settings.filter(|(path, _)| ctx.is_selected(path)) {
+            let remote_settings = remote.as_ref().map(|r| {
+                let mut s = r.settings_only();
+                s.name = local.name.clone();
+                s
+            });
+            let changed = ctx.diff(
+                &path,
+                &format!("authorization settings of client {}", client_id),
+                remote_settings.as_ref(),
+                &local.settings_only(),
+                "authz",
+            )?;
+            record_change(
+                ctx,
+                path,
+                remote_settings.is_some(),
+                changed,
+                &mut changed_files,
+                &mut summary,
+            )?;
+        }
+
+        let remote_scopes = remote
+            .as_ref()
+            .and_then(|r| r.scopes.clone())
+            .unwrap_or_default();
+        plan_authz_items::<AuthzScopeRepresentation>(
+            ctx,
+            &client_dir.join(AuthzScopeRepresentation::DIR_NAME),
+            &client_id,
+            remote_scopes,
+            &mut changed_files,
+            &mut summary,
+        )
+        .await?;
+
+        let remote_resources = remote
+            .as_ref()
+            .and_then(|r| r.resources.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|mut r| {
+                strip_resource_server_owner(&mut r, &client_id);
+                r
+            })
+            .collect();
+        plan_authz_items::<AuthzResourceRepresentation>(
+            ctx,
+            &client_dir.join(AuthzResourceRepresentation::DIR_NAME),
+            &client_id,
+            remote_resources,
+            &mut changed_files,
+            &mut summary,
+        )
+        .await?;
+
+        let (remote_permissions, remote_policies): (Vec<_>, Vec<_>) = remote
+            .as_ref()
+            .and_then(|r| r.policies.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .map(PolicyRepresentation::from_export)
+            .partition(|p| p.is_permission());
+        plan_authz_items::<PolicyRepresentation>(
+            ctx,
+            &client_dir.join(PolicyRepresentation::DIR_NAME),
+            &client_id,
+            remote_policies,
+            &mut changed_files,
+            &mut summary,
+        )
+        .await?;
+        plan_authz_items::<PolicyRepresentation>(
+            ctx,
+            &client_dir.join(PERMISSIONS_DIR),
+            &client_id,
+            remote_permissions,
+            &mut changed_files,
+            &mut summary,
+        )
+        .await?;
+    }
+
+    Ok((changed_files, summary))
+}
+
+async fn plan_authz_items<T>(
+    ctx: &PlanContext<'_>,
+    dir: &Path,
+    client_id: &str,
+    remote: Vec<T>,
+    changed_files: &mut Vec<PathBuf>,
+    summary: &mut PlanSummary,
+) -> Result<()>
+where
+    T: KeycloakResource + ResourceMeta + serde::Serialize + for<'de> serde::Deserialize<'de>,
+{
+    let remote_map: HashMap<String, T> = remote
+        .into_iter()
+        .filter_map(|r| r.get_identity().map(|id| (id, r)))
+        .collect();
+
+    let items = load_resource_files::<T>(dir, &ctx.ws, Arc::clone(&ctx.resolver))
+        .await
+        .with_context(|| format!("Failed to load {} in realm '{}'", T::LABEL, ctx.realm_name))?;
+
+    for (path, local) in items.into_iter().filter(|(path, _)| ctx.is_selected(path)) {
+        let identity = local
+            .get_identity()
+            .with_context(|| format!("Failed to get identity for {} in {:?}", T::LABEL, path))?;
+        let name = format!("{} {} of client {}", T::LABEL, local.get_name(), client_id);
+        let remote = remote_map.get(&identity);
+        if remote.is_none() {
+            println!("\n{} Will create {}", SPARKLE, T::LABEL);
+        }
+        let changed = ctx.diff(&path, &name, remote, &local, T::SECRET_PREFIX)?;
+        record_change(ctx, path, remote.is_some(), changed, changed_files, summary)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use crate::models::{ClientPoliciesRepresentation, ClientProfilesRepresentation};
+use crate::utils::secrets::{SecretResolver, substitute_secrets};
+use crate::utils::ui::{SPARKLE, WARN};
+use crate::utils::yaml::load_yaml_with_overlay;
+use crate::workspace::WorkspaceContext;
+use anyhow::{Context, Result};
+use console::style;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs as async_fs;
+
+use super::{PlanAction, PlanContext, PlanSummary};
+
+pub const CLIENT_POLICIES_DIR: &str = "client-policies";
+pub const PROFILES_FILE: &str = "profiles.yaml";
+pub const POLICIES_FILE: &str = "policies.yaml";
+pub const SECRET_PREFIX: &str = "client_policy";
+
+/// Loads a client policies/profiles document (with overlays and secrets), if present.
+pub async fn load_document<T>(
+    path: &Path,
+    ws: &WorkspaceContext,
+    resolver: Arc<dyn SecretResolver>,
+) -> Result<Option<T>>
+where
+    T: for<'de> serde::Deserialize<'de>,
+{
+    if !async_fs::try_exists(path).await? || !ws.filter.is_managed(path) {
+        return Ok(None);
+    }
+    let mut val = load_yaml_with_overlay(path, ws).await?;
+    substitute_secrets(&mut val, resolver).await?;
+    let doc = serde_json::from_value(val)
+        .with_context(|| format!("Failed to deserialize YAML file {:?}", path))?;
+    Ok(Some(doc))
+}
+
+pub async fn plan_client_policies(ctx: &PlanContext<'_>) -> Result<(Vec<PathBuf>, PlanSummary)> {
+    let mut changed_files = Vec::new();
+    let mut summary = PlanSummary::default();
+    let dir = ctx.workspace_dir.join(CLIENT_POLICIES_DIR);
+    if !ctx.selection.includes_type(CLIENT_POLICIES_DIR) {
+        return Ok((changed_files, summary));
+    }
+
+    let profiles_path = dir.join(PROFILES_FILE);
+    if ctx.is_selected(&profiles_path)
+        && let Some(local) = load_document::<ClientProfilesRepresentation>(
+            &profiles_path,
+            &ctx.ws,
+            Arc::clone(&ctx.resolver),
+        )
+        .await?
+    {
+        let remote = ctx
+            .client
+            .get_client_profiles()
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to get client profiles for realm '{}'",
+                    ctx.realm_name
+                )
+            })?
+            .with_context(|| {
+                format!(
+                    "Client policies are not supported by the server of realm '{}'",
+                    ctx.realm_name
+                )
+            })?;
+        let doc_summary = diff_document(
+            ctx,
+            &profiles_path,
+            "client profile",
+            &local.profiles,
+            &remote.profiles,
+            |p| &p.name,
+        )?;
+        record_document(
+            ctx,
+            profiles_path,
+            doc_summary,
+            &mut changed_files,
+            &mut summary,
+        )?;
+    }
+
+    let policies_path = dir.join(POLICIES_FILE);
+    if ctx.is_selected(&policies_path)
+        && let Some(local) = load_document::<ClientPoliciesRepresentation>(
+            &policies_path,
+            &ctx.ws,
+            Arc::clone(&ctx.resolver),
+        )
+        .await?
+    {
+        let remote = ctx
+            .client
+            .get_client_policies()
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to get client policies for realm '{}'",
+                    ctx.realm_name
+                )
+            })?
+            .with_context(|| {
+                format!(
+                    "Client policies are not supported by the server of realm '{}'",
+                    ctx.realm_name
+                )
+            })?;
+        let doc_summary = diff_document(
+            ctx,
+            &policies_path,
+            "client policy",
+            &local.policies,
+            &remote.policies,
+            |p| &p.name,
+        )?;
+        record_document(
+            ctx,
+            policies_path,
+            doc_summary,
+            &mut changed_files,
+            &mut summary,
+        )?;
+    }
+
+    Ok((changed_files, summary))
+}
+
+/// Diffs the entries of a whole document by name. Entries only present remotely are
+/// reported as removals, since applying the document replaces it entirely.
+fn diff_document<T: Serialize>(
+    ctx: &PlanContext<'_>,
+    path: &Path,
+    label: &str,
+    local: &[T],
+    remote: &[T],
+    name: impl Fn(&T) -> &String,
+) -> Result<PlanSummary> {
+    let mut summary = PlanSummary::default();
+    let remote_map: HashMap<&String, &T> = remote.iter().map(|r| (name(r), r)).collect();
+
+    for item in local {
+        let item_name = name(item);
+        let remote_item = remote_map.get(item_name).copied();
+        if remote_item.is_none() {
+            println!("\n{} Will create {}", SPARKLE, label);
+        }
+        let changed = ctx.diff(
+            path,
+            &format!("{} {}", label, item_name),
+            remote_item,
+            item,
+            SECRET_PREFIX,
+        )?;
+        if changed {
+            if remote_item.is_some() {
+                summary.updated += 1;
+            } else {
+                summary.created += 1;
+            }
+        }
+    }
+
+    for remote_item in remote {
+        let item_name = name(remote_item);
+        if !local.iter().any(|l| name(l) == item_name) {
+            println!(
+                "\n{} {}",
+                WARN,
+                style(format!("Will remove {} {}", label, item_name)).yellow()
+            );
+            let name = format!("{} {}", label, item_name);
+            ctx.record(path, &name, PlanAction::Delete, Vec::new());
+            summary.updated += 1;
+        }
+    }
+    Ok(summary)
+}
+
+fn record_document(
+    ctx: &PlanContext<'_>,
+    path: PathBuf,
+    doc_summary: PlanSummary,
+    changed_files: &mut Vec<PathBuf>,
+    summary: &mut PlanSummary,
+) -> Result<()> {
+    if doc_summary.total() == 0 {
+        return Ok(());
+    }
+    let mut include = true;
+    if ctx.options.interactive {
+        include = ctx.ui.confirm("Include this change in the plan?", true)?;
+    }
+    if include {
+        changed_files.push(path);
+        summary.add(&doc_summary);
+    }
+    Ok(())
+}
@@ -11,7 +11,7 @@ use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::fs as async_fs;
 
-use super::{PlanContext, PlanOptions, PlanSummary, print_diff};
+use super::{PlanContext, PlanOptions, PlanSummary};
 
 pub async fn plan_components_or_keys(
     ctx: &PlanContext<'_>,
@@ -20,7 +20,7 @@ pub async fn plan_components_or_keys(
     let mut changed_files = Vec::new();
     let mut summary = PlanSummary::default();
     let components_dir = ctx.workspace_dir.join(dir_name);
-    if async_fs::try_exists(&components_dir).await? {
+    if ctx.selection.includes_type(dir_name) && async_fs::try_exists(&components_dir).await? {
         let existing_components =
             ctx.client.get_components().await.with_context(|| {
                 format!("Failed to get components for realm '{}'", ctx.realm_name)
@@ -56,8 +56,11 @@ pub async fn plan_components_or_keys(
         while let Some(entry) = entries.next_entry().await? {
             let path = entry.path();
             if path.extension().is_some_and(|ext| ext == "yaml") {
-                // Skip overlay files themselves
-                if is_overlay_file(&path, ctx.profile.as_deref()) {
+                // Skip overlay files themselves, files kcd.yaml excludes and unselected ones
+                if is_overlay_file(&path, &ctx.ws)
+                    || !ctx.ws.filter.is_managed(&path)
+                    || !ctx.is_selected(&path)
+                {
                     continue;
                 }
 
@@ -65,10 +68,10 @@ pub async fn plan_components_or_keys(
                 let by_identity = by_identity.clone();
                 let by_details = by_details.clone();
                 let realm_name = ctx.realm_name.to_string();
-                let profile = ctx.profile.clone();
+                let ws = Arc::clone(&ctx.ws);
 
                 set.spawn(async move {
-                    let mut val = load_yaml_with_overlay(&path, profile.as_deref()).await?;
+                    let mut val = load_yaml_with_overlay(&path, &ws).await?;
                     substitute_secrets(&mut val, resolver).await?;
                     let local_component: ComponentRepresentation = serde_json::from_value(val)
                         .with_context(|| {
@@ -118,11 +121,11 @@ pub async fn plan_components_or_keys(
                 } else {
                     "component"
                 };
-                print_diff(
+                ctx.diff(
+                    &path,
                     &format!("Component {}", local_component.get_name()),
                     Some(&remote_clone),
                     &local_component,
-                    ctx.options.changes_only,
                     prefix,
                 )?
             } else {
@@ -136,11 +139,11 @@ pub async fn plan_components_or_keys(
                 } else {
                     "component"
                 };
-                print_diff(
+                ctx.diff(
+                    &path,
                     &format!("Component {}", local_component.get_name()),
                     None::<&ComponentRepresentation>,
                     &local_component,
-                    ctx.options.changes_only,
                     prefix,
                 )?
             };
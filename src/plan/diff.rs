@@ -0,0 +1,396 @@
+use console::{Style, style};
+use serde::Serialize;
+use serde_json::Value;
+
+/// One change between two JSON documents. Paths are JSON pointers (RFC 6901); `remove`
+/// points into the old document, every other operation into the new one.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum DiffOp {
+    Add {
+        path: String,
+        value: Value,
+    },
+    Remove {
+        path: String,
+        old: Value,
+    },
+    Replace {
+        path: String,
+        old: Value,
+        value: Value,
+    },
+    /// A list item that kept its content (or identity) but changed position.
+    Move {
+        from: String,
+        path: String,
+    },
+}
+
+/// Keys identifying an object inside a list, tried in order.
+const IDENTITY_KEYS: &[&str] = &["clientId", "alias", "name", "id"];
+
+/// The operations turning `old` into `new`. Map keys are compared by name; list items
+/// are matched by value first and then by identity (`clientId`, `alias`, `name`, `id`),
+/// so reordered or edited items show up as moves and field changes rather than as
+/// removals followed by additions.
+pub fn diff_values(old: &Value, new: &Value) -> Vec<DiffOp> {
+    let mut ops = Vec::new();
+    diff_at(old, new, "", &mut ops);
+    ops
+}
+
+fn diff_at(old: &Value, new: &Value, path: &str, ops: &mut Vec<DiffOp>) {
+    match (old, new) {
+        _ if old == new => {}
+        (Value::Object(old), Value::Object(new)) => {
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let child = format!("{}/{}", path, escape(key));
+                match (old.get(key), new.get(key)) {
+                    (Some(o), Some(n)) => diff_at(o, n, &child, ops),
+                    (Some(o), None) => ops.push(DiffOp::Remove {
+                        path: child,
+                        old: o.clone(),
+                    }),
+                    (None, Some(n)) => ops.push(DiffOp::Add {
+                        path: child,
+                        value: n.clone(),
+                    }),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => diff_arrays(old, new, path, ops),
+        _ => ops.push(DiffOp::Replace {
+            path: path.to_string(),
+            old: old.clone(),
+            value: new.clone(),
+        }),
+    }
+}
+
+fn identity(value: &Value) -> Option<(&str, &Value)> {
+    let map = value.as_object()?;
+    IDENTITY_KEYS
+        .iter()
+        .find_map(|key| map.get(*key).map(|v| (*key, v)))
+}
+
+fn diff_arrays(old: &[Value], new: &[Value], path: &str, ops: &mut Vec<DiffOp>) {
+    let mut used = vec![false; old.len()];
+    let mut matched: Vec<Option<usize>> = vec![None; new.len()];
+    for (j, item) in new.iter().enumerate() {
+        if let Some(i) = (0..old.len()).find(|&i| !used[i] && old[i] == *item) {
+            used[i] = true;
+            matched[j] = Some(i);
+        }
+    }
+    for (j, item) in new.iter().enumerate() {
+        let Some(id) = identity(item).filter(|_| matched[j].is_none()) else {
+            continue;
+        };
+        if let Some(i) = (0..old.len()).find(|&i| !used[i] && identity(&old[i]) == Some(id)) {
+            used[i] = true;
+            matched[j] = Some(i);
+        }
+    }
+
+    for (i, item) in old.iter().enumerate().filter(|(i, _)| !used[*i]) {
+        ops.push(DiffOp::Remove {
+            path: format!("{}/{}", path, i),
+            old: item.clone(),
+        });
+    }
+    let in_order = longest_increasing(&matched);
+    for (j, item) in new.iter().enumerate() {
+        let child = format!("{}/{}", path, j);
+        match matched[j] {
+            Some(i) => {
+                if !in_order.contains(&j) {
+                    ops.push(DiffOp::Move {
+                        from: format!("{}/{}", path, i),
+                        path: child.clone(),
+                    });
+                }
+                diff_at(&old[i], item, &child, ops);
+            }
+            None => ops.push(DiffOp::Add {
+                path: child,
+                value: item.clone(),
+            }),
+        }
+    }
+}
+
+/// Positions in `matched` forming the longest run of increasing old indices: the items
+/// that stayed in order. Every other matched item counts as moved.
+fn longest_increasing(matched: &[Option<usize>]) -> Vec<usize> {
+    let positions: Vec<(usize, usize)> = matched
+        .iter()
+        .enumerate()
+        .filter_map(|(j, i)| i.map(|i| (j, i)))
+        .collect();
+    // lengths[k]/previous[k]: longest run ending at positions[k] and its predecessor.
+    let mut lengths = vec![1; positions.len()];
+    let mut previous: Vec<Option<usize>> = vec![None; positions.len()];
+    for k in 0..positions.len() {
+        for p in 0..k {
+            if positions[p].1 < positions[k].1 && lengths[p] + 1 > lengths[k] {
+                lengths[k] = lengths[p] + 1;
+                previous[k] = Some(p);
+            }
+        }
+    }
+    let mut run = Vec::new();
+    let mut cursor = (0..positions.len()).rev().max_by_key(|k| lengths[*k]);
+    while let Some(k) = cursor {
+        run.push(positions[k].0);
+        cursor = previous[k];
+    }
+    run
+}
+
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// A JSON pointer as a readable field path: `/attributes/pkce.method/0` becomes
+/// `attributes."pkce.method"[0]`.
+pub fn display_path(pointer: &str) -> String {
+    let mut out = String::new();
+    for segment in pointer.split('/').skip(1) {
+        let segment = segment.replace("~1", "/").replace("~0", "~");
+        if segment.parse::<usize>().is_ok() {
+            out.push_str(&format!("[{}]", segment));
+            continue;
+        }
+        if !out.is_empty() {
+            out.push('.');
+        }
+        if segment.contains(['.', '[', ' ']) {
+            out.push_str(&format!("{:?}", segment));
+        } else {
+            out.push_str(&segment);
+        }
+    }
+    if out.is_empty() {
+        "(root)".to_string()
+    } else {
+        out
+    }
+}
+
+/// One line per operation, e.g. `~ rootUrl: "http://a" -> "https://b"`.
+pub fn render_ops(ops: &[DiffOp]) -> Vec<String> {
+    ops.iter()
+        .map(|op| match op {
+            DiffOp::Add { path, value } => Style::new()
+                .green()
+                .apply_to(format!("+ {}: {}", display_path(path), value))
+                .to_string(),
+            DiffOp::Remove { path, old } => Style::new()
+                .red()
+                .apply_to(format!("- {}: {}", display_path(path), old))
+                .to_string(),
+            DiffOp::Replace { path, old, value } => format!(
+                "{} {}: {} -> {}",
+                style("~").yellow().bold(),
+                display_path(path),
+                style(old).red(),
+                style(value).green()
+            ),
+            DiffOp::Move { from, path } => Style::new()
+                .cyan()
+                .apply_to(format!(
+                    "> {}: moved from {}",
+                    display_path(path),
+                    display_path(from)
+                ))
+                .to_string(),
+        })
+        .collect()
+}
+
+/// Where a field differing between the server and the workspace was changed, relative
+/// to what kcd last applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Only the workspace changed it; `apply` rolls it out.
+    Local,
+    /// Only the server changed it (e.g. a hotfix in the admin console); `apply` reverts it.
+    Server,
+    /// Both changed it, to different values.
+    Conflict,
+}
+
+/// Classifies the fields differing between `remote` and `local` by comparing both with
+/// `base`, the representation last applied. Lists are compared as a whole, since their
+/// indexes do not line up between the three documents. Fields set on neither side
+/// of the last apply nor locally are left out.
+pub fn three_way(base: &Value, remote: &Value, local: &Value) -> Vec<(String, Origin)> {
+    let mut fields: Vec<String> = Vec::new();
+    for op in diff_values(remote, local) {
+        let path = match op {
+            DiffOp::Add { path, .. }
+            | DiffOp::Remove { path, .. }
+            | DiffOp::Replace { path, .. }
+            | DiffOp::Move { path, .. } => path,
+        };
+        let mut field = String::new();
+        for segment in path.split('/').skip(1) {
+            if segment.parse::<usize>().is_ok() {
+                break;
+            }
+            field.push('/');
+            field.push_str(segment);
+        }
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+    }
+    fields
+        .into_iter()
+        .filter_map(|field| {
+            let base_value = base.pointer(&field);
+            let local_value = local.pointer(&field);
+            let remote_value = remote.pointer(&field);
+            if base_value.is_none() && local_value.is_none() {
+                return None;
+            }
+            let origin = match (local_value == base_value, remote_value == base_value) {
+                (true, true) => return None,
+                (true, false) => Origin::Server,
+                (false, true) => Origin::Local,
+                (false, false) => Origin::Conflict,
+            };
+            Some((field, origin))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_values() {
+        let old = json!({
+            "rootUrl": "http://a",
+            "attributes": {"pkce.method": "plain", "gone": "x"},
+            "protocolMappers": [
+                {"name": "email", "config": {"claim": "email"}},
+                {"name": "roles"},
+                {"name": "old"}
+            ]
+        });
+        let new = json!({
+            "rootUrl": "https://b",
+            "attributes": {"pkce.method": "S256", "added": "y"},
+            "protocolMappers": [
+                {"name": "roles"},
+                {"name": "email", "config": {"claim": "mail"}},
+                {"name": "new"}
+            ]
+        });
+        let ops = diff_values(&old, &new);
+        assert_eq!(
+            ops,
+            vec![
+                DiffOp::Add {
+                    path: "/attributes/added".into(),
+                    value: json!("y")
+                },
+                DiffOp::Remove {
+                    path: "/attributes/gone".into(),
+                    old: json!("x")
+                },
+                DiffOp::Replace {
+                    path: "/attributes/pkce.method".into(),
+                    old: json!("plain"),
+                    value: json!("S256")
+                },
+                DiffOp::Remove {
+                    path: "/protocolMappers/2".into(),
+                    old: json!({"name": "old"})
+                },
+                DiffOp::Move {
+                    from: "/protocolMappers/0".into(),
+                    path: "/protocolMappers/1".into()
+                },
+                DiffOp::Replace {
+                    path: "/protocolMappers/1/config/claim".into(),
+                    old: json!("email"),
+                    value: json!("mail")
+                },
+                DiffOp::Add {
+                    path: "/protocolMappers/2".into(),
+                    value: json!({"name": "new"})
+                },
+                DiffOp::Replace {
+                    path: "/rootUrl".into(),
+                    old: json!("http://a"),
+                    value: json!("https://b")
+                },
+            ]
+        );
+        assert!(diff_values(&old, &old).is_empty());
+
+        let json = serde_json::to_value(&ops[4]).unwrap();
+        assert_eq!(
+            json,
+            json!({"op": "move", "from": "/protocolMappers/0", "path": "/protocolMappers/1"})
+        );
+    }
+
+    #[test]
+    fn test_three_way() {
+        let base = json!({
+            "rootUrl": "http://a",
+            "enabled": true,
+            "redirectUris": ["/a"],
+            "description": "old",
+            "attributes": {"pkce.method": "plain"}
+        });
+        let local = json!({
+            "rootUrl": "https://b",
+            "enabled": true,
+            "redirectUris": ["/a", "/b"],
+            "description": "local",
+            "attributes": {"pkce.method": "plain"}
+        });
+        let remote = json!({
+            "rootUrl": "http://a",
+            "enabled": false,
+            "redirectUris": ["/a"],
+            "description": "server",
+            "attributes": {"pkce.method": "S256"}
+        });
+        assert_eq!(
+            three_way(&base, &remote, &local),
+            vec![
+                ("/attributes/pkce.method".to_string(), Origin::Server),
+                ("/description".to_string(), Origin::Conflict),
+                ("/enabled".to_string(), Origin::Server),
+                ("/redirectUris".to_string(), Origin::Local),
+                ("/rootUrl".to_string(), Origin::Local),
+            ]
+        );
+        assert!(three_way(&base, &local, &local).is_empty());
+    }
+
+    #[test]
+    fn test_display_path() {
+        assert_eq!(display_path("/rootUrl"), "rootUrl");
+        assert_eq!(
+            display_path("/attributes/pkce.method"),
+            "attributes.\"pkce.method\""
+        );
+        assert_eq!(display_path("/redirectUris/2"), "redirectUris[2]");
+        assert_eq!(display_path("/a~1b/c"), "a/b.c");
+        assert_eq!(display_path(""), "(root)");
+    }
+}
@@ -1,14 +1,20 @@
 use crate::models::{KeycloakResource, ResourceMeta};
+use crate::server::strip_unsupported_fields;
+use crate::state;
 use crate::utils::secrets::substitute_secrets;
-use crate::utils::ui::SPARKLE;
+use crate::utils::ui::{INFO, SPARKLE, WARN};
 use crate::utils::yaml::{is_overlay_file, load_yaml_with_overlay};
+use crate::workspace::WorkspaceContext;
 use anyhow::{Context, Result};
-use std::collections::HashMap;
+use console::style;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::fs as async_fs;
 
-use super::{PlanContext, PlanSummary, print_diff};
+use super::diff::{self, Origin};
+use super::{PlanContext, PlanSummary};
 
 pub async fn plan_resources<T>(ctx: &PlanContext<'_>) -> Result<(Vec<PathBuf>, PlanSummary)>
 where
@@ -25,7 +31,7 @@ where
     let resources_dir = ctx.workspace_dir.join(dir_name);
     let mut changed_files = Vec::new();
     let mut summary = PlanSummary::default();
-    if !async_fs::try_exists(&resources_dir).await? {
+    if !ctx.selection.includes_type(dir_name) || !async_fs::try_exists(&resources_dir).await? {
         return Ok((changed_files, summary));
     }
 
@@ -39,6 +45,9 @@ where
         .filter_map(|r| r.get_identity().map(|id| (id, r)))
         .collect();
     let existing_map = Arc::new(existing_map);
+    let root = ctx.workspace_dir.parent().unwrap_or(ctx.workspace_dir);
+    let state = state::load(root).await?;
+    let last_applied = state::load_last_applied(root, ctx.realm_name, dir_name).await?;
 
     let mut set = tokio::task::JoinSet::new();
     let mut entries = async_fs::read_dir(&resources_dir).await?;
@@ -46,20 +55,24 @@ where
     while let Some(entry) = entries.next_entry().await? {
         let path = entry.path();
         if path.is_file() && path.extension().is_some_and(|ext| ext == "yaml") {
-            // Skip overlay files themselves
-            if is_overlay_file(&path, ctx.profile.as_deref()) {
+            // Skip overlay files themselves, files kcd.yaml excludes and unselected ones
+            if is_overlay_file(&path, &ctx.ws)
+                || !ctx.ws.filter.is_managed(&path)
+                || !ctx.is_selected(&path)
+            {
                 continue;
             }
 
             let resolver = Arc::clone(&ctx.resolver);
             let existing_map = Arc::clone(&existing_map);
             let realm_name = ctx.realm_name.to_string();
-            let profile = ctx.profile.clone();
+            let ws = Arc::clone(&ctx.ws);
+            let server_version = ctx.client.server_version();
 
             set.spawn(async move {
-                let mut val = load_yaml_with_overlay(&path, profile.as_deref()).await?;
+                let mut val = load_yaml_with_overlay(&path, &ws).await?;
                 substitute_secrets(&mut val, resolver).await?;
-                let local: T = serde_json::from_value(val).with_context(|| {
+                let mut local: T = serde_json::from_value(val).with_context(|| {
                     format!(
                         "Failed to deserialize YAML file {:?} in realm '{}'",
                         path, realm_name
@@ -75,40 +88,65 @@ where
                     )
                 })?;
                 let remote = existing_map.get(&identity).cloned();
+                local.clear_local_fields();
+                // Hashed as `apply` hashes it, before fields the server lacks are dropped
+                let hash = state::hash(&local, &ws.secrets)?;
+                let mut val = serde_json::to_value(&local)?;
+                if !strip_unsupported_fields(T::API_PATH, server_version, &mut val).is_empty() {
+                    local = serde_json::from_value(val)?;
+                }
 
-                Ok::<(T, PathBuf, Option<T>), anyhow::Error>((local, path, remote))
+                Ok::<_, anyhow::Error>((local, path, remote, identity, hash))
             });
         }
     }
 
+    let mut local_identities = HashSet::new();
     for res in crate::utils::join_all_tasks(set, None).await? {
-        let (local, path, remote) = res;
+        let (local, path, remote, identity, hash) = res;
+        let base = last_applied.get(&identity);
+        local_identities.insert(identity);
 
         let is_update = remote.is_some();
+        let owner = remote.as_ref().and_then(|r| state.owner(ctx.realm_name, r));
         let changed = if let Some(remote) = remote {
             let mut remote_clone = remote.clone();
             // If local doesn't have an ID, clear it from remote clone for diffing
             if !local.has_id() {
                 remote_clone.clear_metadata();
             }
-            print_diff(
+            let changed = ctx.diff(
+                &path,
                 &format!("{} {}", T::LABEL, local.get_name()),
                 Some(&remote_clone),
                 &local,
-                ctx.options.changes_only,
                 T::SECRET_PREFIX,
-            )?
+            )?;
+            if changed && let Some(base) = base {
+                print_origins(&ctx.ws, base, &remote_clone, &local)?;
+            }
+            changed
         } else {
             println!("\n{} Will create {}", SPARKLE, T::LABEL);
-            print_diff(
+            ctx.diff(
+                &path,
                 &format!("{} {}", T::LABEL, local.get_name()),
                 None::<&T>,
                 &local,
-                ctx.options.changes_only,
                 T::SECRET_PREFIX,
             )?
         };
 
+        // Without a last-applied representation, the hash still tells server-side changes
+        if changed && base.is_none() && owner.is_some_and(|o| o.last_applied == hash) {
+            println!(
+                "  {} {} {} was modified outside kcd since it was last applied",
+                WARN,
+                T::LABEL,
+                local.get_name()
+            );
+        }
+
         if changed {
             let mut include = true;
             if ctx.options.interactive {
@@ -124,5 +162,125 @@ where
             }
         }
     }
+    report_missing_resources::<T>(ctx, &existing_map, &state, &local_identities).await?;
     Ok((changed_files, summary))
 }
+
+/// Prints where the fields differing between `remote` and `local` were changed since
+/// `base`, the representation last applied: in the workspace, on the server (which
+/// `apply` overwrites, e.g. a hotfix made in the admin console) or on both.
+pub(crate) fn print_origins<T>(
+    ws: &WorkspaceContext,
+    base: &Value,
+    remote: &T,
+    local: &T,
+) -> Result<()>
+where
+    T: ResourceMeta + serde::Serialize,
+{
+    let mut local = state::applied_value(local, &ws.secrets)?;
+    let mut remote = state::applied_value(remote, &ws.secrets)?;
+    let mut base = base.clone();
+    let rules = &ws.diff;
+    rules.normalize(T::SECRET_PREFIX, Some(&mut remote), &mut local.clone());
+    rules.normalize(T::SECRET_PREFIX, Some(&mut base), &mut local);
+
+    let fields = |origin: Origin, origins: &[(String, Origin)]| -> Vec<String> {
+        origins
+            .iter()
+            .filter(|(_, o)| *o == origin)
+            .map(|(field, _)| diff::display_path(field))
+            .collect()
+    };
+    let origins = diff::three_way(&base, &remote, &local);
+    let changed_locally = fields(Origin::Local, &origins);
+    let changed_remotely = fields(Origin::Server, &origins);
+    let conflicts = fields(Origin::Conflict, &origins);
+    if !changed_locally.is_empty() {
+        println!(
+            "    {} changed locally: {}",
+            INFO,
+            changed_locally.join(", ")
+        );
+    }
+    if !changed_remotely.is_empty() {
+        println!(
+            "    {} {}",
+            WARN,
+            style(format!(
+                "changed on the server since the last apply, apply overwrites: {}",
+                changed_remotely.join(", ")
+            ))
+            .yellow()
+        );
+    }
+    if !conflicts.is_empty() {
+        println!(
+            "    {} {}",
+            WARN,
+            style(format!(
+                "changed both locally and on the server: {}",
+                conflicts.join(", ")
+            ))
+            .red()
+        );
+    }
+    Ok(())
+}
+
+/// Reports the remote resources without a local file: those the workspace created,
+/// which `kcd prune` deletes, and with `drift` also those kcd does not manage.
+async fn report_missing_resources<T>(
+    ctx: &PlanContext<'_>,
+    existing_map: &HashMap<String, T>,
+    state: &state::StateFile,
+    planned: &HashSet<String>,
+) -> Result<()>
+where
+    T: KeycloakResource + ResourceMeta + serde::Serialize + for<'de> serde::Deserialize<'de>,
+{
+    let missing: Vec<(&String, &T)> = existing_map
+        .iter()
+        .filter(|(identity, rep)| {
+            !planned.contains(*identity)
+                && crate::prune::is_prunable(ctx.workspace_dir, *rep, &ctx.ws, ctx.selection)
+        })
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    // Files left out of the plan (protected, unselected, ...) still count as local
+    let local = crate::prune::local_identities::<T>(ctx.workspace_dir, &ctx.ws).await?;
+    let root = ctx.workspace_dir.parent().unwrap_or(ctx.workspace_dir);
+    let workspace_id = state::workspace_id(ctx.ws.workspace_id.as_deref(), root);
+    let mut missing: Vec<(&String, &T)> = missing
+        .into_iter()
+        .filter(|(identity, _)| !local.contains(*identity))
+        .collect();
+    missing.sort_by(|a, b| a.0.cmp(b.0));
+    for (_, rep) in missing {
+        match state.owner(ctx.realm_name, rep).and_then(|o| o.managed_by) {
+            Some(owner) if owner == workspace_id => println!(
+                "\n{} {} {} was removed from the workspace; `kcd prune` deletes it",
+                WARN,
+                T::LABEL,
+                rep.get_name()
+            ),
+            Some(owner) if ctx.options.drift => println!(
+                "\n{} {} {} is managed by workspace '{}'",
+                INFO,
+                T::LABEL,
+                rep.get_name(),
+                owner
+            ),
+            None if ctx.options.drift => println!(
+                "\n{} Unmanaged {} {} (not in the workspace)",
+                INFO,
+                T::LABEL,
+                rep.get_name()
+            ),
+            _ => {}
+        }
+    }
+    Ok(())
+}
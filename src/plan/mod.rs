@@ -1,23 +1,35 @@
+pub mod authz;
+pub mod client_policies;
 pub mod components;
+pub mod diff;
 pub mod generic;
+pub mod normalize;
+pub mod organizations;
 pub mod realm;
+pub mod service_accounts;
 
 use crate::client::KeycloakClient;
-use crate::utils::secrets::{SecretResolver, obfuscate_secrets};
+use crate::utils::secrets::{SecretResolver, obfuscate_secrets, substitute_secrets};
+use crate::utils::selection::Selection;
 use crate::utils::ui::{ACTION, CHECK, MEMO, Ui, WARN};
+use crate::utils::yaml::{is_overlay_file, load_yaml_with_overlay};
+use crate::workspace::WorkspaceContext;
 
-use anyhow::Result;
-use console::{Style, style};
+use anyhow::{Context, Result};
+use console::style;
 use serde::Serialize;
-use similar::{ChangeTag, TextDiff};
-use std::path::PathBuf;
-use std::sync::Arc;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tokio::fs as async_fs;
 
 #[derive(Debug, Clone, Copy)]
 pub struct PlanOptions {
     pub changes_only: bool,
     pub interactive: bool,
+    /// Also report the remote resources that are not in the workspace (`kcd drift`).
+    pub drift: bool,
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -37,6 +49,25 @@ impl PlanSummary {
     }
 }
 
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanAction {
+    Create,
+    Update,
+    Delete,
+}
+
+/// A planned change of one resource, as written to the plan report.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ResourceChange {
+    pub resource: String,
+    pub action: PlanAction,
+    pub changes: Vec<diff::DiffOp>,
+}
+
+/// The resource changes found while planning, by file.
+pub type PlanChanges = Arc<Mutex<BTreeMap<PathBuf, Vec<ResourceChange>>>>;
+
 pub struct PlanContext<'a> {
     pub client: &'a KeycloakClient,
     pub workspace_dir: &'a std::path::Path,
@@ -44,7 +75,78 @@ pub struct PlanContext<'a> {
     pub resolver: Arc<dyn SecretResolver>,
     pub realm_name: &'a str,
     pub ui: &'a dyn Ui,
-    pub profile: Option<String>,
+    pub ws: Arc<WorkspaceContext>,
+    pub selection: &'a Selection,
+    pub changes: PlanChanges,
+}
+
+impl PlanContext<'_> {
+    /// Whether `path` of the realm is part of the selected resources.
+    pub fn is_selected(&self, path: &Path) -> bool {
+        self.selection.includes(self.workspace_dir, path)
+    }
+
+    /// Prints the diff of the resource `name` declared in `path`, like [`print_diff`],
+    /// and keeps its changes for the plan report. Returns whether they differ.
+    pub fn diff<T: Serialize>(
+        &self,
+        path: &Path,
+        name: &str,
+        old: Option<&T>,
+        new: &T,
+        prefix: &str,
+    ) -> Result<bool> {
+        let ops = resource_ops(&self.ws, old, new, prefix)?;
+        let changed = print_changes(name, &ops, self.options.changes_only);
+        if changed {
+            let action = if old.is_some() {
+                PlanAction::Update
+            } else {
+                PlanAction::Create
+            };
+            self.record(path, name, action, ops);
+        }
+        Ok(changed)
+    }
+
+    /// Keeps a change of the resource `name` declared in `path` for the plan report.
+    pub fn record(&self, path: &Path, name: &str, action: PlanAction, changes: Vec<diff::DiffOp>) {
+        let mut recorded = self.changes.lock().unwrap();
+        recorded
+            .entry(path.to_path_buf())
+            .or_default()
+            .push(ResourceChange {
+                resource: name.to_string(),
+                action,
+                changes,
+            });
+    }
+}
+
+/// Fails early when the workspace declares a `minimum_server_version` the server does not meet.
+pub async fn check_server_version(client: &KeycloakClient, workspace_dir: &Path) -> Result<()> {
+    let config = crate::load_workspace_config(workspace_dir).await?;
+    let Some(minimum) = config.minimum_server_version else {
+        return Ok(());
+    };
+    match client.server_version() {
+        Some(version) if version < minimum => anyhow::bail!(
+            "Keycloak server version {} is older than minimum_server_version {} required by the workspace",
+            version,
+            minimum
+        ),
+        Some(_) => {}
+        None => println!(
+            "{} {}",
+            WARN,
+            style(format!(
+                "Could not determine the Keycloak server version, skipping the minimum_server_version {} check",
+                minimum
+            ))
+            .yellow()
+        ),
+    }
+    Ok(())
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -56,17 +158,80 @@ pub async fn run(
     realms_to_plan: &[String],
     ui: Arc<dyn Ui>,
     resolver: Arc<dyn SecretResolver>,
-    profile: Option<String>,
+    ws: Arc<WorkspaceContext>,
+) -> Result<()> {
+    run_with_selection(
+        client,
+        workspace_dir,
+        changes_only,
+        interactive,
+        realms_to_plan,
+        ui,
+        resolver,
+        ws,
+        &Selection::default(),
+    )
+    .await
+}
+
+/// Like [`run`], limited to the resources of `selection`.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_with_selection(
+    client: &KeycloakClient,
+    workspace_dir: PathBuf,
+    changes_only: bool,
+    interactive: bool,
+    realms_to_plan: &[String],
+    ui: Arc<dyn Ui>,
+    resolver: Arc<dyn SecretResolver>,
+    ws: Arc<WorkspaceContext>,
+    selection: &Selection,
+) -> Result<()> {
+    let options = PlanOptions {
+        changes_only,
+        interactive,
+        drift: false,
+    };
+    run_with_options(
+        client,
+        workspace_dir,
+        options,
+        realms_to_plan,
+        ui,
+        resolver,
+        ws,
+        selection,
+        None,
+    )
+    .await
+}
+
+/// Like [`run_with_selection`], with every option of [`PlanOptions`]. With
+/// `report_file`, the planned changes are also written there as JSON.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_with_options(
+    client: &KeycloakClient,
+    workspace_dir: PathBuf,
+    options: PlanOptions,
+    realms_to_plan: &[String],
+    ui: Arc<dyn Ui>,
+    resolver: Arc<dyn SecretResolver>,
+    ws: Arc<WorkspaceContext>,
+    selection: &Selection,
+    report_file: Option<&Path>,
 ) -> Result<()> {
     if !workspace_dir.exists() {
         anyhow::bail!("Input directory {:?} does not exist", workspace_dir);
     }
+    check_server_version(client, &workspace_dir).await?;
 
     let realms = if realms_to_plan.is_empty() {
         let mut dirs = Vec::new();
         let mut entries = async_fs::read_dir(&workspace_dir).await?;
         while let Some(entry) = entries.next_entry().await? {
-            if entry.file_type().await?.is_dir() {
+            if entry.file_type().await?.is_dir()
+                && !entry.file_name().to_string_lossy().starts_with('.')
+            {
                 dirs.push(entry.file_name().to_string_lossy().to_string());
             }
         }
@@ -84,6 +249,7 @@ pub async fn run(
         return Ok(());
     }
 
+    let changes = PlanChanges::default();
     let mut set = tokio::task::JoinSet::new();
 
     for realm_name in realms {
@@ -92,9 +258,12 @@ pub async fn run(
         let realm_dir = workspace_dir.join(&realm_name);
         let resolver = Arc::clone(&resolver);
         let ui = Arc::clone(&ui);
-        let profile = profile.clone();
+        let ws = Arc::clone(&ws);
+        let selection = selection.clone();
+        let changes = Arc::clone(&changes);
 
         set.spawn(async move {
+            let selection = selection.for_realm(&realm_dir, &ws).await?;
             println!(
                 "\n{} {}",
                 ACTION,
@@ -105,10 +274,6 @@ pub async fn run(
 
             let mut changed_files = Vec::new();
             let mut summary = PlanSummary::default();
-            let options = PlanOptions {
-                changes_only,
-                interactive,
-            };
             let ctx = PlanContext {
                 client: &realm_client,
                 workspace_dir: &realm_dir,
@@ -116,7 +281,9 @@ pub async fn run(
                 resolver,
                 realm_name: &realm_name,
                 ui: ui.as_ref(),
-                profile,
+                ws,
+                selection: &selection,
+                changes,
             };
             plan_single_realm(ctx, &mut changed_files, &mut summary).await?;
 
@@ -132,6 +299,16 @@ pub async fn run(
         total_summary.add(&summary);
     }
     changed_files.sort();
+    if let Some(path) = report_file {
+        write_report(
+            path,
+            &workspace_dir,
+            &changed_files,
+            &changes,
+            &total_summary,
+        )
+        .await?;
+    }
 
     let plan_file = workspace_dir.join(".kcdplan");
     if changed_files.is_empty() {
@@ -188,6 +365,10 @@ async fn plan_single_realm(
         (mut required_action_changes, required_action_summary),
         (mut component_changes, component_summary),
         (mut key_changes, key_summary),
+        (mut authz_changes, authz_summary),
+        (mut organization_changes, organization_summary),
+        (mut client_policy_changes, client_policy_summary),
+        (mut service_account_changes, service_account_summary),
         _,
     ) = tokio::try_join!(
         realm::plan_realm(&ctx),
@@ -201,7 +382,17 @@ async fn plan_single_realm(
         generic::plan_resources::<RequiredActionProviderRepresentation>(&ctx),
         components::plan_components_or_keys(&ctx, "components"),
         components::plan_components_or_keys(&ctx, "keys"),
-        components::check_keys_drift(ctx.client, ctx.options, ctx.realm_name),
+        authz::plan_authz(&ctx),
+        organizations::plan_organizations(&ctx),
+        client_policies::plan_client_policies(&ctx),
+        service_accounts::plan_service_accounts(&ctx),
+        async {
+            if ctx.selection.includes_type("keys") {
+                components::check_keys_drift(ctx.client, ctx.options, ctx.realm_name).await
+            } else {
+                Ok(())
+            }
+        },
     )?;
 
     changed_files.append(&mut realm_changes);
@@ -215,6 +406,10 @@ async fn plan_single_realm(
     changed_files.append(&mut required_action_changes);
     changed_files.append(&mut component_changes);
     changed_files.append(&mut key_changes);
+    changed_files.append(&mut authz_changes);
+    changed_files.append(&mut organization_changes);
+    changed_files.append(&mut client_policy_changes);
+    changed_files.append(&mut service_account_changes);
 
     summary.add(&realm_summary);
     summary.add(&role_summary);
@@ -227,44 +422,156 @@ async fn plan_single_realm(
     summary.add(&required_action_summary);
     summary.add(&component_summary);
     summary.add(&key_summary);
+    summary.add(&authz_summary);
+    summary.add(&organization_summary);
+    summary.add(&client_policy_summary);
+    summary.add(&service_account_summary);
 
     Ok(())
 }
 
+/// Writes the plan report: the counts of `summary` and, for every planned file (relative
+/// to the workspace), the changes of its resources.
+async fn write_report(
+    path: &Path,
+    workspace_dir: &Path,
+    changed_files: &[PathBuf],
+    changes: &PlanChanges,
+    summary: &PlanSummary,
+) -> Result<()> {
+    let files: BTreeMap<String, Vec<ResourceChange>> = {
+        let mut recorded = changes.lock().unwrap();
+        changed_files
+            .iter()
+            .map(|file| {
+                let name = file.strip_prefix(workspace_dir).unwrap_or(file);
+                let changes = recorded.remove(file).unwrap_or_default();
+                (name.to_string_lossy().replace('\\', "/"), changes)
+            })
+            .collect()
+    };
+    let json = serde_json::json!({
+        "summary": {"create": summary.created, "update": summary.updated},
+        "files": files,
+    });
+    async_fs::write(path, serde_json::to_string_pretty(&json)? + "\n")
+        .await
+        .with_context(|| format!("Failed to write plan report to {:?}", path))
+}
+
+/// Loads, resolves and deserializes every (non-overlay, managed) YAML file of `dir`, sorted
+/// by path.
+pub async fn load_resource_files<T>(
+    dir: &Path,
+    ws: &WorkspaceContext,
+    resolver: Arc<dyn SecretResolver>,
+) -> Result<Vec<(PathBuf, T)>>
+where
+    T: for<'de> serde::Deserialize<'de>,
+{
+    let mut items = Vec::new();
+    if !async_fs::try_exists(dir).await? {
+        return Ok(items);
+    }
+    let mut entries = async_fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if !path.is_file() || path.extension().is_none_or(|ext| ext != "yaml") {
+            continue;
+        }
+        if is_overlay_file(&path, ws) || !ws.filter.is_managed(&path) {
+            continue;
+        }
+        let mut val = load_yaml_with_overlay(&path, ws).await?;
+        substitute_secrets(&mut val, Arc::clone(&resolver)).await?;
+        let item: T = serde_json::from_value(val)
+            .with_context(|| format!("Failed to deserialize YAML file {:?}", path))?;
+        items.push((path, item));
+    }
+    items.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(items)
+}
+
+/// Records a changed file in the plan, asking for confirmation in interactive mode.
+fn record_change(
+    ctx: &PlanContext<'_>,
+    path: PathBuf,
+    is_update: bool,
+    changed: bool,
+    changed_files: &mut Vec<PathBuf>,
+    summary: &mut PlanSummary,
+) -> Result<()> {
+    if !changed {
+        return Ok(());
+    }
+    let mut include = true;
+    if ctx.options.interactive {
+        include = ctx.ui.confirm("Include this change in the plan?", true)?;
+    }
+    if include {
+        changed_files.push(path);
+        if is_update {
+            summary.updated += 1;
+        } else {
+            summary.created += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Prints the diff between the remote (`old`) and local (`new`) version of a resource,
+/// normalized by the diff rules of `ws` and with secrets masked. `prefix` is the resource
+/// kind (`client`, `idp`, ...). Returns whether they differ.
 pub fn print_diff<T: Serialize>(
+    ws: &WorkspaceContext,
     name: &str,
     old: Option<&T>,
     new: &T,
     changes_only: bool,
     prefix: &str,
 ) -> Result<bool> {
-    let old_yaml = if let Some(o) = old {
-        let mut val = serde_json::to_value(o)?;
-        obfuscate_secrets(&mut val, prefix);
-        crate::utils::to_sorted_yaml(&val)?
-    } else {
-        String::new()
-    };
+    let ops = resource_ops(ws, old, new, prefix)?;
+    Ok(print_changes(name, &ops, changes_only))
+}
 
+/// The changes between the remote (`old`) and local (`new`) version of a resource,
+/// normalized and masked like [`print_diff`] shows them.
+fn resource_ops<T: Serialize>(
+    ws: &WorkspaceContext,
+    old: Option<&T>,
+    new: &T,
+    prefix: &str,
+) -> Result<Vec<diff::DiffOp>> {
+    let mut old_val = old.map(serde_json::to_value).transpose()?;
     let mut new_val = serde_json::to_value(new)?;
-    obfuscate_secrets(&mut new_val, prefix);
-    let new_yaml = crate::utils::to_sorted_yaml(&new_val)?;
-
-    let diff = TextDiff::from_lines(&old_yaml, &new_yaml);
-    let changed = diff.ratio() < 1.0;
-
-    if changed {
-        println!("\n{} Changes for {}:", MEMO, name);
-        for change in diff.iter_all_changes() {
-            let (sign, style) = match change.tag() {
-                ChangeTag::Delete => ("-", Style::new().red()),
-                ChangeTag::Insert => ("+", Style::new().green()),
-                ChangeTag::Equal => (" ", Style::new().dim()),
-            };
-            print!("{}{}", style.apply_to(sign).bold(), style.apply_to(change));
-        }
+    ws.diff.normalize(prefix, old_val.as_mut(), &mut new_val);
+
+    // A resource to create is diffed against an empty one, one field per line.
+    let mut old_val = old_val.unwrap_or_else(|| Value::Object(Default::default()));
+    obfuscate_secrets(&mut old_val, prefix, &ws.secrets);
+    obfuscate_secrets(&mut new_val, prefix, &ws.secrets);
+
+    Ok(diff::diff_values(&old_val, &new_val))
+}
+
+/// Prints the field-level changes between two versions of `name`, returning whether
+/// there are any.
+pub fn print_value_diff(name: &str, old: &Value, new: &Value, changes_only: bool) -> bool {
+    print_changes(name, &diff::diff_values(old, new), changes_only)
+}
+
+fn print_changes(name: &str, ops: &[diff::DiffOp], changes_only: bool) -> bool {
+    if !ops.is_empty() {
+        print_ops(name, ops);
     } else if !changes_only {
         println!("{} No changes for {}", CHECK, name);
     }
-    Ok(changed)
+    !ops.is_empty()
+}
+
+pub fn print_ops(name: &str, ops: &[diff::DiffOp]) {
+    println!("\n{} Changes for {}:", MEMO, name);
+    for line in diff::render_ops(ops) {
+        println!("    {}", line);
+    }
 }
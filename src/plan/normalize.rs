@@ -0,0 +1,206 @@
+use crate::utils::secrets::rules::FieldPattern;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Top-level fields Keycloak fills in on its own; they never take part in a diff.
+const SERVER_MANAGED_FIELDS: &[&str] = &["containerId", "access"];
+
+/// Whether `path` is one of kcd's own `kcd.*` attributes: the lock of a running apply on
+/// a realm, or the ownership markers of a resource.
+fn is_kcd_attribute(path: &[&str]) -> bool {
+    path.len() > 2 && path[0] == "attributes" && path[1] == "kcd"
+}
+
+/// How `plan` compares remote and local resources (`diff:` in `kcd.yaml`).
+///
+/// By default only the fields set in the local file are compared and arrays of plain
+/// values (`redirectUris`, `webOrigins`, ...) are compared as sets. `strict` compares
+/// everything the server returns, in order.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct DiffRules {
+    #[serde(default)]
+    pub strict: bool,
+    /// Fields left out of every diff, in the `[resource:]path` syntax of secret rules,
+    /// e.g. `client:attributes.client.secret.creation.time` or `*Timestamp`.
+    #[serde(default)]
+    pub ignore_fields: Vec<FieldPattern>,
+}
+
+impl DiffRules {
+    fn is_ignored(&self, resource: &str, path: &[&str]) -> bool {
+        (path.len() == 1 && SERVER_MANAGED_FIELDS.contains(&path[0]))
+            || is_kcd_attribute(path)
+            || self.ignore_fields.iter().any(|p| p.matches(resource, path))
+    }
+
+    /// Brings the remote (`old`) and local (`new`) representation of a `resource` into
+    /// the form they are compared in.
+    pub fn normalize(&self, resource: &str, old: Option<&mut Value>, new: &mut Value) {
+        let is_ignored = |path: &[&str]| self.is_ignored(resource, path);
+        remove_fields(new, &mut Vec::new(), &is_ignored);
+        if let Some(old) = old {
+            remove_fields(old, &mut Vec::new(), &is_ignored);
+            if !self.strict {
+                prune_to(old, new);
+                sort_sets(old);
+            }
+        }
+        if !self.strict {
+            sort_sets(new);
+        }
+    }
+}
+
+/// Removes the fields for which `is_ignored` returns true. Dotted keys such as
+/// `client.secret.creation.time` count as several segments, and array items share the
+/// path of their array, so `protocolMappers.id` covers the id of every mapper.
+fn remove_fields(value: &mut Value, path: &mut Vec<String>, is_ignored: &dyn Fn(&[&str]) -> bool) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|key, _| {
+                let field: Vec<&str> = path
+                    .iter()
+                    .chain(std::iter::once(key))
+                    .flat_map(|k| k.split('.'))
+                    .collect();
+                !is_ignored(&field)
+            });
+            for (key, child) in map.iter_mut() {
+                path.push(key.clone());
+                remove_fields(child, path, is_ignored);
+                path.pop();
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                remove_fields(item, path, is_ignored);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Drops from `remote` every key the local value does not set, at any depth. Objects in
+/// arrays keep the keys set by any local item of the same array.
+fn prune_to(remote: &mut Value, local: &Value) {
+    match (remote, local) {
+        (Value::Object(remote), Value::Object(local)) => {
+            remote.retain(|key, _| local.contains_key(key));
+            for (key, child) in remote.iter_mut() {
+                prune_to(child, &local[key]);
+            }
+        }
+        (Value::Array(remote), Value::Array(local)) => {
+            let mut template: Option<Value> = None;
+            for item in local.iter().filter(|item| item.is_object()) {
+                match &mut template {
+                    Some(template) => merge_keys(template, item),
+                    None => template = Some(item.clone()),
+                }
+            }
+            if let Some(template) = template {
+                for item in remote {
+                    prune_to(item, &template);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Adds the keys of `other` to `into`, recursively.
+fn merge_keys(into: &mut Value, other: &Value) {
+    match (into, other) {
+        (Value::Object(into), Value::Object(other)) => {
+            for (key, value) in other {
+                match into.get_mut(key) {
+                    Some(existing) => merge_keys(existing, value),
+                    None => {
+                        into.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (Value::Array(into), Value::Array(other)) => into.extend(other.iter().cloned()),
+        _ => {}
+    }
+}
+
+/// Sorts every array of plain values. Keycloak does not keep their order, so they are
+/// compared as sets; arrays of objects (e.g. flow executions) keep theirs.
+fn sort_sets(value: &mut Value) {
+    match value {
+        Value::Object(map) => map.values_mut().for_each(sort_sets),
+        Value::Array(items) => {
+            if items
+                .iter()
+                .all(|item| !item.is_object() && !item.is_array())
+            {
+                items.sort_by_key(|item| item.to_string());
+            } else {
+                items.iter_mut().for_each(sort_sets);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_normalize() {
+        let rules: DiffRules = serde_yaml::from_str(
+            "ignore_fields: ['client:attributes.client.secret.creation.time', 'protocolMappers.id']\n",
+        )
+        .unwrap();
+        let mut remote = json!({
+            "id": "c1",
+            "clientId": "app",
+            "containerId": "realm-id",
+            "access": {"view": true},
+            "publicClient": false,
+            "redirectUris": ["https://b/*", "https://a/*"],
+            "attributes": {"client.secret.creation.time": "1700000000", "pkce": "S256"},
+            "protocolMappers": [{"id": "m1", "name": "email", "protocol": "openid-connect"}]
+        });
+        let mut local = json!({
+            "clientId": "app",
+            "redirectUris": ["https://a/*", "https://b/*"],
+            "attributes": {"pkce": "S256", "client.secret.creation.time": "1"},
+            "protocolMappers": [{"name": "email", "protocol": "openid-connect"}]
+        });
+        rules.normalize("client", Some(&mut remote), &mut local);
+        assert_eq!(remote, local);
+
+        let strict = DiffRules {
+            strict: true,
+            ..rules.clone()
+        };
+        let mut remote = json!({"id": "c1", "containerId": "r", "webOrigins": ["b", "a"]});
+        let mut local = json!({"webOrigins": ["a", "b"]});
+        strict.normalize("client", Some(&mut remote), &mut local);
+        assert_eq!(remote, json!({"id": "c1", "webOrigins": ["b", "a"]}));
+
+        // The lock of a running apply is not part of the realm.
+        let mut remote = json!({"realm": "r", "attributes": {"kcd.lock": "{}", "a": "1"}});
+        let mut local = json!({"realm": "r", "attributes": {"a": "1"}});
+        strict.normalize("realm", Some(&mut remote), &mut local);
+        assert_eq!(remote, local);
+
+        // Neither are the ownership markers of a resource.
+        let mut remote = json!({"name": "r", "attributes": {"kcd.managed-by": ["ws"]}});
+        let mut local = json!({"name": "r", "attributes": {}});
+        strict.normalize("role", Some(&mut remote), &mut local);
+        assert_eq!(remote, local);
+
+        // Object arrays keep their order.
+        let mut remote = json!({"executions": [{"a": 2}, {"a": 1}]});
+        let mut local = json!({"executions": [{"a": 1}, {"a": 2}]});
+        rules.normalize("flow", Some(&mut remote), &mut local);
+        assert_ne!(remote, local);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::models::{KeycloakResource, OrganizationRepresentation, ResourceMeta};
+use crate::utils::ui::{SPARKLE, WARN};
+use anyhow::{Context, Result};
+use console::style;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs as async_fs;
+
+use super::{PlanContext, PlanSummary, load_resource_files, record_change};
+
+pub async fn plan_organizations(ctx: &PlanContext<'_>) -> Result<(Vec<PathBuf>, PlanSummary)> {
+    let mut changed_files = Vec::new();
+    let mut summary = PlanSummary::default();
+
+    let orgs_dir = ctx.workspace_dir.join(OrganizationRepresentation::DIR_NAME);
+    if !ctx
+        .selection
+        .includes_type(OrganizationRepresentation::DIR_NAME)
+        || !async_fs::try_exists(&orgs_dir).await?
+    {
+        return Ok((changed_files, summary));
+    }
+
+    let Some(existing) = ctx.client.get_organizations().await.with_context(|| {
+        format!(
+            "Failed to get {} for realm '{}'",
+            OrganizationRepresentation::LABEL,
+            ctx.realm_name
+        )
+    })?
+    else {
+        println!(
+            "{} {}",
+            WARN,
+            style(format!(
+                "Organizations are not supported by this server (Keycloak 25+ required), skipping organizations in realm '{}'",
+                ctx.realm_name
+            ))
+            .yellow()
+        );
+        return Ok((changed_files, summary));
+    };
+    let existing_map: HashMap<String, OrganizationRepresentation> = existing
+        .into_iter()
+        .filter_map(|o| o.get_identity().map(|id| (id, o)))
+        .collect();
+
+    let items = load_resource_files::<OrganizationRepresentation>(
+        &orgs_dir,
+        &ctx.ws,
+        Arc::clone(&ctx.resolver),
+    )
+    .await
+    .with_context(|| {
+        format!(
+            "Failed to load {} in realm '{}'",
+            OrganizationRepresentation::LABEL,
+            ctx.realm_name
+        )
+    })?;
+
+    for (path, mut local) in items.into_iter().filter(|(path, _)| ctx.is_selected(path)) {
+        let identity = local.get_identity().with_context(|| {
+            format!(
+                "Failed to get identity for {} in {:?}",
+                OrganizationRepresentation::LABEL,
+                path
+            )
+        })?;
+        local.normalize();
+
+        let remote = match existing_map.get(&identity) {
+            Some(remote) => Some(remote_with_links(ctx, remote, &local).await?),
+            None => None,
+        };
+        if remote.is_none() {
+            println!(
+                "\n{} Will create {}",
+                SPARKLE,
+                OrganizationRepresentation::LABEL
+            );
+        }
+
+        let changed = ctx.diff(
+            &path,
+            &format!("{} {}", OrganizationRepresentation::LABEL, local.get_name()),
+            remote.as_ref(),
+            &local,
+            OrganizationRepresentation::SECRET_PREFIX,
+        )?;
+        record_change(
+            ctx,
+            path,
+            remote.is_some(),
+            changed,
+            &mut changed_files,
+            &mut summary,
+        )?;
+    }
+
+    Ok((changed_files, summary))
+}
+
+/// Completes a remote organization with the member usernames and linked identity
+/// provider aliases, for the links that the local file manages.
+async fn remote_with_links(
+    ctx: &PlanContext<'_>,
+    remote: &OrganizationRepresentation,
+    local: &OrganizationRepresentation,
+) -> Result<OrganizationRepresentation> {
+    let mut remote = remote.clone();
+    let org_id = remote.id.clone().unwrap_or_default();
+    if !local.has_id() {
+        remote.clear_metadata();
+    }
+
+    if local.members.is_some() {
+        let members = ctx
+            .client
+            .get_organization_members(&org_id)
+            .await
+            .with_context(|| format!("Failed to get members of organization '{}'", org_id))?;
+        remote.members = Some(members.into_iter().filter_map(|u| u.username).collect());
+    }
+    if local.identity_providers.is_some() {
+        let idps = ctx
+            .client
+            .get_organization_identity_providers(&org_id)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to get identity providers of organization '{}'",
+                    org_id
+                )
+            })?;
+        remote.identity_providers = Some(idps.into_iter().filter_map(|i| i.alias).collect());
+    }
+    remote.normalize();
+    Ok(remote)
+}
@@ -1,3 +1,5 @@
+use crate::server::strip_unsupported_fields;
+use crate::state;
 use crate::utils::secrets::substitute_secrets;
 use crate::utils::yaml::load_yaml_with_overlay;
 use anyhow::{Context, Result};
@@ -5,22 +7,31 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::fs as async_fs;
 
-use super::{PlanContext, PlanSummary, print_diff};
+use super::generic::print_origins;
+use super::{PlanContext, PlanSummary};
 
 pub async fn plan_realm(ctx: &PlanContext<'_>) -> Result<(Vec<PathBuf>, PlanSummary)> {
     let mut changed_files = Vec::new();
     let mut summary = PlanSummary::default();
     let realm_path = ctx.workspace_dir.join("realm.yaml");
-    if async_fs::try_exists(&realm_path).await? {
-        let mut val = load_yaml_with_overlay(&realm_path, ctx.profile.as_deref()).await?;
+    if async_fs::try_exists(&realm_path).await?
+        && ctx.ws.filter.is_managed(&realm_path)
+        && ctx.is_selected(&realm_path)
+    {
+        let mut val = load_yaml_with_overlay(&realm_path, &ctx.ws).await?;
         substitute_secrets(&mut val, Arc::clone(&ctx.resolver)).await?;
-        let local_realm: crate::models::RealmRepresentation = serde_json::from_value(val)
+        strip_unsupported_fields("realms", ctx.client.server_version(), &mut val);
+        let mut local_realm: crate::models::RealmRepresentation = serde_json::from_value(val)
             .with_context(|| format!("Failed to deserialize YAML file: {:?}", realm_path))?;
+        crate::lock::strip_server_lock(&mut local_realm);
 
         // We handle the case where remote realm fetch might fail (e.g. if we are creating it)
         // by treating it as None (creation). However, usually plan is run against existing realm.
         let remote_realm = match ctx.client.get_realm().await {
-            Ok(r) => Some(r),
+            Ok(mut r) => {
+                crate::lock::strip_server_lock(&mut r);
+                Some(r)
+            }
             Err(e) => {
                 // Check if it's a 404 (Not Found)
                 if e.to_string().contains("404") {
@@ -34,13 +45,22 @@ pub async fn plan_realm(ctx: &PlanContext<'_>) -> Result<(Vec<PathBuf>, PlanSumm
         };
 
         let is_update = remote_realm.is_some();
-        if print_diff(
+        let changed = ctx.diff(
+            &realm_path,
             "Realm",
             remote_realm.as_ref(),
             &local_realm,
-            ctx.options.changes_only,
             "realm",
-        )? {
+        )?;
+        let root = ctx.workspace_dir.parent().unwrap_or(ctx.workspace_dir);
+        let last_applied = state::load_last_applied(root, ctx.realm_name, "realm").await?;
+        if changed
+            && let Some(remote) = &remote_realm
+            && let Some(base) = last_applied.get(ctx.realm_name)
+        {
+            print_origins(&ctx.ws, base, remote, &local_realm)?;
+        }
+        if changed {
             let mut include = true;
             if ctx.options.interactive {
                 include = ctx.ui.confirm("Include this change in the plan?", true)?;
@@ -0,0 +1,163 @@
+use crate::client::KeycloakClient;
+use crate::models::{
+    ClientRepresentation, KeycloakResource, ResourceMeta, ServiceAccountRepresentation,
+};
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs as async_fs;
+
+use super::{PlanContext, PlanSummary, load_resource_files, record_change};
+
+/// The realm default roles composite, which Keycloak assigns to every user (service
+/// accounts included). It is neither exported nor removed by kcd.
+pub fn default_roles_name(realm_name: &str) -> String {
+    format!("default-roles-{}", realm_name.to_lowercase())
+}
+
+/// Reads the current realm roles, client roles and groups of a service account user.
+pub async fn current_service_account(
+    client: &KeycloakClient,
+    user_id: &str,
+    realm_name: &str,
+) -> Result<ServiceAccountRepresentation> {
+    let (mappings, groups) = tokio::try_join!(
+        client.get_user_role_mappings(user_id),
+        client.get_user_groups(user_id)
+    )?;
+    let default_roles = default_roles_name(realm_name);
+
+    let realm_roles = mappings
+        .realm_mappings
+        .unwrap_or_default()
+        .into_iter()
+        .map(|r| r.name)
+        .filter(|name| *name != default_roles)
+        .collect();
+    let client_roles: BTreeMap<String, Vec<String>> = mappings
+        .client_mappings
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(client_id, m)| (client_id, m.mappings.into_iter().map(|r| r.name).collect()))
+        .collect();
+    let groups = groups.into_iter().filter_map(|g| g.path).collect();
+
+    let mut current = ServiceAccountRepresentation {
+        realm_roles: Some(realm_roles),
+        client_roles: Some(client_roles),
+        groups: Some(groups),
+    };
+    current.normalize();
+    Ok(current)
+}
+
+/// Keeps only the assignments that `local` manages.
+pub fn restrict_to(
+    mut current: ServiceAccountRepresentation,
+    local: &ServiceAccountRepresentation,
+) -> ServiceAccountRepresentation {
+    if local.realm_roles.is_none() {
+        current.realm_roles = None;
+    }
+    if local.client_roles.is_none() {
+        current.client_roles = None;
+    }
+    if local.groups.is_none() {
+        current.groups = None;
+    }
+    current
+}
+
+pub async fn plan_service_accounts(ctx: &PlanContext<'_>) -> Result<(Vec<PathBuf>, PlanSummary)> {
+    let mut changed_files = Vec::new();
+    let mut summary = PlanSummary::default();
+
+    let clients_dir = ctx.workspace_dir.join(ClientRepresentation::DIR_NAME);
+    if !ctx.selection.includes_type(ClientRepresentation::DIR_NAME)
+        || !async_fs::try_exists(&clients_dir).await?
+    {
+        return Ok((changed_files, summary));
+    }
+    let locals: Vec<(PathBuf, ServiceAccountRepresentation, String)> =
+        load_resource_files::<ClientRepresentation>(
+            &clients_dir,
+            &ctx.ws,
+            Arc::clone(&ctx.resolver),
+        )
+        .await?
+        .into_iter()
+        .filter(|(path, _)| ctx.is_selected(path))
+        .filter_map(|(path, c)| {
+            let name = c.get_name();
+            c.service_account.map(|sa| (path, sa, name))
+        })
+        .collect();
+    if locals.is_empty() {
+        return Ok((changed_files, summary));
+    }
+
+    let remote_clients: HashMap<String, ClientRepresentation> = ctx
+        .client
+        .get_clients()
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to get {} for realm '{}'",
+                ClientRepresentation::LABEL,
+                ctx.realm_name
+            )
+        })?
+        .into_iter()
+        .filter_map(|c| c.get_identity().map(|id| (id, c)))
+        .collect();
+
+    for (path, mut local, client_id) in locals {
+        local.normalize();
+        let remote = match remote_clients.get(&client_id) {
+            Some(remote_client) if remote_client.service_accounts_enabled == Some(true) => {
+                let uuid = remote_client.id.as_deref().unwrap_or_default();
+                let user = ctx
+                    .client
+                    .get_service_account_user(uuid)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Failed to get service account user of client '{}'",
+                            client_id
+                        )
+                    })?;
+                let user_id = user.id.context("Service account user has no id")?;
+                let current = current_service_account(ctx.client, &user_id, ctx.realm_name)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Failed to get service account roles of client '{}'",
+                            client_id
+                        )
+                    })?;
+                Some(restrict_to(current, &local))
+            }
+            // The client (or its service account) is about to be created.
+            _ => None,
+        };
+
+        let changed = ctx.diff(
+            &path,
+            &format!("service account of client {}", client_id),
+            remote.as_ref(),
+            &local,
+            ClientRepresentation::SECRET_PREFIX,
+        )?;
+        record_change(
+            ctx,
+            path,
+            remote.is_some(),
+            changed,
+            &mut changed_files,
+            &mut summary,
+        )?;
+    }
+
+    Ok((changed_files, summary))
+}
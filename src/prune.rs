@@ -0,0 +1,355 @@
+use crate::client::KeycloakClient;
+use crate::lock::{LocalLock, with_server_lock};
+use crate::models::{
+    AttributeStyle, AuthenticationFlowRepresentation, ClientRepresentation,
+    ClientScopeRepresentation, GroupRepresentation, IdentityProviderRepresentation,
+    KeycloakResource, RequiredActionProviderRepresentation, ResourceMeta, RoleRepresentation,
+    UserRepresentation,
+};
+use crate::snapshot::{RealmSnapshot, SnapshotEntry};
+use crate::state::{self, StateFile};
+use crate::utils::selection::Selection;
+use crate::utils::ui::{ACTION, CHECK, MEMO, SUCCESS_DELETE, Ui, WARN};
+use crate::utils::yaml::{is_overlay_file, load_yaml_with_overlay};
+use crate::workspace::WorkspaceContext;
+use anyhow::{Context, Result};
+use console::style;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+
+/// The identities of every local resource of `T` in `realm_dir`, including the files
+/// `kcd.yaml` filters out, so that none of them counts as removed from the workspace.
+/// Identities are never secrets, so placeholders are left as they are.
+pub async fn local_identities<T>(realm_dir: &Path, ws: &WorkspaceContext) -> Result<HashSet<String>>
+where
+    T: KeycloakResource + DeserializeOwned,
+{
+    let mut identities = HashSet::new();
+    let dir = realm_dir.join(T::DIR_NAME);
+    if !fs::try_exists(&dir).await? {
+        return Ok(identities);
+    }
+    let mut entries = fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if !path.is_file()
+            || path.extension().is_none_or(|ext| ext != "yaml")
+            || is_overlay_file(&path, ws)
+        {
+            continue;
+        }
+        let val = load_yaml_with_overlay(&path, ws).await?;
+        let rep: T = serde_json::from_value(val)
+            .with_context(|| format!("Failed to deserialize YAML file {:?}", path))?;
+        if let Some(identity) = rep.get_identity() {
+            identities.insert(identity);
+        }
+    }
+    Ok(identities)
+}
+
+/// Whether the remote `rep`, which has no local file, can be pruned as far as the
+/// workspace is concerned: the file it would have is managed by `kcd.yaml` and selected.
+pub fn is_prunable<T: KeycloakResource>(
+    realm_dir: &Path,
+    rep: &T,
+    ws: &WorkspaceContext,
+    selection: &Selection,
+) -> bool {
+    let path = remote_path(realm_dir, rep, ws);
+    ws.filter.is_managed(&path) && selection.includes(realm_dir, &path)
+}
+
+/// The file `inspect` would export the remote `rep` to.
+pub fn remote_path<T: KeycloakResource>(
+    realm_dir: &Path,
+    rep: &T,
+    ws: &WorkspaceContext,
+) -> PathBuf {
+    crate::inspect::resource_path(&realm_dir.join(T::DIR_NAME), &rep.get_filename(), ws)
+}
+
+/// A remote resource to delete.
+struct Candidate {
+    kind: &'static str,
+    attributes: AttributeStyle,
+    identity: String,
+    id: String,
+    before: serde_json::Value,
+}
+
+/// Deletes the remote resources the workspace created (see [`crate::state`]) that no
+/// longer have a local file, in `realms`. Resources kcd did not create, created from
+/// another workspace, protected or outside `selection` are left alone. The deleted
+/// resources are snapshotted first, so `kcd rollback` brings them back. With
+/// `lock.server`, each realm is pruned under its server lock.
+#[allow(clippy::too_many_arguments)]
+pub async fn run(
+    client: &KeycloakClient,
+    workspace_dir: &Path,
+    realms: &[String],
+    yes: bool,
+    ui: Arc<dyn Ui>,
+    ws: &WorkspaceContext,
+    selection: &Selection,
+) -> Result<()> {
+    if !workspace_dir.exists() {
+        anyhow::bail!("Input directory {:?} does not exist", workspace_dir);
+    }
+    let _lock = LocalLock::acquire(workspace_dir, &ws.lock).await?;
+    let workspace_id = state::workspace_id(ws.workspace_id.as_deref(), workspace_dir);
+    let snapshot_dir = crate::snapshot::new_snapshot_dir(workspace_dir);
+
+    for realm_name in realms {
+        println!(
+            "\n{} {}",
+            ACTION,
+            style(format!("Pruning realm: {}", realm_name))
+                .cyan()
+                .bold()
+        );
+        let realm_dir = workspace_dir.join(realm_name);
+        // Without its directory, everything in the realm would look removed
+        if !fs::try_exists(&realm_dir).await? {
+            println!(
+                "  {} {}",
+                WARN,
+                style(format!(
+                    "Realm directory {:?} not found, skipping",
+                    realm_dir
+                ))
+                .yellow()
+            );
+            continue;
+        }
+        let mut realm_client = client.clone();
+        realm_client.set_target_realm(realm_name.clone());
+        with_server_lock(
+            &realm_client,
+            realm_name,
+            &ws.lock,
+            prune_realm(
+                &realm_client,
+                workspace_dir,
+                &realm_dir,
+                realm_name,
+                yes,
+                ui.as_ref(),
+                ws,
+                selection,
+                &workspace_id,
+                &snapshot_dir,
+            ),
+        )
+        .await?;
+    }
+    crate::snapshot::retain(workspace_dir, &ws.snapshots).await?;
+    Ok(())
+}
+
+/// Prunes one realm: finds its candidates, confirms, snapshots and deletes them.
+#[allow(clippy::too_many_arguments)]
+async fn prune_realm(
+    realm_client: &KeycloakClient,
+    workspace_dir: &Path,
+    realm_dir: &Path,
+    realm_name: &str,
+    yes: bool,
+    ui: &dyn Ui,
+    ws: &WorkspaceContext,
+    selection: &Selection,
+    workspace_id: &str,
+    snapshot_dir: &Path,
+) -> Result<()> {
+    let selection = selection.for_realm(realm_dir, ws).await?;
+    let state = state::load(workspace_dir).await?;
+
+    // Dependents go before what they depend on
+    let mut candidates = Vec::new();
+    let scope = Scope {
+        client: realm_client,
+        realm_dir,
+        realm_name,
+        ws,
+        selection: &selection,
+        state: &state,
+        workspace_id,
+    };
+    candidates.extend(scope.candidates::<UserRepresentation>().await?);
+    candidates.extend(scope.candidates::<GroupRepresentation>().await?);
+    candidates.extend(
+        scope
+            .candidates::<RequiredActionProviderRepresentation>()
+            .await?,
+    );
+    candidates.extend(
+        scope
+            .candidates::<AuthenticationFlowRepresentation>()
+            .await?,
+    );
+    candidates.extend(scope.candidates::<ClientRepresentation>().await?);
+    candidates.extend(scope.candidates::<ClientScopeRepresentation>().await?);
+    candidates.extend(scope.candidates::<RoleRepresentation>().await?);
+    candidates.extend(scope.candidates::<IdentityProviderRepresentation>().await?);
+
+    if candidates.is_empty() {
+        println!("  {} {}", CHECK, style("Nothing to prune").green());
+        return Ok(());
+    }
+    for candidate in &candidates {
+        println!("  delete {} {}", candidate.kind, candidate.identity);
+    }
+    if !yes
+        && !ui.confirm(
+            &format!(
+                "Delete {} resource(s) from realm '{}'?",
+                candidates.len(),
+                realm_name
+            ),
+            false,
+        )?
+    {
+        println!("{} {}", WARN, style("Skipped").yellow());
+        return Ok(());
+    }
+
+    let snapshot = RealmSnapshot {
+        realm: realm_name.to_string(),
+        resources: candidates
+            .iter()
+            .map(|c| SnapshotEntry {
+                kind: c.kind.to_string(),
+                identity: c.identity.clone(),
+                before: Some(c.before.clone()),
+            })
+            .collect(),
+    };
+    let path = crate::snapshot::save(snapshot_dir, &snapshot).await?;
+    println!(
+        "  {} {}",
+        MEMO,
+        style(format!("Snapshot saved to {:?}", path)).dim()
+    );
+
+    let mut deleted = Vec::new();
+    let result = delete_candidates(realm_client, realm_name, &candidates, &mut deleted).await;
+    if deleted.iter().any(|c| c.attributes == AttributeStyle::None) {
+        state::update(workspace_dir, |state| {
+            for candidate in &deleted {
+                state.remove(realm_name, candidate.kind, &candidate.identity);
+            }
+        })
+        .await?;
+    }
+    for candidate in &deleted {
+        state::update_last_applied(workspace_dir, realm_name, candidate.kind, |applied| {
+            applied.remove(&candidate.identity);
+        })
+        .await?;
+    }
+    result
+}
+
+async fn delete_candidates<'a>(
+    client: &KeycloakClient,
+    realm_name: &str,
+    candidates: &'a [Candidate],
+    deleted: &mut Vec<&'a Candidate>,
+) -> Result<()> {
+    for candidate in candidates {
+        let id = &candidate.id;
+        match candidate.kind {
+            "users" => client.delete_resource::<UserRepresentation>(id).await,
+            "groups" => client.delete_resource::<GroupRepresentation>(id).await,
+            "required-actions" => {
+                client
+                    .delete_resource::<RequiredActionProviderRepresentation>(id)
+                    .await
+            }
+            "authentication-flows" => {
+                client
+                    .delete_resource::<AuthenticationFlowRepresentation>(id)
+                    .await
+            }
+            "clients" => client.delete_resource::<ClientRepresentation>(id).await,
+            "client-scopes" => {
+                client
+                    .delete_resource::<ClientScopeRepresentation>(id)
+                    .await
+            }
+            "roles" => client.delete_resource::<RoleRepresentation>(id).await,
+            "identity-providers" => {
+                client
+                    .delete_resource::<IdentityProviderRepresentation>(id)
+                    .await
+            }
+            kind => anyhow::bail!("Cannot prune {} '{}'", kind, candidate.identity),
+        }
+        .with_context(|| {
+            format!(
+                "Failed to delete {} '{}' in realm '{}'",
+                candidate.kind, candidate.identity, realm_name
+            )
+        })?;
+        println!(
+            "  {} Deleted {} {}",
+            SUCCESS_DELETE, candidate.kind, candidate.identity
+        );
+        deleted.push(candidate);
+    }
+    Ok(())
+}
+
+struct Scope<'a> {
+    client: &'a KeycloakClient,
+    realm_dir: &'a Path,
+    realm_name: &'a str,
+    ws: &'a WorkspaceContext,
+    selection: &'a Selection,
+    state: &'a StateFile,
+    workspace_id: &'a str,
+}
+
+impl Scope<'_> {
+    /// The remote resources of `T` created from the workspace and gone from it.
+    async fn candidates<T>(&self) -> Result<Vec<Candidate>>
+    where
+        T: KeycloakResource + ResourceMeta + Serialize + DeserializeOwned,
+    {
+        if !self.selection.includes_type(T::DIR_NAME) {
+            return Ok(Vec::new());
+        }
+        let local = local_identities::<T>(self.realm_dir, self.ws).await?;
+        let remote = self.client.get_resources::<T>().await.with_context(|| {
+            format!("Failed to get {} for realm '{}'", T::LABEL, self.realm_name)
+        })?;
+        let mut candidates = Vec::new();
+        for rep in remote {
+            let (Some(identity), Some(id)) = (rep.get_identity(), rep.get_id()) else {
+                continue;
+            };
+            if local.contains(&identity)
+                || !is_prunable(self.realm_dir, &rep, self.ws, self.selection)
+                || !self
+                    .state
+                    .owner(self.realm_name, &rep)
+                    .is_some_and(|o| o.is_managed_by(self.workspace_id))
+            {
+                continue;
+            }
+            candidates.push(Candidate {
+                kind: T::DIR_NAME,
+                attributes: T::ATTRIBUTES,
+                id: id.to_string(),
+                before: serde_json::to_value(&rep)?,
+                identity,
+            });
+        }
+        candidates.sort_by(|a, b| a.identity.cmp(&b.identity));
+        Ok(candidates)
+    }
+}
@@ -0,0 +1,282 @@
+use crate::plan::diff::{DiffOp, diff_values};
+use crate::plan::print_ops;
+use crate::utils::secrets::{
+    CompositeResolver, SecretResolver, is_secret_reference, obfuscate_literal_secrets,
+    substitute_secrets,
+};
+use crate::utils::to_sorted_yaml;
+use crate::utils::ui::{CHECK, MEMO, WARN};
+use crate::utils::yaml::{load_yaml_with_overlay, realm_files};
+use crate::workspace::WorkspaceContext;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use console::style;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::fs;
+
+#[derive(clap::ValueEnum, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderFormat {
+    #[default]
+    Yaml,
+    Json,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    /// Only render this file or directory of each realm, e.g. `clients/my-app`.
+    pub resource: Option<String>,
+    /// Resolve and show secrets instead of masking them.
+    pub reveal: bool,
+    pub format: RenderFormat,
+}
+
+/// Resolves secret references to themselves, so that masked output needs no secret
+/// backend while plain variables are still interpolated.
+struct ReferenceResolver;
+
+#[async_trait]
+impl SecretResolver for ReferenceResolver {
+    async fn resolve(&self, key: &str) -> Result<Option<String>> {
+        Ok(is_secret_reference(key).then(|| format!("${{{}}}", key)))
+    }
+}
+
+/// The resolver used without `--reveal`: secret references are kept, `vars` (plain
+/// variables) are interpolated.
+pub fn masked_resolver(vars: Box<dyn SecretResolver>) -> Arc<dyn SecretResolver> {
+    Arc::new(CompositeResolver::new(vec![
+        Box::new(ReferenceResolver),
+        vars,
+    ]))
+}
+
+/// The resource kind of a realm file, as used for secret detection.
+fn resource_kind(relative: &Path) -> &'static str {
+    let parts: Vec<&str> = relative.iter().filter_map(|p| p.to_str()).collect();
+    match parts.as_slice() {
+        ["clients", ..] => "client",
+        ["identity-providers", ..] => "idp",
+        ["components", ..] | ["keys", ..] => "component",
+        ["roles", ..] => "role",
+        ["client-scopes", ..] => "client_scope",
+        ["groups", ..] => "group",
+        ["users", ..] => "user",
+        ["authentication-flows", ..] => "flow",
+        ["required-actions", ..] => "action",
+        ["organizations", ..] => "organization",
+        ["client-policies", ..] => "client_policy",
+        ["authz", _, "scopes", ..] => "authz_scope",
+        ["authz", _, "resources", ..] => "authz_resource",
+        ["authz", _, "policies", ..] | ["authz", _, "permissions", ..] => "authz_policy",
+        ["authz", ..] => "authz",
+        _ => "realm",
+    }
+}
+
+fn matches_resource(relative: &Path, resource: &str) -> bool {
+    let resource = resource.trim_matches('/');
+    let resource = resource.strip_suffix(".yaml").unwrap_or(resource);
+    let without_ext = relative.with_extension("");
+    without_ext == Path::new(resource) || relative.starts_with(resource)
+}
+
+/// The effective configuration of every resource file, keyed by `<realm>/<path>`: the
+/// overlays of the profile of `ws` merged, variables and secrets substituted by `resolver`, and
+/// secrets masked unless `options.reveal` is set.
+pub async fn render_workspace(
+    workspace_dir: &Path,
+    realms: &[String],
+    ws: &WorkspaceContext,
+    resolver: Arc<dyn SecretResolver>,
+    options: &RenderOptions,
+) -> Result<BTreeMap<String, Value>> {
+    if !fs::try_exists(workspace_dir).await? {
+        anyhow::bail!("Input directory {:?} does not exist", workspace_dir);
+    }
+    let realms = if realms.is_empty() {
+        let mut dirs = Vec::new();
+        let mut entries = fs::read_dir(workspace_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir()
+                && fs::try_exists(entry.path().join("realm.yaml")).await?
+            {
+                dirs.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        dirs
+    } else {
+        realms.to_vec()
+    };
+
+    let mut rendered = BTreeMap::new();
+    for realm_name in realms {
+        let realm_dir = workspace_dir.join(&realm_name);
+        for path in realm_files(&realm_dir, ws).await? {
+            let relative = path.strip_prefix(&realm_dir).unwrap_or(&path);
+            if let Some(resource) = &options.resource
+                && !matches_resource(relative, resource)
+            {
+                continue;
+            }
+            let mut value = load_yaml_with_overlay(&path, ws).await?;
+            substitute_secrets(&mut value, Arc::clone(&resolver))
+                .await
+                .with_context(|| format!("Failed to render {:?}", path))?;
+            if !options.reveal {
+                obfuscate_literal_secrets(&mut value, resource_kind(relative), &ws.secrets);
+            }
+            let key = Path::new(&realm_name).join(relative);
+            rendered.insert(key.to_string_lossy().replace('\\', "/"), value);
+        }
+    }
+    Ok(rendered)
+}
+
+/// Formats rendered resources as YAML documents (each preceded by a `# <file>` comment)
+/// or as one JSON object keyed by file.
+pub fn format_rendered(rendered: &BTreeMap<String, Value>, format: RenderFormat) -> Result<String> {
+    match format {
+        RenderFormat::Json => {
+            let mut out = serde_json::to_string_pretty(rendered)?;
+            out.push('\n');
+            Ok(out)
+        }
+        RenderFormat::Yaml => {
+            let documents = rendered
+                .iter()
+                .map(|(file, value)| Ok(format!("# {}\n{}", file, to_sorted_yaml(value)?)))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(documents.join("---\n"))
+        }
+    }
+}
+
+pub async fn run(
+    workspace_dir: &Path,
+    realms: &[String],
+    ws: &WorkspaceContext,
+    resolver: Arc<dyn SecretResolver>,
+    options: &RenderOptions,
+) -> Result<()> {
+    let rendered = render_workspace(workspace_dir, realms, ws, resolver, options).await?;
+    if rendered.is_empty() {
+        eprintln!(
+            "{} {}",
+            WARN,
+            style("No resources matched, nothing to render").yellow()
+        );
+        return Ok(());
+    }
+    print!("{}", format_rendered(&rendered, options.format)?);
+    Ok(())
+}
+
+/// Prints the differences between the effective configuration of two profiles and
+/// returns the files that differ. With the JSON format the operations of every changed
+/// file are printed as one JSON object instead.
+pub async fn diff_profiles(
+    workspace_dir: &Path,
+    realms: &[String],
+    from: (&WorkspaceContext, Arc<dyn SecretResolver>),
+    to: (&WorkspaceContext, Arc<dyn SecretResolver>),
+    options: &RenderOptions,
+) -> Result<Vec<String>> {
+    let old = render_workspace(workspace_dir, realms, from.0, from.1, options).await?;
+    let new = render_workspace(workspace_dir, realms, to.0, to.1, options).await?;
+    let from_name = from.0.profile.as_deref().unwrap_or_default();
+    let to_name = to.0.profile.as_deref().unwrap_or_default();
+
+    let mut files: Vec<&String> = old.keys().chain(new.keys()).collect();
+    files.sort();
+    files.dedup();
+
+    // A file missing from one profile is diffed against an empty document.
+    let empty = Value::Object(Default::default());
+    let mut changes: BTreeMap<String, Vec<DiffOp>> = BTreeMap::new();
+    for file in files {
+        let old_value = old.get(file).unwrap_or(&empty);
+        let new_value = new.get(file).unwrap_or(&empty);
+        let ops = diff_values(old_value, new_value);
+        if ops.is_empty() {
+            continue;
+        }
+        if options.format == RenderFormat::Yaml {
+            print_ops(file, &ops);
+        }
+        changes.insert(file.clone(), ops);
+    }
+    let changed: Vec<String> = changes.keys().cloned().collect();
+
+    if options.format == RenderFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&changes)?);
+    } else if changed.is_empty() {
+        println!(
+            "{} {}",
+            CHECK,
+            style(format!(
+                "No differences between profiles '{}' and '{}'",
+                from_name, to_name
+            ))
+            .green()
+        );
+    } else {
+        println!(
+            "\n{} {}",
+            MEMO,
+            style(format!(
+                "{} file(s) differ between profiles '{}' and '{}'",
+                changed.len(),
+                from_name,
+                to_name
+            ))
+            .cyan()
+            .bold()
+        );
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resource_kind_and_filter() {
+        assert_eq!(resource_kind(Path::new("realm.yaml")), "realm");
+        assert_eq!(resource_kind(Path::new("clients/app.yaml")), "client");
+        assert_eq!(resource_kind(Path::new("keys/rsa.yaml")), "component");
+        assert_eq!(
+            resource_kind(Path::new("authz/app/resource-server.yaml")),
+            "authz"
+        );
+        assert_eq!(
+            resource_kind(Path::new("authz/app/permissions/p.yaml")),
+            "authz_policy"
+        );
+
+        assert!(matches_resource(
+            Path::new("clients/my-app.yaml"),
+            "clients/my-app"
+        ));
+        assert!(matches_resource(
+            Path::new("clients/my-app.yaml"),
+            "clients/my-app.yaml"
+        ));
+        assert!(matches_resource(
+            Path::new("clients/my-app.yaml"),
+            "clients/"
+        ));
+        assert!(matches_resource(
+            Path::new("authz/my-app/scopes/read.yaml"),
+            "authz/my-app"
+        ));
+        assert!(!matches_resource(
+            Path::new("clients/my-app-2.yaml"),
+            "clients/my-app"
+        ));
+    }
+}
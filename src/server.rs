@@ -0,0 +1,210 @@
+use anyhow::{Context, Result};
+use log::warn;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// A Keycloak server version, as reported by `/admin/serverinfo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ServerVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl ServerVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl FromStr for ServerVersion {
+    type Err = anyhow::Error;
+
+    /// Parses `26`, `26.1` or `26.1.4`, ignoring build suffixes such as
+    /// `-SNAPSHOT` or `.redhat-00001`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.trim().split(['.', '-']).map(|p| p.parse::<u32>());
+        let major = parts
+            .next()
+            .and_then(|p| p.ok())
+            .with_context(|| format!("Invalid Keycloak version '{}'", s))?;
+        let minor = parts.next().and_then(|p| p.ok()).unwrap_or(0);
+        let patch = parts.next().and_then(|p| p.ok()).unwrap_or(0);
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl<'de> Deserialize<'de> for ServerVersion {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Accept both `minimum_server_version: 25` and `minimum_server_version: "25.0.1"`.
+        let raw = serde_yaml::Value::deserialize(deserializer)?;
+        let text = match raw {
+            serde_yaml::Value::String(s) => s,
+            serde_yaml::Value::Number(n) => n.to_string(),
+            other => {
+                return Err(serde::de::Error::custom(format!(
+                    "expected a version, got {:?}",
+                    other
+                )));
+            }
+        };
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Resource types or endpoints that only exist on some Keycloak versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    ClientPolicies,
+    Organizations,
+}
+
+impl Capability {
+    pub fn minimum_version(self) -> ServerVersion {
+        match self {
+            Capability::ClientPolicies => ServerVersion::new(15, 0, 0),
+            Capability::Organizations => ServerVersion::new(25, 0, 0),
+        }
+    }
+
+    /// Unknown versions are assumed to support everything, so a failed version
+    /// detection never hides resources.
+    pub fn is_supported_by(self, version: Option<ServerVersion>) -> bool {
+        version.is_none_or(|v| v >= self.minimum_version())
+    }
+}
+
+/// Representation fields introduced after Keycloak 21, keyed by resource API path.
+/// Older servers reject or silently ignore them, so they are stripped before sending.
+const VERSIONED_FIELDS: &[(&str, &str, ServerVersion)] = &[
+    (
+        "realms",
+        "maxTemporaryLockouts",
+        ServerVersion::new(24, 0, 0),
+    ),
+    (
+        "realms",
+        "organizationsEnabled",
+        ServerVersion::new(25, 0, 0),
+    ),
+    ("realms", "bruteForceStrategy", ServerVersion::new(26, 1, 0)),
+    (
+        "realms",
+        "adminPermissionsEnabled",
+        ServerVersion::new(26, 2, 0),
+    ),
+    (
+        "identity-provider/instances",
+        "organizationId",
+        ServerVersion::new(25, 0, 0),
+    ),
+    (
+        "identity-provider/instances",
+        "hideOnLogin",
+        ServerVersion::new(26, 0, 0),
+    ),
+];
+
+/// Removes the top-level fields of `value` that `version` does not know about and
+/// returns their names. Nothing is removed when the version is unknown.
+pub fn strip_unsupported_fields(
+    api_path: &str,
+    version: Option<ServerVersion>,
+    value: &mut serde_json::Value,
+) -> Vec<&'static str> {
+    let (Some(version), Some(obj)) = (version, value.as_object_mut()) else {
+        return Vec::new();
+    };
+    let mut removed = Vec::new();
+    for (path, field, since) in VERSIONED_FIELDS {
+        if *path == api_path && version < *since && obj.remove(*field).is_some() {
+            warn!(
+                "Ignoring field '{}' of {}: requires Keycloak {} (server is {})",
+                field, api_path, since, version
+            );
+            removed.push(*field);
+        }
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_server_version() {
+        assert_eq!(
+            "26.0.5".parse::<ServerVersion>().unwrap(),
+            ServerVersion::new(26, 0, 5)
+        );
+        assert_eq!(
+            "25".parse::<ServerVersion>().unwrap(),
+            ServerVersion::new(25, 0, 0)
+        );
+        assert_eq!(
+            "24.0.5.redhat-00001".parse::<ServerVersion>().unwrap(),
+            ServerVersion::new(24, 0, 5)
+        );
+        assert_eq!(
+            "999.0.0-SNAPSHOT".parse::<ServerVersion>().unwrap(),
+            ServerVersion::new(999, 0, 0)
+        );
+        assert!("nightly".parse::<ServerVersion>().is_err());
+        assert!(ServerVersion::new(26, 1, 0) > ServerVersion::new(26, 0, 9));
+    }
+
+    #[test]
+    fn test_capability_support() {
+        assert!(Capability::Organizations.is_supported_by(None));
+        assert!(!Capability::Organizations.is_supported_by(Some(ServerVersion::new(24, 0, 5))));
+        assert!(Capability::Organizations.is_supported_by(Some(ServerVersion::new(25, 0, 0))));
+    }
+
+    #[test]
+    fn test_strip_unsupported_fields() {
+        let mut realm = json!({
+            "realm": "test",
+            "organizationsEnabled": true,
+            "bruteForceStrategy": "MULTIPLE"
+        });
+        let removed =
+            strip_unsupported_fields("realms", Some(ServerVersion::new(25, 0, 6)), &mut realm);
+        assert_eq!(removed, vec!["bruteForceStrategy"]);
+        assert_eq!(
+            realm,
+            json!({"realm": "test", "organizationsEnabled": true})
+        );
+
+        let mut idp = json!({"alias": "google", "organizationId": "org-1", "hideOnLogin": true});
+        let removed = strip_unsupported_fields(
+            "identity-provider/instances",
+            Some(ServerVersion::new(25, 0, 0)),
+            &mut idp,
+        );
+        assert_eq!(removed, vec!["hideOnLogin"]);
+
+        let mut untouched = realm.clone();
+        assert!(strip_unsupported_fields("realms", None, &mut untouched).is_empty());
+        assert!(
+            strip_unsupported_fields(
+                "clients",
+                Some(ServerVersion::new(21, 0, 0)),
+                &mut untouched
+            )
+            .is_empty()
+        );
+    }
+}
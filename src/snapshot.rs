@@ -0,0 +1,623 @@
+use crate::client::KeycloakClient;
+use crate::lock::{LocalLock, SERVER_LOCK_ATTRIBUTE, with_server_lock};
+use crate::models::{
+    AuthenticationFlowRepresentation, ClientRepresentation, ClientScopeRepresentation,
+    ComponentRepresentation, GroupRepresentation, IdentityProviderRepresentation, KeycloakResource,
+    OrganizationRepresentation, RealmRepresentation, RequiredActionProviderRepresentation,
+    RoleRepresentation, UserRepresentation,
+};
+use crate::plan::client_policies::{CLIENT_POLICIES_DIR, POLICIES_FILE, PROFILES_FILE};
+use crate::server::Capability;
+use crate::utils::secrets::{SecretResolver, substitute_secrets};
+use crate::utils::selection::Selection;
+use crate::utils::ui::{ACTION, CHECK, SUCCESS_CREATE, SUCCESS_DELETE, SUCCESS_UPDATE, Ui, WARN};
+use crate::utils::yaml::{load_yaml_with_overlay, realm_files};
+use crate::workspace::WorkspaceContext;
+use anyhow::{Context, Result};
+use console::style;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+
+/// Where kcd keeps its own state in a workspace.
+pub const STATE_DIR: &str = ".kcd";
+
+/// Snapshots kept when `snapshots.keep` is not set.
+const DEFAULT_KEEP: usize = 20;
+
+/// How many snapshots `apply` and `prune` keep (`snapshots:` in `kcd.yaml`).
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct SnapshotSettings {
+    /// Number of snapshots kept; older ones are removed after each run (default: 20).
+    pub keep: Option<usize>,
+}
+
+impl SnapshotSettings {
+    pub fn keep(&self) -> usize {
+        self.keep.unwrap_or(DEFAULT_KEEP)
+    }
+}
+
+/// Creates the state directory of the workspace, with a `.gitignore` that keeps it out
+/// of version control: snapshots hold secrets as the server returns them.
+pub async fn create_state_dir(workspace_dir: &Path) -> Result<()> {
+    let dir = workspace_dir.join(STATE_DIR);
+    fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("Failed to create {:?}", dir))?;
+    let gitignore = dir.join(".gitignore");
+    if !fs::try_exists(&gitignore).await? {
+        fs::write(&gitignore, "*\n")
+            .await
+            .with_context(|| format!("Failed to write {:?}", gitignore))?;
+    }
+    Ok(())
+}
+
+/// Resource types a snapshot covers, in the order they are restored.
+const SNAPSHOT_TYPES: &[&str] = &[
+    "realm",
+    "identity-providers",
+    "roles",
+    "client-scopes",
+    "clients",
+    "authentication-flows",
+    "required-actions",
+    "groups",
+    "users",
+    "components",
+    "keys",
+    CLIENT_POLICIES_DIR,
+    "organizations",
+];
+
+/// A resource as it was before an `apply`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotEntry {
+    /// The resource type, named after its directory (`realm` for `realm.yaml`).
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// What the resource is matched by: its `clientId`, alias, name, ...
+    pub identity: String,
+    /// The remote representation; absent when the `apply` created the resource.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+}
+
+/// The snapshot of one realm, stored as `.kcd/snapshots/<id>/<realm>.json`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RealmSnapshot {
+    pub realm: String,
+    pub resources: Vec<SnapshotEntry>,
+}
+
+pub fn snapshots_dir(workspace_dir: &Path) -> PathBuf {
+    workspace_dir.join(STATE_DIR).join("snapshots")
+}
+
+/// A new snapshot id: the current UTC time, e.g. `20261018T224828Z`.
+pub fn new_snapshot_id() -> String {
+    jiff::Timestamp::now()
+        .strftime("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+/// Records the remote representation of every resource the pending files of the realm
+/// in `realm_dir` are going to touch (all its files when `planned_files` is `None`).
+/// Authorization services, service account roles and organization members are not
+/// part of snapshots.
+pub async fn capture(
+    client: &KeycloakClient,
+    realm_dir: &Path,
+    realm_name: &str,
+    ws: &WorkspaceContext,
+    resolver: Arc<dyn SecretResolver>,
+    selection: &Selection,
+    planned_files: &Option<HashSet<PathBuf>>,
+) -> Result<RealmSnapshot> {
+    let mut by_kind: BTreeMap<String, Vec<(PathBuf, Value)>> = BTreeMap::new();
+    if fs::try_exists(realm_dir).await? {
+        for path in realm_files(realm_dir, ws).await? {
+            let pending = selection.includes(realm_dir, &path)
+                && planned_files
+                    .as_ref()
+                    .is_none_or(|plan| plan.contains(&path));
+            let Some(relative) = path.strip_prefix(realm_dir).ok().filter(|_| pending) else {
+                continue;
+            };
+            let kind = match relative.components().count() {
+                1 => "realm".to_string(),
+                _ => relative
+                    .iter()
+                    .next()
+                    .map(|c| c.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            };
+            if !SNAPSHOT_TYPES.contains(&kind.as_str()) {
+                continue;
+            }
+            let mut value = load_yaml_with_overlay(&path, ws).await?;
+            substitute_secrets(&mut value, Arc::clone(&resolver)).await?;
+            by_kind.entry(kind).or_default().push((path, value));
+        }
+    }
+
+    let mut resources = Vec::new();
+    for kind in SNAPSHOT_TYPES {
+        let Some(files) = by_kind.get(*kind) else {
+            continue;
+        };
+        let entries = match *kind {
+            "realm" => match client.get_realm().await {
+                Ok(realm) => {
+                    let mut before = serde_json::to_value(realm)?;
+                    // The lock of the running apply must not come back on rollback
+                    if let Some(attributes) = before["attributes"].as_object_mut() {
+                        attributes.remove(SERVER_LOCK_ATTRIBUTE);
+                    }
+                    vec![SnapshotEntry {
+                        kind: kind.to_string(),
+                        identity: realm_name.to_string(),
+                        before: Some(before),
+                    }]
+                }
+                // Realms are never deleted, so there is nothing to restore
+                Err(e) if e.to_string().contains("404") => Vec::new(),
+                Err(e) => return Err(e),
+            },
+            "identity-providers" => {
+                capture_resources::<IdentityProviderRepresentation>(client, kind, files).await?
+            }
+            "roles" => capture_resources::<RoleRepresentation>(client, kind, files).await?,
+            "client-scopes" => {
+                capture_resources::<ClientScopeRepresentation>(client, kind, files).await?
+            }
+            "clients" => capture_resources::<ClientRepresentation>(client, kind, files).await?,
+            "authentication-flows" => {
+                capture_resources::<AuthenticationFlowRepresentation>(client, kind, files).await?
+            }
+            "required-actions" => {
+                capture_resources::<RequiredActionProviderRepresentation>(client, kind, files)
+                    .await?
+            }
+            "groups" => capture_resources::<GroupRepresentation>(client, kind, files).await?,
+            "users" => capture_resources::<UserRepresentation>(client, kind, files).await?,
+            "components" | "keys" => {
+                capture_resources::<ComponentRepresentation>(client, kind, files).await?
+            }
+            "organizations" if client.supports(Capability::Organizations) => {
+                capture_resources::<OrganizationRepresentation>(client, kind, files).await?
+            }
+            CLIENT_POLICIES_DIR if client.supports(Capability::ClientPolicies) => {
+                capture_client_policies(client, files).await?
+            }
+            _ => Vec::new(),
+        };
+        resources.extend(entries);
+    }
+    Ok(RealmSnapshot {
+        realm: realm_name.to_string(),
+        resources,
+    })
+}
+
+async fn capture_resources<T>(
+    client: &KeycloakClient,
+    kind: &str,
+    files: &[(PathBuf, Value)],
+) -> Result<Vec<SnapshotEntry>>
+where
+    T: KeycloakResource + Serialize + DeserializeOwned,
+{
+    let remote: HashMap<String, T> = client
+        .get_resources::<T>()
+        .await
+        .with_context(|| format!("Failed to fetch {} for the snapshot", kind))?
+        .into_iter()
+        .filter_map(|r| r.get_identity().map(|identity| (identity, r)))
+        .collect();
+    let mut entries = Vec::new();
+    for (_, value) in files {
+        // Invalid files are reported by the apply itself
+        let Some(identity) = serde_json::from_value::<T>(value.clone())
+            .ok()
+            .and_then(|local| local.get_identity())
+        else {
+            continue;
+        };
+        let before = remote
+            .get(&identity)
+            .map(serde_json::to_value)
+            .transpose()?;
+        entries.push(SnapshotEntry {
+            kind: kind.to_string(),
+            identity,
+            before,
+        });
+    }
+    Ok(entries)
+}
+
+async fn capture_client_policies(
+    client: &KeycloakClient,
+    files: &[(PathBuf, Value)],
+) -> Result<Vec<SnapshotEntry>> {
+    let mut entries = Vec::new();
+    for (path, _) in files {
+        let file = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let before = match file {
+            PROFILES_FILE => serde_json::to_value(client.get_client_profiles().await?)?,
+            POLICIES_FILE => serde_json::to_value(client.get_client_policies().await?)?,
+            _ => continue,
+        };
+        entries.push(SnapshotEntry {
+            kind: CLIENT_POLICIES_DIR.to_string(),
+            identity: file.trim_end_matches(".yaml").to_string(),
+            before: (!before.is_null()).then_some(before),
+        });
+    }
+    Ok(entries)
+}
+
+/// The directory of a new snapshot of the workspace. Two applies within the same second
+/// get distinct directories.
+pub fn new_snapshot_dir(workspace_dir: &Path) -> PathBuf {
+    let id = new_snapshot_id();
+    let base = snapshots_dir(workspace_dir);
+    let mut dir = base.join(&id);
+    let mut n = 1;
+    while dir.exists() {
+        dir = base.join(format!("{}-{}", id, n));
+        n += 1;
+    }
+    dir
+}
+
+/// Writes the snapshot of a realm to `<dir>/<realm>.json`. Snapshots hold the remote
+/// representations as they are, secrets included, so they are only readable by the owner.
+pub async fn save(dir: &Path, snapshot: &RealmSnapshot) -> Result<PathBuf> {
+    fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("Failed to create snapshot directory {:?}", dir))?;
+    let path = dir.join(format!("{}.json", snapshot.realm));
+    crate::utils::write_secure(&path, &serde_json::to_string_pretty(snapshot)?).await?;
+    Ok(path)
+}
+
+/// The ids of the snapshots of the workspace, oldest first.
+pub async fn list(workspace_dir: &Path) -> Result<Vec<String>> {
+    let dir = snapshots_dir(workspace_dir);
+    let mut ids = Vec::new();
+    if !fs::try_exists(&dir).await? {
+        return Ok(ids);
+    }
+    let mut entries = fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            ids.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+/// Removes the oldest snapshots of the workspace, keeping the last `settings.keep()`.
+/// Returns the ids of the removed snapshots.
+pub async fn retain(workspace_dir: &Path, settings: &SnapshotSettings) -> Result<Vec<String>> {
+    let ids = list(workspace_dir).await?;
+    let removed = ids.len().saturating_sub(settings.keep());
+    let dir = snapshots_dir(workspace_dir);
+    for id in &ids[..removed] {
+        fs::remove_dir_all(dir.join(id))
+            .await
+            .with_context(|| format!("Failed to remove snapshot {:?}", dir.join(id)))?;
+    }
+    Ok(ids[..removed].to_vec())
+}
+
+/// Restores the snapshot `id` (the latest one by default) of the given realms (all
+/// realms of the snapshot when empty): resources the `apply` created are deleted, the
+/// others are put back as they were, recreating them if they are gone.
+pub async fn rollback(
+    client: &KeycloakClient,
+    workspace_dir: &Path,
+    realms: &[String],
+    id: Option<&str>,
+    yes: bool,
+    ui: Arc<dyn Ui>,
+    ws: &WorkspaceContext,
+) -> Result<()> {
+    let _lock = LocalLock::acquire(workspace_dir, &ws.lock).await?;
+    let ids = list(workspace_dir).await?;
+    let id = match id {
+        Some(id) if ids.iter().any(|i| i == id) => id.to_string(),
+        Some(id) => anyhow::bail!(
+            "Snapshot '{}' not found, available snapshots: {}",
+            id,
+            if ids.is_empty() {
+                "none".to_string()
+            } else {
+                ids.join(", ")
+            }
+        ),
+        None => ids
+            .last()
+            .cloned()
+            .with_context(|| format!("No snapshots found in {:?}", snapshots_dir(workspace_dir)))?,
+    };
+
+    let dir = snapshots_dir(workspace_dir).join(&id);
+    let mut snapshots = Vec::new();
+    let mut entries = fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let content = fs::read_to_string(&path).await?;
+        let snapshot: RealmSnapshot = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to read snapshot {:?}", path))?;
+        if realms.is_empty() || realms.contains(&snapshot.realm) {
+            snapshots.push(snapshot);
+        }
+    }
+    snapshots.sort_by(|a, b| a.realm.cmp(&b.realm));
+
+    for snapshot in snapshots {
+        println!(
+            "\n{} {}",
+            ACTION,
+            style(format!(
+                "Rolling back realm {} to snapshot {}",
+                snapshot.realm, id
+            ))
+            .cyan()
+            .bold()
+        );
+        if snapshot.resources.is_empty() {
+            println!("  {} {}", CHECK, style("Nothing to restore").green());
+            continue;
+        }
+        for entry in &snapshot.resources {
+            let action = if entry.before.is_some() {
+                "restore"
+            } else {
+                "delete"
+            };
+            println!("  {} {} {}", action, entry.kind, entry.identity);
+        }
+        if !yes && !ui.confirm(&format!("Roll back realm '{}'?", snapshot.realm), false)? {
+            println!("{} {}", WARN, style("Skipped").yellow());
+            continue;
+        }
+        let mut realm_client = client.clone();
+        realm_client.set_target_realm(snapshot.realm.clone());
+        with_server_lock(
+            &realm_client,
+            &snapshot.realm,
+            &ws.lock,
+            restore_realm(&realm_client, &snapshot),
+        )
+        .await
+        .with_context(|| format!("Failed to roll back realm '{}'", snapshot.realm))?;
+    }
+    Ok(())
+}
+
+async fn restore_realm(client: &KeycloakClient, snapshot: &RealmSnapshot) -> Result<()> {
+    // Created resources go first, dependents before what they depend on; the others
+    // are restored in dependency order.
+    for delete in [true, false] {
+        let kinds: Vec<&&str> = if delete {
+            SNAPSHOT_TYPES.iter().rev().collect()
+        } else {
+            SNAPSHOT_TYPES.iter().collect()
+        };
+        for kind in kinds {
+            let entries: Vec<&SnapshotEntry> = snapshot
+                .resources
+                .iter()
+                .filter(|e| e.kind == *kind && e.before.is_none() == delete)
+                .collect();
+            if entries.is_empty() {
+                continue;
+            }
+            match *kind {
+                "realm" => {
+                    for entry in entries {
+                        if let Some(before) = &entry.before {
+                            let realm: RealmRepresentation =
+                                serde_json::from_value(before.clone())?;
+                            client.update_realm(&realm).await?;
+                            println!(
+                                "  {} {}",
+                                SUCCESS_UPDATE,
+                                style("Restored realm configuration").cyan()
+                            );
+                        }
+                    }
+                }
+                "identity-providers" => {
+                    restore_resources::<IdentityProviderRepresentation>(client, &entries).await?
+                }
+                "roles" => restore_resources::<RoleRepresentation>(client, &entries).await?,
+                "client-scopes" => {
+                    restore_resources::<ClientScopeRepresentation>(client, &entries).await?
+                }
+                "clients" => restore_resources::<ClientRepresentation>(client, &entries).await?,
+                "authentication-flows" => {
+                    restore_resources::<AuthenticationFlowRepresentation>(client, &entries).await?
+                }
+                "required-actions" => {
+                    restore_resources::<RequiredActionProviderRepresentation>(client, &entries)
+                        .await?
+                }
+                "groups" => restore_resources::<GroupRepresentation>(client, &entries).await?,
+                "users" => restore_resources::<UserRepresentation>(client, &entries).await?,
+                "components" | "keys" => {
+                    restore_resources::<ComponentRepresentation>(client, &entries).await?
+                }
+                "organizations" => {
+                    restore_resources::<OrganizationRepresentation>(client, &entries).await?
+                }
+                CLIENT_POLICIES_DIR => restore_client_policies(client, &entries).await?,
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn restore_resources<T>(client: &KeycloakClient, entries: &[&SnapshotEntry]) -> Result<()>
+where
+    T: KeycloakResource + Serialize + DeserializeOwned,
+{
+    let existing: HashMap<String, String> = client
+        .get_resources::<T>()
+        .await?
+        .into_iter()
+        .filter_map(|r| Some((r.get_identity()?, r.get_id()?.to_string())))
+        .collect();
+    for entry in entries {
+        let id = existing.get(&entry.identity);
+        match (&entry.before, id) {
+            (Some(before), Some(id)) => {
+                let mut rep: T = serde_json::from_value(before.clone())?;
+                rep.set_id(Some(id.clone()));
+                client.update_resource(id, &rep).await.with_context(|| {
+                    format!("Failed to restore {} {}", entry.kind, entry.identity)
+                })?;
+                println!(
+                    "  {} Restored {} {}",
+                    SUCCESS_UPDATE, entry.kind, entry.identity
+                );
+            }
+            (Some(before), None) => {
+                let rep: T = serde_json::from_value(before.clone())?;
+                client.create_resource(&rep).await.with_context(|| {
+                    format!("Failed to recreate {} {}", entry.kind, entry.identity)
+                })?;
+                println!(
+                    "  {} Recreated {} {}",
+                    SUCCESS_CREATE, entry.kind, entry.identity
+                );
+            }
+            (None, Some(id)) => {
+                client.delete_resource::<T>(id).await.with_context(|| {
+                    format!("Failed to delete {} {}", entry.kind, entry.identity)
+                })?;
+                println!(
+                    "  {} Deleted {} {}",
+                    SUCCESS_DELETE, entry.kind, entry.identity
+                );
+            }
+            (None, None) => {}
+        }
+    }
+    Ok(())
+}
+
+async fn restore_client_policies(
+    client: &KeycloakClient,
+    entries: &[&SnapshotEntry],
+) -> Result<()> {
+    for entry in entries {
+        let Some(before) = &entry.before else {
+            continue;
+        };
+        match format!("{}.yaml", entry.identity).as_str() {
+            PROFILES_FILE => {
+                client
+                    .update_client_profiles(&serde_json::from_value(before.clone())?)
+                    .await?
+            }
+            POLICIES_FILE => {
+                client
+                    .update_client_policies(&serde_json::from_value(before.clone())?)
+                    .await?
+            }
+            _ => continue,
+        }
+        println!("  {} Restored client {}", SUCCESS_UPDATE, entry.identity);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_snapshot_format() {
+        let snapshot = RealmSnapshot {
+            realm: "master".to_string(),
+            resources: vec![
+                SnapshotEntry {
+                    kind: "roles".to_string(),
+                    identity: "admin".to_string(),
+                    before: Some(json!({"name": "admin"})),
+                },
+                SnapshotEntry {
+                    kind: "clients".to_string(),
+                    identity: "new-app".to_string(),
+                    before: None,
+                },
+            ],
+        };
+        let value = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(value["resources"][0]["type"], "roles");
+        assert!(value["resources"][1].get("before").is_none());
+        assert_eq!(
+            serde_json::from_value::<RealmSnapshot>(value).unwrap(),
+            snapshot
+        );
+
+        let id = new_snapshot_id();
+        assert_eq!(id.len(), "20261018T224828Z".len());
+        assert!(id.ends_with('Z'));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_directories() {
+        let dir = tempdir().unwrap();
+        assert!(list(dir.path()).await.unwrap().is_empty());
+
+        let first = new_snapshot_dir(dir.path());
+        save(&first, &RealmSnapshot::default()).await.unwrap();
+        let second = new_snapshot_dir(dir.path());
+        assert_ne!(first, second);
+        save(&second, &RealmSnapshot::default()).await.unwrap();
+
+        let ids = list(dir.path()).await.unwrap();
+        assert_eq!(ids.len(), 2);
+        assert!(second.ends_with(&ids[1]));
+        // The state directory stays out of version control
+        create_state_dir(dir.path()).await.unwrap();
+        let gitignore = dir.path().join(STATE_DIR).join(".gitignore");
+        assert_eq!(std::fs::read_to_string(gitignore).unwrap(), "*\n");
+
+        // Only the latest snapshots are kept
+        let third = new_snapshot_dir(dir.path());
+        save(&third, &RealmSnapshot::default()).await.unwrap();
+        let keep = SnapshotSettings { keep: Some(2) };
+        assert_eq!(
+            retain(dir.path(), &keep).await.unwrap(),
+            vec![ids[0].clone()]
+        );
+        let left = list(dir.path()).await.unwrap();
+        assert_eq!(left.len(), 2);
+        assert_eq!(left[0], ids[1]);
+        assert!(third.ends_with(&left[1]));
+        assert!(retain(dir.path(), &keep).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+use crate::models::{AttributeStyle, KeycloakResource, ResourceMeta};
+use crate::snapshot::STATE_DIR;
+use crate::utils::secrets::redact_secrets;
+use crate::utils::secrets::rules::SecretRules;
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::sync::Mutex;
+
+/// Attribute naming the workspace that created a resource.
+pub const MANAGED_BY_ATTRIBUTE: &str = "kcd.managed-by";
+/// Attribute holding the hash of the local representation last applied to a resource.
+pub const LAST_APPLIED_ATTRIBUTE: &str = "kcd.last-applied";
+
+/// What kcd knows about a remote resource it applied.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ownership {
+    /// The workspace that created the resource; `None` for resources that existed before
+    /// kcd first applied them.
+    #[serde(rename = "managedBy", default, skip_serializing_if = "Option::is_none")]
+    pub managed_by: Option<String>,
+    /// [`hash`] of the local representation last applied.
+    #[serde(rename = "lastApplied")]
+    pub last_applied: String,
+}
+
+impl Ownership {
+    /// Whether the workspace `id` created the resource.
+    pub fn is_managed_by(&self, id: &str) -> bool {
+        self.managed_by.as_deref() == Some(id)
+    }
+}
+
+/// The id resources created from the workspace are marked with: the `configured`
+/// `workspace_id`, or the name of the workspace directory.
+pub fn workspace_id(configured: Option<&str>, workspace_dir: &Path) -> String {
+    if let Some(id) = configured {
+        return id.to_string();
+    }
+    std::fs::canonicalize(workspace_dir)
+        .unwrap_or_else(|_| workspace_dir.to_path_buf())
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "workspace".to_string())
+}
+
+/// A local representation as kcd records it once applied: without kcd's own attributes
+/// and with every secret replaced by the same placeholder, so that neither the state
+/// files nor the hash on the server say anything about them. `rules` are the secret
+/// detection rules of the workspace.
+pub fn applied_value<T: Serialize + ResourceMeta>(rep: &T, rules: &SecretRules) -> Result<Value> {
+    let mut value = serde_json::to_value(rep)?;
+    remove_markers(&mut value);
+    redact_secrets(&mut value, T::SECRET_PREFIX, rules);
+    Ok(value)
+}
+
+/// A stable hash of the [`applied_value`] of a local representation.
+pub fn hash<T: Serialize + ResourceMeta>(rep: &T, rules: &SecretRules) -> Result<String> {
+    Ok(hash_value(&applied_value(rep, rules)?))
+}
+
+/// The SHA-256 of `value`, in hex.
+pub fn hash_value(value: &Value) -> String {
+    // Objects serialize with sorted keys, so equal representations hash the same.
+    let digest = Sha256::digest(value.to_string().as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Removes kcd's own attributes (`kcd.*`) from a serialized representation.
+pub fn remove_markers(value: &mut Value) {
+    if let Some(attributes) = value.get_mut("attributes").and_then(Value::as_object_mut) {
+        attributes.retain(|key, _| !key.starts_with("kcd."));
+    }
+}
+
+/// `rep` without kcd's own attributes, e.g. to export it.
+pub fn without_markers<T: Serialize + DeserializeOwned>(rep: T) -> Result<T> {
+    let mut value = serde_json::to_value(&rep)?;
+    if value
+        .get("attributes")
+        .and_then(Value::as_object)
+        .is_none_or(|attributes| !attributes.keys().any(|k| k.starts_with("kcd.")))
+    {
+        return Ok(rep);
+    }
+    remove_markers(&mut value);
+    Ok(serde_json::from_value(value)?)
+}
+
+/// The ownership recorded in the attributes of `rep`, if its type has attributes and it
+/// was applied by kcd.
+pub fn read_markers<T: Serialize + ResourceMeta>(rep: &T) -> Option<Ownership> {
+    if T::ATTRIBUTES == AttributeStyle::None {
+        return None;
+    }
+    let value = serde_json::to_value(rep).ok()?;
+    let attribute = |name: &str| match &value["attributes"][name] {
+        Value::String(s) => Some(s.clone()),
+        Value::Array(items) => items.first()?.as_str().map(str::to_string),
+        _ => None,
+    };
+    Some(Ownership {
+        managed_by: attribute(MANAGED_BY_ATTRIBUTE),
+        last_applied: attribute(LAST_APPLIED_ATTRIBUTE)?,
+    })
+}
+
+/// Writes `ownership` into the attributes of `rep`. Representations without attributes
+/// are left as they are; their ownership goes to the [`StateFile`].
+pub fn stamp<T>(rep: &mut T, ownership: &Ownership) -> Result<()>
+where
+    T: Serialize + DeserializeOwned + ResourceMeta,
+{
+    let wrap = |s: &str| match T::ATTRIBUTES {
+        AttributeStyle::Multi => json!([s]),
+        _ => json!(s),
+    };
+    if T::ATTRIBUTES == AttributeStyle::None {
+        return Ok(());
+    }
+    let mut value = serde_json::to_value(&*rep)?;
+    let object = value
+        .as_object_mut()
+        .context("Representation is not an object")?;
+    let attributes = object.entry("attributes").or_insert_with(|| json!({}));
+    if attributes.is_null() {
+        *attributes = json!({});
+    }
+    let attributes = attributes
+        .as_object_mut()
+        .context("Representation attributes are not a map")?;
+    if let Some(managed_by) = &ownership.managed_by {
+        attributes.insert(MANAGED_BY_ATTRIBUTE.to_string(), wrap(managed_by));
+    }
+    attributes.insert(
+        LAST_APPLIED_ATTRIBUTE.to_string(),
+        wrap(&ownership.last_applied),
+    );
+    *rep = serde_json::from_value(value)?;
+    Ok(())
+}
+
+/// The ownership of resources whose representation has no attributes (identity
+/// providers, users, flows, ...), stored as `.kcd/state.json`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct StateFile {
+    /// By realm, resource type and identity.
+    #[serde(default)]
+    pub resources: BTreeMap<String, BTreeMap<String, BTreeMap<String, Ownership>>>,
+}
+
+impl StateFile {
+    pub fn get(&self, realm: &str, kind: &str, identity: &str) -> Option<&Ownership> {
+        self.resources.get(realm)?.get(kind)?.get(identity)
+    }
+
+    pub fn insert(&mut self, realm: &str, kind: &str, identity: &str, ownership: Ownership) {
+        self.resources
+            .entry(realm.to_string())
+            .or_default()
+            .entry(kind.to_string())
+            .or_default()
+            .insert(identity.to_string(), ownership);
+    }
+
+    pub fn remove(&mut self, realm: &str, kind: &str, identity: &str) {
+        if let Some(kinds) = self.resources.get_mut(realm) {
+            if let Some(resources) = kinds.get_mut(kind) {
+                resources.remove(identity);
+                if resources.is_empty() {
+                    kinds.remove(kind);
+                }
+            }
+            if kinds.is_empty() {
+                self.resources.remove(realm);
+            }
+        }
+    }
+
+    /// The ownership of the remote `rep` of `realm`: its markers, or the recorded one
+    /// when its type has no attributes.
+    pub fn owner<T>(&self, realm: &str, rep: &T) -> Option<Ownership>
+    where
+        T: KeycloakResource + ResourceMeta + Serialize,
+    {
+        match T::ATTRIBUTES {
+            AttributeStyle::None => self.get(realm, T::DIR_NAME, &rep.get_identity()?).cloned(),
+            _ => read_markers(rep),
+        }
+    }
+}
+
+pub fn state_file(workspace_dir: &Path) -> PathBuf {
+    workspace_dir.join(STATE_DIR).join("state.json")
+}
+
+/// Reads the state file of the workspace; a missing one is empty.
+pub async fn load(workspace_dir: &Path) -> Result<StateFile> {
+    let path = state_file(workspace_dir);
+    match fs::read_to_string(&path).await {
+        Ok(content) => serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse state file {:?}", path)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(StateFile::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read state file {:?}", path)),
+    }
+}
+
+/// Serializes the updates of the state file between the tasks of a run.
+static UPDATE: Mutex<()> = Mutex::const_new(());
+
+/// Changes the state file of the workspace with `change`.
+pub async fn update(workspace_dir: &Path, change: impl FnOnce(&mut StateFile)) -> Result<()> {
+    let _guard = UPDATE.lock().await;
+    let mut state = load(workspace_dir).await?;
+    change(&mut state);
+    let path = state_file(workspace_dir);
+    crate::snapshot::create_state_dir(workspace_dir).await?;
+    crate::utils::write_secure(&path, &(serde_json::to_string_pretty(&state)? + "\n"))
+        .await
+        .with_context(|| format!("Failed to write state file {:?}", path))
+}
+
+/// The last-applied representations of the resources of one type in a realm, by
+/// identity, stored as `.kcd/last-applied/<realm>/<type>.json`.
+pub type LastApplied = BTreeMap<String, Value>;
+
+pub fn last_applied_file(workspace_dir: &Path, realm: &str, kind: &str) -> PathBuf {
+    workspace_dir
+        .join(STATE_DIR)
+        .join("last-applied")
+        .join(realm)
+        .join(format!("{}.json", kind))
+}
+
+/// Reads the last-applied representations of `kind` in `realm`; none when missing.
+pub async fn load_last_applied(
+    workspace_dir: &Path,
+    realm: &str,
+    kind: &str,
+) -> Result<LastApplied> {
+    let path = last_applied_file(workspace_dir, realm, kind);
+    match fs::read_to_string(&path).await {
+        Ok(content) => serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse last-applied file {:?}", path)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(LastApplied::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read last-applied file {:?}", path)),
+    }
+}
+
+/// Changes the last-applied representations of `kind` in `realm` with `change`. Each
+/// type of a realm is applied by a single task, so the file needs no lock of its own.
+pub async fn update_last_applied(
+    workspace_dir: &Path,
+    realm: &str,
+    kind: &str,
+    change: impl FnOnce(&mut LastApplied),
+) -> Result<()> {
+    let mut applied = load_last_applied(workspace_dir, realm, kind).await?;
+    change(&mut applied);
+    let path = last_applied_file(workspace_dir, realm, kind);
+    crate::snapshot::create_state_dir(workspace_dir).await?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    crate::utils::write_secure(&path, &(serde_json::to_string_pretty(&applied)? + "\n"))
+        .await
+        .with_context(|| format!("Failed to write last-applied file {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ClientRepresentation, IdentityProviderRepresentation, RoleRepresentation};
+
+    #[test]
+    fn test_markers() {
+        let ownership = Ownership {
+            managed_by: Some("prod".to_string()),
+            last_applied: "abc".to_string(),
+        };
+
+        let mut client: ClientRepresentation =
+            serde_json::from_value(json!({"clientId": "app", "attributes": {"pkce": "S256"}}))
+                .unwrap();
+        let before = hash(&client, &SecretRules::default()).unwrap();
+        stamp(&mut client, &ownership).unwrap();
+        assert_eq!(client.extra["attributes"]["kcd.managed-by"], "prod");
+        assert_eq!(client.extra["attributes"]["pkce"], "S256");
+        assert_eq!(read_markers(&client), Some(ownership.clone()));
+        // The markers are not part of the hash
+        assert_eq!(hash(&client, &SecretRules::default()).unwrap(), before);
+
+        let mut role: RoleRepresentation = serde_json::from_value(json!({"name": "r"})).unwrap();
+        stamp(&mut role, &ownership).unwrap();
+        assert_eq!(role.extra["attributes"]["kcd.last-applied"], json!(["abc"]));
+        assert_eq!(read_markers(&role), Some(ownership.clone()));
+        let role = without_markers(role).unwrap();
+        assert_eq!(role.extra["attributes"], json!({}));
+        assert_eq!(read_markers(&role), None);
+
+        // Identity providers have no attributes; the state file holds their ownership
+        let mut idp: IdentityProviderRepresentation =
+            serde_json::from_value(json!({"alias": "google"})).unwrap();
+        stamp(&mut idp, &ownership).unwrap();
+        assert!(idp.extra.is_empty());
+        let mut state = StateFile::default();
+        assert_eq!(state.owner("master", &idp), None);
+        state.insert("master", "identity-providers", "google", ownership.clone());
+        assert_eq!(state.owner("master", &idp), Some(ownership));
+        state.remove("master", "identity-providers", "google");
+        assert_eq!(state, StateFile::default());
+    }
+
+    #[test]
+    fn test_hash_masks_secrets() {
+        let client = |secret: &str| -> ClientRepresentation {
+            serde_json::from_value(json!({"clientId": "app", "secret": secret})).unwrap()
+        };
+        assert_eq!(
+            hash(&client("s3cr3t-1x"), &SecretRules::default()).unwrap(),
+            hash(&client("s3cr3t-2x"), &SecretRules::default()).unwrap()
+        );
+        // Not even the first and last characters of the secret
+        assert_eq!(
+            hash(&client("s3cr3t-1x"), &SecretRules::default()).unwrap(),
+            hash(&client("other"), &SecretRules::default()).unwrap()
+        );
+        assert_eq!(
+            applied_value(&client("other"), &SecretRules::default()).unwrap()["secret"],
+            "********"
+        );
+    }
+}
@@ -1,4 +1,8 @@
+pub mod dotenv;
+pub mod filter;
+pub mod hooks;
 pub mod secrets;
+pub mod selection;
 pub mod ui;
 pub mod yaml;
 use anyhow::Context;
@@ -86,10 +90,33 @@ pub fn to_sorted_yaml_with_secrets<T: Serialize>(
     value: &T,
     prefix: &str,
     secrets: &mut std::collections::BTreeMap<String, String>,
+    rules: &crate::utils::secrets::rules::SecretRules,
+) -> anyhow::Result<String> {
+    to_sorted_yaml_with_secret_refs(value, "", prefix, secrets, rules, &|name| {
+        format!("${{{}}}", name)
+    })
+}
+
+/// Like [`to_sorted_yaml_with_secrets`], with the secret rules of `resource` and a custom
+/// placeholder for extracted secrets.
+pub fn to_sorted_yaml_with_secret_refs<T: Serialize>(
+    value: &T,
+    resource: &str,
+    prefix: &str,
+    secrets: &mut std::collections::BTreeMap<String, String>,
+    rules: &crate::utils::secrets::rules::SecretRules,
+    reference: &dyn Fn(&str) -> String,
 ) -> anyhow::Result<String> {
     let mut json_value =
         serde_json::to_value(value).context("Failed to serialize to JSON value")?;
-    crate::utils::secrets::extract_secrets(&mut json_value, prefix, secrets);
+    crate::utils::secrets::extract_secrets_with(
+        &mut json_value,
+        resource,
+        prefix,
+        secrets,
+        rules,
+        reference,
+    );
     recursive_sort(&mut json_value);
     serde_yaml::to_string(&json_value).context("Failed to serialize to sorted YAML")
 }
@@ -318,7 +345,8 @@ mod tests {
             }
         });
 
-        let yaml = to_sorted_yaml_with_secrets(&val, "CLIENT", &mut secrets).unwrap();
+        let yaml =
+            to_sorted_yaml_with_secrets(&val, "CLIENT", &mut secrets, &Default::default()).unwrap();
         // current_prefix should be "CLIENT_myclient"
         // secret env var should be "KEYCLOAK_CLIENT_MYCLIENT_SECRET"
         // nested password env var should be "KEYCLOAK_CLIENT_MYCLIENT_NESTED_PASSWORD"
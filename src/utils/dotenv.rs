@@ -0,0 +1,264 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A `.secrets` (dotenv) file that can be updated in place, keeping comments, blank
+/// lines and the order of the entries it already has.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DotenvFile {
+    lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    Entry {
+        key: String,
+        value: String,
+        /// The line as read, rendered as is while the value is unchanged.
+        raw: Option<String>,
+    },
+    /// Comments, blank lines and anything that is not a `KEY=value` entry, kept verbatim.
+    Other(String),
+}
+
+/// What [`DotenvFile::merge`] and [`DotenvFile::prune`] changed, by key.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeSummary {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl MergeSummary {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl DotenvFile {
+    pub fn parse(content: &str) -> Self {
+        let lines = content
+            .lines()
+            .map(|line| match parse_entry(line) {
+                Some((key, value)) => Line::Entry {
+                    key,
+                    value,
+                    raw: Some(line.to_string()),
+                },
+                None => Line::Other(line.to_string()),
+            })
+            .collect();
+        Self { lines }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        // Like dotenv, the last definition of a duplicated key wins.
+        self.lines.iter().rev().find_map(|line| match line {
+            Line::Entry { key: k, value, .. } if k == key => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn keys(&self) -> BTreeSet<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Entry { key, .. } => Some(key.as_str()),
+                Line::Other(_) => None,
+            })
+            .collect()
+    }
+
+    /// Sets every key of `secrets`: existing entries are updated where they are (and
+    /// duplicates dropped), new ones are appended.
+    pub fn merge(&mut self, secrets: &BTreeMap<String, String>) -> MergeSummary {
+        let mut summary = MergeSummary::default();
+        for (key, value) in secrets {
+            match self.get(key) {
+                Some(current) if current == value => {}
+                Some(_) => summary.changed.push(key.clone()),
+                None => summary.added.push(key.clone()),
+            }
+        }
+
+        let mut written = BTreeSet::new();
+        self.lines.retain_mut(|line| match line {
+            Line::Entry { key, value, raw } => match secrets.get(key.as_str()) {
+                Some(new_value) => {
+                    if value != new_value {
+                        *value = new_value.clone();
+                        *raw = None;
+                    }
+                    written.insert(key.clone())
+                }
+                None => true,
+            },
+            Line::Other(_) => true,
+        });
+        for (key, value) in secrets {
+            if !written.contains(key) {
+                self.lines.push(Line::Entry {
+                    key: key.clone(),
+                    value: value.clone(),
+                    raw: None,
+                });
+            }
+        }
+        summary
+    }
+
+    /// Removes the entries whose key `keep` rejects and returns their keys.
+    pub fn prune(&mut self, keep: impl Fn(&str) -> bool) -> Vec<String> {
+        let mut removed = BTreeSet::new();
+        self.lines.retain(|line| match line {
+            Line::Entry { key, .. } if !keep(key) => {
+                removed.insert(key.clone());
+                false
+            }
+            _ => true,
+        });
+        removed.into_iter().collect()
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for line in &self.lines {
+            match line {
+                Line::Entry {
+                    raw: Some(text), ..
+                } => out.push_str(text),
+                Line::Entry { key, value, .. } => {
+                    out.push_str(key);
+                    out.push('=');
+                    out.push_str(&quote(value));
+                }
+                Line::Other(text) => out.push_str(text),
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn parse_entry(line: &str) -> Option<(String, String)> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with('#') {
+        return None;
+    }
+    let trimmed = trimmed.strip_prefix("export ").unwrap_or(trimmed);
+    let (key, raw) = trimmed.split_once('=')?;
+    let key = key.trim();
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+    {
+        return None;
+    }
+    Some((key.to_string(), unquote(raw.trim())))
+}
+
+fn unquote(raw: &str) -> String {
+    if let Some(rest) = raw.strip_prefix('\'') {
+        return rest.split('\'').next().unwrap_or_default().to_string();
+    }
+    if let Some(rest) = raw.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some(other) => value.push(other),
+                    None => {}
+                },
+                other => value.push(other),
+            }
+        }
+        return value;
+    }
+    // Unquoted values end at an inline ` #` comment.
+    match raw.find(" #") {
+        Some(idx) => raw[..idx].trim_end().to_string(),
+        None => raw.to_string(),
+    }
+}
+
+/// Quotes `value` when dotenv would otherwise alter it (whitespace, comments, `$`
+/// expansion, quotes).
+fn quote(value: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-./:+=@,%~^!?*&()[]{}<>|;".contains(c);
+    if value.chars().all(plain) {
+        value.to_string()
+    } else if !value.contains(['\'', '\n']) {
+        format!("'{}'", value)
+    } else {
+        let mut quoted = String::from("\"");
+        for c in value.chars() {
+            match c {
+                '"' | '\\' | '$' => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                '\n' => quoted.push_str("\\n"),
+                other => quoted.push(other),
+            }
+        }
+        quoted.push('"');
+        quoted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_keeps_comments_and_updates_in_place() {
+        let mut file = DotenvFile::parse(
+            "# Keycloak secrets\nKEYCLOAK_A=old\n\nexport OTHER='unrelated value'\nKEYCLOAK_B=same # note\nKEYCLOAK_A=dup\n",
+        );
+        assert_eq!(file.get("KEYCLOAK_A"), Some("dup"));
+        assert_eq!(file.get("OTHER"), Some("unrelated value"));
+        assert_eq!(file.get("KEYCLOAK_B"), Some("same"));
+
+        let summary = file.merge(&BTreeMap::from([
+            ("KEYCLOAK_A".to_string(), "new".to_string()),
+            ("KEYCLOAK_B".to_string(), "same".to_string()),
+            ("KEYCLOAK_C".to_string(), "with space".to_string()),
+        ]));
+        assert_eq!(summary.added, vec!["KEYCLOAK_C"]);
+        assert_eq!(summary.changed, vec!["KEYCLOAK_A"]);
+        assert_eq!(
+            file.render(),
+            "# Keycloak secrets\nKEYCLOAK_A=new\n\nexport OTHER='unrelated value'\nKEYCLOAK_B=same # note\nKEYCLOAK_C='with space'\n"
+        );
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut file = DotenvFile::parse("# c\nKEYCLOAK_A=1\nKEYCLOAK_B=2\n");
+        assert_eq!(file.prune(|k| k == "KEYCLOAK_A"), vec!["KEYCLOAK_B"]);
+        assert_eq!(file.render(), "# c\nKEYCLOAK_A=1\n");
+    }
+
+    #[test]
+    fn test_quote_round_trip() {
+        for value in [
+            "plain-value_1",
+            "has space",
+            "it's $HOME",
+            "a\"b\\c",
+            "#hash",
+        ] {
+            let line = format!("K={}", quote(value));
+            let file = DotenvFile::parse(&line);
+            assert_eq!(file.get("K"), Some(value), "value {:?}", value);
+            // `init_secrets` loads the file with dotenvy, which must read the same value.
+            let (_, loaded) = dotenvy::from_read_iter(line.as_bytes())
+                .next()
+                .unwrap()
+                .unwrap();
+            assert_eq!(loaded, value, "dotenvy value {:?}", value);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use crate::utils::secrets::rules::match_segments;
+use serde::Deserialize;
+use std::path::{Component, Path, PathBuf};
+
+/// A workspace-relative path glob such as `master/clients/admin-cli` or `*/users/**`.
+/// `*` matches within a segment and `**` any number of segments. The `.yaml` extension
+/// is optional, and a pattern naming a directory covers everything below it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathPattern {
+    segments: Vec<String>,
+}
+
+impl<'de> Deserialize<'de> for PathPattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl std::str::FromStr for PathPattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let trimmed = s.trim().trim_matches('/');
+        if trimmed.is_empty() || trimmed.split('/').any(|p| p.is_empty() || p == "..") {
+            anyhow::bail!("Invalid path pattern '{}'", s);
+        }
+        let segments = trimmed
+            .split('/')
+            .map(|segment| segment.strip_suffix(".yaml").unwrap_or(segment).to_string())
+            .collect();
+        Ok(Self { segments })
+    }
+}
+
+impl PathPattern {
+    /// Whether the pattern matches `relative` (segments without `.yaml`) or one of its
+    /// parent directories.
+    pub fn matches(&self, relative: &[String]) -> bool {
+        (1..=relative.len()).any(|len| match_segments(&self.segments, &relative[..len]))
+    }
+
+    /// Whether the pattern can match anything below the top-level directory `first`.
+    pub fn may_match_below(&self, first: &str) -> bool {
+        self.segments.first().is_some_and(|segment| {
+            segment == "**" || match_segments(std::slice::from_ref(segment), &[first.to_string()])
+        })
+    }
+}
+
+/// Which workspace files `plan` and `apply` work on (`ignore`, `managed` and
+/// `protected` in `kcd.yaml`).
+#[derive(Debug, Clone, Default)]
+pub struct FileFilter {
+    /// Workspace root the patterns are relative to.
+    pub root: PathBuf,
+    /// Files no command reads.
+    pub ignore: Vec<PathPattern>,
+    /// Resource types (`realm`, `clients`, `roles`, ...) planned and applied; all when `None`.
+    pub managed: Option<Vec<String>>,
+    /// Resources that are never planned or applied, e.g. the `admin-cli` client.
+    pub protected: Vec<PathPattern>,
+}
+
+impl FileFilter {
+    /// Segments of `path` below the workspace root, without the `.yaml` extension.
+    fn relative(&self, path: &Path) -> Option<Vec<String>> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let mut segments: Vec<String> = relative
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(s) => s.to_str().map(str::to_string),
+                _ => None,
+            })
+            .collect();
+        if let Some(last) = segments.last_mut()
+            && let Some(stem) = last.strip_suffix(".yaml")
+        {
+            *last = stem.to_string();
+        }
+        Some(segments)
+    }
+
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        self.relative(path)
+            .is_some_and(|rel| self.ignore.iter().any(|p| p.matches(&rel)))
+    }
+
+    /// Whether `plan` and `apply` handle the file: not ignored, of a managed resource
+    /// type and not protected.
+    pub fn is_managed(&self, path: &Path) -> bool {
+        let Some(rel) = self.relative(path) else {
+            return true;
+        };
+        if self.ignore.iter().any(|p| p.matches(&rel)) {
+            return false;
+        }
+        if self.protected.iter().any(|p| p.matches(&rel)) {
+            log::info!("Skipping protected resource {:?}", path);
+            return false;
+        }
+        match (&self.managed, rel.get(1)) {
+            (Some(managed), Some(kind)) => {
+                // `<realm>/realm.yaml` is the `realm` type, anything else its directory.
+                let kind = if rel.len() == 2 { "realm" } else { kind };
+                managed.iter().any(|m| m == kind)
+            }
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_filter() {
+        let filter = FileFilter {
+            root: PathBuf::from("/ws"),
+            ignore: vec!["*/users/test-*".parse().unwrap()],
+            managed: Some(vec!["realm".to_string(), "clients".to_string()]),
+            protected: vec!["master/clients/admin-cli.yaml".parse().unwrap()],
+        };
+        assert!(filter.is_managed(Path::new("/ws/master/realm.yaml")));
+        assert!(filter.is_managed(Path::new("/ws/master/clients/app.yaml")));
+        assert!(!filter.is_managed(Path::new("/ws/master/clients/admin-cli.yaml")));
+        assert!(!filter.is_managed(Path::new("/ws/master/roles/admin.yaml")));
+        assert!(filter.is_ignored(Path::new("/ws/dev/users/test-1.yaml")));
+        assert!(!filter.is_ignored(Path::new("/ws/dev/users/alice.yaml")));
+        // Files outside the workspace are not filtered.
+        assert!(filter.is_managed(Path::new("/elsewhere/x/roles/r.yaml")));
+
+        let dir: PathPattern = "*/authz".parse().unwrap();
+        let rel = |s: &str| s.split('/').map(str::to_string).collect::<Vec<_>>();
+        assert!(dir.matches(&rel("master/authz/app/scopes/read")));
+        assert!(!dir.matches(&rel("master/clients/authz")));
+        assert!("a/../b".parse::<PathPattern>().is_err());
+    }
+}
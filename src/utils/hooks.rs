@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+use tokio::process::Command;
+
+/// Shell commands run around `plan` and `apply` (`hooks:` in `kcd.yaml`).
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    #[serde(default)]
+    pub pre_plan: Vec<String>,
+    #[serde(default)]
+    pub post_plan: Vec<String>,
+    #[serde(default)]
+    pub pre_apply: Vec<String>,
+    #[serde(default)]
+    pub post_apply: Vec<String>,
+}
+
+/// Runs `commands` one after the other through the system shell, from the workspace
+/// root, stopping at the first failure. `KCD_HOOK` and `KCD_PROFILE` tell the command
+/// where it runs.
+pub async fn run_hooks(
+    stage: &str,
+    commands: &[String],
+    workspace: &Path,
+    profile: Option<&str>,
+) -> Result<()> {
+    for command in commands {
+        log::info!("Running {} hook: {}", stage, command);
+        let mut cmd = if cfg!(windows) {
+            let mut cmd = Command::new("cmd");
+            cmd.arg("/C").arg(command);
+            cmd
+        } else {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(command);
+            cmd
+        };
+        let status = cmd
+            .current_dir(workspace)
+            .env("KCD_HOOK", stage)
+            .env("KCD_PROFILE", profile.unwrap_or_default())
+            .status()
+            .await
+            .with_context(|| format!("Failed to run {} hook '{}'", stage, command))?;
+        if !status.success() {
+            anyhow::bail!("{} hook '{}' failed with {}", stage, command, status);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_run_hooks() {
+        let dir = tempdir().unwrap();
+        run_hooks(
+            "pre_apply",
+            &["echo \"$KCD_HOOK $KCD_PROFILE\" > hook.out".to_string()],
+            dir.path(),
+            Some("prod"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("hook.out")).unwrap(),
+            "pre_apply prod\n"
+        );
+
+        let err = run_hooks("post_plan", &["exit 3".to_string()], dir.path(), None)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("post_plan hook 'exit 3' failed")
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use super::SecretResolver;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::path::PathBuf;
+use tokio::process::Command;
+
+/// Resolves `${exec:command args}` to the standard output of the command, run from the
+/// workspace directory without a shell (arguments are split on whitespace).
+pub struct ExecResolver {
+    base_dir: PathBuf,
+}
+
+impl ExecResolver {
+    pub fn new(base_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            base_dir: base_dir.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl SecretResolver for ExecResolver {
+    async fn resolve(&self, key: &str) -> Result<Option<String>> {
+        let Some(command_line) = key.strip_prefix("exec:") else {
+            return Ok(None);
+        };
+        let mut args = command_line.split_whitespace();
+        let program = args
+            .next()
+            .with_context(|| format!("Empty command in secret '{}'", key))?;
+
+        let output = Command::new(program)
+            .args(args)
+            .current_dir(&self.base_dir)
+            .output()
+            .await
+            .with_context(|| format!("Failed to run secret command '{}'", command_line))?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Secret command '{}' failed ({}): {}",
+                command_line,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let stdout = String::from_utf8(output.stdout)
+            .with_context(|| format!("Secret command '{}' printed invalid UTF-8", command_line))?;
+        Ok(Some(stdout.trim_end_matches(['\r', '\n']).to_string()))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_exec_resolver() {
+        let resolver = ExecResolver::new(".");
+
+        assert_eq!(
+            resolver.resolve("exec:echo hunter2").await.unwrap(),
+            Some("hunter2".to_string())
+        );
+        assert!(resolver.resolve("vault:a/b#c").await.unwrap().is_none());
+        assert!(resolver.resolve("exec:false").await.is_err());
+        assert!(resolver.resolve("exec: ").await.is_err());
+    }
+}
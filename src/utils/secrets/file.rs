@@ -0,0 +1,58 @@
+use super::SecretResolver;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::path::PathBuf;
+
+/// Resolves `${file:/path/to/secret}` to the content of the file, e.g. a Kubernetes
+/// mounted secret. Relative paths are taken from the workspace directory.
+pub struct FileResolver {
+    base_dir: PathBuf,
+}
+
+impl FileResolver {
+    pub fn new(base_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            base_dir: base_dir.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl SecretResolver for FileResolver {
+    async fn resolve(&self, key: &str) -> Result<Option<String>> {
+        let Some(path) = key.strip_prefix("file:") else {
+            return Ok(None);
+        };
+        let path = self.base_dir.join(path.trim());
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read secret file {:?}", path))?;
+        // Mounted secrets and `echo`-created files usually end with a newline.
+        Ok(Some(content.trim_end_matches(['\r', '\n']).to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_file_resolver() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("db-password"), "s3cr3t\n").unwrap();
+        let resolver = FileResolver::new(dir.path());
+
+        assert_eq!(
+            resolver.resolve("file:db-password").await.unwrap(),
+            Some("s3cr3t".to_string())
+        );
+        let absolute = format!("file:{}", dir.path().join("db-password").display());
+        assert_eq!(
+            resolver.resolve(&absolute).await.unwrap(),
+            Some("s3cr3t".to_string())
+        );
+        assert!(resolver.resolve("KEYCLOAK_X").await.unwrap().is_none());
+        assert!(resolver.resolve("file:missing").await.is_err());
+    }
+}
@@ -1,11 +1,68 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use rules::SecretRules;
 use serde_json::Value;
 use std::collections::HashMap;
-use std::sync::Arc;
-
+use std::sync::{Arc, OnceLock};
+
+pub mod exec;
+pub mod file;
+pub mod rules;
+pub mod sink;
+pub mod sops;
+pub mod template;
 pub mod vault;
 
+/// Secret sources that can be enabled per profile through `secret_backends`.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretBackend {
+    /// `${KEYCLOAK_*}` from the environment or the secrets file.
+    Env,
+    /// `${vault:mount/path#field}` from HashiCorp Vault.
+    Vault,
+    /// `${file:/path}` from plain files.
+    File,
+    /// `${sops:file#key}` from age-encrypted SOPS files.
+    Sops,
+    /// `${exec:command args}` from the output of a command.
+    Exec,
+}
+
+impl SecretBackend {
+    /// Backends used when a profile does not list any.
+    pub const DEFAULT: &'static [SecretBackend] = &[SecretBackend::Vault, SecretBackend::Env];
+
+    /// The reference prefix handled by the backend, if any.
+    pub fn prefix(self) -> Option<&'static str> {
+        match self {
+            SecretBackend::Env => None,
+            SecretBackend::Vault => Some("vault:"),
+            SecretBackend::File => Some("file:"),
+            SecretBackend::Sops => Some("sops:"),
+            SecretBackend::Exec => Some("exec:"),
+        }
+    }
+}
+
+/// Returns the backend a `${...}` reference is meant for.
+fn backend_of(var_name: &str) -> Option<SecretBackend> {
+    [
+        SecretBackend::Vault,
+        SecretBackend::File,
+        SecretBackend::Sops,
+        SecretBackend::Exec,
+    ]
+    .into_iter()
+    .find(|b| b.prefix().is_some_and(|p| var_name.starts_with(p)))
+}
+
+/// Whether `${name}` refers to a secret (`KEYCLOAK_*` or a backend reference) rather
+/// than a plain variable.
+pub fn is_secret_reference(name: &str) -> bool {
+    name.starts_with("KEYCLOAK_") || backend_of(name).is_some()
+}
+
 #[async_trait]
 pub trait SecretResolver: Send + Sync {
     async fn resolve(&self, key: &str) -> Result<Option<String>>;
@@ -93,6 +150,40 @@ pub fn is_secret_key(key: &str, prefix: &str) -> bool {
     false
 }
 
+/// Whether the field at `path` of a `resource` (e.g. `["config", "clientSecret"]` of an
+/// `idp`) is a secret: the workspace `rules` decide first, then [`is_secret_key`].
+pub fn is_secret_field(rules: &SecretRules, resource: &str, path: &[&str], prefix: &str) -> bool {
+    rules
+        .classify(resource, path)
+        .unwrap_or_else(|| path.last().is_some_and(|key| is_secret_key(key, prefix)))
+}
+
+/// Multivalued `config` entries (components, keys) hold their values as string lists.
+/// Other string lists (e.g. `requiredCredentials: [password]`) are only secrets when a
+/// workspace rule says so.
+fn is_secret_list(
+    value: &Value,
+    rules: &SecretRules,
+    resource: &str,
+    path: &[&str],
+    prefix: &str,
+) -> bool {
+    let Some(items) = value.as_array() else {
+        return false;
+    };
+    if items.is_empty() || !items.iter().all(Value::is_string) {
+        return false;
+    }
+    match rules.classify(resource, path) {
+        Some(decision) => decision,
+        None => {
+            path.len() >= 2
+                && path[path.len() - 2] == "config"
+                && is_secret_key(path[path.len() - 1], prefix)
+        }
+    }
+}
+
 /// Heuristics to identify if a string looks like a boolean or simple toggle.
 fn is_boolean_string(s: &str) -> bool {
     let lower = s.to_lowercase();
@@ -133,6 +224,47 @@ pub fn extract_secrets(
     value: &mut Value,
     prefix: &str,
     secrets: &mut std::collections::BTreeMap<String, String>,
+    rules: &SecretRules,
+) {
+    extract_secrets_with(value, "", prefix, secrets, rules, &|name| {
+        format!("${{{}}}", name)
+    });
+}
+
+/// Like [`extract_secrets`], applying the rules for `resource` and letting `reference`
+/// build the placeholder written in place of each secret from its generated name (e.g. a
+/// `${vault:...#NAME}` reference).
+pub fn extract_secrets_with(
+    value: &mut Value,
+    resource: &str,
+    prefix: &str,
+    secrets: &mut std::collections::BTreeMap<String, String>,
+    rules: &SecretRules,
+    reference: &dyn Fn(&str) -> String,
+) {
+    walk_secrets(
+        value,
+        rules,
+        resource,
+        prefix,
+        &mut Vec::new(),
+        &mut |s, env_var_name| {
+            if !is_boolean_string(s) {
+                secrets.insert(env_var_name.to_string(), s.clone());
+                *s = reference(env_var_name);
+            }
+        },
+    );
+}
+
+/// Calls `on_secret` with every secret string of `value` and its generated variable name.
+fn walk_secrets<'a>(
+    value: &'a mut Value,
+    rules: &SecretRules,
+    resource: &str,
+    prefix: &str,
+    path: &mut Vec<&'a str>,
+    on_secret: &mut dyn FnMut(&mut String, &str),
 ) {
     match value {
         Value::Object(map) => {
@@ -149,11 +281,23 @@ pub fn extract_secrets(
             };
 
             for (k, v) in map.iter_mut() {
+                path.push(k.as_str());
                 if let Value::String(s) = v {
-                    if is_secret_key(k, &current_prefix) && !is_boolean_string(s) {
-                        let env_var_name = format_env_var_name(&current_prefix, k);
-                        secrets.insert(env_var_name.clone(), s.clone());
-                        *s = format!("${{{}}}", env_var_name);
+                    if is_secret_field(rules, resource, path, &current_prefix) {
+                        on_secret(s, &format_env_var_name(&current_prefix, k));
+                    }
+                } else if is_secret_list(v, rules, resource, path, &current_prefix) {
+                    let name = format_env_var_name(&current_prefix, k);
+                    let items = v.as_array_mut().map(Vec::as_mut_slice).unwrap_or_default();
+                    let single = items.len() == 1;
+                    for (i, item) in items.iter_mut().enumerate() {
+                        if let Value::String(s) = item {
+                            if single {
+                                on_secret(s, &name);
+                            } else {
+                                on_secret(s, &format!("{}_{}", name, i));
+                            }
+                        }
                     }
                 } else if v.is_object() || v.is_array() {
                     let new_prefix = if current_prefix.is_empty() {
@@ -161,21 +305,23 @@ pub fn extract_secrets(
                     } else {
                         format!("{}_{}", current_prefix, k)
                     };
-                    extract_secrets(v, &new_prefix, secrets);
+                    walk_secrets(v, rules, resource, &new_prefix, path, on_secret);
                 }
+                path.pop();
             }
         }
         Value::Array(arr) => {
             for (i, v) in arr.iter_mut().enumerate() {
                 let new_prefix = format!("{}_{}", prefix, i);
-                extract_secrets(v, &new_prefix, secrets);
+                walk_secrets(v, rules, resource, &new_prefix, path, on_secret);
             }
         }
         _ => {}
     }
 }
 
-/// Recursively substitute ${ENV_VAR} or ${vault:path#key} with actual values
+/// Recursively substitute ${ENV_VAR}, ${vault:path#key} and inline ${VAR:-default}
+/// references with actual values (see [`template`])
 #[async_recursion::async_recursion]
 pub async fn substitute_secrets(
     value: &mut Value,
@@ -196,16 +342,8 @@ pub async fn substitute_secrets(
                 .collect();
             futures::future::try_join_all(futures).await?;
         }
-        Value::String(s) if s.starts_with("${") && s.ends_with("}") => {
-            let var_name = &s[2..s.len() - 1];
-            if let Some(val) = resolver.resolve(var_name).await? {
-                *s = val;
-            } else if var_name.starts_with("KEYCLOAK_") {
-                return Err(anyhow::anyhow!(
-                    "Missing required secret or environment variable: {}",
-                    var_name
-                ));
-            }
+        Value::String(s) if s.contains("${") => {
+            *s = template::interpolate(s, resolver.as_ref()).await?;
         }
         _ => {}
     }
@@ -213,7 +351,7 @@ pub async fn substitute_secrets(
 }
 
 /// Helper to obfuscate a single string
-fn obfuscate_string(s: &str) -> String {
+pub fn obfuscate_string(s: &str) -> String {
     if s.is_empty() {
         return s.to_string();
     }
@@ -227,45 +365,85 @@ fn obfuscate_string(s: &str) -> String {
     format!("{}***{}", first, last)
 }
 
-/// Recursively obfuscate known secret fields
-pub fn obfuscate_secrets(value: &mut Value, prefix: &str) {
-    match value {
-        Value::Object(map) => {
-            let id = get_object_identifier(map);
+/// Recursively obfuscate known secret fields. `prefix` is the resource kind (e.g.
+/// `client`), which also selects the workspace `rules` that apply.
+pub fn obfuscate_secrets(value: &mut Value, prefix: &str, rules: &SecretRules) {
+    walk_secrets(
+        value,
+        rules,
+        prefix,
+        prefix,
+        &mut Vec::new(),
+        &mut |s, _| {
+            *s = obfuscate_string(s);
+        },
+    );
+}
 
-            let current_prefix = if let Some(id_str) = id {
-                if prefix.is_empty() {
-                    id_str
-                } else {
-                    format!("{}_{}", prefix, id_str)
-                }
-            } else {
-                prefix.to_string()
-            };
+/// What [`redact_secrets`] replaces every secret with.
+pub const REDACTED: &str = "********";
+
+/// Replaces every secret of `value` with [`REDACTED`]. Unlike [`obfuscate_secrets`],
+/// nothing of the secret is kept, not even its first and last characters.
+pub fn redact_secrets(value: &mut Value, prefix: &str, rules: &SecretRules) {
+    walk_secrets(
+        value,
+        rules,
+        prefix,
+        prefix,
+        &mut Vec::new(),
+        &mut |s, _| {
+            *s = REDACTED.to_string();
+        },
+    );
+}
 
-            for (k, v) in map.iter_mut() {
-                if let Value::String(s) = v {
-                    if is_secret_key(k, &current_prefix) {
-                        *s = obfuscate_string(s);
-                    }
-                } else if v.is_object() || v.is_array() {
-                    let new_prefix = if current_prefix.is_empty() {
-                        k.clone()
-                    } else {
-                        format!("{}_{}", current_prefix, k)
-                    };
-                    obfuscate_secrets(v, &new_prefix);
-                }
-            }
-        }
-        Value::Array(arr) => {
-            for (i, v) in arr.iter_mut().enumerate() {
-                let new_prefix = format!("{}_{}", prefix, i);
-                obfuscate_secrets(v, &new_prefix);
+/// Like [`obfuscate_secrets`], but keeps `${...}` references, which reveal nothing.
+pub fn obfuscate_literal_secrets(value: &mut Value, resource: &str, rules: &SecretRules) {
+    walk_secrets(
+        value,
+        rules,
+        resource,
+        resource,
+        &mut Vec::new(),
+        &mut |s, _| {
+            if !(s.starts_with("${") && s.ends_with('}')) {
+                *s = obfuscate_string(s);
             }
+        },
+    );
+}
+
+/// The secret rules of the workspace, for `Debug` output, which has no workspace at hand.
+static DEBUG_RULES: OnceLock<SecretRules> = OnceLock::new();
+
+/// Makes `Debug` output mask the secrets of `rules` (see [`obfuscate_map`]). `run_app`
+/// calls it once the workspace configuration is loaded; later calls are ignored.
+pub fn set_debug_rules(rules: SecretRules) {
+    let _ = DEBUG_RULES.set(rules);
+}
+
+/// Masks the secret entries of a map field (`config`, `extra`) of `resource` for `Debug`
+/// output, following the rules given to [`set_debug_rules`] (the built-in detection
+/// before that).
+pub fn obfuscate_map<T>(
+    map: Option<&HashMap<String, T>>,
+    resource: &str,
+    field: Option<&str>,
+) -> Option<HashMap<String, T>>
+where
+    T: From<&'static str> + Clone,
+{
+    let default = SecretRules::default();
+    let rules = DEBUG_RULES.get().unwrap_or(&default);
+    let mut obfuscated = map.cloned();
+    for (key, val) in obfuscated.iter_mut().flatten() {
+        let path: Vec<&str> = field.into_iter().chain([key.as_str()]).collect();
+        if is_secret_field(rules, resource, &path, resource) {
+            *val = T::from(REDACTED);
         }
-        _ => {}
     }
+    obfuscated
 }
 
 #[cfg(test)]
@@ -281,7 +459,7 @@ mod tests {
             "storeToken": "true"
         });
         let mut secrets = std::collections::BTreeMap::new();
-        extract_secrets(&mut val, "client", &mut secrets);
+        extract_secrets(&mut val, "client", &mut secrets, &SecretRules::default());
 
         assert_eq!(
             val["clientSecret"],
@@ -295,7 +473,7 @@ mod tests {
 
         let mut val2 = json!({"clientSecret": "secret_value_2"});
         let mut secrets2 = std::collections::BTreeMap::new();
-        extract_secrets(&mut val2, "", &mut secrets2);
+        extract_secrets(&mut val2, "", &mut secrets2, &SecretRules::default());
         assert_eq!(val2["clientSecret"], "${KEYCLOAK_CLIENTSECRET}");
         assert_eq!(
             secrets2.get("KEYCLOAK_CLIENTSECRET").unwrap(),
@@ -304,7 +482,7 @@ mod tests {
 
         let mut val3 = json!({"clientSecret-special": "secret_value_3"});
         let mut secrets3 = std::collections::BTreeMap::new();
-        extract_secrets(&mut val3, "prefix", &mut secrets3);
+        extract_secrets(&mut val3, "prefix", &mut secrets3, &SecretRules::default());
         assert_eq!(
             val3["clientSecret-special"],
             "${KEYCLOAK_PREFIX_CLIENTSECRET_SPECIAL}"
@@ -323,41 +501,59 @@ mod tests {
 
         // Null
         let mut val = json!(null);
-        extract_secrets(&mut val, "prefix", &mut secrets);
+        extract_secrets(&mut val, "prefix", &mut secrets, &SecretRules::default());
         assert_eq!(val, json!(null));
         assert!(secrets.is_empty());
 
         // Bool
         let mut val = json!(true);
-        extract_secrets(&mut val, "prefix", &mut secrets);
+        extract_secrets(&mut val, "prefix", &mut secrets, &SecretRules::default());
         assert_eq!(val, json!(true));
         assert!(secrets.is_empty());
 
         // Number
         let mut val = json!(42);
-        extract_secrets(&mut val, "prefix", &mut secrets);
+        extract_secrets(&mut val, "prefix", &mut secrets, &SecretRules::default());
         assert_eq!(val, json!(42));
         assert!(secrets.is_empty());
 
         // String
         let mut val = json!("just a string");
-        extract_secrets(&mut val, "prefix", &mut secrets);
+        extract_secrets(&mut val, "prefix", &mut secrets, &SecretRules::default());
         assert_eq!(val, json!("just a string"));
         assert!(secrets.is_empty());
 
         // Empty object
         let mut val = json!({});
-        extract_secrets(&mut val, "prefix", &mut secrets);
+        extract_secrets(&mut val, "prefix", &mut secrets, &SecretRules::default());
         assert_eq!(val, json!({}));
         assert!(secrets.is_empty());
 
         // Empty array
         let mut val = json!([]);
-        extract_secrets(&mut val, "prefix", &mut secrets);
+        extract_secrets(&mut val, "prefix", &mut secrets, &SecretRules::default());
         assert_eq!(val, json!([]));
         assert!(secrets.is_empty());
     }
 
+    #[test]
+    fn test_extract_secrets_from_config_lists() {
+        let mut secrets = std::collections::BTreeMap::new();
+        let mut val = json!({
+            "name": "ldap",
+            "config": {"bindCredential": ["pw"], "bindDn": ["cn=admin"]},
+            "requiredCredentials": ["password"]
+        });
+        extract_secrets(&mut val, "component", &mut secrets, &SecretRules::default());
+        assert_eq!(
+            val["config"]["bindCredential"],
+            json!(["${KEYCLOAK_COMPONENT_LDAP_CONFIG_BINDCREDENTIAL}"])
+        );
+        assert_eq!(val["config"]["bindDn"], json!(["cn=admin"]));
+        assert_eq!(val["requiredCredentials"], json!(["password"]));
+        assert_eq!(secrets.len(), 1);
+    }
+
     #[tokio::test]
     async fn test_substitute_secrets() {
         let mut vars = HashMap::new();
@@ -464,7 +660,7 @@ mod tests {
             ]
         });
 
-        obfuscate_secrets(&mut val, "client");
+        obfuscate_secrets(&mut val, "client", &SecretRules::default());
 
         assert_eq!(val["clientSecret"], "m***t");
         assert_eq!(val["normal"], "value");
@@ -472,7 +668,7 @@ mod tests {
         assert_eq!(val["array"][0]["token"], "s***n");
 
         let mut val2 = json!({"secret": "secret_value"});
-        obfuscate_secrets(&mut val2, "");
+        obfuscate_secrets(&mut val2, "", &SecretRules::default());
         assert_eq!(val2["secret"], "s***e");
     }
 
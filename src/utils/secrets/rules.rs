@@ -0,0 +1,145 @@
+use serde::Deserialize;
+
+/// Workspace rules that override the built-in secret detection heuristic
+/// (`secrets:` in `kcd.yaml`). Exclusions win over inclusions.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct SecretRules {
+    /// Fields that are always secrets.
+    #[serde(default)]
+    pub include: Vec<FieldPattern>,
+    /// Fields that are never secrets.
+    #[serde(default)]
+    pub exclude: Vec<FieldPattern>,
+}
+
+/// `[resource:]path`, e.g. `component:config.apiKey` or `*ApiKey`. Used for secret
+/// detection rules and for the `ignore_fields` of diffs.
+///
+/// `resource` is the kind used in generated secret names (`client`, `idp`, `component`,
+/// `user`, `realm`, ...). `path` is the dot-separated JSON path of the field inside the
+/// resource; `*` matches within a segment, `**` any number of segments, and a path
+/// without dots matches the field name at any depth. Matching ignores case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldPattern {
+    resource: Option<String>,
+    segments: Vec<String>,
+}
+
+impl<'de> Deserialize<'de> for FieldPattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl std::str::FromStr for FieldPattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (resource, path) = match s.split_once(':') {
+            Some((resource, path)) => (Some(resource.trim().to_lowercase()), path.trim()),
+            None => (None, s.trim()),
+        };
+        if path.is_empty() || path.split('.').any(str::is_empty) {
+            anyhow::bail!("Invalid field pattern '{}'", s);
+        }
+        let mut segments: Vec<String> = path.split('.').map(str::to_lowercase).collect();
+        if segments.len() == 1 {
+            segments.insert(0, "**".to_string());
+        }
+        Ok(Self { resource, segments })
+    }
+}
+
+impl FieldPattern {
+    pub fn matches(&self, resource: &str, path: &[&str]) -> bool {
+        if self
+            .resource
+            .as_deref()
+            .is_some_and(|r| !r.eq_ignore_ascii_case(resource))
+        {
+            return false;
+        }
+        let path: Vec<String> = path.iter().map(|p| p.to_lowercase()).collect();
+        match_segments(&self.segments, &path)
+    }
+}
+
+pub(crate) fn match_segments(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| match_segments(rest, &path[skip..]))
+        }
+        Some((first, rest)) => path
+            .split_first()
+            .is_some_and(|(segment, tail)| glob(first, segment) && match_segments(rest, tail)),
+    }
+}
+
+/// `*` wildcard matching within a single segment.
+fn glob(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let Some(text) = text.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=text.len())
+                .filter(|i| text.is_char_boundary(*i))
+                .any(|i| glob(rest, &text[i..]))
+        }
+    }
+}
+
+impl SecretRules {
+    /// `Some(true)`/`Some(false)` when a rule decides, `None` to use the heuristic.
+    pub fn classify(&self, resource: &str, path: &[&str]) -> Option<bool> {
+        if self.exclude.iter().any(|p| p.matches(resource, path)) {
+            Some(false)
+        } else if self.include.iter().any(|p| p.matches(resource, path)) {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(s: &str) -> FieldPattern {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_pattern_matching() {
+        assert!(pattern("component:config.apiKey").matches("component", &["config", "apikey"]));
+        assert!(!pattern("component:config.apiKey").matches("idp", &["config", "apiKey"]));
+        assert!(!pattern("component:config.apiKey").matches("component", &["apiKey"]));
+        assert!(pattern("*ApiKey").matches("client", &["attributes", "myApiKey"]));
+        assert!(pattern("*ApiKey").matches("client", &["serviceApiKey"]));
+        assert!(pattern("config.**.token*").matches("idp", &["config", "a", "b", "tokenValue"]));
+        assert!(!pattern("config.*").matches("idp", &["config", "a", "b"]));
+        assert!("component:".parse::<FieldPattern>().is_err());
+        assert!("a..b".parse::<FieldPattern>().is_err());
+    }
+
+    #[test]
+    fn test_rules_classify() {
+        let rules: SecretRules = serde_yaml::from_str(
+            "include: ['component:config.apiKey', 'signingKey']\nexclude: ['idp:config.tokenUrl', 'signingKey']\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rules.classify("component", &["config", "apiKey"]),
+            Some(true)
+        );
+        assert_eq!(rules.classify("idp", &["config", "tokenUrl"]), Some(false));
+        // Exclusions win.
+        assert_eq!(rules.classify("client", &["signingKey"]), Some(false));
+        assert_eq!(rules.classify("client", &["secret"]), None);
+    }
+}
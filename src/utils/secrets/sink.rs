@@ -0,0 +1,107 @@
+use anyhow::{Context, Result};
+use std::str::FromStr;
+
+/// Where `inspect` stores the secrets it extracts from the exported configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SecretsSink {
+    /// Append `KEY=value` lines to the workspace `.secrets` file, referenced as `${KEY}`.
+    #[default]
+    Dotenv,
+    /// Write the secrets of each realm to a Vault KV secret, referenced as
+    /// `${vault:mount/path#KEY}`. `<realm>` in `path` is replaced by the realm name.
+    Vault { mount: String, path: String },
+}
+
+impl SecretsSink {
+    /// The Vault secret path (without mount) holding the secrets of `realm_name`.
+    pub fn vault_path(&self, realm_name: &str) -> Option<String> {
+        match self {
+            SecretsSink::Dotenv => None,
+            SecretsSink::Vault { path, .. } => Some(path.replace("<realm>", realm_name)),
+        }
+    }
+
+    /// The placeholder written into YAML for the secret `name` of `realm_name`.
+    pub fn reference(&self, realm_name: &str, name: &str) -> String {
+        match self {
+            SecretsSink::Dotenv => format!("${{{}}}", name),
+            SecretsSink::Vault { mount, path } => format!(
+                "${{vault:{}/{}#{}}}",
+                mount,
+                path.replace("<realm>", realm_name),
+                name
+            ),
+        }
+    }
+}
+
+impl FromStr for SecretsSink {
+    type Err = anyhow::Error;
+
+    /// Parses `dotenv` or `vault:<mount>/<path>`.
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "dotenv" {
+            return Ok(SecretsSink::Dotenv);
+        }
+        let target = s.strip_prefix("vault:").with_context(|| {
+            format!(
+                "Unknown secrets sink '{}', expected 'dotenv' or 'vault:<mount>/<path>'",
+                s
+            )
+        })?;
+        let (mount, path) = target
+            .trim_matches('/')
+            .split_once('/')
+            .filter(|(m, p)| !m.is_empty() && !p.is_empty())
+            .with_context(|| {
+                format!(
+                    "Invalid Vault secrets sink '{}', expected 'vault:<mount>/<path>'",
+                    s
+                )
+            })?;
+        if target.contains("..") {
+            anyhow::bail!(
+                "Invalid Vault secrets sink '{}': path traversal detected",
+                s
+            );
+        }
+        Ok(SecretsSink::Vault {
+            mount: mount.to_string(),
+            path: path.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_secrets_sink() {
+        assert_eq!(
+            "dotenv".parse::<SecretsSink>().unwrap(),
+            SecretsSink::Dotenv
+        );
+        let sink: SecretsSink = "vault:kv/keycloak/<realm>".parse().unwrap();
+        assert_eq!(
+            sink,
+            SecretsSink::Vault {
+                mount: "kv".to_string(),
+                path: "keycloak/<realm>".to_string()
+            }
+        );
+        assert_eq!(sink.vault_path("prod").unwrap(), "keycloak/prod");
+        assert_eq!(
+            sink.reference("prod", "KEYCLOAK_X"),
+            "${vault:kv/keycloak/prod#KEYCLOAK_X}"
+        );
+        assert_eq!(
+            SecretsSink::Dotenv.reference("prod", "KEYCLOAK_X"),
+            "${KEYCLOAK_X}"
+        );
+
+        assert!("vault:kv".parse::<SecretsSink>().is_err());
+        assert!("vault:kv/../x".parse::<SecretsSink>().is_err());
+        assert!("s3:bucket".parse::<SecretsSink>().is_err());
+    }
+}
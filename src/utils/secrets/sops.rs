@@ -0,0 +1,329 @@
+use super::SecretResolver;
+use aes_gcm::aead::consts::U32;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::aes::Aes256;
+use aes_gcm::{AesGcm, Key, Nonce};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// SOPS encrypts values with AES-256-GCM and a 32-byte IV.
+type SopsCipher = AesGcm<Aes256, U32>;
+
+/// Resolves `${sops:path/to/secrets.enc.yaml#key}` from SOPS files encrypted for age
+/// recipients. Nested keys are separated by dots (`#db.password`). Files are decrypted
+/// locally once per run; the age identities are read from `SOPS_AGE_KEY`,
+/// `SOPS_AGE_KEY_FILE` or the default `sops/age/keys.txt` in the user config directory,
+/// like the `sops` CLI does.
+pub struct SopsResolver {
+    base_dir: PathBuf,
+    age_keys: Option<String>,
+    cache: Mutex<HashMap<PathBuf, Arc<Value>>>,
+}
+
+impl SopsResolver {
+    pub fn new(base_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            base_dir: base_dir.into(),
+            age_keys: None,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Uses the given age identities (in `age-keygen` format) instead of the environment.
+    pub fn with_age_keys(mut self, keys: impl Into<String>) -> Self {
+        self.age_keys = Some(keys.into());
+        self
+    }
+
+    async fn age_keys(&self) -> Result<String> {
+        if let Some(keys) = &self.age_keys {
+            return Ok(keys.clone());
+        }
+        if let Ok(keys) = std::env::var("SOPS_AGE_KEY") {
+            return Ok(keys);
+        }
+        let path = match std::env::var("SOPS_AGE_KEY_FILE") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => std::env::var("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|_| std::env::var("HOME").map(|h| PathBuf::from(h).join(".config")))
+                .context("Cannot locate age keys: set SOPS_AGE_KEY or SOPS_AGE_KEY_FILE")?
+                .join("sops/age/keys.txt"),
+        };
+        tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read age keys from {:?}", path))
+    }
+
+    async fn document(&self, path: PathBuf) -> Result<Arc<Value>> {
+        let mut cache = self.cache.lock().await;
+        if let Some(doc) = cache.get(&path) {
+            return Ok(Arc::clone(doc));
+        }
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read SOPS file {:?}", path))?;
+        let keys = self.age_keys().await?;
+        let doc = Arc::new(
+            decrypt_document(&content, &keys)
+                .with_context(|| format!("Failed to decrypt SOPS file {:?}", path))?,
+        );
+        cache.insert(path, Arc::clone(&doc));
+        Ok(doc)
+    }
+}
+
+#[async_trait]
+impl SecretResolver for SopsResolver {
+    async fn resolve(&self, key: &str) -> Result<Option<String>> {
+        let Some(reference) = key.strip_prefix("sops:") else {
+            return Ok(None);
+        };
+        let (file, field) = reference.split_once('#').with_context(|| {
+            format!(
+                "Invalid sops secret format. Expected 'sops:path#key', got '{}'",
+                key
+            )
+        })?;
+
+        let doc = self.document(self.base_dir.join(file)).await?;
+        let value = field
+            .split('.')
+            .try_fold(doc.as_ref(), |v, k| v.get(k))
+            .with_context(|| format!("Key '{}' not found in SOPS file '{}'", field, file))?;
+        match value {
+            Value::String(s) => Ok(Some(s.clone())),
+            Value::Object(_) | Value::Array(_) => {
+                anyhow::bail!("Key '{}' in SOPS file '{}' is not a scalar", field, file)
+            }
+            other => Ok(Some(other.to_string())),
+        }
+    }
+}
+
+/// Decrypts a SOPS YAML/JSON document and returns it without its `sops` metadata.
+///
+/// Each value is authenticated by AES-GCM against its key path; the document-wide MAC is
+/// not checked.
+pub fn decrypt_document(content: &str, age_keys: &str) -> Result<Value> {
+    let mut doc: Value = serde_yaml::from_str(content).context("Invalid SOPS document")?;
+    let metadata = doc
+        .as_object_mut()
+        .and_then(|m| m.remove("sops"))
+        .context("Missing 'sops' metadata, is the file encrypted?")?;
+    let data_key = decrypt_data_key(&metadata, age_keys)?;
+    decrypt_tree(&mut doc, &data_key, &mut Vec::new())?;
+    Ok(doc)
+}
+
+fn decrypt_data_key(metadata: &Value, age_keys: &str) -> Result<Vec<u8>> {
+    let recipients = metadata
+        .get("age")
+        .and_then(Value::as_array)
+        .filter(|r| !r.is_empty())
+        .context("Only SOPS files encrypted for age recipients are supported")?;
+    let identities = age::IdentityFile::from_buffer(age_keys.as_bytes())
+        .context("Invalid age keys")?
+        .into_identities()
+        .context("Invalid age keys")?;
+
+    for recipient in recipients {
+        let Some(enc) = recipient.get("enc").and_then(Value::as_str) else {
+            continue;
+        };
+        let decryptor =
+            age::Decryptor::new_buffered(age::armor::ArmoredReader::new(enc.as_bytes()))?;
+        if let Ok(mut reader) = decryptor.decrypt(identities.iter().map(|i| i.as_ref())) {
+            let mut key = Vec::new();
+            reader.read_to_end(&mut key)?;
+            return Ok(key);
+        }
+    }
+    anyhow::bail!("None of the available age identities can decrypt this file")
+}
+
+fn decrypt_tree(value: &mut Value, key: &[u8], path: &mut Vec<String>) -> Result<()> {
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map.iter_mut() {
+                path.push(k.clone());
+                decrypt_tree(v, key, path)?;
+                path.pop();
+            }
+        }
+        // List items share the path of their parent key.
+        Value::Array(items) => {
+            for item in items {
+                decrypt_tree(item, key, path)?;
+            }
+        }
+        Value::String(s) if s.starts_with("ENC[") => {
+            let aad = format!("{}:", path.join(":"));
+            *value = decrypt_value(s, key, &aad)
+                .with_context(|| format!("Failed to decrypt '{}'", path.join(".")))?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Decrypts `ENC[AES256_GCM,data:...,iv:...,tag:...,type:...]`.
+fn decrypt_value(encoded: &str, key: &[u8], aad: &str) -> Result<Value> {
+    let inner = encoded
+        .strip_prefix("ENC[AES256_GCM,")
+        .and_then(|s| s.strip_suffix(']'))
+        .context("Unsupported SOPS value encoding")?;
+    let fields: HashMap<&str, &str> = inner.split(',').filter_map(|f| f.split_once(':')).collect();
+    let field = |name: &str| {
+        fields
+            .get(name)
+            .copied()
+            .with_context(|| format!("Missing '{}' in encrypted value", name))
+    };
+
+    let mut ciphertext = BASE64.decode(field("data")?)?;
+    let iv = BASE64.decode(field("iv")?)?;
+    ciphertext.extend(BASE64.decode(field("tag")?)?);
+    anyhow::ensure!(iv.len() == 32, "Unexpected IV length {}", iv.len());
+    anyhow::ensure!(key.len() == 32, "Unexpected data key length {}", key.len());
+
+    let cipher = SopsCipher::new(Key::<SopsCipher>::from_slice(key));
+    let plaintext = cipher
+        .decrypt(
+            Nonce::from_slice(&iv),
+            Payload {
+                msg: &ciphertext,
+                aad: aad.as_bytes(),
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("Authentication failed (wrong key or tampered value)"))?;
+    let text = String::from_utf8(plaintext)?;
+
+    Ok(match field("type")? {
+        "int" => Value::from(text.parse::<i64>()?),
+        "float" => Value::from(text.parse::<f64>()?),
+        "bool" => Value::Bool(text.eq_ignore_ascii_case("true")),
+        _ => Value::String(text),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+    use tempfile::tempdir;
+
+    const DATA_KEY: [u8; 32] = [7; 32];
+
+    fn encrypt_value(plaintext: &str, aad: &str, value_type: &str) -> String {
+        let iv = [3u8; 32];
+        let cipher = SopsCipher::new(Key::<SopsCipher>::from_slice(&DATA_KEY));
+        let mut data = cipher
+            .encrypt(
+                Nonce::from_slice(&iv),
+                Payload {
+                    msg: plaintext.as_bytes(),
+                    aad: aad.as_bytes(),
+                },
+            )
+            .unwrap();
+        let tag = data.split_off(data.len() - 16);
+        format!(
+            "ENC[AES256_GCM,data:{},iv:{},tag:{},type:{}]",
+            BASE64.encode(data),
+            BASE64.encode(iv),
+            BASE64.encode(tag),
+            value_type
+        )
+    }
+
+    fn encrypted_file(identity: &age::x25519::Identity) -> String {
+        let enc = age::encrypt_and_armor(&identity.to_public(), &DATA_KEY).unwrap();
+        let doc = serde_json::json!({
+            "db": {
+                "password": encrypt_value("s3cr3t", "db:password:", "str"),
+                "port": encrypt_value("5432", "db:port:", "int")
+            },
+            "hosts": [encrypt_value("a.example.com", "hosts:", "str")],
+            "note_unencrypted": "plain",
+            "sops": {"age": [{"recipient": identity.to_public().to_string(), "enc": enc}]}
+        });
+        serde_yaml::to_string(&doc).unwrap()
+    }
+
+    #[test]
+    fn test_decrypt_document() {
+        let identity = age::x25519::Identity::generate();
+        let keys = identity.to_string().expose_secret().to_string();
+
+        let doc = decrypt_document(&encrypted_file(&identity), &keys).unwrap();
+        assert_eq!(doc["db"]["password"], "s3cr3t");
+        assert_eq!(doc["db"]["port"], 5432);
+        assert_eq!(doc["hosts"][0], "a.example.com");
+        assert_eq!(doc["note_unencrypted"], "plain");
+        assert!(doc.get("sops").is_none());
+
+        let other = age::x25519::Identity::generate();
+        let err = decrypt_document(
+            &encrypted_file(&identity),
+            other.to_string().expose_secret(),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("None of the available age identities")
+        );
+    }
+
+    #[test]
+    fn test_decrypt_value_rejects_wrong_path() {
+        let encoded = encrypt_value("s3cr3t", "db:password:", "str");
+        assert!(decrypt_value(&encoded, &DATA_KEY, "db:password:").is_ok());
+        assert!(decrypt_value(&encoded, &DATA_KEY, "other:").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sops_resolver() {
+        let identity = age::x25519::Identity::generate();
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("secrets.enc.yaml"),
+            encrypted_file(&identity),
+        )
+        .unwrap();
+        let resolver = SopsResolver::new(dir.path())
+            .with_age_keys(identity.to_string().expose_secret().to_string());
+
+        assert_eq!(
+            resolver
+                .resolve("sops:secrets.enc.yaml#db.password")
+                .await
+                .unwrap(),
+            Some("s3cr3t".to_string())
+        );
+        assert_eq!(
+            resolver
+                .resolve("sops:secrets.enc.yaml#db.port")
+                .await
+                .unwrap(),
+            Some("5432".to_string())
+        );
+        assert!(resolver.resolve("sops:secrets.enc.yaml#db").await.is_err());
+        assert!(
+            resolver
+                .resolve("sops:secrets.enc.yaml#missing")
+                .await
+                .is_err()
+        );
+        assert!(resolver.resolve("sops:secrets.enc.yaml").await.is_err());
+        assert!(resolver.resolve("KEYCLOAK_X").await.unwrap().is_none());
+    }
+}
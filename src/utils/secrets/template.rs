@@ -0,0 +1,295 @@
+use super::{SecretResolver, backend_of};
+use crate::utils::ui::WARN;
+use anyhow::Result;
+use async_trait::async_trait;
+use console::style;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// A piece of a YAML string: literal text or a `${...}` reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    Reference {
+        name: &'a str,
+        default: Option<&'a str>,
+        /// The reference as written, used when it is left untouched.
+        raw: &'a str,
+    },
+}
+
+impl Segment<'_> {
+    /// Whether kcd substitutes this reference. Keycloak itself uses `${...}` for
+    /// localized strings (`${client_account}`), so only environment-style names
+    /// (`APP_HOST`), backend references and references with a default are interpolated.
+    pub fn is_interpolated(&self) -> bool {
+        match self {
+            Segment::Text(_) => false,
+            Segment::Reference { name, default, .. } => {
+                default.is_some() || backend_of(name).is_some() || is_variable_name(name)
+            }
+        }
+    }
+
+    /// An interpolated reference to a plain variable (not a secret), e.g. `${APP_HOST}`.
+    pub fn variable(&self) -> Option<&str> {
+        match self {
+            Segment::Reference { name, .. }
+                if self.is_interpolated()
+                    && backend_of(name).is_none()
+                    && !name.starts_with("KEYCLOAK_") =>
+            {
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// `UPPER_SNAKE_CASE` names, as used for environment variables.
+pub fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_uppercase() || c == '_')
+        && chars.all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Splits `s` into text and references. `$${` is an escaped, literal `${`; an unclosed
+/// `${` is kept as text.
+pub fn parse(s: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut text_start = 0;
+    let mut i = 0;
+    while i < s.len() {
+        let rest = &s[i..];
+        if rest.starts_with("$${") {
+            if text_start < i {
+                segments.push(Segment::Text(&s[text_start..i]));
+            }
+            segments.push(Segment::Text("${"));
+            i += 3;
+            text_start = i;
+        } else if rest.starts_with("${")
+            && let Some(end) = rest.find('}')
+        {
+            if text_start < i {
+                segments.push(Segment::Text(&s[text_start..i]));
+            }
+            let content = &rest[2..end];
+            // Backend references (e.g. `exec:...`) may legitimately contain `:-`.
+            let (name, default) = match content.split_once(":-") {
+                Some((name, default)) if backend_of(content).is_none() => (name, Some(default)),
+                _ => (content, None),
+            };
+            segments.push(Segment::Reference {
+                name,
+                default,
+                raw: &rest[..=end],
+            });
+            i += end + 1;
+            text_start = i;
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    if text_start < s.len() {
+        segments.push(Segment::Text(&s[text_start..]));
+    }
+    segments
+}
+
+/// Replaces the references of `s` with their values. Undefined plain variables are kept
+/// as written, with a warning (`validate` reports them).
+pub async fn interpolate(s: &str, resolver: &dyn SecretResolver) -> Result<String> {
+    let mut out = String::with_capacity(s.len());
+    for segment in parse(s) {
+        match segment {
+            Segment::Text(text) => out.push_str(text),
+            Segment::Reference { raw, .. } if !segment.is_interpolated() => out.push_str(raw),
+            Segment::Reference { name, default, raw } => {
+                match (resolver.resolve(name).await?, default) {
+                    (Some(value), _) => out.push_str(&value),
+                    (None, Some(default)) => out.push_str(default),
+                    (None, None) if name.starts_with("KEYCLOAK_") => {
+                        anyhow::bail!("Missing required secret or environment variable: {}", name)
+                    }
+                    (None, None) => match backend_of(name) {
+                        Some(backend) => anyhow::bail!(
+                            "Cannot resolve secret '{}': the {:?} secret backend is not enabled",
+                            name,
+                            backend
+                        ),
+                        None => {
+                            println!(
+                                "{} {}",
+                                WARN,
+                                style(format!("Undefined variable {}, kept as written", name))
+                                    .yellow()
+                            );
+                            out.push_str(raw);
+                        }
+                    },
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Collects the variables referenced in `value` (without a default) that `is_defined`
+/// does not know about.
+pub fn undefined_variables(value: &Value, is_defined: &dyn Fn(&str) -> bool) -> Vec<String> {
+    let mut undefined = Vec::new();
+    collect_undefined(value, is_defined, &mut undefined);
+    undefined.sort();
+    undefined.dedup();
+    undefined
+}
+
+fn collect_undefined(value: &Value, is_defined: &dyn Fn(&str) -> bool, out: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => map
+            .values()
+            .for_each(|v| collect_undefined(v, is_defined, out)),
+        Value::Array(items) => items
+            .iter()
+            .for_each(|v| collect_undefined(v, is_defined, out)),
+        Value::String(s) => {
+            for segment in parse(s) {
+                if let (Some(name), Segment::Reference { default: None, .. }) =
+                    (segment.variable(), &segment)
+                    && !is_defined(name)
+                {
+                    out.push(name.to_string());
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Resolves plain variables from the environment, then from the profile `vars`.
+pub struct VarsResolver {
+    vars: HashMap<String, String>,
+}
+
+impl VarsResolver {
+    pub fn new(vars: HashMap<String, String>) -> Self {
+        Self { vars }
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.vars.contains_key(name) || std::env::var(name).is_ok()
+    }
+}
+
+#[async_trait]
+impl SecretResolver for VarsResolver {
+    async fn resolve(&self, key: &str) -> Result<Option<String>> {
+        if backend_of(key).is_some() || key.starts_with("KEYCLOAK_") {
+            return Ok(None);
+        }
+        if let Ok(val) = std::env::var(key) {
+            return Ok(Some(val));
+        }
+        Ok(self.vars.get(key).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::secrets::{CompositeResolver, EnvResolver};
+    use serde_json::json;
+
+    fn resolver() -> CompositeResolver {
+        CompositeResolver::new(vec![
+            Box::new(EnvResolver::new(HashMap::from([(
+                "KEYCLOAK_SECRET".to_string(),
+                "s3cr3t".to_string(),
+            )]))),
+            Box::new(VarsResolver::new(HashMap::from([
+                (
+                    "KCD_TEST_APP_HOST".to_string(),
+                    "app.example.com".to_string(),
+                ),
+                ("KCD_TEST_ENV".to_string(), "prod".to_string()),
+            ]))),
+        ])
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("https://${HOST:-localhost}/x $${LITERAL} ${open"),
+            vec![
+                Segment::Text("https://"),
+                Segment::Reference {
+                    name: "HOST",
+                    default: Some("localhost"),
+                    raw: "${HOST:-localhost}"
+                },
+                Segment::Text("/x "),
+                Segment::Text("${"),
+                Segment::Text("LITERAL} ${open"),
+            ]
+        );
+        assert!(!parse("${client_account}")[0].is_interpolated());
+        assert!(parse("${vault:kv/a#b}")[0].is_interpolated());
+        assert_eq!(parse("${exec:echo a:-b}")[0].variable(), None);
+        assert_eq!(parse("${APP_HOST}")[0].variable(), Some("APP_HOST"));
+        assert_eq!(parse("${KEYCLOAK_X}")[0].variable(), None);
+    }
+
+    #[tokio::test]
+    async fn test_interpolate() {
+        let resolver = resolver();
+        let check = |input: &'static str| {
+            let resolver = &resolver;
+            async move { interpolate(input, resolver).await }
+        };
+
+        assert_eq!(
+            check("https://${KCD_TEST_APP_HOST}/*").await.unwrap(),
+            "https://app.example.com/*"
+        );
+        assert_eq!(
+            check("https://${KCD_TEST_ENV}.example.com").await.unwrap(),
+            "https://prod.example.com"
+        );
+        assert_eq!(check("${KEYCLOAK_SECRET}").await.unwrap(), "s3cr3t");
+        assert_eq!(
+            check("${KCD_TEST_MISSING:-fallback}").await.unwrap(),
+            "fallback"
+        );
+        assert_eq!(check("${KCD_TEST_MISSING:-}").await.unwrap(), "");
+        assert_eq!(check("$${KCD_TEST_ENV}").await.unwrap(), "${KCD_TEST_ENV}");
+        assert_eq!(
+            check("${client_account} ü").await.unwrap(),
+            "${client_account} ü"
+        );
+
+        assert_eq!(
+            check("a-${KCD_TEST_MISSING}").await.unwrap(),
+            "a-${KCD_TEST_MISSING}"
+        );
+        assert!(check("${KEYCLOAK_MISSING}").await.is_err());
+        assert!(check("${sops:a.yaml#b}").await.is_err());
+    }
+
+    #[test]
+    fn test_undefined_variables() {
+        let value = json!({
+            "rootUrl": "https://${APP_HOST}",
+            "redirectUris": ["https://${APP_HOST}/*", "${OTHER:-x}", "$${ESCAPED}"],
+            "secret": "${KEYCLOAK_SECRET}",
+            "name": "${client_account}",
+            "baseUrl": "${DEFINED}"
+        });
+        assert_eq!(
+            undefined_variables(&value, &|name| name == "DEFINED"),
+            vec!["APP_HOST".to_string()]
+        );
+    }
+}
@@ -1,33 +1,411 @@
 use super::SecretResolver;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
+use log::debug;
 use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, OnceCell};
+
+/// Tokens are renewed (or re-acquired) when they expire within this margin.
+const RENEW_MARGIN: Duration = Duration::from_secs(30);
+const DEFAULT_KUBERNETES_JWT_PATH: &str = "/var/run/secrets/kubernetes.io/serviceaccount/token";
+
+/// How kcd logs in to Vault when no static token is given.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum VaultAuth {
+    /// AppRole login; `secret_id` falls back to `VAULT_SECRET_ID`.
+    AppRole {
+        role_id: String,
+        secret_id: Option<String>,
+        mount: Option<String>,
+    },
+    /// Kubernetes login with the pod service account token.
+    Kubernetes {
+        role: String,
+        jwt_path: Option<String>,
+        mount: Option<String>,
+    },
+    /// JWT/OIDC login with a token given inline, in a file, or in `VAULT_JWT`.
+    Jwt {
+        role: String,
+        jwt: Option<String>,
+        jwt_file: Option<String>,
+        mount: Option<String>,
+    },
+}
+
+impl std::fmt::Debug for VaultAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VaultAuth::AppRole { role_id, mount, .. } => f
+                .debug_struct("AppRole")
+                .field("role_id", role_id)
+                .field("secret_id", &"********")
+                .field("mount", mount)
+                .finish(),
+            VaultAuth::Kubernetes {
+                role,
+                jwt_path,
+                mount,
+            } => f
+                .debug_struct("Kubernetes")
+                .field("role", role)
+                .field("jwt_path", jwt_path)
+                .field("mount", mount)
+                .finish(),
+            VaultAuth::Jwt {
+                role,
+                jwt_file,
+                mount,
+                ..
+            } => f
+                .debug_struct("Jwt")
+                .field("role", role)
+                .field("jwt", &"********")
+                .field("jwt_file", jwt_file)
+                .field("mount", mount)
+                .finish(),
+        }
+    }
+}
+
+impl VaultAuth {
+    fn mount(&self) -> &str {
+        match self {
+            VaultAuth::AppRole { mount, .. } => mount.as_deref().unwrap_or("approle"),
+            VaultAuth::Kubernetes { mount, .. } => mount.as_deref().unwrap_or("kubernetes"),
+            VaultAuth::Jwt { mount, .. } => mount.as_deref().unwrap_or("jwt"),
+        }
+    }
+
+    async fn login_body(&self) -> Result<Value> {
+        Ok(match self {
+            VaultAuth::AppRole {
+                role_id, secret_id, ..
+            } => {
+                let secret_id = match secret_id {
+                    Some(s) => s.clone(),
+                    None => std::env::var("VAULT_SECRET_ID")
+                        .context("AppRole auth needs secret_id or VAULT_SECRET_ID")?,
+                };
+                serde_json::json!({"role_id": role_id, "secret_id": secret_id})
+            }
+            VaultAuth::Kubernetes { role, jwt_path, .. } => {
+                let path = jwt_path.as_deref().unwrap_or(DEFAULT_KUBERNETES_JWT_PATH);
+                let jwt = read_jwt(path).await?;
+                serde_json::json!({"role": role, "jwt": jwt})
+            }
+            VaultAuth::Jwt {
+                role,
+                jwt,
+                jwt_file,
+                ..
+            } => {
+                let jwt = match (jwt, jwt_file) {
+                    (Some(jwt), _) => jwt.clone(),
+                    (None, Some(path)) => read_jwt(path).await?,
+                    (None, None) => std::env::var("VAULT_JWT")
+                        .context("JWT auth needs jwt, jwt_file or VAULT_JWT")?,
+                };
+                serde_json::json!({"role": role, "jwt": jwt})
+            }
+        })
+    }
+}
+
+async fn read_jwt(path: &str) -> Result<String> {
+    let jwt = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read JWT from {}", path))?;
+    Ok(jwt.trim().to_string())
+}
+
+/// Connection settings for [`VaultResolver`].
+#[derive(Debug, Clone)]
+pub struct VaultConfig {
+    pub address: String,
+    /// Static token; used as-is and never renewed.
+    pub token: Option<String>,
+    /// Login method, used when there is no static token or when it has to be re-acquired.
+    pub auth: Option<VaultAuth>,
+    /// Vault Enterprise namespace, sent as `X-Vault-Namespace`.
+    pub namespace: Option<String>,
+    /// KV secrets engine version (1 or 2).
+    pub kv_version: u8,
+}
+
+impl VaultConfig {
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            token: None,
+            auth: None,
+            namespace: None,
+            kv_version: 2,
+        }
+    }
+}
+
+struct Session {
+    token: String,
+    expires_at: Option<Instant>,
+    renewable: bool,
+}
+
+impl Session {
+    fn needs_refresh(&self) -> bool {
+        self.expires_at
+            .is_some_and(|at| Instant::now() + RENEW_MARGIN >= at)
+    }
+}
+
+#[derive(Deserialize)]
+struct AuthResponse {
+    auth: AuthInfo,
+}
+
+#[derive(Deserialize)]
+struct AuthInfo {
+    client_token: String,
+    #[serde(default)]
+    lease_duration: u64,
+    #[serde(default)]
+    renewable: bool,
+}
+
+impl From<AuthInfo> for Session {
+    fn from(auth: AuthInfo) -> Self {
+        Self {
+            token: auth.client_token,
+            // A zero lease (e.g. root tokens) never expires.
+            expires_at: (auth.lease_duration > 0)
+                .then(|| Instant::now() + Duration::from_secs(auth.lease_duration)),
+            renewable: auth.renewable,
+        }
+    }
+}
 
 pub struct VaultResolver {
     address: String,
-    token: String,
+    namespace: Option<String>,
+    kv_version: u8,
+    auth: Option<VaultAuth>,
+    session: Mutex<Option<Session>>,
+    /// Secret data per `mount/path`, fetched at most once per run.
+    cache: Mutex<HashMap<String, Arc<OnceCell<Value>>>>,
     client: reqwest::Client,
 }
 
 impl VaultResolver {
     pub fn new(address: &str, token: &str) -> Result<Self> {
-        reqwest::Url::parse(address)?;
+        let mut config = VaultConfig::new(address);
+        config.token = Some(token.to_string());
+        Self::from_config(config)
+    }
+
+    pub fn from_config(config: VaultConfig) -> Result<Self> {
+        reqwest::Url::parse(&config.address)?;
+        if !matches!(config.kv_version, 1 | 2) {
+            anyhow::bail!(
+                "Unsupported Vault KV version {}, expected 1 or 2",
+                config.kv_version
+            );
+        }
+        if config.token.is_none() && config.auth.is_none() {
+            anyhow::bail!("Vault needs either a token or an auth method");
+        }
+        let session = config.token.map(|token| Session {
+            token,
+            expires_at: None,
+            renewable: false,
+        });
         Ok(Self {
-            address: address.trim_end_matches('/').to_string(),
-            token: token.to_string(),
+            address: config.address.trim_end_matches('/').to_string(),
+            namespace: config.namespace,
+            kv_version: config.kv_version,
+            auth: config.auth,
+            session: Mutex::new(session),
+            cache: Mutex::new(HashMap::new()),
             client: reqwest::Client::new(),
         })
     }
-}
 
-#[derive(Deserialize)]
-struct VaultResponse {
-    data: VaultData,
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self
+            .client
+            .request(method, format!("{}/v1/{}", self.address, path));
+        match &self.namespace {
+            Some(ns) => request.header("X-Vault-Namespace", ns),
+            None => request,
+        }
+    }
+
+    async fn token(&self) -> Result<String> {
+        let mut session = self.session.lock().await;
+        if let Some(current) = session.as_ref() {
+            if !current.needs_refresh() {
+                return Ok(current.token.clone());
+            }
+            if current.renewable {
+                match self.renew(&current.token).await {
+                    Ok(renewed) => {
+                        let token = renewed.token.clone();
+                        *session = Some(renewed);
+                        return Ok(token);
+                    }
+                    Err(e) => debug!("Vault token renewal failed, logging in again: {:#}", e),
+                }
+            }
+        }
+        let auth = self
+            .auth
+            .as_ref()
+            .context("Vault token expired and no auth method is configured")?;
+        let fresh = self.login(auth).await?;
+        let token = fresh.token.clone();
+        *session = Some(fresh);
+        Ok(token)
+    }
+
+    async fn login(&self, auth: &VaultAuth) -> Result<Session> {
+        let mount = auth.mount();
+        debug!("Logging in to Vault with auth/{}", mount);
+        let resp = self
+            .request(reqwest::Method::POST, &format!("auth/{}/login", mount))
+            .json(&auth.login_body().await?)
+            .send()
+            .await?;
+        Self::auth_session(resp, "Vault login failed").await
+    }
+
+    async fn renew(&self, token: &str) -> Result<Session> {
+        let resp = self
+            .request(reqwest::Method::POST, "auth/token/renew-self")
+            .header("X-Vault-Token", token)
+            .send()
+            .await?;
+        Self::auth_session(resp, "Vault token renewal failed").await
+    }
+
+    async fn auth_session(resp: reqwest::Response, context_msg: &str) -> Result<Session> {
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("{} ({}): {}", context_msg, status, text);
+        }
+        let body: AuthResponse = resp.json().await?;
+        Ok(body.auth.into())
+    }
+
+    /// Returns the data of a secret, fetching it on first use.
+    async fn secret(&self, mount: &str, path: &str) -> Result<Value> {
+        let cell = {
+            let mut cache = self.cache.lock().await;
+            Arc::clone(cache.entry(format!("{}/{}", mount, path)).or_default())
+        };
+        cell.get_or_try_init(|| self.fetch_secret(mount, path))
+            .await
+            .cloned()
+    }
+
+    async fn fetch_secret(&self, mount: &str, path: &str) -> Result<Value> {
+        self.read_secret(mount, path)
+            .await?
+            .with_context(|| format!("Vault secret not found: {}/{}", mount, path))
+    }
+
+    fn secret_api_path(&self, mount: &str, path: &str) -> String {
+        if self.kv_version == 1 {
+            format!("{}/{}", mount, path)
+        } else {
+            format!("{}/data/{}", mount, path)
+        }
+    }
+
+    /// Reads the data of a secret, bypassing the cache. Returns `None` if it does not exist.
+    async fn read_secret(&self, mount: &str, path: &str) -> Result<Option<Value>> {
+        let token = self.token().await?;
+        let resp = self
+            .request(reqwest::Method::GET, &self.secret_api_path(mount, path))
+            .header("X-Vault-Token", token)
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            let body: VaultResponse = resp.json().await?;
+            // KV v2 nests the secret in a second `data` object, next to its metadata.
+            let data = if self.kv_version == 1 {
+                body.data
+            } else {
+                body.data.get("data").cloned().unwrap_or(Value::Null)
+            };
+            Ok(Some(data))
+        } else if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            Ok(None)
+        } else {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            Err(anyhow::anyhow!("Vault error ({}): {}", status, text))
+        }
+    }
+
+    /// Stores `values` in the secret at `mount/path`, keeping the fields already stored
+    /// there that are not overwritten.
+    pub async fn write_secret(
+        &self,
+        mount: &str,
+        path: &str,
+        values: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        if path.contains("..") || mount.contains("..") {
+            anyhow::bail!("Invalid vault path: path traversal detected");
+        }
+        let mut data = match self.read_secret(mount, path).await? {
+            Some(Value::Object(existing)) => existing,
+            _ => serde_json::Map::new(),
+        };
+        for (key, value) in values {
+            data.insert(key.clone(), Value::String(value.clone()));
+        }
+        let body = if self.kv_version == 1 {
+            Value::Object(data)
+        } else {
+            serde_json::json!({ "data": data })
+        };
+
+        let token = self.token().await?;
+        let resp = self
+            .request(reqwest::Method::POST, &self.secret_api_path(mount, path))
+            .header("X-Vault-Token", token)
+            .json(&body)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            anyhow::bail!(
+                "Failed to write Vault secret {}/{} ({}): {}",
+                mount,
+                path,
+                status,
+                text
+            );
+        }
+        self.cache
+            .lock()
+            .await
+            .remove(&format!("{}/{}", mount, path));
+        Ok(())
+    }
 }
 
 #[derive(Deserialize)]
-struct VaultData {
-    data: serde_json::Value,
+struct VaultResponse {
+    data: Value,
 }
 
 #[async_trait]
@@ -66,34 +444,18 @@ impl SecretResolver for VaultResolver {
         let mount = path_parts[0];
         let path = path_parts[1];
 
-        let url = format!("{}/v1/{}/data/{}", self.address, mount, path);
-        let resp = self
-            .client
-            .get(&url)
-            .header("X-Vault-Token", &self.token)
-            .send()
-            .await?;
-
-        if resp.status().is_success() {
-            let body: VaultResponse = resp.json().await?;
-            if let Some(val) = body.data.data.get(field) {
-                if let Some(s) = val.as_str() {
-                    return Ok(Some(s.to_string()));
-                }
-                return Ok(Some(val.to_string()));
+        let data = self.secret(mount, path).await?;
+        if let Some(val) = data.get(field) {
+            if let Some(s) = val.as_str() {
+                return Ok(Some(s.to_string()));
             }
-            Err(anyhow::anyhow!(
-                "Field '{}' not found in vault secret '{}'",
-                field,
-                full_path
-            ))
-        } else if resp.status() == reqwest::StatusCode::NOT_FOUND {
-            Err(anyhow::anyhow!("Vault secret not found: {}", full_path))
-        } else {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            Err(anyhow::anyhow!("Vault error ({}): {}", status, text))
+            return Ok(Some(val.to_string()));
         }
+        Err(anyhow::anyhow!(
+            "Field '{}' not found in vault secret '{}'",
+            field,
+            full_path
+        ))
     }
 }
 
@@ -218,4 +580,224 @@ mod tests {
             assert!(e.to_string().contains("relative URL without a base"));
         }
     }
+
+    fn approle() -> VaultAuth {
+        VaultAuth::AppRole {
+            role_id: "kcd".to_string(),
+            secret_id: Some("approle-secret".to_string()),
+            mount: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_vault_approle_login_and_cache() {
+        let mut server = Server::new_async().await;
+        let login = server
+            .mock("POST", "/v1/auth/approle/login")
+            .match_header("X-Vault-Namespace", "team-a")
+            .match_body(mockito::Matcher::Json(
+                json!({"role_id": "kcd", "secret_id": "approle-secret"}),
+            ))
+            .with_status(200)
+            .with_body(
+                json!({"auth": {"client_token": "s.approle", "lease_duration": 3600, "renewable": true}})
+                    .to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+        let read = server
+            .mock("GET", "/v1/secret/data/app")
+            .match_header("X-Vault-Token", "s.approle")
+            .match_header("X-Vault-Namespace", "team-a")
+            .with_status(200)
+            .with_body(json!({"data": {"data": {"user": "svc", "password": "pw"}}}).to_string())
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut config = VaultConfig::new(&server.url());
+        config.auth = Some(approle());
+        config.namespace = Some("team-a".to_string());
+        let resolver = VaultResolver::from_config(config).unwrap();
+
+        let (user, password) = tokio::join!(
+            resolver.resolve("vault:secret/app#user"),
+            resolver.resolve("vault:secret/app#password")
+        );
+        assert_eq!(user.unwrap(), Some("svc".to_string()));
+        assert_eq!(password.unwrap(), Some("pw".to_string()));
+        assert_eq!(
+            resolver.resolve("vault:secret/app#user").await.unwrap(),
+            Some("svc".to_string())
+        );
+
+        login.assert_async().await;
+        read.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_vault_token_renewal() {
+        let mut server = Server::new_async().await;
+        // A lease shorter than the renewal margin is refreshed before the next use.
+        let _login = server
+            .mock("POST", "/v1/auth/approle/login")
+            .with_status(200)
+            .with_body(
+                json!({"auth": {"client_token": "s.short", "lease_duration": 5, "renewable": true}})
+                    .to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+        let renew = server
+            .mock("POST", "/v1/auth/token/renew-self")
+            .match_header("X-Vault-Token", "s.short")
+            .with_status(200)
+            .with_body(
+                json!({"auth": {"client_token": "s.short", "lease_duration": 3600, "renewable": true}})
+                    .to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut config = VaultConfig::new(&server.url());
+        config.auth = Some(approle());
+        let resolver = VaultResolver::from_config(config).unwrap();
+
+        assert_eq!(resolver.token().await.unwrap(), "s.short");
+        assert_eq!(resolver.token().await.unwrap(), "s.short");
+        assert_eq!(resolver.token().await.unwrap(), "s.short");
+        renew.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_vault_kv_v1() {
+        let mut server = Server::new_async().await;
+        let read = server
+            .mock("GET", "/v1/kv/app")
+            .with_status(200)
+            .with_body(json!({"data": {"password": "v1-pw"}, "lease_duration": 0}).to_string())
+            .create_async()
+            .await;
+
+        let mut config = VaultConfig::new(&server.url());
+        config.token = Some("token".to_string());
+        config.kv_version = 1;
+        let resolver = VaultResolver::from_config(config).unwrap();
+
+        assert_eq!(
+            resolver.resolve("vault:kv/app#password").await.unwrap(),
+            Some("v1-pw".to_string())
+        );
+        read.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_vault_write_secret_merges_existing() {
+        let mut server = Server::new_async().await;
+        let read = server
+            .mock("GET", "/v1/kv/data/keycloak/prod")
+            .with_status(200)
+            .with_body(json!({"data": {"data": {"OTHER": "keep", "KEY": "old"}}}).to_string())
+            .create_async()
+            .await;
+        let write = server
+            .mock("POST", "/v1/kv/data/keycloak/prod")
+            .match_header("X-Vault-Token", "token")
+            .match_body(mockito::Matcher::Json(
+                json!({"data": {"OTHER": "keep", "KEY": "new"}}),
+            ))
+            .with_status(200)
+            .create_async()
+            .await;
+        let missing = server
+            .mock("GET", "/v1/kv/data/keycloak/dev")
+            .with_status(404)
+            .create_async()
+            .await;
+        let create = server
+            .mock("POST", "/v1/kv/data/keycloak/dev")
+            .match_body(mockito::Matcher::Json(json!({"data": {"KEY": "new"}})))
+            .with_status(204)
+            .create_async()
+            .await;
+
+        let resolver = VaultResolver::new(&server.url(), "token").unwrap();
+        let values = BTreeMap::from([("KEY".to_string(), "new".to_string())]);
+        resolver
+            .write_secret("kv", "keycloak/prod", &values)
+            .await
+            .unwrap();
+        resolver
+            .write_secret("kv", "keycloak/dev", &values)
+            .await
+            .unwrap();
+        assert!(
+            resolver
+                .write_secret("kv", "../escape", &values)
+                .await
+                .is_err()
+        );
+
+        read.assert_async().await;
+        write.assert_async().await;
+        missing.assert_async().await;
+        create.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_vault_jwt_login_from_file() {
+        let mut server = Server::new_async().await;
+        let dir = tempfile::tempdir().unwrap();
+        let jwt_path = dir.path().join("token");
+        std::fs::write(&jwt_path, "eyJ.payload.sig\n").unwrap();
+        let login = server
+            .mock("POST", "/v1/auth/gitlab/login")
+            .match_body(mockito::Matcher::Json(
+                json!({"role": "ci", "jwt": "eyJ.payload.sig"}),
+            ))
+            .with_status(200)
+            .with_body(json!({"auth": {"client_token": "s.jwt"}}).to_string())
+            .create_async()
+            .await;
+
+        let mut config = VaultConfig::new(&server.url());
+        config.auth = Some(VaultAuth::Jwt {
+            role: "ci".to_string(),
+            jwt: None,
+            jwt_file: Some(jwt_path.to_string_lossy().to_string()),
+            mount: Some("gitlab".to_string()),
+        });
+        let resolver = VaultResolver::from_config(config).unwrap();
+
+        assert_eq!(resolver.token().await.unwrap(), "s.jwt");
+        login.assert_async().await;
+    }
+
+    #[test]
+    fn test_vault_config_validation() {
+        let config = VaultConfig::new("http://localhost");
+        assert!(VaultResolver::from_config(config.clone()).is_err());
+
+        let mut config = config;
+        config.token = Some("token".to_string());
+        config.kv_version = 3;
+        assert!(VaultResolver::from_config(config).is_err());
+    }
+
+    #[test]
+    fn test_vault_auth_parsing_and_debug() {
+        let auth: VaultAuth =
+            serde_yaml::from_str("method: approle\nrole_id: kcd\nsecret_id: top-secret\n").unwrap();
+        assert_eq!(auth.mount(), "approle");
+        let debug = format!("{:?}", auth);
+        assert!(debug.contains("kcd"));
+        assert!(!debug.contains("top-secret"));
+
+        let auth: VaultAuth =
+            serde_yaml::from_str("method: kubernetes\nrole: kcd\nmount: k8s-prod\n").unwrap();
+        assert_eq!(auth.mount(), "k8s-prod");
+    }
 }
@@ -0,0 +1,236 @@
+use crate::graph::{self, Reference, identity, references, relative_segments};
+use crate::utils::filter::PathPattern;
+use crate::utils::yaml::{load_yaml_with_overlay, realm_files};
+use crate::workspace::WorkspaceContext;
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Resource types, named after their directory in a realm (`realm` is `realm.yaml`).
+pub const RESOURCE_TYPES: &[&str] = &[
+    "realm",
+    "roles",
+    "clients",
+    "identity-providers",
+    "client-scopes",
+    "groups",
+    "users",
+    "authentication-flows",
+    "required-actions",
+    "components",
+    "keys",
+    "authz",
+    "organizations",
+    "client-policies",
+];
+
+/// The resources a command works on (`--only`, `--exclude`, `--target` and `--with-deps`).
+/// The default selects everything.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    only: Vec<String>,
+    exclude: Vec<String>,
+    /// Realm-relative paths such as `clients/my-app` or `roles/app-*`.
+    targets: Vec<PathPattern>,
+    with_deps: bool,
+}
+
+impl Selection {
+    pub fn new(
+        only: &[String],
+        exclude: &[String],
+        targets: &[String],
+        with_deps: bool,
+    ) -> Result<Self> {
+        for kind in only.iter().chain(exclude) {
+            if !RESOURCE_TYPES.contains(&kind.as_str()) {
+                anyhow::bail!(
+                    "Unknown resource type '{}', expected one of: {}",
+                    kind,
+                    RESOURCE_TYPES.join(", ")
+                );
+            }
+        }
+        Ok(Self {
+            only: only.to_vec(),
+            exclude: exclude.to_vec(),
+            targets: targets.iter().map(|t| t.parse()).collect::<Result<_>>()?,
+            with_deps,
+        })
+    }
+
+    /// This selection with `targets` in place of its own, keeping the resource types and
+    /// `--with-deps`.
+    pub fn with_targets(&self, targets: &[String]) -> Result<Self> {
+        Ok(Self {
+            targets: targets.iter().map(|t| t.parse()).collect::<Result<_>>()?,
+            ..self.clone()
+        })
+    }
+
+    /// Whether nothing is filtered out.
+    pub fn is_all(&self) -> bool {
+        self.only.is_empty() && self.exclude.is_empty() && self.targets.is_empty()
+    }
+
+    /// Whether any resource of `kind` can be selected, so that whole resource types can be
+    /// skipped without fetching them.
+    pub fn includes_type(&self, kind: &str) -> bool {
+        (self.only.is_empty() || self.only.iter().any(|k| k == kind))
+            && !self.exclude.iter().any(|k| k == kind)
+            && (self.targets.is_empty() || self.targets.iter().any(|t| t.may_match_below(kind)))
+    }
+
+    /// Whether the file or directory `path` of the realm in `realm_dir` is selected.
+    /// Paths outside the realm are.
+    pub fn includes(&self, realm_dir: &Path, path: &Path) -> bool {
+        let Some(relative) = relative_segments(realm_dir, path) else {
+            return true;
+        };
+        let kind = if relative.len() == 1 {
+            "realm"
+        } else {
+            relative[0].as_str()
+        };
+        self.includes_type(kind)
+            && (self.targets.is_empty() || self.targets.iter().any(|t| t.matches(&relative)))
+    }
+
+    /// The selection for the realm in `realm_dir`. With `--with-deps`, it also targets the
+    /// local files of every resource the selected ones refer to (client scopes and roles
+    /// of a client, groups of a user, ...), transitively.
+    pub async fn for_realm(&self, realm_dir: &Path, ws: &WorkspaceContext) -> Result<Selection> {
+        if !self.with_deps || self.is_all() || !realm_dir.exists() {
+            return Ok(self.clone());
+        }
+        let mut documents = Vec::new();
+        let mut by_identity: HashMap<Reference, PathBuf> = HashMap::new();
+        let mut redirects = HashMap::new();
+        for path in realm_files(realm_dir, ws).await? {
+            let Some(relative) = relative_segments(realm_dir, &path) else {
+                continue;
+            };
+            let value = load_yaml_with_overlay(&path, ws).await?;
+            if relative.len() == 1 {
+                redirects.extend(graph::redirects(&value));
+            }
+            if relative.len() == 2
+                && let Some(name) = identity(&relative[0], &value)
+            {
+                by_identity.insert((relative[0].clone(), name), path.clone());
+            }
+            documents.push((path, relative, value));
+        }
+
+        let mut selected: BTreeSet<PathBuf> = documents
+            .iter()
+            .filter(|(path, _, _)| self.includes(realm_dir, path))
+            .map(|(path, _, _)| path.clone())
+            .collect();
+        let mut pending: Vec<PathBuf> = selected.iter().cloned().collect();
+        while let Some(path) = pending.pop() {
+            let Some((_, relative, value)) = documents.iter().find(|(p, _, _)| *p == path) else {
+                continue;
+            };
+            for (_, dependency) in references(relative, value, &redirects) {
+                // Only explicit exclusions keep dependencies out.
+                if let Some(dep_path) = by_identity.get(&dependency)
+                    && !self.exclude.contains(&dependency.0)
+                    && selected.insert(dep_path.clone())
+                {
+                    pending.push(dep_path.clone());
+                }
+            }
+        }
+
+        let targets = selected
+            .iter()
+            .filter_map(|path| relative_segments(realm_dir, path))
+            .map(|segments| segments.join("/").parse())
+            .collect::<Result<_>>()?;
+        Ok(Selection {
+            only: Vec::new(),
+            exclude: self.exclude.clone(),
+            targets,
+            with_deps: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_selection() {
+        let realm = Path::new("/ws/master");
+        let all = Selection::default();
+        assert!(all.is_all());
+        assert!(all.includes(realm, Path::new("/ws/master/users/alice.yaml")));
+
+        let only = Selection::new(&["clients".into(), "realm".into()], &[], &[], false).unwrap();
+        assert!(only.includes(realm, Path::new("/ws/master/realm.yaml")));
+        assert!(only.includes(realm, Path::new("/ws/master/clients/app.yaml")));
+        assert!(!only.includes_type("users"));
+
+        let exclude = Selection::new(&[], &["users".into()], &[], false).unwrap();
+        assert!(!exclude.includes(realm, Path::new("/ws/master/users/alice.yaml")));
+        assert!(exclude.includes(realm, Path::new("/ws/master/roles/admin.yaml")));
+
+        let targets = Selection::new(
+            &[],
+            &[],
+            &["clients/my-app".into(), "roles/app-*".into()],
+            false,
+        )
+        .unwrap();
+        assert!(targets.includes(realm, Path::new("/ws/master/clients/my-app.yaml")));
+        assert!(!targets.includes(realm, Path::new("/ws/master/clients/other.yaml")));
+        assert!(targets.includes(realm, Path::new("/ws/master/roles/app-admin.yaml")));
+        assert!(!targets.includes(realm, Path::new("/ws/master/realm.yaml")));
+        assert!(targets.includes_type("roles"));
+        assert!(!targets.includes_type("users"));
+
+        assert!(Selection::new(&["client".into()], &[], &[], false).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_selection_with_dependencies() {
+        let dir = tempdir().unwrap();
+        let realm = dir.path().join("master");
+        for sub in ["clients", "client-scopes", "roles", "groups", "users"] {
+            std::fs::create_dir_all(realm.join(sub)).unwrap();
+        }
+        let write = |path: &str, content: &str| std::fs::write(realm.join(path), content).unwrap();
+        write("realm.yaml", "realm: master\n");
+        write(
+            "clients/my-app.yaml",
+            "clientId: my-app\ndefaultClientScopes: [app-scope]\nserviceAccount:\n  realmRoles: [app-role]\n",
+        );
+        write("clients/other.yaml", "clientId: other\n");
+        write("client-scopes/app-scope.yaml", "name: app-scope\n");
+        write(
+            "roles/app-role.yaml",
+            "name: app-role\ncomposites:\n  realm: [base-role]\n",
+        );
+        write("roles/base-role.yaml", "name: base-role\n");
+        write("roles/unrelated.yaml", "name: unrelated\n");
+        write("users/alice.yaml", "username: alice\ngroups: [/staff]\n");
+
+        let selection = Selection::new(&[], &[], &["clients/my-app".into()], true)
+            .unwrap()
+            .for_realm(&realm, &WorkspaceContext::default())
+            .await
+            .unwrap();
+        let selected = |path: &str| selection.includes(&realm, &realm.join(path));
+        assert!(selected("clients/my-app.yaml"));
+        assert!(selected("client-scopes/app-scope.yaml"));
+        assert!(selected("roles/app-role.yaml"));
+        assert!(selected("roles/base-role.yaml"));
+        assert!(!selected("roles/unrelated.yaml"));
+        assert!(!selected("clients/other.yaml"));
+        assert!(!selected("users/alice.yaml"));
+        assert!(!selected("realm.yaml"));
+    }
+}
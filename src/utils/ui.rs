@@ -6,6 +6,7 @@ pub static CHECK: Emoji<'_, '_> = Emoji("✅ ", "√ ");
 pub static SUCCESS: Emoji<'_, '_> = Emoji("🎉 ", "* ");
 pub static SUCCESS_CREATE: Emoji<'_, '_> = Emoji("✨ ", "+ ");
 pub static SUCCESS_UPDATE: Emoji<'_, '_> = Emoji("🔄 ", "~ ");
+pub static SUCCESS_DELETE: Emoji<'_, '_> = Emoji("🗑️ ", "- ");
 pub static WARN: Emoji<'_, '_> = Emoji("⚠️ ", "! ");
 pub static ERROR: Emoji<'_, '_> = Emoji("❌ ", "x ");
 pub static INFO: Emoji<'_, '_> = Emoji("💡 ", "i ");
@@ -1,22 +1,132 @@
+use crate::workspace::WorkspaceContext;
 use anyhow::{Context, Result};
 use serde_json::Value;
-use std::path::Path;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
 use tokio::fs as async_fs;
 
 /// Deep merges two JSON values. `b` is merged into `a`.
-pub fn deep_merge(a: &mut Value, b: &Value) {
-    match (a, b) {
-        (Value::Object(a_map), Value::Object(b_map)) => {
+///
+/// Objects are merged key by key; a `null` or `"$delete"` value removes the key from
+/// `a`. Any other value replaces the one in `a`, except for list directives:
+///
+/// ```yaml
+/// redirectUris: { $append: [...], $prepend: [...], $remove: [...] }
+/// protocolMappers: { $mergeBy: name, $items: [...], $remove: [old-mapper] }
+/// ```
+///
+/// `$mergeBy` deep-merges `$items` into the list items with the same key and appends
+/// the others; its `$remove` entries may be keys instead of whole items.
+pub fn deep_merge(a: &mut Value, b: &Value) -> Result<()> {
+    match b {
+        Value::Object(b_map) if is_list_directive(b_map) => {
+            let items = match std::mem::take(a) {
+                Value::Array(items) => items,
+                Value::Null => Vec::new(),
+                other => anyhow::bail!(
+                    "List directives can only be applied to lists, found {}",
+                    other
+                ),
+            };
+            *a = Value::Array(apply_list_directive(items, b_map)?);
+        }
+        Value::Object(b_map) => {
+            if !a.is_object() {
+                *a = Value::Object(serde_json::Map::new());
+            }
+            let a_map = a.as_object_mut().expect("object");
             for (key, val) in b_map {
-                deep_merge(a_map.entry(key.clone()).or_insert(Value::Null), val);
+                if is_delete(val) {
+                    a_map.remove(key);
+                } else {
+                    deep_merge(a_map.entry(key.clone()).or_insert(Value::Null), val)
+                        .with_context(|| format!("Failed to merge '{}'", key))?;
+                }
+            }
+        }
+        b => *a = b.clone(),
+    }
+    Ok(())
+}
+
+fn is_delete(value: &Value) -> bool {
+    value.is_null() || value.as_str() == Some("$delete")
+}
+
+const LIST_DIRECTIVES: [&str; 5] = ["$append", "$prepend", "$remove", "$mergeBy", "$items"];
+
+fn is_list_directive(map: &serde_json::Map<String, Value>) -> bool {
+    !map.is_empty() && map.keys().all(|k| k.starts_with('$'))
+}
+
+fn apply_list_directive(
+    mut items: Vec<Value>,
+    directive: &serde_json::Map<String, Value>,
+) -> Result<Vec<Value>> {
+    if let Some(unknown) = directive
+        .keys()
+        .find(|k| !LIST_DIRECTIVES.contains(&k.as_str()))
+    {
+        anyhow::bail!(
+            "Unknown list directive '{}', expected one of {}",
+            unknown,
+            LIST_DIRECTIVES.join(", ")
+        );
+    }
+    let list = |name: &str| -> Result<&[Value]> {
+        match directive.get(name) {
+            None => Ok(&[]),
+            Some(Value::Array(values)) => Ok(values),
+            Some(_) => anyhow::bail!("'{}' must be a list", name),
+        }
+    };
+    let merge_by = match directive.get("$mergeBy") {
+        None => None,
+        Some(Value::String(key)) => Some(key.as_str()),
+        Some(_) => anyhow::bail!("'$mergeBy' must be a field name"),
+    };
+    if merge_by.is_none() && directive.contains_key("$items") {
+        anyhow::bail!("'$items' requires '$mergeBy'");
+    }
+
+    let key_of = |item: &Value| merge_by.and_then(|key| item.get(key)).cloned();
+    let removed = list("$remove")?;
+    items.retain(|item| {
+        !removed.iter().any(|r| {
+            r == item
+                || key_of(item).is_some_and(|key| &key == r || key_of(r).as_ref() == Some(&key))
+        })
+    });
+
+    for new_item in list("$items")? {
+        let key = key_of(new_item).with_context(|| {
+            format!(
+                "Item of '$items' has no '{}' field",
+                merge_by.unwrap_or_default()
+            )
+        })?;
+        match items
+            .iter_mut()
+            .find(|item| key_of(item) == Some(key.clone()))
+        {
+            Some(item) => deep_merge(item, new_item)?,
+            None => {
+                let mut item = Value::Null;
+                deep_merge(&mut item, new_item)?;
+                items.push(item);
             }
         }
-        (a, b) => *a = b.clone(),
     }
+
+    let mut result = list("$prepend")?.to_vec();
+    result.append(&mut items);
+    result.extend_from_slice(list("$append")?);
+    Ok(result)
 }
 
-/// Loads a base YAML file and optionally merges it with a profile-specific overlay.
-pub async fn load_yaml_with_overlay(base_path: &Path, profile: Option<&str>) -> Result<Value> {
+/// Loads a base YAML file and merges the overlays of the profile chain of `ws` onto it,
+/// e.g. `client.yaml`, then `client.eu.yaml`, then `client.eu-prod.yaml`.
+pub async fn load_yaml_with_overlay(base_path: &Path, ws: &WorkspaceContext) -> Result<Value> {
     let content = async_fs::read_to_string(base_path)
         .await
         .with_context(|| format!("Failed to read base YAML file: {:?}", base_path))?;
@@ -24,46 +134,86 @@ pub async fn load_yaml_with_overlay(base_path: &Path, profile: Option<&str>) ->
     let mut val: Value = serde_yaml::from_str(&content)
         .with_context(|| format!("Failed to parse base YAML file: {:?}", base_path))?;
 
-    if let Some(profile_name) = profile
-        && let Some(stem) = base_path.file_stem().and_then(|s| s.to_str())
+    if let Some(stem) = base_path.file_stem().and_then(|s| s.to_str())
         && let Some(ext) = base_path.extension().and_then(|e| e.to_str())
     {
-        let overlay_path = base_path.with_file_name(format!("{}.{}.{}", stem, profile_name, ext));
-        if async_fs::try_exists(&overlay_path).await? {
+        for layer in &ws.overlays {
+            let overlay_path = base_path.with_file_name(format!("{}.{}.{}", stem, layer, ext));
+            if !async_fs::try_exists(&overlay_path).await? {
+                continue;
+            }
             let overlay_content = async_fs::read_to_string(&overlay_path)
                 .await
                 .with_context(|| format!("Failed to read overlay YAML file: {:?}", overlay_path))?;
             let overlay_val: Value = serde_yaml::from_str(&overlay_content).with_context(|| {
                 format!("Failed to parse overlay YAML file: {:?}", overlay_path)
             })?;
-            deep_merge(&mut val, &overlay_val);
+            deep_merge(&mut val, &overlay_val)
+                .with_context(|| format!("Failed to apply overlay {:?}", overlay_path))?;
         }
     }
 
     Ok(val)
 }
 
-/// Returns true if the file is a profile-specific overlay.
-pub fn is_overlay_file(path: &Path, profile: Option<&str>) -> bool {
-    if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-        // Pattern: *.profile.yaml
-        if let Some(p) = profile
-            && file_name.contains(&format!(".{}.yaml", p))
-        {
-            return true;
-        }
-        // Generic pattern for any profile: *.*.yaml
-        // We want to skip any file that looks like an overlay if we are not in that profile
-        // but wait, if we are in profile 'prod', we skip 'test.yaml'?
-        // No, 'test.yaml' is NOT an overlay. 'resource.test.yaml' IS an overlay.
-
-        let parts: Vec<&str> = file_name.split('.').collect();
-        if parts.len() >= 3 && parts[parts.len() - 1] == "yaml" {
-            // It has at least two dots before 'yaml'. e.g. 'resource.prod.yaml'
-            return true;
+/// The profiles of the workspace, from the `profiles/*.yaml` files. Read once per run
+/// into [`WorkspaceContext::profiles`].
+pub fn workspace_profiles(workspace: &Path) -> BTreeSet<String> {
+    let Ok(entries) = std::fs::read_dir(workspace.join("profiles")) else {
+        return BTreeSet::new();
+    };
+    entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "yaml" {
+                return None;
+            }
+            Some(path.file_stem()?.to_str()?.to_string())
+        })
+        .collect()
+}
+
+/// The profile `path` is an overlay of (`resource.<profile>.yaml` with a known profile),
+/// and the base file it applies to.
+pub fn overlay_target(path: &Path, ws: &WorkspaceContext) -> Option<(String, PathBuf)> {
+    let stem = path.file_name()?.to_str()?.strip_suffix(".yaml")?;
+    ws.profiles.iter().chain(&ws.overlays).find_map(|name| {
+        let base = stem.strip_suffix(&format!(".{}", name))?;
+        (!base.is_empty()).then(|| (name.clone(), path.with_file_name(format!("{}.yaml", base))))
+    })
+}
+
+/// Returns true if the file is a profile-specific overlay. Only known profile names
+/// count, so dotted resource names such as `my.app.yaml` are regular resources.
+pub fn is_overlay_file(path: &Path, ws: &WorkspaceContext) -> bool {
+    overlay_target(path, ws).is_some()
+}
+
+/// Every YAML file below `realm_dir` that is neither an overlay nor ignored by
+/// `kcd.yaml`, sorted.
+pub async fn realm_files(realm_dir: &Path, ws: &WorkspaceContext) -> Result<Vec<PathBuf>> {
+    if !async_fs::try_exists(realm_dir).await? {
+        anyhow::bail!("Realm directory {:?} does not exist", realm_dir);
+    }
+    let mut files = Vec::new();
+    let mut dirs = vec![realm_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = async_fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if ws.filter.is_ignored(&path) {
+                continue;
+            } else if entry.file_type().await?.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "yaml")
+                && !is_overlay_file(&path, ws)
+            {
+                files.push(path);
+            }
         }
     }
-    false
+    files.sort();
+    Ok(files)
 }
 
 #[cfg(test)]
@@ -87,7 +237,7 @@ mod tests {
                 "sub2": 2
             }
         });
-        deep_merge(&mut a, &b);
+        deep_merge(&mut a, &b).unwrap();
         assert_eq!(
             a,
             json!({
@@ -102,10 +252,95 @@ mod tests {
 
         let mut c = json!({ "a": 1 });
         let d = json!({ "a": 2 });
-        deep_merge(&mut c, &d);
+        deep_merge(&mut c, &d).unwrap();
         assert_eq!(c, json!({ "a": 2 }));
     }
 
+    #[test]
+    fn test_deep_merge_directives() {
+        let mut a = json!({
+            "clientId": "app",
+            "description": "base",
+            "attributes": { "a": "1", "b": "2" },
+            "redirectUris": ["https://a/*", "https://b/*"],
+            "webOrigins": ["+"],
+            "protocolMappers": [
+                { "name": "email", "config": { "claim": "email", "multivalued": "false" } },
+                { "name": "legacy", "config": {} }
+            ]
+        });
+        let b = json!({
+            "description": null,
+            "attributes": { "b": "$delete", "c": "3" },
+            "redirectUris": { "$remove": ["https://a/*"], "$append": ["https://c/*"], "$prepend": ["https://z/*"] },
+            "webOrigins": { "$append": ["https://c"] },
+            "defaultClientScopes": { "$append": ["profile"] },
+            "protocolMappers": {
+                "$mergeBy": "name",
+                "$remove": ["legacy"],
+                "$items": [
+                    { "name": "email", "config": { "multivalued": "true" } },
+                    { "name": "groups", "config": { "claim": "groups", "x": null } }
+                ]
+            }
+        });
+        deep_merge(&mut a, &b).unwrap();
+        assert_eq!(
+            a,
+            json!({
+                "clientId": "app",
+                "attributes": { "a": "1", "c": "3" },
+                "redirectUris": ["https://z/*", "https://b/*", "https://c/*"],
+                "webOrigins": ["+", "https://c"],
+                "defaultClientScopes": ["profile"],
+                "protocolMappers": [
+                    { "name": "email", "config": { "claim": "email", "multivalued": "true" } },
+                    { "name": "groups", "config": { "claim": "groups" } }
+                ]
+            })
+        );
+
+        let mut a = json!({ "list": [1] });
+        let err = deep_merge(&mut a, &json!({ "list": { "$apend": [2] } })).unwrap_err();
+        assert!(format!("{:#}", err).contains("Unknown list directive '$apend'"));
+        assert!(
+            deep_merge(
+                &mut json!({ "x": "s" }),
+                &json!({ "x": { "$append": [1] } })
+            )
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_yaml_with_overlay_chain() {
+        let dir = tempdir().unwrap();
+        let base_path = dir.path().join("client.yaml");
+        fs::write(
+            &base_path,
+            "clientId: app\nredirectUris: [\"http://localhost/*\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("client.kcd-test-eu.yaml"),
+            "region: eu\nredirectUris:\n  $append: [\"https://eu.example.com/*\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("client.kcd-test-eu-prod.yaml"),
+            "redirectUris:\n  $remove: [\"http://localhost/*\"]\n",
+        )
+        .unwrap();
+
+        let ws = WorkspaceContext {
+            overlays: vec!["kcd-test-eu".to_string(), "kcd-test-eu-prod".to_string()],
+            ..WorkspaceContext::with_profile(Some("kcd-test-eu-prod"))
+        };
+        let val = load_yaml_with_overlay(&base_path, &ws).await.unwrap();
+        assert_eq!(val["region"], "eu");
+        assert_eq!(val["redirectUris"], json!(["https://eu.example.com/*"]));
+    }
+
     #[tokio::test]
     async fn test_load_yaml_with_overlay() {
         let dir = tempdir().unwrap();
@@ -113,12 +348,14 @@ mod tests {
         fs::write(&base_path, "name: base\nenabled: true\nconfig:\n  k1: v1").unwrap();
 
         // 1. Load without profile
-        let val = load_yaml_with_overlay(&base_path, None).await.unwrap();
+        let val = load_yaml_with_overlay(&base_path, &WorkspaceContext::default())
+            .await
+            .unwrap();
         assert_eq!(val["name"], "base");
         assert_eq!(val["enabled"], true);
 
         // 2. Load with non-existent profile
-        let val = load_yaml_with_overlay(&base_path, Some("prod"))
+        let val = load_yaml_with_overlay(&base_path, &WorkspaceContext::with_profile(Some("prod")))
             .await
             .unwrap();
         assert_eq!(val["name"], "base");
@@ -127,7 +364,7 @@ mod tests {
         let overlay_path = dir.path().join("resource.prod.yaml");
         fs::write(&overlay_path, "name: prod-override\nconfig:\n  k2: v2").unwrap();
 
-        let val = load_yaml_with_overlay(&base_path, Some("prod"))
+        let val = load_yaml_with_overlay(&base_path, &WorkspaceContext::with_profile(Some("prod")))
             .await
             .unwrap();
         assert_eq!(val["name"], "prod-override");
@@ -138,14 +375,37 @@ mod tests {
 
     #[test]
     fn test_is_overlay_file() {
-        assert!(is_overlay_file(Path::new("role.prod.yaml"), Some("prod")));
-        assert!(is_overlay_file(Path::new("client.test.yaml"), Some("prod"))); // Generic pattern match
-        assert!(!is_overlay_file(Path::new("role.yaml"), Some("prod")));
-        assert!(!is_overlay_file(Path::new("some.txt"), Some("prod")));
-
-        // Multi-dot non-overlay cases?
-        // Our current logic says if it has >= 3 parts (2 dots) and ends in yaml, it is an overlay.
-        // e.g. "my.resource.yaml" is an overlay.
-        assert!(is_overlay_file(Path::new("my.resource.yaml"), None));
+        let prod = WorkspaceContext::with_profile(Some("prod"));
+        assert!(is_overlay_file(Path::new("role.prod.yaml"), &prod));
+        assert!(!is_overlay_file(Path::new("client.test.yaml"), &prod));
+        assert!(!is_overlay_file(Path::new("role.yaml"), &prod));
+        assert!(!is_overlay_file(Path::new("some.txt"), &prod));
+        assert!(!is_overlay_file(Path::new(".prod.yaml"), &prod));
+        assert!(!is_overlay_file(
+            Path::new("my.resource.yaml"),
+            &WorkspaceContext::default()
+        ));
+    }
+
+    #[test]
+    fn test_overlay_target_uses_workspace_profiles() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("profiles")).unwrap();
+        fs::write(dir.path().join("profiles/staging.yaml"), "server_url: x").unwrap();
+        let clients = dir.path().join("realm/clients");
+        fs::create_dir_all(&clients).unwrap();
+        let ws = WorkspaceContext::for_workspace(dir.path());
+        assert_eq!(ws.profiles, BTreeSet::from(["staging".to_string()]));
+
+        assert_eq!(
+            overlay_target(&clients.join("my.app.staging.yaml"), &ws),
+            Some(("staging".to_string(), clients.join("my.app.yaml")))
+        );
+        assert!(!is_overlay_file(&clients.join("my.app.yaml"), &ws));
+        assert!(!is_overlay_file(&clients.join("app.prod.yaml"), &ws));
+        assert!(is_overlay_file(
+            &clients.join("app.prod.yaml"),
+            &WorkspaceContext::with_profile(Some("prod"))
+        ));
     }
 }
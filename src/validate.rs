@@ -1,10 +1,13 @@
 use crate::models::{
-    AuthenticationFlowRepresentation, ClientRepresentation, ClientScopeRepresentation,
-    ComponentRepresentation, GroupRepresentation, IdentityProviderRepresentation,
-    RealmRepresentation, RequiredActionProviderRepresentation, RoleRepresentation,
-    UserRepresentation,
+    AuthenticationFlowRepresentation, ClientPoliciesRepresentation, ClientProfilesRepresentation,
+    ClientRepresentation, ClientScopeRepresentation, ComponentRepresentation, GroupRepresentation,
+    IdentityProviderRepresentation, OrganizationRepresentation, RealmRepresentation,
+    RequiredActionProviderRepresentation, RoleRepresentation, UserRepresentation,
 };
+use crate::utils::secrets::template::undefined_variables;
 use crate::utils::ui::{CHECK, SEARCH, SUCCESS, WARN};
+use crate::utils::yaml::{is_overlay_file, load_yaml_with_overlay, overlay_target};
+use crate::workspace::WorkspaceContext;
 use anyhow::{Context, Result};
 use console::style;
 use serde::de::DeserializeOwned;
@@ -16,6 +19,7 @@ use tokio::task::JoinSet;
 async fn read_yaml_files<T: DeserializeOwned + Send + 'static>(
     dir: &Path,
     file_type: &str,
+    ws: &WorkspaceContext,
 ) -> Result<Vec<(PathBuf, T)>> {
     let mut results = Vec::new();
     if fs::try_exists(dir).await? {
@@ -25,7 +29,12 @@ async fn read_yaml_files<T: DeserializeOwned + Send + 'static>(
 
         while let Some(entry) = entries.next_entry().await? {
             let path = entry.path();
-            if path.extension().is_some_and(|ext| ext == "yaml") {
+            // Overlays are partial (and may hold merge directives); they are checked
+            // merged onto their base by `validate_variables`.
+            if path.extension().is_some_and(|ext| ext == "yaml")
+                && !is_overlay_file(&path, ws)
+                && !ws.filter.is_ignored(&path)
+            {
                 let ft = file_type_str.clone();
                 join_set.spawn(async move {
                     let content = fs::read_to_string(&path)
@@ -46,6 +55,21 @@ async fn read_yaml_files<T: DeserializeOwned + Send + 'static>(
 }
 
 pub async fn run(workspace_dir: PathBuf, realms_to_validate: &[String]) -> Result<()> {
+    let ws = WorkspaceContext::for_workspace(&workspace_dir);
+    run_with_vars(workspace_dir, realms_to_validate, &ws, &|name| {
+        std::env::var(name).is_ok()
+    })
+    .await
+}
+
+/// Like [`run`], also checking that every `${VAR}` reference (with the overlays of
+/// the profile of `ws` applied) is known to `is_defined`.
+pub async fn run_with_vars(
+    workspace_dir: PathBuf,
+    realms_to_validate: &[String],
+    ws: &WorkspaceContext,
+    is_defined: &(dyn Fn(&str) -> bool + Sync),
+) -> Result<()> {
     if !fs::try_exists(&workspace_dir).await? {
         anyhow::bail!("Input directory {:?} does not exist", workspace_dir);
     }
@@ -54,7 +78,9 @@ pub async fn run(workspace_dir: PathBuf, realms_to_validate: &[String]) -> Resul
         let mut dirs = Vec::new();
         let mut entries = fs::read_dir(&workspace_dir).await?;
         while let Some(entry) = entries.next_entry().await? {
-            if entry.file_type().await?.is_dir() {
+            if entry.file_type().await?.is_dir()
+                && !entry.file_name().to_string_lossy().starts_with('.')
+            {
                 dirs.push(entry.file_name().to_string_lossy().to_string());
             }
         }
@@ -85,7 +111,8 @@ pub async fn run(workspace_dir: PathBuf, realms_to_validate: &[String]) -> Resul
                 .bold()
         );
         let realm_dir = workspace_dir.join(realm_name);
-        validate_realm(realm_dir).await?;
+        validate_realm(realm_dir.clone(), ws).await?;
+        validate_variables(&realm_dir, ws, is_defined).await?;
         println!(
             "  {} {}",
             SUCCESS,
@@ -264,7 +291,118 @@ fn validate_required_actions(
     Ok(())
 }
 
-async fn validate_realm(workspace_dir: PathBuf) -> Result<()> {
+fn validate_organizations(
+    orgs: &[(PathBuf, OrganizationRepresentation)],
+    idps: &[(PathBuf, IdentityProviderRepresentation)],
+) -> Result<()> {
+    let known_idps: HashSet<&str> = idps
+        .iter()
+        .filter_map(|(_, i)| i.alias.as_deref())
+        .collect();
+    for (path, org) in orgs {
+        if org.name.as_deref().unwrap_or_default().is_empty() {
+            anyhow::bail!("Organization name is missing or empty in {:?}", path);
+        }
+        for domain in org.domains.iter().flatten() {
+            if domain.name.is_empty() {
+                anyhow::bail!("Organization domain name is empty in {:?}", path);
+            }
+        }
+        for alias in org.identity_providers.iter().flatten() {
+            if !known_idps.contains(alias.as_str()) {
+                println!(
+                    "  {} {}",
+                    WARN,
+                    style(format!(
+                        "Organization in {:?} links identity provider '{}' which is not defined locally",
+                        path, alias
+                    ))
+                    .yellow()
+                );
+            }
+        }
+    }
+    println!(
+        "  {} {} {}",
+        CHECK,
+        style("Validated organizations:").dim(),
+        style(orgs.len()).green()
+    );
+    Ok(())
+}
+
+async fn validate_client_policies(workspace_dir: &Path) -> Result<()> {
+    let dir = workspace_dir.join("client-policies");
+    let profiles_path = dir.join("profiles.yaml");
+    let policies_path = dir.join("policies.yaml");
+
+    let mut profile_names = HashSet::new();
+    if fs::try_exists(&profiles_path).await? {
+        let content = fs::read_to_string(&profiles_path).await?;
+        let profiles: ClientProfilesRepresentation = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse client profiles file {:?}", profiles_path))?;
+        for profile in &profiles.profiles {
+            if profile.name.is_empty() {
+                anyhow::bail!("Client profile name is empty in {:?}", profiles_path);
+            }
+            if !profile_names.insert(profile.name.clone()) {
+                anyhow::bail!(
+                    "Duplicate client profile '{}' in {:?}",
+                    profile.name,
+                    profiles_path
+                );
+            }
+        }
+        println!(
+            "  {} {} {}",
+            CHECK,
+            style("Validated client profiles:").dim(),
+            style(profiles.profiles.len()).green()
+        );
+    }
+
+    if fs::try_exists(&policies_path).await? {
+        let content = fs::read_to_string(&policies_path).await?;
+        let policies: ClientPoliciesRepresentation = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse client policies file {:?}", policies_path))?;
+        let mut policy_names = HashSet::new();
+        for policy in &policies.policies {
+            if policy.name.is_empty() {
+                anyhow::bail!("Client policy name is empty in {:?}", policies_path);
+            }
+            if !policy_names.insert(policy.name.clone()) {
+                anyhow::bail!(
+                    "Duplicate client policy '{}' in {:?}",
+                    policy.name,
+                    policies_path
+                );
+            }
+            for profile in policy.profiles.iter().flatten() {
+                // Global profiles (e.g. `fapi-1-baseline`) are built into the server.
+                if !profile_names.contains(profile) {
+                    println!(
+                        "  {} {}",
+                        WARN,
+                        style(format!(
+                            "Client policy '{}' references profile '{}' which is not defined locally (ok if it is a global profile)",
+                            policy.name, profile
+                        ))
+                        .yellow()
+                    );
+                }
+            }
+        }
+        println!(
+            "  {} {} {}",
+            CHECK,
+            style("Validated client policies:").dim(),
+            style(policies.policies.len()).green()
+        );
+    }
+    Ok(())
+}
+
+async fn validate_realm(workspace_dir: PathBuf, ws: &WorkspaceContext) -> Result<()> {
     // 1. Validate Realm
     validate_realm_config(&workspace_dir).await?;
 
@@ -277,16 +415,22 @@ async fn validate_realm(workspace_dir: PathBuf) -> Result<()> {
     let users_dir = workspace_dir.join("users");
     let flows_dir = workspace_dir.join("authentication-flows");
     let actions_dir = workspace_dir.join("required-actions");
+    let orgs_dir = workspace_dir.join("organizations");
 
-    let (roles, clients, idps, scopes, groups, users, flows, actions) = tokio::try_join!(
-        read_yaml_files::<RoleRepresentation>(&roles_dir, "role"),
-        read_yaml_files::<ClientRepresentation>(&clients_dir, "client"),
-        read_yaml_files::<IdentityProviderRepresentation>(&idps_dir, "idp"),
-        read_yaml_files::<ClientScopeRepresentation>(&scopes_dir, "client-scope"),
-        read_yaml_files::<GroupRepresentation>(&groups_dir, "group"),
-        read_yaml_files::<UserRepresentation>(&users_dir, "user"),
-        read_yaml_files::<AuthenticationFlowRepresentation>(&flows_dir, "authentication-flow"),
-        read_yaml_files::<RequiredActionProviderRepresentation>(&actions_dir, "required-action"),
+    let (roles, clients, idps, scopes, groups, users, flows, actions, orgs) = tokio::try_join!(
+        read_yaml_files::<RoleRepresentation>(&roles_dir, "role", ws),
+        read_yaml_files::<ClientRepresentation>(&clients_dir, "client", ws),
+        read_yaml_files::<IdentityProviderRepresentation>(&idps_dir, "idp", ws),
+        read_yaml_files::<ClientScopeRepresentation>(&scopes_dir, "client-scope", ws),
+        read_yaml_files::<GroupRepresentation>(&groups_dir, "group", ws),
+        read_yaml_files::<UserRepresentation>(&users_dir, "user", ws),
+        read_yaml_files::<AuthenticationFlowRepresentation>(&flows_dir, "authentication-flow", ws),
+        read_yaml_files::<RequiredActionProviderRepresentation>(
+            &actions_dir,
+            "required-action",
+            ws
+        ),
+        read_yaml_files::<OrganizationRepresentation>(&orgs_dir, "organization", ws),
     )?;
 
     // Validate resources
@@ -298,21 +442,84 @@ async fn validate_realm(workspace_dir: PathBuf) -> Result<()> {
     validate_users(&users)?;
     validate_authentication_flows(&flows)?;
     validate_required_actions(&actions)?;
+    validate_organizations(&orgs, &idps)?;
 
-    // Validate Components and Keys
+    // Validate Components, Keys and Client Policies
     tokio::try_join!(
-        validate_components_in_dir(&workspace_dir, "components"),
-        validate_components_in_dir(&workspace_dir, "keys")
+        validate_components_in_dir(&workspace_dir, "components", ws),
+        validate_components_in_dir(&workspace_dir, "keys", ws),
+        validate_client_policies(&workspace_dir)
     )?;
 
     Ok(())
 }
 
-async fn validate_components_in_dir(workspace_dir: &Path, dir_name: &str) -> Result<()> {
+/// Reports the variables referenced by the realm files that are neither in the profile
+/// `vars` nor in the environment and have no `${VAR:-default}`.
+async fn validate_variables(
+    realm_dir: &Path,
+    ws: &WorkspaceContext,
+    is_defined: &(dyn Fn(&str) -> bool + Sync),
+) -> Result<()> {
+    let mut undefined = Vec::new();
+    let mut orphans = Vec::new();
+    let mut dirs = vec![realm_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if ws.filter.is_ignored(&path) {
+                continue;
+            } else if entry.file_type().await?.is_dir() {
+                dirs.push(path);
+            } else if let Some((overlay_profile, base)) = overlay_target(&path, ws) {
+                if !fs::try_exists(&base).await? {
+                    let file = path.strip_prefix(realm_dir).unwrap_or(&path);
+                    orphans.push(format!("{} ({})", file.display(), overlay_profile));
+                }
+            } else if path.extension().is_some_and(|ext| ext == "yaml") {
+                let value = load_yaml_with_overlay(&path, ws).await?;
+                for name in undefined_variables(&value, is_defined) {
+                    let file = path.strip_prefix(realm_dir).unwrap_or(&path);
+                    undefined.push(format!("{} ({})", name, file.display()));
+                }
+            }
+        }
+    }
+
+    orphans.sort();
+    for orphan in &orphans {
+        println!(
+            "  {} {}",
+            WARN,
+            style(format!("Overlay without a base file: {}", orphan)).yellow()
+        );
+    }
+
+    if !undefined.is_empty() {
+        undefined.sort();
+        anyhow::bail!(
+            "Undefined variables (add them to the profile `vars`, the environment or use ${{VAR:-default}}):\n  {}",
+            undefined.join("\n  ")
+        );
+    }
+    println!(
+        "  {} {}",
+        CHECK,
+        style("Validated variable references").dim()
+    );
+    Ok(())
+}
+
+async fn validate_components_in_dir(
+    workspace_dir: &Path,
+    dir_name: &str,
+    ws: &WorkspaceContext,
+) -> Result<()> {
     let dir = workspace_dir.join(dir_name);
     if fs::try_exists(&dir).await? {
         let components: Vec<(PathBuf, ComponentRepresentation)> =
-            read_yaml_files(&dir, dir_name).await?;
+            read_yaml_files(&dir, dir_name, ws).await?;
         for (path, component) in &components {
             if let Some(name) = &component.name
                 && name.is_empty()
@@ -0,0 +1,331 @@
+use crate::apply::{self, report::ApplyReport};
+use crate::client::KeycloakClient;
+use crate::graph::relative_segments;
+use crate::plan::{self, PlanOptions};
+use crate::utils::filter::FileFilter;
+use crate::utils::secrets::SecretResolver;
+use crate::utils::selection::Selection;
+use crate::utils::ui::{ACTION, ERROR, SEARCH, Ui, WARN};
+use crate::utils::yaml::overlay_target;
+use crate::workspace::WorkspaceContext;
+use anyhow::{Context, Result};
+use console::style;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs;
+use tokio::sync::mpsc;
+
+/// The changed `paths` of the workspace in `workspace_dir`, as `--target` patterns by
+/// realm. Overlays count as their base file; files outside a realm, hidden ones (such
+/// as `.kcd/` and `.kcdplan`), files `kcd.yaml` does not manage and those outside
+/// `realms` (when given) or `selection` are left out.
+pub fn changed_targets(
+    workspace_dir: &Path,
+    paths: &[PathBuf],
+    ws: &WorkspaceContext,
+    realms: &[String],
+    selection: &Selection,
+) -> BTreeMap<String, BTreeSet<String>> {
+    let mut targets: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for path in paths {
+        let Ok(relative) = path.strip_prefix(workspace_dir) else {
+            continue;
+        };
+        let hidden = relative.components().any(|c| match c {
+            Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+            _ => true,
+        });
+        if hidden
+            || relative.components().count() < 2
+            || path.extension().is_none_or(|ext| ext != "yaml")
+        {
+            continue;
+        }
+        let Some(Component::Normal(realm)) = relative.components().next() else {
+            continue;
+        };
+        let realm = realm.to_string_lossy().to_string();
+        if !realms.is_empty() && !realms.contains(&realm) {
+            continue;
+        }
+        let realm_dir = workspace_dir.join(&realm);
+        let path = overlay_target(path, ws)
+            .map(|(_, base)| base)
+            .unwrap_or_else(|| path.clone());
+        if !ws.filter.is_managed(&path) || !selection.includes(&realm_dir, &path) {
+            continue;
+        }
+        if let Some(segments) = relative_segments(&realm_dir, &path) {
+            targets.entry(realm).or_default().insert(segments.join("/"));
+        }
+    }
+    targets
+}
+
+/// Watches the workspace and, once a burst of changes has been quiet for `debounce`,
+/// plans the changed files and applies them, until interrupted. Failures are reported
+/// and the next change is waited for.
+#[allow(clippy::too_many_arguments)]
+pub async fn run(
+    client: &KeycloakClient,
+    workspace_dir: &Path,
+    realms: &[String],
+    ui: Arc<dyn Ui>,
+    resolver: Arc<dyn SecretResolver>,
+    ws: Arc<WorkspaceContext>,
+    selection: &Selection,
+    debounce: Duration,
+) -> Result<()> {
+    // Notifications carry absolute paths
+    let root = fs::canonicalize(workspace_dir)
+        .await
+        .with_context(|| format!("Input directory {:?} does not exist", workspace_dir))?;
+    let ws = Arc::new(rooted(&ws, &root));
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })
+    .context("Failed to start the file watcher")?;
+    watcher
+        .watch(&root, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {:?}", workspace_dir))?;
+    println!(
+        "{} {}",
+        SEARCH,
+        style(format!(
+            "Watching {:?} for changes (press Ctrl+C to stop)",
+            workspace_dir
+        ))
+        .cyan()
+        .bold()
+    );
+
+    loop {
+        let event = tokio::select! {
+            event = rx.recv() => event,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let Some(event) = event else {
+            break;
+        };
+        let mut paths = event_paths(event);
+        // Editors save in several steps; wait for them to settle
+        while let Ok(Some(event)) = tokio::time::timeout(debounce, rx.recv()).await {
+            paths.extend(event_paths(event));
+        }
+        let targets = changed_targets(&root, &paths, &ws, realms, selection);
+        for (realm, targets) in targets {
+            let result = reconcile(
+                client,
+                &root,
+                &realm,
+                &targets,
+                Arc::clone(&ui),
+                Arc::clone(&resolver),
+                Arc::clone(&ws),
+                selection,
+            )
+            .await;
+            if let Err(e) = result {
+                println!("{} {}", ERROR, style(format!("{:#}", e)).red());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `ws` with its filter rooted at `root`, the canonical workspace directory the
+/// notifications are relative to, so that the rules of `kcd.yaml` still match.
+fn rooted(ws: &WorkspaceContext, root: &Path) -> WorkspaceContext {
+    WorkspaceContext {
+        filter: FileFilter {
+            root: root.to_path_buf(),
+            ..ws.filter.clone()
+        },
+        ..ws.clone()
+    }
+}
+
+fn event_paths(event: notify::Result<Event>) -> Vec<PathBuf> {
+    match event {
+        Ok(event) if matches!(event.kind, EventKind::Access(_)) => Vec::new(),
+        Ok(event) => event.paths,
+        Err(e) => {
+            println!(
+                "{} {}",
+                WARN,
+                style(format!("File watcher error: {}", e)).yellow()
+            );
+            Vec::new()
+        }
+    }
+}
+
+/// Plans the `targets` of `realm` and applies what changed. The plan replaces
+/// `.kcdplan`.
+#[allow(clippy::too_many_arguments)]
+async fn reconcile(
+    client: &KeycloakClient,
+    workspace_dir: &Path,
+    realm: &str,
+    targets: &BTreeSet<String>,
+    ui: Arc<dyn Ui>,
+    resolver: Arc<dyn SecretResolver>,
+    ws: Arc<WorkspaceContext>,
+    selection: &Selection,
+) -> Result<()> {
+    println!(
+        "\n{} {}",
+        ACTION,
+        style(format!(
+            "Changed in realm {}: {}",
+            realm,
+            targets.iter().cloned().collect::<Vec<_>>().join(", ")
+        ))
+        .cyan()
+        .bold()
+    );
+    let targets: Vec<String> = targets.iter().cloned().collect();
+    let selection = selection.with_targets(&targets)?;
+    let realms = vec![realm.to_string()];
+    let options = PlanOptions {
+        changes_only: true,
+        interactive: false,
+        drift: false,
+    };
+    plan::run_with_options(
+        client,
+        workspace_dir.to_path_buf(),
+        options,
+        &realms,
+        Arc::clone(&ui),
+        Arc::clone(&resolver),
+        Arc::clone(&ws),
+        &selection,
+        None,
+    )
+    .await?;
+    // Without a plan file, apply would send everything
+    if !fs::try_exists(workspace_dir.join(".kcdplan")).await? {
+        return Ok(());
+    }
+    let report = ApplyReport::new(false);
+    apply::run_with_selection(
+        client,
+        workspace_dir.to_path_buf(),
+        &realms,
+        true,
+        false,
+        ui,
+        resolver,
+        ws,
+        &selection,
+        &report,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_targets() {
+        let root = Path::new("/ws");
+        let ws = WorkspaceContext::with_profile(Some("prod"));
+        let paths: Vec<PathBuf> = [
+            "/ws/master/clients/app.yaml",
+            "/ws/master/clients/app.yaml",
+            "/ws/master/realm.yaml",
+            "/ws/master/clients/web.prod.yaml",
+            "/ws/master/authz/app/policies/admins.yaml",
+            "/ws/dev/roles/admin.yaml",
+            "/ws/master/clients/.app.yaml.swp",
+            "/ws/master/clients/notes.txt",
+            "/ws/.kcd/last-applied/master/clients.json",
+            "/ws/.kcdplan",
+            "/ws/kcd.yaml",
+            "/elsewhere/master/clients/app.yaml",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+
+        let targets = changed_targets(root, &paths, &ws, &[], &Selection::default());
+        let expected: BTreeMap<String, BTreeSet<String>> = [
+            ("dev".to_string(), ["roles/admin"].map(String::from).into()),
+            (
+                "master".to_string(),
+                [
+                    "authz/app/policies/admins",
+                    "clients/app",
+                    "clients/web",
+                    "realm",
+                ]
+                .map(String::from)
+                .into(),
+            ),
+        ]
+        .into();
+        assert_eq!(targets, expected);
+
+        let only_master = changed_targets(
+            root,
+            &paths,
+            &ws,
+            &["master".to_string()],
+            &Selection::new(&["clients".to_string()], &[], &[], false).unwrap(),
+        );
+        assert_eq!(
+            only_master,
+            [(
+                "master".to_string(),
+                ["clients/app", "clients/web"].map(String::from).into()
+            )]
+            .into()
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_protected_through_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().join("workspace");
+        std::fs::create_dir_all(workspace.join("master/clients")).unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&workspace, &link).unwrap();
+
+        // kcd.yaml rules are relative to the workspace as given on the command line
+        let ws = WorkspaceContext {
+            filter: FileFilter {
+                root: link.clone(),
+                protected: vec!["master/clients/admin-cli".parse().unwrap()],
+                ..FileFilter::default()
+            },
+            ..WorkspaceContext::default()
+        };
+        let root = fs::canonicalize(&link).await.unwrap();
+        let paths = vec![
+            root.join("master/clients/admin-cli.yaml"),
+            root.join("master/clients/app.yaml"),
+        ];
+        let targets = changed_targets(
+            &root,
+            &paths,
+            &rooted(&ws, &root),
+            &[],
+            &Selection::default(),
+        );
+        assert_eq!(
+            targets,
+            [(
+                "master".to_string(),
+                ["clients/app"].map(String::from).into()
+            )]
+            .into()
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use crate::lock::LockSettings;
+use crate::plan::normalize::DiffRules;
+use crate::snapshot::SnapshotSettings;
+use crate::utils::filter::FileFilter;
+use crate::utils::secrets::rules::SecretRules;
+use crate::utils::yaml::workspace_profiles;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// What a run reads and writes the workspace with: the active profile and the rules of
+/// `kcd.yaml`. It is built once per command by [`crate::WorkspaceConfig::context`] and
+/// handed down to every step; the default has no profile and no workspace rules.
+#[derive(Debug, Clone, Default)]
+pub struct WorkspaceContext {
+    /// Profile whose overlays are merged onto the resource files.
+    pub profile: Option<String>,
+    /// Overlay layers of the profile, applied in order (`eu` then `eu-prod`); empty
+    /// without a profile.
+    pub overlays: Vec<String>,
+    /// Profiles of the workspace `profiles/` directory. Only `resource.<name>.yaml` files
+    /// of these (or of `overlays`) are overlays.
+    pub profiles: BTreeSet<String>,
+    /// Which files `plan` and `apply` work on.
+    pub filter: FileFilter,
+    /// Fields treated as secrets besides the built-in detection.
+    pub secrets: SecretRules,
+    /// How `plan` compares remote and local resources.
+    pub diff: DiffRules,
+    /// How `apply` locks the workspace and its realms.
+    pub lock: LockSettings,
+    /// How many snapshots `apply` and `prune` keep.
+    pub snapshots: SnapshotSettings,
+    /// `workspace_id` of `kcd.yaml`, see [`crate::state::workspace_id`].
+    pub workspace_id: Option<String>,
+}
+
+impl WorkspaceContext {
+    /// A context for the workspace in `workspace` without a profile and without any
+    /// `kcd.yaml` rules.
+    pub fn for_workspace(workspace: &Path) -> Self {
+        Self {
+            profiles: workspace_profiles(workspace),
+            ..Self::default()
+        }
+    }
+
+    /// A context for `profile`, without parents and without any workspace rules.
+    pub fn with_profile(profile: Option<&str>) -> Self {
+        Self {
+            profile: profile.map(str::to_string),
+            overlays: profile.map(str::to_string).into_iter().collect(),
+            ..Self::default()
+        }
+    }
+}
@@ -0,0 +1,122 @@
+mod common;
+use common::start_mock_server;
+use kcd::apply;
+use kcd::apply::report::{ApplyReport, Status};
+use kcd::client::KeycloakClient;
+use kcd::utils::secrets::{EnvResolver, SecretResolver};
+use kcd::utils::selection::Selection;
+use kcd::utils::ui::MockUi;
+use kcd::workspace::WorkspaceContext;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn test_apply_keep_going() {
+    let mock_url = start_mock_server().await;
+    let mut client = KeycloakClient::new(mock_url);
+    client.set_target_realm("test-realm".to_string());
+    client
+        .login("admin-cli", Some("secret"), None, None)
+        .await
+        .expect("Login failed");
+
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().to_path_buf();
+    let realm_dir = workspace_dir.join("test-realm");
+    fs::create_dir_all(realm_dir.join("roles")).unwrap();
+    fs::create_dir_all(realm_dir.join("clients")).unwrap();
+    fs::create_dir_all(realm_dir.join("users")).unwrap();
+    fs::write(realm_dir.join("realm.yaml"), "realm: test-realm\n").unwrap();
+    fs::write(realm_dir.join("roles/role-1.yaml"), "name: role-1\n").unwrap();
+    fs::write(realm_dir.join("roles/broken.yaml"), "name: [broken]\n").unwrap();
+    fs::write(
+        realm_dir.join("clients/new-app.yaml"),
+        "clientId: new-app\n",
+    )
+    .unwrap();
+    fs::write(
+        realm_dir.join("users/alice.yaml"),
+        "username: alice\nrealmRoles: [role-1]\n",
+    )
+    .unwrap();
+
+    let resolver = Arc::new(EnvResolver::new(HashMap::new())) as Arc<dyn SecretResolver>;
+    let ui = Arc::new(MockUi {
+        inputs: std::sync::Mutex::new(Vec::new()),
+        confirms: std::sync::Mutex::new(Vec::new()),
+        selects: std::sync::Mutex::new(Vec::new()),
+        passwords: std::sync::Mutex::new(Vec::new()),
+    });
+    let apply = |report: ApplyReport| {
+        let client = client.clone();
+        let workspace_dir = workspace_dir.clone();
+        let ui = ui.clone();
+        let resolver = resolver.clone();
+        async move {
+            apply::run_with_selection(
+                &client,
+                workspace_dir,
+                &["test-realm".to_string()],
+                true,
+                false,
+                ui,
+                resolver,
+                Arc::new(WorkspaceContext::default()),
+                &Selection::default(),
+                &report,
+            )
+            .await
+        }
+    };
+
+    // The first failure stops the run, with what was done so far in the report.
+    let report = ApplyReport::default();
+    assert!(apply(report.clone()).await.is_err());
+    assert_eq!(report.failures(), 1);
+
+    // Keeping going applies everything else, then fails.
+    let report = ApplyReport::new(true);
+    let err = apply(report.clone()).await.unwrap_err();
+    assert!(err.to_string().contains("1 resource(s) failed to apply"));
+    let status = |resource: &str| {
+        report
+            .entries()
+            .into_iter()
+            .find(|e| e.resource == resource)
+            .map(|e| e.status)
+    };
+    assert_eq!(status("realm"), Some(Status::Updated));
+    // The realm is recorded as the base of the three-way diffs of `plan`
+    let last_applied = kcd::state::load_last_applied(&workspace_dir, "test-realm", "realm")
+        .await
+        .unwrap();
+    assert_eq!(last_applied["test-realm"]["realm"], "test-realm");
+    assert_eq!(status("roles/role-1"), Some(Status::Updated));
+    assert_eq!(status("roles/broken"), Some(Status::Failed));
+    assert_eq!(status("clients/new-app"), Some(Status::Created));
+    // Users wait for the roles, which failed, so they are not applied.
+    assert_eq!(status("users/alice"), None);
+    assert!(
+        report
+            .entries()
+            .iter()
+            .any(|e| e.kind == "users" && e.resource == "*" && e.status == Status::Skipped)
+    );
+
+    let path = workspace_dir.join("report.json");
+    report.write_json(&path).await.unwrap();
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(json["summary"]["failed"], 1);
+    assert_eq!(json["summary"]["created"], 1);
+    let broken = json["resources"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|e| e["resource"] == "roles/broken")
+        .unwrap();
+    assert_eq!(broken["type"], "roles");
+    assert!(broken["error"].as_str().unwrap().contains("broken"));
+}
@@ -4,6 +4,7 @@ use kcd::apply;
 use kcd::client::KeycloakClient;
 use kcd::models::{ClientRepresentation, RealmRepresentation, RoleRepresentation};
 use kcd::utils::secrets::{EnvResolver, SecretResolver};
+use kcd::workspace::WorkspaceContext;
 use std::collections::HashMap;
 use std::fs;
 use std::sync::Arc;
@@ -87,6 +88,7 @@ async fn test_apply() {
         public_client: None,
         bearer_only: None,
         service_accounts_enabled: None,
+        service_account: None,
         extra: std::collections::HashMap::new(),
     };
     fs::write(
@@ -107,6 +109,7 @@ async fn test_apply() {
         public_client: None,
         bearer_only: None,
         service_accounts_enabled: None,
+        service_account: None,
         extra: std::collections::HashMap::new(),
     };
     fs::write(
@@ -401,7 +404,7 @@ async fn test_apply() {
         false,
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .expect("Apply failed");
@@ -419,7 +422,7 @@ async fn test_apply() {
         false,
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .expect("Apply with plan failed");
@@ -434,7 +437,7 @@ async fn test_apply() {
         false,
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .expect("Apply with empty plan failed");
@@ -473,7 +476,7 @@ async fn test_apply() {
         true,  // review = true
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .expect("Apply with review failed");
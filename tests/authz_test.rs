@@ -0,0 +1,353 @@
+use kcd::apply::authz::apply_authz;
+use kcd::apply::report::{ApplyReport, Status};
+use kcd::client::KeycloakClient;
+use kcd::plan::authz::plan_authz;
+use kcd::plan::{PlanContext, PlanOptions};
+use kcd::utils::secrets::{EnvResolver, SecretResolver};
+use kcd::utils::ui::DialoguerUi;
+use kcd::workspace::WorkspaceContext;
+use mockito::Matcher;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+fn write_authz_workspace(realm_dir: &std::path::Path) {
+    let app_dir = realm_dir.join("authz").join("my-app");
+    fs::create_dir_all(app_dir.join("scopes")).unwrap();
+    fs::create_dir_all(app_dir.join("resources")).unwrap();
+    fs::create_dir_all(app_dir.join("policies")).unwrap();
+    fs::create_dir_all(app_dir.join("permissions")).unwrap();
+
+    fs::write(
+        app_dir.join("resource-server.yaml"),
+        "name: my-app\npolicyEnforcementMode: ENFORCING\ndecisionStrategy: UNANIMOUS\n",
+    )
+    .unwrap();
+    fs::write(app_dir.join("scopes").join("view.yaml"), "name: view\n").unwrap();
+    fs::write(
+        app_dir.join("resources").join("orders.yaml"),
+        "name: orders\nuris:\n- /orders/*\nscopes:\n- name: view\n",
+    )
+    .unwrap();
+    fs::write(
+        app_dir.join("policies").join("admins.yaml"),
+        "name: admins\ntype: role\nlogic: POSITIVE\nroles:\n- id: admin\n  required: true\n",
+    )
+    .unwrap();
+    fs::write(
+        app_dir.join("policies").join("all.yaml"),
+        "name: all\ntype: aggregate\npolicies:\n- admins\n",
+    )
+    .unwrap();
+    fs::write(
+        app_dir.join("permissions").join("orders-view.yaml"),
+        "name: orders-view\ntype: scope\nresources:\n- orders\nscopes:\n- view\npolicies:\n- all\n",
+    )
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_plan_authz_detects_changes() {
+    let mut server = mockito::Server::new_async().await;
+    let mut client = KeycloakClient::new(server.url());
+    client.set_target_realm("test".to_string());
+    client.set_token("mock_token".to_string());
+
+    let _clients = server
+        .mock("GET", "/admin/realms/test/clients")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!([{"id": "app-uuid", "clientId": "my-app"}]).to_string())
+        .create_async()
+        .await;
+    let _export = server
+        .mock(
+            "GET",
+            "/admin/realms/test/clients/app-uuid/authz/resource-server/settings",
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            json!({
+                "allowRemoteResourceManagement": false,
+                "policyEnforcementMode": "ENFORCING",
+                "decisionStrategy": "UNANIMOUS",
+                "scopes": [{"name": "view"}],
+                "resources": [{
+                    "name": "orders",
+                    "uris": ["/orders/*"],
+                    "scopes": [{"name": "view"}],
+                    "owner": {"name": "my-app"}
+                }, {
+                    "name": "invoices",
+                    "owner": {"name": "alice"}
+                }],
+                "policies": [
+                    {
+                        "name": "admins",
+                        "type": "role",
+                        "logic": "POSITIVE",
+                        "decisionStrategy": "UNANIMOUS",
+                        "config": {"roles": "[{\"id\":\"admin\",\"required\":false}]"}
+                    }
+                ]
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+
+    let dir = tempdir().unwrap();
+    let realm_dir = dir.path().join("test");
+    write_authz_workspace(&realm_dir);
+    fs::write(
+        realm_dir.join("authz/my-app/resource-server.yaml"),
+        "name: my-app\nallowRemoteResourceManagement: false\npolicyEnforcementMode: ENFORCING\ndecisionStrategy: UNANIMOUS\n",
+    )
+    .unwrap();
+    fs::write(
+        realm_dir.join("authz/my-app/policies/admins.yaml"),
+        "name: admins\ntype: role\nlogic: POSITIVE\ndecisionStrategy: UNANIMOUS\nroles:\n- id: admin\n  required: true\n",
+    )
+    .unwrap();
+    // Only an owner pointing at the client itself is implied; a user's one is kept
+    fs::write(
+        realm_dir.join("authz/my-app/resources/invoices.yaml"),
+        "name: invoices\nowner:\n  name: alice\n",
+    )
+    .unwrap();
+
+    let ui = DialoguerUi::new();
+    let ctx = PlanContext {
+        client: &client,
+        workspace_dir: &realm_dir,
+        options: PlanOptions {
+            changes_only: true,
+            interactive: false,
+            drift: false,
+        },
+        resolver: Arc::new(EnvResolver::new(HashMap::new())) as Arc<dyn SecretResolver>,
+        realm_name: "test",
+        ui: &ui,
+        ws: Arc::new(WorkspaceContext::default()),
+        selection: &Default::default(),
+        changes: Default::default(),
+    };
+
+    let (changed, summary) = plan_authz(&ctx).await.unwrap();
+    let changed: Vec<String> = changed
+        .iter()
+        .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+        .collect();
+
+    // Settings, scope and resource match; the role policy differs in `required`,
+    // the aggregated policy and the permission do not exist yet.
+    assert!(!changed.contains(&"resource-server.yaml".to_string()));
+    assert!(!changed.contains(&"view.yaml".to_string()));
+    assert!(!changed.contains(&"orders.yaml".to_string()));
+    assert!(!changed.contains(&"invoices.yaml".to_string()));
+    assert!(changed.contains(&"admins.yaml".to_string()));
+    assert!(changed.contains(&"all.yaml".to_string()));
+    assert!(changed.contains(&"orders-view.yaml".to_string()));
+    assert_eq!(summary.updated, 1);
+    assert_eq!(summary.created, 2);
+}
+
+#[tokio::test]
+async fn test_apply_authz_resolves_names_to_ids() {
+    let mut server = mockito::Server::new_async().await;
+    let mut client = KeycloakClient::new(server.url());
+    client.set_target_realm("test".to_string());
+    client.set_token("mock_token".to_string());
+    let base = "/admin/realms/test/clients/app-uuid/authz/resource-server";
+
+    let _clients = server
+        .mock("GET", "/admin/realms/test/clients")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!([{"id": "app-uuid", "clientId": "my-app"}]).to_string())
+        .create_async()
+        .await;
+    let _roles = server
+        .mock("GET", "/admin/realms/test/roles")
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            json!([
+                {"id": "role-admin-id", "name": "admin"},
+                {"id": "role-ops-id", "name": "ops/admin"}
+            ])
+            .to_string(),
+        )
+        .create_async()
+        .await;
+    let settings = server
+        .mock("PUT", base)
+        .match_body(Matcher::PartialJson(
+            json!({"policyEnforcementMode": "ENFORCING"}),
+        ))
+        .with_status(204)
+        .create_async()
+        .await;
+    let _scopes = server
+        .mock("GET", format!("{}/scope", base).as_str())
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!([{"id": "scope-view-id", "name": "view"}]).to_string())
+        .create_async()
+        .await;
+    let scope_update = server
+        .mock("PUT", format!("{}/scope/scope-view-id", base).as_str())
+        .with_status(204)
+        .create_async()
+        .await;
+    let _resources = server
+        .mock("GET", format!("{}/resource", base).as_str())
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!([{"_id": "res-orders-id", "name": "orders"}]).to_string())
+        .create_async()
+        .await;
+    let resource_update = server
+        .mock("PUT", format!("{}/resource/res-orders-id", base).as_str())
+        .match_body(Matcher::PartialJson(
+            json!({"scopes": [{"id": "scope-view-id", "name": "view"}]}),
+        ))
+        .with_status(204)
+        .create_async()
+        .await;
+    let _policies = server
+        .mock("GET", format!("{}/policy", base).as_str())
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            json!([
+                {"id": "pol-admins-id", "name": "admins", "type": "role"},
+                {"id": "pol-all-id", "name": "all", "type": "aggregate"}
+            ])
+            .to_string(),
+        )
+        .create_async()
+        .await;
+    let role_policy = server
+        .mock(
+            "PUT",
+            format!("{}/policy/role/pol-admins-id", base).as_str(),
+        )
+        .match_body(Matcher::PartialJson(json!({"roles": [
+            {"id": "role-admin-id", "required": true},
+            {"id": "role-ops-id"}
+        ]})))
+        .with_status(201)
+        .create_async()
+        .await;
+    let aggregate_policy = server
+        .mock(
+            "PUT",
+            format!("{}/policy/aggregate/pol-all-id", base).as_str(),
+        )
+        .match_body(Matcher::PartialJson(json!({"policies": ["pol-admins-id"]})))
+        .with_status(201)
+        .create_async()
+        .await;
+    let permission = server
+        .mock("POST", format!("{}/policy/scope", base).as_str())
+        .match_body(Matcher::PartialJson(json!({
+            "name": "orders-view",
+            "resources": ["res-orders-id"],
+            "scopes": ["scope-view-id"],
+            "policies": ["pol-all-id"]
+        })))
+        .with_status(201)
+        .create_async()
+        .await;
+
+    let dir = tempdir().unwrap();
+    let realm_dir = dir.path().join("test");
+    write_authz_workspace(&realm_dir);
+    // `ops` is not a client, so `ops/admin` is a realm role with a slash in its name
+    fs::write(
+        realm_dir.join("authz/my-app/policies/admins.yaml"),
+        "name: admins\ntype: role\nlogic: POSITIVE\nroles:\n- id: admin\n  required: true\n- id: ops/admin\n",
+    )
+    .unwrap();
+
+    let report = ApplyReport::default();
+    apply_authz(
+        &client,
+        &realm_dir,
+        Arc::new(EnvResolver::new(HashMap::new())),
+        Arc::new(None),
+        "test",
+        Arc::new(WorkspaceContext::default()),
+        &report,
+    )
+    .await
+    .unwrap();
+
+    // Every file is reported with what happened to it
+    let mut outcomes: Vec<(String, Status)> = report
+        .entries()
+        .into_iter()
+        .map(|e| (e.resource, e.status))
+        .collect();
+    outcomes.sort();
+    assert_eq!(
+        outcomes,
+        vec![
+            (
+                "authz/my-app/permissions/orders-view".to_string(),
+                Status::Created
+            ),
+            ("authz/my-app/policies/admins".to_string(), Status::Updated),
+            ("authz/my-app/policies/all".to_string(), Status::Updated),
+            ("authz/my-app/resource-server".to_string(), Status::Updated),
+            ("authz/my-app/resources/orders".to_string(), Status::Updated),
+            ("authz/my-app/scopes/view".to_string(), Status::Updated),
+        ]
+    );
+
+    settings.assert_async().await;
+    scope_update.assert_async().await;
+    resource_update.assert_async().await;
+    role_policy.assert_async().await;
+    aggregate_policy.assert_async().await;
+    permission.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_apply_authz_unknown_client_fails() {
+    let mut server = mockito::Server::new_async().await;
+    let mut client = KeycloakClient::new(server.url());
+    client.set_target_realm("test".to_string());
+    client.set_token("mock_token".to_string());
+
+    let _clients = server
+        .mock("GET", "/admin/realms/test/clients")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body("[]")
+        .create_async()
+        .await;
+
+    let dir = tempdir().unwrap();
+    let realm_dir = dir.path().join("test");
+    write_authz_workspace(&realm_dir);
+
+    let err = apply_authz(
+        &client,
+        &realm_dir,
+        Arc::new(EnvResolver::new(HashMap::new())),
+        Arc::new(None),
+        "test",
+        Arc::new(WorkspaceContext::default()),
+        &ApplyReport::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("does not exist in realm 'test'"));
+}
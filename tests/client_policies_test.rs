@@ -0,0 +1,193 @@
+mod common;
+use common::mock_client;
+use kcd::apply::client_policies::apply_client_policies;
+use kcd::apply::report::ApplyReport;
+use kcd::plan::client_policies::plan_client_policies;
+use kcd::plan::{PlanContext, PlanOptions};
+use kcd::utils::secrets::{EnvResolver, SecretResolver};
+use kcd::utils::ui::DialoguerUi;
+use kcd::workspace::WorkspaceContext;
+use mockito::Matcher;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+const PROFILES: &str = "profiles:\n- name: strict\n  executors:\n  - executor: secure-session\n    configuration: {}\n";
+const POLICIES: &str = "policies:\n- name: fapi\n  enabled: true\n  conditions:\n  - condition: any-client\n    configuration: {}\n  profiles:\n  - strict\n";
+
+#[tokio::test]
+async fn test_plan_client_policies_by_name() {
+    let mut server = mockito::Server::new_async().await;
+    let client = mock_client(&server);
+    let _profiles = server
+        .mock("GET", "/admin/realms/test/client-policies/profiles")
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            json!({
+                "profiles": [
+                    {"name": "strict", "executors": [{"executor": "secure-session", "configuration": {}}]},
+                    {"name": "legacy", "executors": []}
+                ],
+                "globalProfiles": [{"name": "fapi-1-baseline"}]
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+    let _policies = server
+        .mock("GET", "/admin/realms/test/client-policies/policies")
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!({"policies": []}).to_string())
+        .create_async()
+        .await;
+
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("client-policies")).unwrap();
+    fs::write(dir.path().join("client-policies/profiles.yaml"), PROFILES).unwrap();
+    fs::write(dir.path().join("client-policies/policies.yaml"), POLICIES).unwrap();
+
+    let ui = DialoguerUi::new();
+    let ctx = PlanContext {
+        client: &client,
+        workspace_dir: dir.path(),
+        options: PlanOptions {
+            changes_only: true,
+            interactive: false,
+            drift: false,
+        },
+        resolver: Arc::new(EnvResolver::new(HashMap::new())) as Arc<dyn SecretResolver>,
+        realm_name: "test",
+        ui: &ui,
+        ws: Arc::new(WorkspaceContext::default()),
+        selection: &Default::default(),
+        changes: Default::default(),
+    };
+
+    let (changed, summary) = plan_client_policies(&ctx).await.unwrap();
+    // `strict` is unchanged, `legacy` is removed, policy `fapi` is created.
+    assert_eq!(changed.len(), 2);
+    assert_eq!(summary.updated, 1);
+    assert_eq!(summary.created, 1);
+}
+
+#[tokio::test]
+async fn test_apply_client_policies_puts_whole_documents() {
+    let mut server = mockito::Server::new_async().await;
+    let client = mock_client(&server);
+    let _remote = server
+        .mock("GET", "/admin/realms/test/client-policies/profiles")
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!({"profiles": [{"name": "strict"}]}).to_string())
+        .create_async()
+        .await;
+    let profiles = server
+        .mock("PUT", "/admin/realms/test/client-policies/profiles")
+        .match_body(Matcher::Json(json!({
+            "profiles": [{"name": "strict", "executors": [{"executor": "secure-session", "configuration": {}}]}]
+        })))
+        .with_status(204)
+        .create_async()
+        .await;
+    let policies = server
+        .mock("PUT", "/admin/realms/test/client-policies/policies")
+        .match_body(Matcher::PartialJson(json!({
+            "policies": [{"name": "fapi", "profiles": ["strict"]}]
+        })))
+        .with_status(204)
+        .create_async()
+        .await;
+
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("client-policies")).unwrap();
+    fs::write(dir.path().join("client-policies/profiles.yaml"), PROFILES).unwrap();
+    fs::write(dir.path().join("client-policies/policies.yaml"), POLICIES).unwrap();
+
+    apply_client_policies(
+        &client,
+        dir.path(),
+        Arc::new(EnvResolver::new(HashMap::new())),
+        Arc::new(None),
+        "test",
+        Arc::new(WorkspaceContext::default()),
+        &ApplyReport::default(),
+    )
+    .await
+    .unwrap();
+
+    profiles.assert_async().await;
+    policies.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_apply_client_policies_removes_profiles_after_policies() {
+    let mut server = mockito::Server::new_async().await;
+    let client = mock_client(&server);
+    let _remote = server
+        .mock("GET", "/admin/realms/test/client-policies/profiles")
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!({"profiles": [{"name": "strict"}, {"name": "legacy"}]}).to_string())
+        .create_async()
+        .await;
+    // `legacy` is kept while policies may still use it, then dropped
+    let kept = server
+        .mock("PUT", "/admin/realms/test/client-policies/profiles")
+        .match_body(Matcher::PartialJson(
+            json!({"profiles": [{"name": "strict"}, {"name": "legacy"}]}),
+        ))
+        .with_status(204)
+        .expect(1)
+        .create_async()
+        .await;
+    let policies = server
+        .mock("PUT", "/admin/realms/test/client-policies/policies")
+        .with_status(204)
+        .expect(1)
+        .create_async()
+        .await;
+    let removed = server
+        .mock("PUT", "/admin/realms/test/client-policies/profiles")
+        .match_body(Matcher::Json(json!({
+            "profiles": [{"name": "strict", "executors": [{"executor": "secure-session", "configuration": {}}]}]
+        })))
+        .with_status(204)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("client-policies")).unwrap();
+    fs::write(dir.path().join("client-policies/profiles.yaml"), PROFILES).unwrap();
+    fs::write(dir.path().join("client-policies/policies.yaml"), POLICIES).unwrap();
+
+    let report = ApplyReport::default();
+    apply_client_policies(
+        &client,
+        dir.path(),
+        Arc::new(EnvResolver::new(HashMap::new())),
+        Arc::new(None),
+        "test",
+        Arc::new(WorkspaceContext::default()),
+        &report,
+    )
+    .await
+    .unwrap();
+
+    kept.assert_async().await;
+    policies.assert_async().await;
+    removed.assert_async().await;
+    let resources: Vec<String> = report.entries().into_iter().map(|e| e.resource).collect();
+    assert_eq!(
+        resources,
+        vec!["client-policies/policies", "client-policies/profiles"]
+    );
+}
@@ -126,6 +126,7 @@ async fn test_create_client() {
         public_client: None,
         bearer_only: None,
         service_accounts_enabled: None,
+        service_account: None,
         extra: std::collections::HashMap::new(),
     };
 
@@ -155,6 +156,7 @@ async fn test_update_client() {
         public_client: None,
         bearer_only: None,
         service_accounts_enabled: None,
+        service_account: None,
         extra: std::collections::HashMap::new(),
     };
 
@@ -574,6 +576,7 @@ async fn test_post_send_failure() {
         public_client: None,
         bearer_only: None,
         service_accounts_enabled: None,
+        service_account: None,
         extra: std::collections::HashMap::new(),
     };
     let result = client.create_client(&client_rep).await;
@@ -13,6 +13,14 @@ pub struct TokenRequest {
     pub client_secret: Option<String>,
 }
 
+/// A client of the `test` realm of a mockito server, already authenticated.
+pub fn mock_client(server: &mockito::ServerGuard) -> kcd::client::KeycloakClient {
+    let mut client = kcd::client::KeycloakClient::new(server.url());
+    client.set_target_realm("test".to_string());
+    client.set_token("mock_token".to_string());
+    client
+}
+
 pub async fn start_mock_server() -> String {
     let app = Router::new()
         .route(
@@ -174,7 +182,9 @@ async fn get_realm_handler(
             Json(serde_json::json!({
                 "realm": realm,
                 "enabled": true,
-                "displayName": display_name
+                "displayName": display_name,
+                // Left behind by an apply that held the realm lock
+                "attributes": {"kcd.lock": ""}
             })),
         )
     } else {
@@ -5,6 +5,7 @@ use kcd::client::KeycloakClient;
 use kcd::models::*;
 use kcd::utils::secrets::{EnvResolver, SecretResolver};
 use kcd::utils::ui::MockUi;
+use kcd::workspace::WorkspaceContext;
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
@@ -42,6 +43,12 @@ async fn test_coverage_gaps_apply_generic() {
     fs::write(realm_dir.join("roles/r1.yaml"), "name: r1\n").unwrap();
     fs::write(realm_dir.join("roles/r2.yaml"), "name: r2\n").unwrap();
     fs::write(realm_dir.join("roles/r1.prod.yaml"), "name: r1-prod\n").unwrap(); // Overlay
+    fs::create_dir_all(workspace_dir.join("profiles")).unwrap();
+    fs::write(
+        workspace_dir.join("profiles/prod.yaml"),
+        "server_url: http://localhost\n",
+    )
+    .unwrap();
 
     // 1. Test review mode rejection and overlay skipping
     apply::run(
@@ -52,7 +59,7 @@ async fn test_coverage_gaps_apply_generic() {
         true,  // review = true
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::for_workspace(&workspace_dir)),
     )
     .await
     .unwrap();
@@ -71,7 +78,7 @@ async fn test_coverage_gaps_apply_generic() {
         false, // review = false
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -89,7 +96,7 @@ async fn test_coverage_gaps_apply_generic() {
         false,
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -118,7 +125,7 @@ async fn test_coverage_gaps_apply_mod_errors() {
         false,
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -132,7 +139,7 @@ async fn test_coverage_gaps_apply_mod_errors() {
         false,
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_err());
@@ -147,7 +154,7 @@ async fn test_coverage_gaps_apply_mod_errors() {
         false,
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -294,7 +301,8 @@ async fn test_apply_components_gaps() {
         resolver,
         planned_files,
         "test-realm",
-        None,
+        Arc::new(WorkspaceContext::default()),
+        &kcd::apply::report::ApplyReport::default(),
     )
     .await;
 }
@@ -3,6 +3,7 @@ mod common;
 use common::start_mock_server;
 use kcd::client::KeycloakClient;
 use kcd::models::RealmRepresentation;
+use kcd::workspace::WorkspaceContext;
 use kcd::{apply, clean, inspect, plan};
 use std::fs;
 use tempfile::tempdir;
@@ -42,7 +43,7 @@ async fn test_plan_edge_cases() {
         &[],
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_err());
@@ -56,7 +57,7 @@ async fn test_plan_edge_cases() {
         &[],
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -87,7 +88,7 @@ async fn test_plan_edge_cases() {
         &[],
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -102,7 +103,7 @@ async fn test_plan_edge_cases() {
         &["new-realm".to_string()],
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -117,7 +118,7 @@ async fn test_plan_edge_cases() {
         &["new-realm".to_string()],
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
 
@@ -132,7 +133,7 @@ async fn test_plan_edge_cases() {
         &["new-realm".to_string()],
         ui.clone(),
         resolver,
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_err());
@@ -171,7 +172,7 @@ async fn test_apply_edge_cases() {
         false,
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_err());
@@ -185,7 +186,7 @@ async fn test_apply_edge_cases() {
         false,
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -216,7 +217,7 @@ async fn test_apply_edge_cases() {
         false,
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -231,7 +232,7 @@ async fn test_apply_edge_cases() {
         false,
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -248,7 +249,7 @@ async fn test_apply_edge_cases() {
         false,
         ui,
         resolver,
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_err());
@@ -284,7 +285,7 @@ async fn test_check_keys_drift() {
         &["test-realm".to_string()],
         ui,
         resolver,
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -1,7 +1,10 @@
 mod common;
 use common::start_mock_server;
 use kcd::client::KeycloakClient;
-use kcd::inspect;
+use kcd::inspect::{self, InspectOptions};
+use kcd::utils::secrets::sink::SecretsSink;
+use kcd::utils::secrets::vault::VaultResolver;
+use kcd::workspace::WorkspaceContext;
 use std::fs;
 use tempfile::tempdir;
 
@@ -50,6 +53,7 @@ async fn test_inspect() {
     let realm_content =
         fs::read_to_string(workspace_dir.join("test-realm").join("realm.yaml")).unwrap();
     assert!(realm_content.contains("test-realm"));
+    assert!(!realm_content.contains("kcd.lock"));
 }
 
 #[tokio::test]
@@ -80,3 +84,125 @@ async fn test_inspect_auto_discovery() {
         "test-realm realm directory missing"
     );
 }
+
+#[tokio::test]
+async fn test_inspect_vault_secrets_sink() {
+    let mock_url = start_mock_server().await;
+    let mut client = KeycloakClient::new(mock_url);
+    client.set_target_realm("test-realm".to_string());
+    client
+        .login("admin-cli", Some("secret"), None, None)
+        .await
+        .expect("Login failed");
+
+    let mut vault_server = mockito::Server::new_async().await;
+    let read = vault_server
+        .mock("GET", "/v1/kv/data/keycloak/test-realm")
+        .with_status(404)
+        .create_async()
+        .await;
+    let write = vault_server
+        .mock("POST", "/v1/kv/data/keycloak/test-realm")
+        .match_header("X-Vault-Token", "vault-token")
+        .match_body(mockito::Matcher::Regex("client-1-secret".to_string()))
+        .with_status(200)
+        .create_async()
+        .await;
+    let vault = VaultResolver::new(&vault_server.url(), "vault-token").unwrap();
+    let sink: SecretsSink = "vault:kv/keycloak/<realm>".parse().unwrap();
+
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().to_path_buf();
+    let options = InspectOptions {
+        yes: true,
+        secrets_sink: sink,
+        ..Default::default()
+    };
+    inspect::run_with_options(
+        &client,
+        workspace_dir.clone(),
+        &["test-realm".to_string()],
+        &options,
+        &WorkspaceContext::default(),
+        Some(&vault),
+    )
+    .await
+    .expect("Inspect failed");
+
+    read.assert_async().await;
+    write.assert_async().await;
+    let client_yaml =
+        fs::read_to_string(workspace_dir.join("test-realm/clients/client-1.yaml")).unwrap();
+    assert!(client_yaml.contains("${vault:kv/keycloak/test-realm#"));
+    assert!(!client_yaml.contains("client-1-secret"));
+    assert!(!workspace_dir.join(".secrets").exists());
+
+    let err = inspect::run_with_options(
+        &client,
+        workspace_dir,
+        &[],
+        &options,
+        &WorkspaceContext::default(),
+        None,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("requires Vault"));
+}
+
+#[tokio::test]
+async fn test_inspect_merges_secrets_file() {
+    let mock_url = start_mock_server().await;
+    let mut client = KeycloakClient::new(mock_url);
+    client.set_target_realm("test-realm".to_string());
+    client
+        .login("admin-cli", Some("secret"), None, None)
+        .await
+        .expect("Login failed");
+
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().to_path_buf();
+    let secrets_path = workspace_dir.join(".secrets");
+    fs::write(
+        &secrets_path,
+        "# local overrides\nVAULT_TOKEN=abc\nKEYCLOAK_STALE=old\n",
+    )
+    .unwrap();
+
+    let realms = ["test-realm".to_string()];
+    for _ in 0..2 {
+        inspect::run(&client, workspace_dir.clone(), &realms, true)
+            .await
+            .expect("Inspect failed");
+    }
+
+    let content = fs::read_to_string(&secrets_path).unwrap();
+    assert!(content.starts_with("# local overrides\nVAULT_TOKEN=abc\nKEYCLOAK_STALE=old\n"));
+    assert_eq!(content.matches("client-1-secret").count(), 1);
+    let keys: Vec<&str> = content
+        .lines()
+        .filter_map(|l| l.split_once('=').map(|(k, _)| k))
+        .collect();
+    let unique: std::collections::HashSet<_> = keys.iter().collect();
+    assert_eq!(keys.len(), unique.len(), "duplicated keys in {}", content);
+
+    let options = InspectOptions {
+        yes: true,
+        prune_secrets: true,
+        ..Default::default()
+    };
+    inspect::run_with_options(
+        &client,
+        workspace_dir,
+        &realms,
+        &options,
+        &WorkspaceContext::default(),
+        None,
+    )
+    .await
+    .expect("Inspect failed");
+    let pruned = fs::read_to_string(&secrets_path).unwrap();
+    assert!(!pruned.contains("KEYCLOAK_STALE"));
+    assert!(pruned.contains("# local overrides\nVAULT_TOKEN=abc\n"));
+    assert!(pruned.contains("client-1-secret"));
+}
@@ -7,17 +7,19 @@ use std::path::PathBuf;
 async fn test_init_client_fail() {
     let cli = Cli {
         server: Some("http://invalid".to_string()),
-        client_id: "admin-cli".to_string(),
+        client_id: None,
         client_secret: None,
         user: Some("admin".to_string()),
         password: Some("password".to_string()),
         realms: vec![],
         profile: None,
+        concurrency: None,
         command: Commands::Validate {
             workspace: PathBuf::from("."),
         },
         vault_addr: None,
         vault_token: None,
+        vault_namespace: None,
     };
 
     let res = init_client(&cli, None).await;
@@ -28,17 +30,19 @@ async fn test_init_client_fail() {
 async fn test_run_app_validate_non_existent() {
     let cli = Cli {
         server: Some("http://localhost:8080".to_string()),
-        client_id: "admin-cli".to_string(),
+        client_id: None,
         client_secret: None,
         user: None,
         password: None,
         realms: vec![],
         profile: None,
+        concurrency: None,
         command: Commands::Validate {
             workspace: PathBuf::from("non-existent-dir-123"),
         },
         vault_addr: None,
         vault_token: None,
+        vault_namespace: None,
     };
 
     let res = run_app(cli).await;
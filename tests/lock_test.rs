@@ -0,0 +1,226 @@
+mod common;
+use axum::{Json, Router, extract::State, routing::get};
+use common::start_mock_server;
+use kcd::apply;
+use kcd::client::KeycloakClient;
+use kcd::lock::{
+    LocalLock, LockInfo, LockSettings, acquire_server_lock, force_unlock, lock_file,
+    release_server_lock, server_lock,
+};
+use kcd::prune;
+use kcd::utils::secrets::EnvResolver;
+use kcd::utils::selection::Selection;
+use kcd::utils::ui::MockUi;
+use kcd::workspace::WorkspaceContext;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use tempfile::tempdir;
+use tokio::net::TcpListener;
+
+fn mock_ui() -> Arc<MockUi> {
+    Arc::new(MockUi {
+        inputs: Mutex::new(Vec::new()),
+        confirms: Mutex::new(Vec::new()),
+        selects: Mutex::new(Vec::new()),
+        passwords: Mutex::new(Vec::new()),
+    })
+}
+
+type Attributes = Arc<Mutex<serde_json::Map<String, Value>>>;
+
+/// A realm endpoint that keeps the attributes it is sent, like Keycloak does; `null`
+/// removes an attribute.
+async fn start_realm_server(attributes: Attributes) -> String {
+    async fn get_realm(State(attributes): State<Attributes>) -> Json<Value> {
+        Json(json!({"realm": "test", "attributes": *attributes.lock().unwrap()}))
+    }
+    async fn put_realm(State(attributes): State<Attributes>, Json(rep): Json<Value>) {
+        if let Some(update) = rep["attributes"].as_object() {
+            let mut attributes = attributes.lock().unwrap();
+            for (name, value) in update {
+                match value {
+                    Value::Null => attributes.remove(name),
+                    value => attributes.insert(name.clone(), value.clone()),
+                };
+            }
+        }
+    }
+    let app = Router::new()
+        .route("/admin/realms/{realm}", get(get_realm).put(put_realm))
+        .with_state(attributes);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    format!("http://127.0.0.1:{}", port)
+}
+
+#[tokio::test]
+async fn test_server_lock() {
+    let attributes = Attributes::default();
+    let mut client = KeycloakClient::new(start_realm_server(attributes.clone()).await);
+    client.set_target_realm("test".to_string());
+    client.set_token("token".to_string());
+
+    let lock = acquire_server_lock(&client, "test", &LockSettings::default())
+        .await
+        .unwrap();
+    assert_eq!(server_lock(&client).await.unwrap(), Some(lock.clone()));
+    let err = acquire_server_lock(&client, "test", &LockSettings::default())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Realm 'test' is locked"));
+
+    // Only the run that took the lock releases it
+    let other = LockInfo::new(jiff::SignedDuration::from_mins(1)).unwrap();
+    release_server_lock(&client, "test", &other).await.unwrap();
+    assert!(server_lock(&client).await.unwrap().is_some());
+    release_server_lock(&client, "test", &lock).await.unwrap();
+    assert_eq!(server_lock(&client).await.unwrap(), None);
+    // Released locks leave no attribute behind for inspect to export
+    assert!(!attributes.lock().unwrap().contains_key("kcd.lock"));
+
+    // Stale locks are removed by force-unlock
+    let dir = tempdir().unwrap();
+    acquire_server_lock(&client, "test", &LockSettings::default())
+        .await
+        .unwrap();
+    force_unlock(
+        dir.path(),
+        Some(&client),
+        &["test".to_string()],
+        true,
+        mock_ui(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(server_lock(&client).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_apply_locks_workspace() {
+    let mock_url = start_mock_server().await;
+    let mut client = KeycloakClient::new(mock_url);
+    client.set_target_realm("test-realm".to_string());
+    client
+        .login("admin-cli", Some("secret"), None, None)
+        .await
+        .expect("Login failed");
+
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().to_path_buf();
+    fs::create_dir_all(workspace_dir.join("test-realm")).unwrap();
+    fs::write(
+        workspace_dir.join("test-realm/realm.yaml"),
+        "realm: test-realm\n",
+    )
+    .unwrap();
+
+    let realms = vec!["test-realm".to_string()];
+    let apply = || {
+        apply::run(
+            &client,
+            workspace_dir.clone(),
+            &realms,
+            true,
+            false,
+            mock_ui(),
+            Arc::new(EnvResolver::new(HashMap::new())),
+            Arc::new(WorkspaceContext::default()),
+        )
+    };
+
+    // A concurrent apply holds the workspace
+    let lock = LocalLock::acquire(&workspace_dir, &LockSettings::default())
+        .await
+        .unwrap();
+    let err = apply().await.unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("Workspace is locked by another apply")
+    );
+    std::mem::forget(lock);
+
+    // Until the lock is forced open
+    force_unlock(&workspace_dir, None, &[], true, mock_ui())
+        .await
+        .unwrap();
+    assert!(!lock_file(&workspace_dir).exists());
+    apply().await.unwrap();
+    assert!(!lock_file(&workspace_dir).exists());
+}
+
+#[tokio::test]
+async fn test_apply_keeps_its_server_lock() {
+    let attributes = Attributes::default();
+    let mut client = KeycloakClient::new(start_realm_server(attributes.clone()).await);
+    client.set_target_realm("test".to_string());
+    client.set_token("token".to_string());
+
+    // A realm.yaml exported while another run held the lock
+    let foreign = LockInfo::new(jiff::SignedDuration::from_mins(30)).unwrap();
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().to_path_buf();
+    fs::create_dir_all(workspace_dir.join("test")).unwrap();
+    let realm = json!({
+        "realm": "test",
+        "attributes": {"kcd.lock": serde_json::to_string(&foreign).unwrap(), "custom": "x"},
+    });
+    fs::write(
+        workspace_dir.join("test/realm.yaml"),
+        serde_yaml::to_string(&realm).unwrap(),
+    )
+    .unwrap();
+
+    let mut ws = WorkspaceContext::default();
+    ws.lock.server = true;
+    apply::run(
+        &client,
+        workspace_dir,
+        &["test".to_string()],
+        true,
+        false,
+        mock_ui(),
+        Arc::new(EnvResolver::new(HashMap::new())),
+        Arc::new(ws),
+    )
+    .await
+    .unwrap();
+
+    // The foreign lock was not installed, and the run released its own
+    let attributes = attributes.lock().unwrap();
+    assert_eq!(attributes["custom"], "x");
+    assert!(!attributes.contains_key("kcd.lock"));
+}
+
+#[tokio::test]
+async fn test_prune_takes_server_lock() {
+    let attributes = Attributes::default();
+    let mut client = KeycloakClient::new(start_realm_server(attributes.clone()).await);
+    client.set_target_realm("test".to_string());
+    client.set_token("token".to_string());
+    let held = acquire_server_lock(&client, "test", &LockSettings::default())
+        .await
+        .unwrap();
+
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("test")).unwrap();
+    let mut ws = WorkspaceContext::default();
+    ws.lock.server = true;
+    let err = prune::run(
+        &client,
+        dir.path(),
+        &["test".to_string()],
+        true,
+        mock_ui(),
+        &ws,
+        &Selection::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("Realm 'test' is locked"));
+    assert_eq!(server_lock(&client).await.unwrap(), Some(held));
+}
@@ -210,3 +210,52 @@ async fn test_drift_command() {
         .assert()
         .success();
 }
+
+#[test]
+fn test_plan_graph_command() {
+    let temp = tempdir().unwrap();
+    let workspace = temp.path().join("workspace");
+    let realm_dir = workspace.join("test-realm");
+    fs::create_dir_all(realm_dir.join("clients")).unwrap();
+    fs::create_dir_all(realm_dir.join("client-scopes")).unwrap();
+    fs::write(realm_dir.join("realm.yaml"), "realm: test-realm\n").unwrap();
+    fs::write(
+        realm_dir.join("clients/app.yaml"),
+        "clientId: app\ndefaultClientScopes: [app-scope]\n",
+    )
+    .unwrap();
+    fs::write(
+        realm_dir.join("client-scopes/app-scope.yaml"),
+        "name: app-scope\n",
+    )
+    .unwrap();
+
+    // No server is needed to print the graph.
+    let mut cmd = Command::cargo_bin("kcd").unwrap();
+    cmd.env_clear()
+        .arg("plan")
+        .arg("--workspace")
+        .arg(&workspace)
+        .arg("--graph")
+        .arg("dot")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("digraph \"test-realm\""))
+        .stdout(predicate::str::contains(
+            "\"client-scopes\" -> \"clients\" [label=\"clients/app -> client-scopes/app-scope\"];",
+        ));
+
+    let mut cmd = Command::cargo_bin("kcd").unwrap();
+    cmd.env_clear()
+        .arg("plan")
+        .arg("--workspace")
+        .arg(&workspace)
+        .arg("--only")
+        .arg("clients,client-scopes")
+        .arg("--graph")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1. client-scopes"))
+        .stdout(predicate::str::contains("2. clients\n"))
+        .stdout(predicate::str::contains("3. service-accounts"));
+}
@@ -58,6 +58,7 @@ fn test_models_resource_trait() {
         public_client: None,
         bearer_only: None,
         service_accounts_enabled: None,
+        service_account: None,
         extra: HashMap::new(),
     };
     assert_eq!(client.get_identity(), Some("cid".to_string()));
@@ -0,0 +1,280 @@
+mod common;
+use common::mock_client;
+use kcd::apply::organizations::apply_organizations;
+use kcd::apply::report::ApplyReport;
+use kcd::plan::organizations::plan_organizations;
+use kcd::plan::{PlanContext, PlanOptions};
+use kcd::utils::secrets::{EnvResolver, SecretResolver};
+use kcd::utils::ui::DialoguerUi;
+use kcd::workspace::WorkspaceContext;
+use mockito::Matcher;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+const ACME: &str = "name: acme\nalias: acme\nenabled: true\ndomains:\n- name: acme.com\nmembers:\n- alice\n- bob\nidentityProviders:\n- google\n";
+
+#[tokio::test]
+async fn test_plan_organizations_skips_unsupported_server() {
+    let mut server = mockito::Server::new_async().await;
+    let client = mock_client(&server);
+    let _orgs = server
+        .mock("GET", "/admin/realms/test/organizations")
+        .match_query(Matcher::Any)
+        .with_status(404)
+        .create_async()
+        .await;
+
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("organizations")).unwrap();
+    fs::write(dir.path().join("organizations/acme.yaml"), ACME).unwrap();
+
+    let ui = DialoguerUi::new();
+    let ctx = PlanContext {
+        client: &client,
+        workspace_dir: dir.path(),
+        options: PlanOptions {
+            changes_only: true,
+            interactive: false,
+            drift: false,
+        },
+        resolver: Arc::new(EnvResolver::new(HashMap::new())) as Arc<dyn SecretResolver>,
+        realm_name: "test",
+        ui: &ui,
+        ws: Arc::new(WorkspaceContext::default()),
+        selection: &Default::default(),
+        changes: Default::default(),
+    };
+
+    let (changed, summary) = plan_organizations(&ctx).await.unwrap();
+    assert!(changed.is_empty());
+    assert_eq!(summary.total(), 0);
+}
+
+#[tokio::test]
+async fn test_plan_organizations_compares_links_order_independently() {
+    let mut server = mockito::Server::new_async().await;
+    let client = mock_client(&server);
+    let _orgs = server
+        .mock("GET", "/admin/realms/test/organizations")
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            json!([{
+                "id": "org-1",
+                "name": "acme",
+                "alias": "acme",
+                "enabled": true,
+                "domains": [{"name": "acme.com"}]
+            }])
+            .to_string(),
+        )
+        .create_async()
+        .await;
+    let _members = server
+        .mock("GET", "/admin/realms/test/organizations/org-1/members")
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            json!([{"id": "u2", "username": "bob"}, {"id": "u1", "username": "alice"}]).to_string(),
+        )
+        .create_async()
+        .await;
+    let _idps = server
+        .mock(
+            "GET",
+            "/admin/realms/test/organizations/org-1/identity-providers",
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!([{"alias": "google"}]).to_string())
+        .create_async()
+        .await;
+
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("organizations")).unwrap();
+    fs::write(dir.path().join("organizations/acme.yaml"), ACME).unwrap();
+
+    let ui = DialoguerUi::new();
+    let ctx = PlanContext {
+        client: &client,
+        workspace_dir: dir.path(),
+        options: PlanOptions {
+            changes_only: true,
+            interactive: false,
+            drift: false,
+        },
+        resolver: Arc::new(EnvResolver::new(HashMap::new())) as Arc<dyn SecretResolver>,
+        realm_name: "test",
+        ui: &ui,
+        ws: Arc::new(WorkspaceContext::default()),
+        selection: &Default::default(),
+        changes: Default::default(),
+    };
+
+    let (changed, summary) = plan_organizations(&ctx).await.unwrap();
+    assert!(changed.is_empty());
+    assert_eq!(summary.total(), 0);
+}
+
+#[tokio::test]
+async fn test_apply_organizations_reconciles_members_and_idps() {
+    let mut server = mockito::Server::new_async().await;
+    let client = mock_client(&server);
+    let base = "/admin/realms/test/organizations/org-1";
+
+    let _orgs = server
+        .mock("GET", "/admin/realms/test/organizations")
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!([{"id": "org-1", "name": "acme", "alias": "acme"}]).to_string())
+        .create_async()
+        .await;
+    let update = server
+        .mock("PUT", base)
+        .match_body(Matcher::PartialJson(
+            json!({"id": "org-1", "name": "acme", "domains": [{"name": "acme.com"}]}),
+        ))
+        .with_status(204)
+        .create_async()
+        .await;
+    let _members = server
+        .mock("GET", format!("{}/members", base).as_str())
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            json!([{"id": "u1", "username": "alice"}, {"id": "u3", "username": "carol"}])
+                .to_string(),
+        )
+        .create_async()
+        .await;
+    let _lookup = server
+        .mock("GET", "/admin/realms/test/users")
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("username".into(), "bob".into()),
+            Matcher::UrlEncoded("exact".into(), "true".into()),
+        ]))
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!([{"id": "u2", "username": "bob"}]).to_string())
+        .create_async()
+        .await;
+    let add_bob = server
+        .mock("POST", format!("{}/members", base).as_str())
+        .match_body(Matcher::Json(json!("u2")))
+        .with_status(201)
+        .create_async()
+        .await;
+    let remove_carol = server
+        .mock("DELETE", format!("{}/members/u3", base).as_str())
+        .with_status(204)
+        .create_async()
+        .await;
+    let _idps = server
+        .mock("GET", format!("{}/identity-providers", base).as_str())
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!([{"alias": "github"}]).to_string())
+        .create_async()
+        .await;
+    let link_google = server
+        .mock("POST", format!("{}/identity-providers", base).as_str())
+        .match_body(Matcher::Json(json!("google")))
+        .with_status(204)
+        .create_async()
+        .await;
+    let unlink_github = server
+        .mock(
+            "DELETE",
+            format!("{}/identity-providers/github", base).as_str(),
+        )
+        .with_status(204)
+        .create_async()
+        .await;
+
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("organizations")).unwrap();
+    fs::write(dir.path().join("organizations/acme.yaml"), ACME).unwrap();
+
+    apply_organizations(
+        &client,
+        dir.path(),
+        Arc::new(EnvResolver::new(HashMap::new())),
+        Arc::new(None),
+        "test",
+        Arc::new(WorkspaceContext::default()),
+        &ApplyReport::default(),
+    )
+    .await
+    .unwrap();
+
+    update.assert_async().await;
+    add_bob.assert_async().await;
+    remove_carol.assert_async().await;
+    link_google.assert_async().await;
+    unlink_github.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_apply_organizations_unknown_member_fails() {
+    let mut server = mockito::Server::new_async().await;
+    let client = mock_client(&server);
+    let base = "/admin/realms/test/organizations/org-1";
+
+    let _orgs = server
+        .mock("GET", "/admin/realms/test/organizations")
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!([{"id": "org-1", "name": "acme"}]).to_string())
+        .create_async()
+        .await;
+    let _update = server
+        .mock("PUT", base)
+        .with_status(204)
+        .create_async()
+        .await;
+    let _members = server
+        .mock("GET", format!("{}/members", base).as_str())
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body("[]")
+        .create_async()
+        .await;
+    let _lookup = server
+        .mock("GET", "/admin/realms/test/users")
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body("[]")
+        .create_async()
+        .await;
+
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("organizations")).unwrap();
+    fs::write(
+        dir.path().join("organizations/acme.yaml"),
+        "name: acme\nmembers:\n- ghost\n",
+    )
+    .unwrap();
+
+    let err = apply_organizations(
+        &client,
+        dir.path(),
+        Arc::new(EnvResolver::new(HashMap::new())),
+        Arc::new(None),
+        "test",
+        Arc::new(WorkspaceContext::default()),
+        &ApplyReport::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(format!("{:#}", err).contains("Unknown user 'ghost'"));
+}
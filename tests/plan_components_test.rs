@@ -3,6 +3,7 @@ use kcd::client::KeycloakClient;
 use kcd::plan::components::{check_keys_drift, plan_components_or_keys};
 use kcd::plan::{PlanContext, PlanOptions};
 use kcd::utils::ui::DialoguerUi;
+use kcd::workspace::WorkspaceContext;
 use std::sync::Arc;
 use tempfile::tempdir;
 use tokio::fs;
@@ -20,6 +21,7 @@ async fn test_plan_components_no_dir() {
     let options = PlanOptions {
         changes_only: false,
         interactive: false,
+        drift: false,
     };
 
     let ctx = PlanContext {
@@ -29,7 +31,9 @@ async fn test_plan_components_no_dir() {
         resolver,
         realm_name: "master",
         ui: &ui,
-        profile: None,
+        ws: Arc::new(WorkspaceContext::default()),
+        selection: &Default::default(),
+        changes: Default::default(),
     };
 
     // Should not fail if directory doesn't exist
@@ -44,6 +48,7 @@ async fn test_check_keys_drift_fail() {
     let options = PlanOptions {
         changes_only: true,
         interactive: false,
+        drift: false,
     };
     let res = check_keys_drift(&client, options, "master").await;
     // check_keys_drift ignores error if not available
@@ -69,6 +74,7 @@ async fn test_plan_components_with_invalid_yaml() {
     let options = PlanOptions {
         changes_only: false,
         interactive: false,
+        drift: false,
     };
 
     let ctx = PlanContext {
@@ -78,7 +84,9 @@ async fn test_plan_components_with_invalid_yaml() {
         resolver,
         realm_name: "master",
         ui: &ui,
-        profile: None,
+        ws: Arc::new(WorkspaceContext::default()),
+        selection: &Default::default(),
+        changes: Default::default(),
     };
 
     let res = plan_components_or_keys(&ctx, "components").await;
@@ -94,6 +102,7 @@ async fn test_check_keys_drift_warning() {
     let options = PlanOptions {
         changes_only: true,
         interactive: false,
+        drift: false,
     };
 
     // This should run and print a warning (we can't easily assert on stdout here without more effort,
@@ -115,6 +124,7 @@ async fn test_plan_components_no_identity() {
     let options = PlanOptions {
         changes_only: false,
         interactive: false,
+        drift: false,
     };
 
     let ctx = PlanContext {
@@ -124,7 +134,9 @@ async fn test_plan_components_no_identity() {
         resolver,
         realm_name: "master",
         ui: &ui,
-        profile: None,
+        ws: Arc::new(WorkspaceContext::default()),
+        selection: &Default::default(),
+        changes: Default::default(),
     };
 
     let components_dir = workspace_dir.join("components");
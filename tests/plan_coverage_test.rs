@@ -3,6 +3,7 @@ use common::start_mock_server;
 use kcd::client::KeycloakClient;
 use kcd::plan;
 use kcd::utils::secrets::{EnvResolver, SecretResolver};
+use kcd::workspace::WorkspaceContext;
 use std::collections::HashMap;
 use std::fs;
 use std::sync::Arc;
@@ -24,7 +25,7 @@ async fn test_plan_non_existent_workspace() {
         Arc::new(kcd::utils::secrets::EnvResolver::new(
             std::collections::HashMap::new(),
         )),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_err());
@@ -44,7 +45,7 @@ async fn test_plan_empty_workspace() {
         &[],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -76,7 +77,7 @@ async fn test_plan_with_secrets_file() {
         &["test-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -110,7 +111,7 @@ async fn test_plan_cleanup_old_plan_file() {
         &["test-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -154,7 +155,7 @@ async fn test_plan_realm_not_found_remote() {
         &["new-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -198,7 +199,7 @@ async fn test_plan_resources_creation() {
         &["test-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -247,7 +248,7 @@ description: ${KEYCLOAK_ROLE_DESC}
         &["test-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -286,7 +287,7 @@ async fn test_plan_resources_invalid_yaml() {
         &["test-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_err());
@@ -321,7 +322,7 @@ async fn test_plan_resources_missing_identity() {
         &["test-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_err());
@@ -366,7 +367,7 @@ async fn test_plan_resources_update() {
         &["test-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -414,7 +415,7 @@ async fn test_plan_resources_changes_only() {
         &["test-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -471,7 +472,7 @@ async fn test_plan_interactive_include() {
         &["test-realm".to_string()],
         ui,
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -527,7 +528,7 @@ async fn test_plan_interactive_exclude() {
         &["test-realm".to_string()],
         ui,
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -564,7 +565,7 @@ async fn test_plan_error_paths() {
         &["error-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_err());
@@ -585,7 +586,7 @@ async fn test_plan_error_paths() {
         &["error-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_err());
@@ -605,7 +606,7 @@ async fn test_plan_error_paths() {
         &["error-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_err());
@@ -633,7 +634,7 @@ async fn test_plan_empty_realms_list() {
         &[],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -664,7 +665,31 @@ fn test_print_diff_delete() {
         extra: HashMap::new(),
     };
 
-    let res = print_diff("test", Some(&old), &new, false, "role").unwrap();
+    // Outside strict mode only the fields set locally are compared.
+    let res = print_diff(
+        &WorkspaceContext::default(),
+        "test",
+        Some(&old),
+        &new,
+        false,
+        "role",
+    )
+    .unwrap();
+    assert!(!res);
+
+    let new = RoleRepresentation {
+        description: Some("new".to_string()),
+        ..new
+    };
+    let res = print_diff(
+        &WorkspaceContext::default(),
+        "test",
+        Some(&old),
+        &new,
+        false,
+        "role",
+    )
+    .unwrap();
     assert!(res);
 }
 
@@ -691,7 +716,7 @@ async fn test_plan_auto_discovery_no_realm_yaml() {
         &[],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -744,7 +769,7 @@ async fn test_plan_resources_ignore_non_yaml() {
         &["test-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -790,7 +815,7 @@ async fn test_plan_resources_with_id_no_clear() {
         &["test-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
@@ -831,7 +856,7 @@ description: ${KEYCLOAK_ROLE_MISSING_SECRET}
         &["test-realm".to_string()],
         Arc::new(DialoguerUi::new()),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
 
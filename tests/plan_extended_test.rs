@@ -4,6 +4,7 @@ use common::start_mock_server;
 use kcd::client::KeycloakClient;
 use kcd::models::RealmRepresentation;
 use kcd::plan;
+use kcd::workspace::WorkspaceContext;
 use std::fs;
 use tempfile::tempdir;
 
@@ -58,7 +59,7 @@ async fn test_plan_extended_scenarios() {
         &["test-realm".to_string()],
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -73,7 +74,7 @@ async fn test_plan_extended_scenarios() {
         &["test-realm".to_string()],
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -92,7 +93,7 @@ async fn test_plan_extended_scenarios() {
         &["test-realm".to_string()],
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -106,7 +107,7 @@ async fn test_plan_extended_scenarios() {
         &["no-dir-realm".to_string()],
         ui.clone(),
         resolver,
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await;
     assert!(res.is_ok());
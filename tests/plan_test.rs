@@ -3,7 +3,9 @@ use common::start_mock_server;
 use kcd::client::KeycloakClient;
 use kcd::models::{ClientRepresentation, RealmRepresentation, RoleRepresentation};
 use kcd::plan;
+use kcd::utils::selection::Selection;
 use kcd::utils::ui::DialoguerUi;
+use kcd::workspace::WorkspaceContext;
 use std::fs;
 use std::sync::Arc;
 use tempfile::tempdir;
@@ -84,6 +86,7 @@ async fn test_plan() {
         public_client: None,
         bearer_only: None,
         service_accounts_enabled: None,
+        service_account: None,
         extra: std::collections::HashMap::new(),
     };
     fs::write(
@@ -104,6 +107,7 @@ async fn test_plan() {
         public_client: None,
         bearer_only: None,
         service_accounts_enabled: None,
+        service_account: None,
         extra: std::collections::HashMap::new(),
     };
     fs::write(
@@ -396,7 +400,7 @@ async fn test_plan() {
         &["test-realm".to_string()],
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .expect("Plan failed");
@@ -410,7 +414,7 @@ async fn test_plan() {
         &["test-realm".to_string()],
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .expect("Plan with changes_only failed");
@@ -424,8 +428,78 @@ async fn test_plan() {
         &["non-existent".to_string()],
         ui,
         resolver,
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .expect("Plan for non-existent realm failed");
 }
+
+#[tokio::test]
+async fn test_plan_report() {
+    let mock_url = start_mock_server().await;
+    let mut client = KeycloakClient::new(mock_url);
+    client.set_target_realm("test-realm".to_string());
+    client
+        .login("admin-cli", Some("secret"), None, None)
+        .await
+        .expect("Login failed");
+
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().join("workspace");
+    let roles_dir = workspace_dir.join("test-realm/roles");
+    fs::create_dir_all(&roles_dir).unwrap();
+    fs::write(
+        roles_dir.join("role-1.yaml"),
+        "name: role-1\ndescription: Changed\n",
+    )
+    .unwrap();
+    fs::write(roles_dir.join("new-role.yaml"), "name: new-role\n").unwrap();
+
+    let report = dir.path().join("plan.json");
+    let options = plan::PlanOptions {
+        changes_only: true,
+        interactive: false,
+        drift: false,
+    };
+    plan::run_with_options(
+        &client,
+        workspace_dir,
+        options,
+        &["test-realm".to_string()],
+        Arc::new(DialoguerUi::new()),
+        Arc::new(kcd::utils::secrets::EnvResolver::new(
+            std::collections::HashMap::new(),
+        )),
+        Arc::new(WorkspaceContext::default()),
+        &Selection::new(&["roles".into()], &[], &[], false).unwrap(),
+        Some(&report),
+    )
+    .await
+    .unwrap();
+
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(
+        report["summary"],
+        serde_json::json!({"create": 1, "update": 1})
+    );
+    let files = &report["files"];
+    assert_eq!(
+        files["test-realm/roles/role-1.yaml"],
+        serde_json::json!([{
+            "resource": "roles role-1",
+            "action": "update",
+            "changes": [
+                {"op": "replace", "path": "/description", "old": "Role 1", "value": "Changed"}
+            ],
+        }])
+    );
+    let created = &files["test-realm/roles/new-role.yaml"][0];
+    assert_eq!(created["action"], "create");
+    assert!(
+        created["changes"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!({"op": "add", "path": "/name", "value": "new-role"}))
+    );
+}
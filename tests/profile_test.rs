@@ -1,7 +1,10 @@
 mod common;
 use anyhow::Result;
 use kcd::args::{Cli, Commands};
-use kcd::{init_client, init_secrets, load_profile};
+use kcd::utils::secrets::{SecretBackend, substitute_secrets};
+use kcd::utils::yaml::load_yaml_with_overlay;
+use kcd::workspace::WorkspaceContext;
+use kcd::{init_client, init_secrets, load_profile, validate};
 use std::fs;
 use tempfile::tempdir;
 
@@ -54,16 +57,20 @@ client_secret: "secret"
     let cli = Cli {
         command: Commands::Drift {
             workspace: workspace.to_path_buf(),
+            report: None,
+            selection: Default::default(),
         },
         server: None, // Required unless profile is present
         realms: vec![],
         user: None,
         password: None,
-        client_id: "ignored".to_string(),
+        client_id: None,
         client_secret: None,
         profile: Some("test".to_string()),
+        concurrency: None,
         vault_addr: None,
         vault_token: None,
+        vault_namespace: None,
     };
 
     let profile = load_profile(workspace, "test").await?;
@@ -73,6 +80,56 @@ client_secret: "secret"
     Ok(())
 }
 
+#[tokio::test]
+async fn test_init_client_precedence() -> Result<()> {
+    let mut server = mockito::Server::new_async().await;
+    // The client id comes from the command line, the user from the profile
+    let token = server
+        .mock("POST", "/realms/master/protocol/openid-connect/token")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("client_id".into(), "cli-client".into()),
+            mockito::Matcher::UrlEncoded("username".into(), "profile-admin".into()),
+            mockito::Matcher::UrlEncoded("password".into(), "cli-password".into()),
+        ]))
+        .with_body(r#"{"access_token": "token"}"#)
+        .create_async()
+        .await;
+
+    let dir = tempdir().unwrap();
+    let workspace = dir.path();
+    fs::create_dir(workspace.join("profiles"))?;
+    fs::write(
+        workspace.join("profiles/prod.yaml"),
+        "server_url: \"http://127.0.0.1:1\"\nclient_id: profile-cli\nuser: profile-admin\npassword: profile-password\n",
+    )?;
+
+    let cli = Cli {
+        command: Commands::Drift {
+            workspace: workspace.to_path_buf(),
+            report: None,
+            selection: Default::default(),
+        },
+        server: Some(server.url()),
+        realms: vec![],
+        user: None,
+        password: Some("cli-password".to_string()),
+        client_id: Some("cli-client".to_string()),
+        client_secret: None,
+        profile: Some("prod".to_string()),
+        concurrency: None,
+        vault_addr: None,
+        vault_token: None,
+        vault_namespace: None,
+    };
+
+    let profile = load_profile(workspace, "prod").await?;
+    let client = init_client(&cli, Some(&profile)).await?;
+    assert_eq!(client.get_base_url(), server.url());
+    token.assert_async().await;
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_init_secrets_with_profile() -> Result<()> {
     let dir = tempdir().unwrap();
@@ -93,6 +150,15 @@ async fn test_init_secrets_with_profile() -> Result<()> {
         secrets_file: Some(".secrets.prod".to_string()),
         vault_addr: None,
         vault_token: None,
+        vault_namespace: None,
+        vault_kv_version: None,
+        vault_auth: None,
+        secret_backends: None,
+        vars: None,
+        extends: None,
+        realms: None,
+        concurrency: None,
+        overlays: vec!["prod".to_string()],
     };
 
     let cli = Cli {
@@ -103,11 +169,13 @@ async fn test_init_secrets_with_profile() -> Result<()> {
         realms: vec![],
         user: None,
         password: None,
-        client_id: "admin-cli".to_string(),
+        client_id: None,
         client_secret: None,
         profile: Some("prod".to_string()),
+        concurrency: None,
         vault_addr: None,
         vault_token: None,
+        vault_namespace: None,
     };
 
     let resolver = init_secrets(&cli, workspace, Some(&profile)).await?;
@@ -116,3 +184,173 @@ async fn test_init_secrets_with_profile() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_profile_secret_backends() -> Result<()> {
+    let dir = tempdir().unwrap();
+    let workspace = dir.path();
+    fs::create_dir(workspace.join("profiles"))?;
+    fs::write(
+        workspace.join("profiles/k8s.yaml"),
+        "server_url: \"http://localhost:8080\"\nsecret_backends: [file, env]\n",
+    )?;
+    fs::write(workspace.join("client-secret"), "from-file\n")?;
+
+    let profile = load_profile(workspace, "k8s").await?;
+    assert_eq!(
+        profile.secret_backends,
+        Some(vec![SecretBackend::File, SecretBackend::Env])
+    );
+
+    let cli = Cli {
+        command: Commands::Validate {
+            workspace: workspace.to_path_buf(),
+        },
+        server: None,
+        realms: vec![],
+        user: None,
+        password: None,
+        client_id: None,
+        client_secret: None,
+        profile: Some("k8s".to_string()),
+        concurrency: None,
+        vault_addr: None,
+        vault_token: None,
+        vault_namespace: None,
+    };
+
+    let resolver = init_secrets(&cli, workspace, Some(&profile)).await?;
+    assert_eq!(
+        resolver.resolve("file:client-secret").await?,
+        Some("from-file".to_string())
+    );
+
+    // References to backends the profile does not enable are reported, not kept verbatim.
+    let mut value = serde_json::json!({"secret": "${exec:echo nope}"});
+    let err = substitute_secrets(&mut value, resolver).await.unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("Exec secret backend is not enabled")
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_profile_vars_interpolation() -> Result<()> {
+    let dir = tempdir().unwrap();
+    let workspace = dir.path();
+    fs::create_dir(workspace.join("profiles"))?;
+    fs::write(
+        workspace.join("profiles/prod.yaml"),
+        "server_url: \"http://localhost:8080\"\nvars:\n  KCD_TEST_PROFILE_HOST: app.prod.example.com\n",
+    )?;
+    let realm_dir = workspace.join("test-realm");
+    fs::create_dir_all(realm_dir.join("clients"))?;
+    fs::write(realm_dir.join("realm.yaml"), "realm: test-realm\n")?;
+    fs::write(
+        realm_dir.join("clients/app.yaml"),
+        "clientId: app\nrootUrl: \"https://${KCD_TEST_PROFILE_HOST}\"\nredirectUris:\n  - \"https://${KCD_TEST_PROFILE_HOST}/*\"\n  - \"http://localhost:${KCD_TEST_PROFILE_PORT:-8080}/*\"\nname: \"${client_app}\"\ndescription: \"$${KCD_TEST_PROFILE_HOST}\"\n",
+    )?;
+
+    let profile = load_profile(workspace, "prod").await?;
+    let cli = Cli {
+        command: Commands::Validate {
+            workspace: workspace.to_path_buf(),
+        },
+        server: None,
+        realms: vec![],
+        user: None,
+        password: None,
+        client_id: None,
+        client_secret: None,
+        profile: Some("prod".to_string()),
+        concurrency: None,
+        vault_addr: None,
+        vault_token: None,
+        vault_namespace: None,
+    };
+
+    let resolver = init_secrets(&cli, workspace, Some(&profile)).await?;
+    let mut value: serde_json::Value =
+        serde_yaml::from_str(&fs::read_to_string(realm_dir.join("clients/app.yaml"))?)?;
+    substitute_secrets(&mut value, resolver).await?;
+    assert_eq!(value["rootUrl"], "https://app.prod.example.com");
+    assert_eq!(
+        value["redirectUris"],
+        serde_json::json!(["https://app.prod.example.com/*", "http://localhost:8080/*"])
+    );
+    assert_eq!(value["name"], "${client_app}");
+    assert_eq!(value["description"], "${KCD_TEST_PROFILE_HOST}");
+
+    // Without the profile, `validate` reports the variable the files need.
+    validate::run_with_vars(
+        workspace.to_path_buf(),
+        &["test-realm".to_string()],
+        &WorkspaceContext::with_profile(Some("prod")),
+        &|name| profile.vars.as_ref().is_some_and(|v| v.contains_key(name)),
+    )
+    .await?;
+    let err = validate::run(workspace.to_path_buf(), &["test-realm".to_string()])
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("KCD_TEST_PROFILE_HOST (clients/app.yaml)")
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_profile_inheritance_and_overlay_chain() -> Result<()> {
+    let dir = tempdir().unwrap();
+    let workspace = dir.path();
+    fs::create_dir(workspace.join("profiles"))?;
+    fs::write(
+        workspace.join("profiles/eu.yaml"),
+        "server_url: \"https://keycloak.eu.example.com\"\nclient_id: kcd\nvars:\n  REGION: eu\n  TIER: dev\n",
+    )?;
+    fs::write(
+        workspace.join("profiles/eu-prod.yaml"),
+        "extends: eu\nclient_id: kcd-prod\nvars:\n  TIER: prod\n",
+    )?;
+
+    let profile = load_profile(workspace, "eu-prod").await?;
+    assert_eq!(profile.server_url, "https://keycloak.eu.example.com");
+    assert_eq!(profile.client_id, Some("kcd-prod".to_string()));
+    let vars = profile.vars.unwrap();
+    assert_eq!(vars["REGION"], "eu");
+    assert_eq!(vars["TIER"], "prod");
+    assert_eq!(profile.overlays, vec!["eu", "eu-prod"]);
+
+    let clients = workspace.join("realm/clients");
+    fs::create_dir_all(&clients)?;
+    fs::write(
+        clients.join("app.yaml"),
+        "clientId: app\ndescription: base\nredirectUris: [\"http://localhost/*\"]\n",
+    )?;
+    fs::write(
+        clients.join("app.eu.yaml"),
+        "description: eu\nredirectUris:\n  $append: [\"https://eu.example.com/*\"]\n",
+    )?;
+    fs::write(
+        clients.join("app.eu-prod.yaml"),
+        "description: null\nredirectUris:\n  $remove: [\"http://localhost/*\"]\n",
+    )?;
+    let ws = WorkspaceContext {
+        overlays: profile.overlays.clone(),
+        ..WorkspaceContext::with_profile(Some("eu-prod"))
+    };
+    let value = load_yaml_with_overlay(&clients.join("app.yaml"), &ws).await?;
+    assert_eq!(
+        value,
+        serde_json::json!({"clientId": "app", "redirectUris": ["https://eu.example.com/*"]})
+    );
+
+    fs::write(workspace.join("profiles/eu.yaml"), "extends: eu-prod\n")?;
+    let err = load_profile(workspace, "eu-prod").await.unwrap_err();
+    assert!(format!("{:#}", err).contains("Profile inheritance cycle: eu-prod -> eu -> eu-prod"));
+
+    Ok(())
+}
@@ -0,0 +1,368 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use kcd::client::KeycloakClient;
+use kcd::state;
+use kcd::utils::secrets::EnvResolver;
+use kcd::utils::selection::Selection;
+use kcd::utils::ui::MockUi;
+use kcd::workspace::WorkspaceContext;
+use kcd::{apply, prune};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use tempfile::tempdir;
+use tokio::net::TcpListener;
+
+type Store = Arc<Mutex<HashMap<String, Vec<Value>>>>;
+
+const COLLECTIONS: &[(&str, &str)] = &[
+    ("clients", "id"),
+    ("identity-provider/instances", "internalId"),
+    ("roles", "id"),
+    ("groups", "id"),
+];
+
+fn mock_ui() -> Arc<MockUi> {
+    Arc::new(MockUi {
+        inputs: Mutex::new(Vec::new()),
+        confirms: Mutex::new(Vec::new()),
+        selects: Mutex::new(Vec::new()),
+        passwords: Mutex::new(Vec::new()),
+    })
+}
+
+/// A realm admin API keeping clients, identity providers, roles and groups, like Keycloak
+/// does. Role and group lists leave the attributes out unless asked for the full
+/// representation.
+async fn start_store_server(store: Store) -> String {
+    fn split(rest: &str) -> Option<(&'static str, &'static str, &str)> {
+        let (collection, id) = rest.rsplit_once('/')?;
+        let collection = if collection == "roles-by-id" {
+            "roles"
+        } else {
+            collection
+        };
+        COLLECTIONS
+            .iter()
+            .find(|(c, _)| *c == collection)
+            .map(|(c, id_field)| (*c, *id_field, id))
+    }
+    async fn list(
+        State(store): State<Store>,
+        Path((_, rest)): Path<(String, String)>,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> Json<Value> {
+        let mut items = store
+            .lock()
+            .unwrap()
+            .get(&rest)
+            .cloned()
+            .unwrap_or_default();
+        let brief = query
+            .get("briefRepresentation")
+            .is_none_or(|b| b != "false");
+        if brief && (rest == "roles" || rest == "groups") {
+            for item in &mut items {
+                item.as_object_mut().unwrap().remove("attributes");
+            }
+        }
+        Json(json!(items))
+    }
+    async fn create(
+        State(store): State<Store>,
+        Path((_, rest)): Path<(String, String)>,
+        Json(mut rep): Json<Value>,
+    ) -> StatusCode {
+        let Some((_, id_field)) = COLLECTIONS.iter().find(|(c, _)| *c == rest) else {
+            return StatusCode::NOT_FOUND;
+        };
+        let mut store = store.lock().unwrap();
+        let items = store.entry(rest).or_default();
+        rep[*id_field] = json!(format!("id-{}", items.len() + 1));
+        items.push(rep);
+        StatusCode::CREATED
+    }
+    async fn update(
+        State(store): State<Store>,
+        Path((_, rest)): Path<(String, String)>,
+        Json(rep): Json<Value>,
+    ) -> StatusCode {
+        let Some((collection, id_field, id)) = split(&rest) else {
+            return StatusCode::NOT_FOUND;
+        };
+        let mut store = store.lock().unwrap();
+        match store
+            .entry(collection.to_string())
+            .or_default()
+            .iter_mut()
+            .find(|item| item[id_field] == id)
+        {
+            Some(item) => {
+                *item = rep;
+                StatusCode::NO_CONTENT
+            }
+            None => StatusCode::NOT_FOUND,
+        }
+    }
+    async fn delete(
+        State(store): State<Store>,
+        Path((_, rest)): Path<(String, String)>,
+    ) -> StatusCode {
+        let Some((collection, id_field, id)) = split(&rest) else {
+            return StatusCode::NOT_FOUND;
+        };
+        store
+            .lock()
+            .unwrap()
+            .entry(collection.to_string())
+            .or_default()
+            .retain(|item| item[id_field] != id);
+        StatusCode::NO_CONTENT
+    }
+    let app = Router::new()
+        .route(
+            "/admin/realms/{realm}/{*rest}",
+            get(list).post(create).put(update).delete(delete),
+        )
+        .with_state(store);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    format!("http://127.0.0.1:{}", port)
+}
+
+#[tokio::test]
+async fn test_prune_only_deletes_created_resources() {
+    let store = Store::default();
+    store.lock().unwrap().insert(
+        "clients".to_string(),
+        vec![
+            json!({"id": "c-account", "clientId": "account"}),
+            json!({"id": "c-legacy", "clientId": "legacy", "attributes": {}}),
+        ],
+    );
+    let mut client = KeycloakClient::new(start_store_server(store.clone()).await);
+    client.set_target_realm("test".to_string());
+    client.set_token("token".to_string());
+
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().join("prod");
+    let realm_dir = workspace_dir.join("test");
+    fs::create_dir_all(realm_dir.join("clients")).unwrap();
+    fs::create_dir_all(realm_dir.join("identity-providers")).unwrap();
+    fs::write(realm_dir.join("clients/app.yaml"), "clientId: app\n").unwrap();
+    fs::write(realm_dir.join("clients/legacy.yaml"), "clientId: legacy\n").unwrap();
+    fs::write(
+        realm_dir.join("identity-providers/google.yaml"),
+        "alias: google\nproviderId: google\n",
+    )
+    .unwrap();
+
+    let realms = vec!["test".to_string()];
+    let resolver = Arc::new(EnvResolver::new(HashMap::new()));
+    apply::run(
+        &client,
+        workspace_dir.clone(),
+        &realms,
+        true,
+        false,
+        mock_ui(),
+        resolver,
+        Arc::new(WorkspaceContext::default()),
+    )
+    .await
+    .unwrap();
+
+    // Created clients are marked with the workspace, adopted ones only with their hash
+    let clients = store.lock().unwrap()["clients"].clone();
+    let attributes = |client_id: &str| {
+        clients.iter().find(|c| c["clientId"] == client_id).unwrap()["attributes"].clone()
+    };
+    assert_eq!(attributes("app")["kcd.managed-by"], "prod");
+    assert!(attributes("app")["kcd.last-applied"].is_string());
+    assert!(attributes("legacy")["kcd.managed-by"].is_null());
+    assert!(attributes("legacy")["kcd.last-applied"].is_string());
+
+    // Identity providers have no attributes; the state file records them
+    let recorded = state::load(&workspace_dir).await.unwrap();
+    let google = recorded
+        .get("test", "identity-providers", "google")
+        .unwrap();
+    assert!(google.is_managed_by("prod"));
+
+    // Once their files are gone, only the resources the workspace created are pruned
+    fs::remove_file(realm_dir.join("clients/app.yaml")).unwrap();
+    fs::remove_file(realm_dir.join("clients/legacy.yaml")).unwrap();
+    fs::remove_file(realm_dir.join("identity-providers/google.yaml")).unwrap();
+    prune::run(
+        &client,
+        &workspace_dir,
+        &realms,
+        true,
+        mock_ui(),
+        &WorkspaceContext::default(),
+        &Selection::default(),
+    )
+    .await
+    .unwrap();
+
+    let remote = store.lock().unwrap().clone();
+    let mut remaining: Vec<&str> = remote["clients"]
+        .iter()
+        .map(|c| c["clientId"].as_str().unwrap())
+        .collect();
+    remaining.sort();
+    assert_eq!(remaining, vec!["account", "legacy"]);
+    assert!(remote["identity-provider/instances"].is_empty());
+    let recorded = state::load(&workspace_dir).await.unwrap();
+    assert_eq!(recorded.get("test", "identity-providers", "google"), None);
+    let last_applied = state::load_last_applied(&workspace_dir, "test", "clients")
+        .await
+        .unwrap();
+    assert_eq!(last_applied.keys().collect::<Vec<_>>(), vec!["legacy"]);
+
+    // The pruned resources can be rolled back
+    assert_eq!(kcd::snapshot::list(&workspace_dir).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_apply_records_last_applied() {
+    let store = Store::default();
+    let mut client = KeycloakClient::new(start_store_server(store.clone()).await);
+    client.set_target_realm("test".to_string());
+    client.set_token("token".to_string());
+
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().join("prod");
+    let realm_dir = workspace_dir.join("test");
+    fs::create_dir_all(realm_dir.join("clients")).unwrap();
+    fs::write(
+        realm_dir.join("clients/app.yaml"),
+        "clientId: app\nsecret: s3cr3t-value\nrootUrl: https://app\n",
+    )
+    .unwrap();
+
+    let realms = vec!["test".to_string()];
+    let resolver = Arc::new(EnvResolver::new(HashMap::new()));
+    apply::run(
+        &client,
+        workspace_dir.clone(),
+        &realms,
+        true,
+        false,
+        mock_ui(),
+        resolver,
+        Arc::new(WorkspaceContext::default()),
+    )
+    .await
+    .unwrap();
+
+    // Recorded without kcd's markers and with secrets masked
+    let last_applied = state::load_last_applied(&workspace_dir, "test", "clients")
+        .await
+        .unwrap();
+    let app = &last_applied["app"];
+    assert_eq!(app["rootUrl"], "https://app");
+    assert_eq!(app["secret"], "********");
+    assert!(app.get("attributes").is_none());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let path = state::last_applied_file(&workspace_dir, "test", "clients");
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // The hash on the server is the hash of the recorded representation
+    let remote = store.lock().unwrap()["clients"][0].clone();
+    assert_eq!(
+        remote["attributes"]["kcd.last-applied"],
+        json!(state::hash_value(app))
+    );
+}
+
+#[tokio::test]
+async fn test_prune_roles_and_groups() {
+    let store = Store::default();
+    store.lock().unwrap().insert(
+        "roles".to_string(),
+        vec![json!({"id": "r-offline", "name": "offline_access", "attributes": {}})],
+    );
+    let mut client = KeycloakClient::new(start_store_server(store.clone()).await);
+    client.set_target_realm("test".to_string());
+    client.set_token("token".to_string());
+
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().join("prod");
+    let realm_dir = workspace_dir.join("test");
+    fs::create_dir_all(realm_dir.join("roles")).unwrap();
+    fs::create_dir_all(realm_dir.join("groups")).unwrap();
+    fs::write(realm_dir.join("roles/app-role.yaml"), "name: app-role\n").unwrap();
+    fs::write(realm_dir.join("groups/staff.yaml"), "name: staff\n").unwrap();
+
+    let realms = vec!["test".to_string()];
+    let apply = || {
+        apply::run(
+            &client,
+            workspace_dir.clone(),
+            &realms,
+            true,
+            false,
+            mock_ui(),
+            Arc::new(EnvResolver::new(HashMap::new())),
+            Arc::new(WorkspaceContext::default()),
+        )
+    };
+    apply().await.unwrap();
+    // Applying again keeps the owner of the resources the first apply created
+    fs::write(
+        realm_dir.join("roles/app-role.yaml"),
+        "name: app-role\ndescription: App\n",
+    )
+    .unwrap();
+    apply().await.unwrap();
+
+    let remote = store.lock().unwrap().clone();
+    let attributes = |collection: &str, name: &str| {
+        remote[collection]
+            .iter()
+            .find(|r| r["name"] == name)
+            .unwrap()["attributes"]
+            .clone()
+    };
+    assert_eq!(
+        attributes("roles", "app-role")["kcd.managed-by"],
+        json!(["prod"])
+    );
+    assert_eq!(
+        attributes("groups", "staff")["kcd.managed-by"],
+        json!(["prod"])
+    );
+
+    fs::remove_file(realm_dir.join("roles/app-role.yaml")).unwrap();
+    fs::remove_file(realm_dir.join("groups/staff.yaml")).unwrap();
+    prune::run(
+        &client,
+        &workspace_dir,
+        &realms,
+        true,
+        mock_ui(),
+        &WorkspaceContext::default(),
+        &Selection::default(),
+    )
+    .await
+    .unwrap();
+
+    let remote = store.lock().unwrap().clone();
+    let roles: Vec<&str> = remote["roles"]
+        .iter()
+        .map(|r| r["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(roles, vec!["offline_access"]);
+    assert!(remote["groups"].is_empty());
+}
@@ -1,5 +1,6 @@
 use anyhow::Result;
 use kcd::client::KeycloakClient;
+use kcd::workspace::WorkspaceContext;
 use kcd::{apply, inspect, plan};
 use std::fs;
 use std::process::Command;
@@ -116,7 +117,7 @@ standardFlowEnabled: true
         &["master".to_string()],
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await?;
 
@@ -130,7 +131,7 @@ standardFlowEnabled: true
         false,
         ui,
         resolver,
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await?;
 
@@ -0,0 +1,190 @@
+use anyhow::Result;
+use kcd::WorkspaceConfig;
+use kcd::load_profile;
+use kcd::render::{self, RenderFormat, RenderOptions};
+use kcd::utils::secrets::template::VarsResolver;
+use kcd::utils::secrets::{CompositeResolver, EnvResolver, SecretResolver};
+use kcd::workspace::WorkspaceContext;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+/// The context `run_app` builds for `profile` in a workspace without `kcd.yaml`.
+async fn context(workspace: &Path, profile: &str) -> Result<WorkspaceContext> {
+    let loaded = load_profile(workspace, profile).await?;
+    Ok(WorkspaceConfig::default().context(workspace, Some(profile), Some(&loaded)))
+}
+
+fn write_workspace(workspace: &Path) -> Result<()> {
+    fs::create_dir_all(workspace.join("profiles"))?;
+    fs::write(
+        workspace.join("profiles/staging.yaml"),
+        "server_url: http://localhost\nvars:\n  APP_HOST: staging.example.com\n",
+    )?;
+    fs::write(
+        workspace.join("profiles/prod.yaml"),
+        "server_url: http://localhost\nvars:\n  APP_HOST: app.example.com\n",
+    )?;
+    let realm_dir = workspace.join("test-realm");
+    fs::create_dir_all(realm_dir.join("clients"))?;
+    fs::create_dir_all(realm_dir.join("roles"))?;
+    fs::write(realm_dir.join("realm.yaml"), "realm: test-realm\n")?;
+    fs::write(realm_dir.join("roles/admin.yaml"), "name: admin\n")?;
+    fs::write(
+        realm_dir.join("clients/my-app.yaml"),
+        "clientId: my-app\nsecret: ${KEYCLOAK_MY_APP_SECRET}\nattributes:\n  apiPassword: literal-value\nrootUrl: https://${APP_HOST}\n",
+    )?;
+    fs::write(
+        realm_dir.join("clients/my-app.prod.yaml"),
+        "description: production\n",
+    )?;
+    Ok(())
+}
+
+fn masked(profile_vars: &[(&str, &str)]) -> Arc<dyn SecretResolver> {
+    let vars = profile_vars
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    render::masked_resolver(Box::new(VarsResolver::new(vars)))
+}
+
+#[tokio::test]
+async fn test_render_profile() -> Result<()> {
+    let dir = tempdir()?;
+    let workspace = dir.path();
+    write_workspace(workspace)?;
+
+    let options = RenderOptions {
+        resource: Some("clients/my-app".to_string()),
+        ..Default::default()
+    };
+    let rendered = render::render_workspace(
+        workspace,
+        &[],
+        &context(workspace, "prod").await?,
+        masked(&[("APP_HOST", "app.example.com")]),
+        &options,
+    )
+    .await?;
+    assert_eq!(
+        rendered.keys().collect::<Vec<_>>(),
+        vec!["test-realm/clients/my-app.yaml"]
+    );
+    assert_eq!(
+        rendered["test-realm/clients/my-app.yaml"],
+        json!({
+            "clientId": "my-app",
+            "description": "production",
+            "rootUrl": "https://app.example.com",
+            "secret": "${KEYCLOAK_MY_APP_SECRET}",
+            "attributes": {"apiPassword": "l***e"}
+        })
+    );
+
+    // `--reveal` resolves secrets and shows literal ones.
+    let revealed: Arc<dyn SecretResolver> = Arc::new(CompositeResolver::new(vec![
+        Box::new(EnvResolver::new(HashMap::from([(
+            "KEYCLOAK_MY_APP_SECRET".to_string(),
+            "s3cr3t".to_string(),
+        )]))),
+        Box::new(VarsResolver::new(HashMap::from([(
+            "APP_HOST".to_string(),
+            "app.example.com".to_string(),
+        )]))),
+    ]));
+    let options = RenderOptions {
+        reveal: true,
+        format: RenderFormat::Json,
+        ..Default::default()
+    };
+    let rendered = render::render_workspace(
+        workspace,
+        &[],
+        &context(workspace, "prod").await?,
+        revealed,
+        &options,
+    )
+    .await?;
+    assert_eq!(rendered.len(), 3);
+    let client = &rendered["test-realm/clients/my-app.yaml"];
+    assert_eq!(client["secret"], "s3cr3t");
+    assert_eq!(client["attributes"]["apiPassword"], "literal-value");
+
+    let output = render::format_rendered(&rendered, RenderFormat::Json)?;
+    let parsed: serde_json::Value = serde_json::from_str(&output)?;
+    assert_eq!(
+        parsed["test-realm/roles/admin.yaml"],
+        json!({"name": "admin"})
+    );
+    let output = render::format_rendered(&rendered, RenderFormat::Yaml)?;
+    assert!(output.starts_with("# test-realm/clients/my-app.yaml\n"));
+    assert!(output.contains("---\n# test-realm/realm.yaml\nrealm: test-realm\n"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_render_diff_profiles() -> Result<()> {
+    let dir = tempdir()?;
+    let workspace = dir.path();
+    write_workspace(workspace)?;
+
+    let staging = load_profile(workspace, "staging").await?;
+    let prod = load_profile(workspace, "prod").await?;
+    let changed = render::diff_profiles(
+        workspace,
+        &["test-realm".to_string()],
+        (
+            &context(workspace, "staging").await?,
+            render::masked_resolver(Box::new(VarsResolver::new(staging.vars.unwrap()))),
+        ),
+        (
+            &context(workspace, "prod").await?,
+            render::masked_resolver(Box::new(VarsResolver::new(prod.vars.unwrap()))),
+        ),
+        &RenderOptions::default(),
+    )
+    .await?;
+    assert_eq!(changed, vec!["test-realm/clients/my-app.yaml"]);
+
+    let changed = render::diff_profiles(
+        workspace,
+        &["test-realm".to_string()],
+        (
+            &context(workspace, "prod").await?,
+            masked(&[("APP_HOST", "app.example.com")]),
+        ),
+        (
+            &context(workspace, "prod").await?,
+            masked(&[("APP_HOST", "app.example.com")]),
+        ),
+        &RenderOptions::default(),
+    )
+    .await?;
+    assert!(changed.is_empty());
+
+    let changed = render::diff_profiles(
+        workspace,
+        &["test-realm".to_string()],
+        (
+            &context(workspace, "staging").await?,
+            masked(&[("APP_HOST", "staging.example.com")]),
+        ),
+        (
+            &context(workspace, "prod").await?,
+            masked(&[("APP_HOST", "app.example.com")]),
+        ),
+        &RenderOptions {
+            format: RenderFormat::Json,
+            ..Default::default()
+        },
+    )
+    .await?;
+    assert_eq!(changed, vec!["test-realm/clients/my-app.yaml"]);
+
+    Ok(())
+}
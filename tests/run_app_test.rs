@@ -15,11 +15,13 @@ async fn test_run_app_validate() -> Result<()> {
         realms: vec![],
         user: None,
         password: None,
-        client_id: "admin-cli".to_string(),
+        client_id: None,
         client_secret: None,
         profile: None,
+        concurrency: None,
         vault_addr: None,
         vault_token: None,
+        vault_namespace: None,
     };
 
     run_app(cli).await?;
@@ -38,16 +40,21 @@ async fn test_run_app_inspect() -> Result<()> {
         command: Commands::Inspect {
             workspace,
             yes: true,
+            secrets_sink: Default::default(),
+            prune_secrets: false,
+            selection: Default::default(),
         },
         server: Some(mock_url),
         realms: vec!["test-realm".to_string()],
         user: None,
         password: None,
-        client_id: "admin-cli".to_string(),
+        client_id: None,
         client_secret: Some("secret".to_string()),
         profile: None,
+        concurrency: None,
         vault_addr: None,
         vault_token: None,
+        vault_namespace: None,
     };
 
     run_app(cli).await?;
@@ -70,16 +77,21 @@ async fn test_run_app_apply() -> Result<()> {
             workspace,
             yes: true,
             review: false,
+            keep_going: false,
+            report: None,
+            selection: Default::default(),
         },
         server: Some(mock_url),
         realms: vec!["test-realm".to_string()],
         user: None,
         password: None,
-        client_id: "admin-cli".to_string(),
+        client_id: None,
         client_secret: Some("secret".to_string()),
         profile: None,
+        concurrency: None,
         vault_addr: None,
         vault_token: None,
+        vault_namespace: None,
     };
 
     run_app(cli).await?;
@@ -99,16 +111,22 @@ async fn test_run_app_plan() -> Result<()> {
             workspace,
             changes_only: false,
             interactive: false,
+            strict: false,
+            graph: None,
+            report: None,
+            selection: Default::default(),
         },
         server: Some(mock_url),
         realms: vec![],
         user: None,
         password: None,
-        client_id: "admin-cli".to_string(),
+        client_id: None,
         client_secret: Some("secret".to_string()),
         profile: None,
+        concurrency: None,
         vault_addr: None,
         vault_token: None,
+        vault_namespace: None,
     };
 
     run_app(cli).await?;
@@ -129,11 +147,13 @@ async fn test_run_app_clean() -> Result<()> {
         realms: vec![],
         user: None,
         password: None,
-        client_id: "admin-cli".to_string(),
+        client_id: None,
         client_secret: None,
         profile: None,
+        concurrency: None,
         vault_addr: None,
         vault_token: None,
+        vault_namespace: None,
     };
 
     run_app(cli).await?;
@@ -150,16 +170,22 @@ async fn test_run_app_drift() -> Result<()> {
     let workspace = dir.path().to_path_buf();
 
     let cli = Cli {
-        command: Commands::Drift { workspace },
+        command: Commands::Drift {
+            workspace,
+            report: None,
+            selection: Default::default(),
+        },
         server: Some(mock_url),
         realms: vec![],
         user: None,
         password: None,
-        client_id: "admin-cli".to_string(),
+        client_id: None,
         client_secret: Some("secret".to_string()),
         profile: None,
+        concurrency: None,
         vault_addr: None,
         vault_token: None,
+        vault_namespace: None,
     };
 
     run_app(cli).await?;
@@ -0,0 +1,94 @@
+use kcd::load_workspace_config;
+use kcd::models::ComponentRepresentation;
+use kcd::utils::secrets::rules::SecretRules;
+use kcd::utils::secrets::{obfuscate_secrets, set_debug_rules};
+use kcd::utils::to_sorted_yaml_with_secret_refs;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs;
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn test_workspace_secret_rules() {
+    let dir = tempdir().unwrap();
+    fs::write(
+        dir.path().join("kcd.yaml"),
+        r#"
+secrets:
+  include:
+    - "component:config.apiKey"
+    - "*Signature"
+  exclude:
+    - "idp:config.tokenUrl"
+    - "component:config.publicToken"
+"#,
+    )
+    .unwrap();
+    let config = load_workspace_config(dir.path()).await.unwrap();
+    let rules = config.secrets;
+
+    let component: ComponentRepresentation = serde_json::from_value(json!({
+        "name": "my-spi",
+        "providerId": "custom",
+        "config": {
+            "apiKey": ["key-123"],
+            "publicToken": ["not-a-secret"],
+            "bindCredential": ["pw"]
+        }
+    }))
+    .unwrap();
+
+    // inspect: custom SPI keys are extracted, excluded ones stay in YAML.
+    let mut secrets = BTreeMap::new();
+    let yaml = to_sorted_yaml_with_secret_refs(
+        &component,
+        "component",
+        "component",
+        &mut secrets,
+        &rules,
+        &|name| format!("${{{}}}", name),
+    )
+    .unwrap();
+    assert!(!yaml.contains("key-123"));
+    assert!(yaml.contains("not-a-secret"));
+    assert!(!yaml.contains("- pw"));
+    assert_eq!(secrets.len(), 2);
+
+    // plan: the same fields are obfuscated.
+    let mut idp = json!({
+        "alias": "google",
+        "requestSignature": "abcdef",
+        "config": {"tokenUrl": "https://token.example.com", "clientSecret": "s3cr3t"}
+    });
+    obfuscate_secrets(&mut idp, "idp", &rules);
+    assert_eq!(idp["config"]["tokenUrl"], "https://token.example.com");
+    assert_eq!(idp["config"]["clientSecret"], "s***t");
+    assert_eq!(idp["requestSignature"], "a***f");
+
+    // Debug output follows the rules once they are installed.
+    let debug = format!("{:?}", component);
+    assert!(!debug.contains("\"pw\""));
+    assert!(!debug.contains("not-a-secret"));
+    assert!(debug.contains("key-123"));
+    set_debug_rules(rules.clone());
+    let debug = format!("{:?}", component);
+    assert!(!debug.contains("\"pw\""));
+    assert!(debug.contains("not-a-secret"));
+    assert!(!debug.contains("key-123"));
+
+    let mut secrets = BTreeMap::new();
+    to_sorted_yaml_with_secret_refs(
+        &component,
+        "component",
+        "component",
+        &mut secrets,
+        &SecretRules::default(),
+        &|n| n.to_string(),
+    )
+    .unwrap();
+    assert_eq!(
+        secrets.len(),
+        2,
+        "built-in heuristic: resetTokenSecret and bindCredential"
+    );
+}
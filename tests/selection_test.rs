@@ -0,0 +1,124 @@
+mod common;
+use common::start_mock_server;
+use kcd::apply::report::ApplyReport;
+use kcd::client::KeycloakClient;
+use kcd::utils::secrets::{EnvResolver, SecretResolver};
+use kcd::utils::selection::Selection;
+use kcd::utils::ui::MockUi;
+use kcd::workspace::WorkspaceContext;
+use kcd::{apply, plan};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn test_targeted_plan_and_apply() {
+    let mock_url = start_mock_server().await;
+    let mut client = KeycloakClient::new(mock_url);
+    client.set_target_realm("test-realm".to_string());
+    client
+        .login("admin-cli", Some("secret"), None, None)
+        .await
+        .expect("Login failed");
+
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().to_path_buf();
+    let realm_dir = workspace_dir.join("test-realm");
+    fs::create_dir_all(realm_dir.join("clients")).unwrap();
+    fs::create_dir_all(realm_dir.join("roles")).unwrap();
+    fs::write(realm_dir.join("realm.yaml"), "realm: test-realm\n").unwrap();
+    fs::write(
+        realm_dir.join("clients/new-app.yaml"),
+        "clientId: new-app\nserviceAccount:\n  realmRoles: [app-role]\n",
+    )
+    .unwrap();
+    fs::write(
+        realm_dir.join("clients/other-app.yaml"),
+        "clientId: other-app\n",
+    )
+    .unwrap();
+    fs::write(realm_dir.join("roles/app-role.yaml"), "name: app-role\n").unwrap();
+    fs::write(realm_dir.join("roles/unrelated.yaml"), "name: unrelated\n").unwrap();
+
+    let resolver = Arc::new(EnvResolver::new(HashMap::new())) as Arc<dyn SecretResolver>;
+    let ui = Arc::new(MockUi {
+        inputs: std::sync::Mutex::new(Vec::new()),
+        confirms: std::sync::Mutex::new(Vec::new()),
+        selects: std::sync::Mutex::new(Vec::new()),
+        passwords: std::sync::Mutex::new(Vec::new()),
+    });
+    let plan_path = workspace_dir.join(".kcdplan");
+    let planned = || -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> =
+            serde_json::from_str(&fs::read_to_string(&plan_path).unwrap()).unwrap();
+        files.sort();
+        files.dedup();
+        files
+    };
+
+    // The targeted client and the role its service account needs.
+    let selection = Selection::new(&[], &[], &["clients/new-app".into()], true).unwrap();
+    plan::run_with_selection(
+        &client,
+        workspace_dir.clone(),
+        true,
+        false,
+        &["test-realm".to_string()],
+        ui.clone(),
+        resolver.clone(),
+        Arc::new(WorkspaceContext::default()),
+        &selection,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        planned(),
+        vec![
+            realm_dir.join("clients/new-app.yaml"),
+            realm_dir.join("roles/app-role.yaml"),
+        ]
+    );
+
+    // Applying only the roles keeps the client in the plan.
+    let selection = Selection::new(&["roles".into()], &[], &[], false).unwrap();
+    apply::run_with_selection(
+        &client,
+        workspace_dir.clone(),
+        &["test-realm".to_string()],
+        true,
+        false,
+        ui.clone(),
+        resolver.clone(),
+        Arc::new(WorkspaceContext::default()),
+        &selection,
+        &ApplyReport::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(planned(), vec![realm_dir.join("clients/new-app.yaml")]);
+
+    // Excluded types are left out of the plan.
+    let selection = Selection::new(&[], &["clients".into()], &[], false).unwrap();
+    plan::run_with_selection(
+        &client,
+        workspace_dir.clone(),
+        true,
+        false,
+        &["test-realm".to_string()],
+        ui.clone(),
+        resolver.clone(),
+        Arc::new(WorkspaceContext::default()),
+        &selection,
+    )
+    .await
+    .unwrap();
+    let files = planned();
+    assert!(files.contains(&realm_dir.join("roles/unrelated.yaml")));
+    assert!(
+        files
+            .iter()
+            .all(|f| !f.starts_with(realm_dir.join("clients")))
+    );
+}
@@ -0,0 +1,107 @@
+mod common;
+use common::mock_client;
+use kcd::plan;
+use kcd::server::{Capability, ServerVersion};
+use kcd::utils::secrets::{EnvResolver, SecretResolver};
+use kcd::utils::ui::DialoguerUi;
+use kcd::workspace::WorkspaceContext;
+use mockito::Matcher;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn test_detect_server_version() {
+    let mut server = mockito::Server::new_async().await;
+    let mut client = mock_client(&server);
+    let _info = server
+        .mock("GET", "/admin/serverinfo")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!({"systemInfo": {"version": "24.0.5", "uptime": "1 hour"}}).to_string())
+        .create_async()
+        .await;
+
+    assert!(client.server_version().is_none());
+    assert!(client.supports(Capability::Organizations));
+
+    let version = client.detect_server_version().await.unwrap();
+    assert_eq!(version, ServerVersion::new(24, 0, 5));
+    assert_eq!(client.server_version(), Some(version));
+    assert!(!client.supports(Capability::Organizations));
+    assert!(client.supports(Capability::ClientPolicies));
+}
+
+#[tokio::test]
+async fn test_unsupported_capability_skips_request() {
+    let mut server = mockito::Server::new_async().await;
+    let mut client = mock_client(&server);
+    client.set_server_version(ServerVersion::new(24, 0, 0));
+    let orgs = server
+        .mock("GET", "/admin/realms/test/organizations")
+        .match_query(Matcher::Any)
+        .expect(0)
+        .create_async()
+        .await;
+
+    assert!(client.get_organizations().await.unwrap().is_none());
+    orgs.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_update_realm_strips_fields_unknown_to_server() {
+    let mut server = mockito::Server::new_async().await;
+    let mut client = mock_client(&server);
+    client.set_server_version(ServerVersion::new(25, 0, 6));
+    let update = server
+        .mock("PUT", "/admin/realms/test")
+        .match_body(Matcher::Json(
+            json!({"realm": "test", "organizationsEnabled": true}),
+        ))
+        .with_status(204)
+        .create_async()
+        .await;
+
+    let realm = serde_json::from_value(json!({
+        "realm": "test",
+        "organizationsEnabled": true,
+        "bruteForceStrategy": "MULTIPLE"
+    }))
+    .unwrap();
+    client.update_realm(&realm).await.unwrap();
+    update.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_plan_enforces_minimum_server_version() {
+    let server = mockito::Server::new_async().await;
+    let mut client = mock_client(&server);
+    client.set_server_version(ServerVersion::new(24, 0, 5));
+
+    let dir = tempdir().unwrap();
+    fs::write(
+        dir.path().join("kcd.yaml"),
+        "minimum_server_version: \"25.0\"\n",
+    )
+    .unwrap();
+    fs::create_dir_all(dir.path().join("test")).unwrap();
+
+    let err = plan::run(
+        &client,
+        dir.path().to_path_buf(),
+        true,
+        false,
+        &["test".to_string()],
+        Arc::new(DialoguerUi::new()),
+        Arc::new(EnvResolver::new(HashMap::new())) as Arc<dyn SecretResolver>,
+        Arc::new(WorkspaceContext::default()),
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("older than minimum_server_version 25.0.0")
+    );
+}
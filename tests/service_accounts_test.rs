@@ -0,0 +1,260 @@
+mod common;
+use common::mock_client;
+use kcd::apply::report::ApplyReport;
+use kcd::apply::service_accounts::apply_service_accounts;
+use kcd::plan::service_accounts::plan_service_accounts;
+use kcd::plan::{PlanContext, PlanOptions};
+use kcd::utils::secrets::{EnvResolver, SecretResolver};
+use kcd::utils::ui::DialoguerUi;
+use kcd::workspace::WorkspaceContext;
+use mockito::Matcher;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+const WORKER: &str = "clientId: worker\nserviceAccountsEnabled: true\nserviceAccount:\n  realmRoles:\n  - reader\n  - writer\n  clientRoles:\n    api:\n    - invoke\n  groups:\n  - /robots\n";
+
+async fn mock_service_account(server: &mut mockito::ServerGuard) -> Vec<mockito::Mock> {
+    vec![
+        server
+            .mock("GET", "/admin/realms/test/clients")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!([
+                    {"id": "worker-uuid", "clientId": "worker", "serviceAccountsEnabled": true},
+                    {"id": "api-uuid", "clientId": "api"},
+                    {"id": "legacy-uuid", "clientId": "legacy"}
+                ])
+                .to_string(),
+            )
+            .create_async()
+            .await,
+        server
+            .mock(
+                "GET",
+                "/admin/realms/test/clients/worker-uuid/service-account-user",
+            )
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!({"id": "sa-id", "username": "service-account-worker"}).to_string())
+            .create_async()
+            .await,
+        server
+            .mock("GET", "/admin/realms/test/users/sa-id/role-mappings")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "realmMappings": [
+                        {"id": "r-default", "name": "default-roles-test"},
+                        {"id": "r-reader", "name": "reader"},
+                        {"id": "r-admin", "name": "admin"}
+                    ],
+                    "clientMappings": {
+                        "legacy": {"id": "legacy-uuid", "client": "legacy", "mappings": [{"id": "l-old", "name": "old"}]}
+                    }
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await,
+        server
+            .mock("GET", "/admin/realms/test/users/sa-id/groups")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!([{"id": "g-old", "name": "old", "path": "/old"}]).to_string())
+            .create_async()
+            .await,
+    ]
+}
+
+#[tokio::test]
+async fn test_plan_service_accounts_detects_changes() {
+    let mut server = mockito::Server::new_async().await;
+    let client = mock_client(&server);
+    let _mocks = mock_service_account(&mut server).await;
+
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("clients")).unwrap();
+    fs::write(dir.path().join("clients/worker.yaml"), WORKER).unwrap();
+    // Clients without a serviceAccount block are not looked at.
+    fs::write(dir.path().join("clients/api.yaml"), "clientId: api\n").unwrap();
+
+    let ui = DialoguerUi::new();
+    let ctx = PlanContext {
+        client: &client,
+        workspace_dir: dir.path(),
+        options: PlanOptions {
+            changes_only: true,
+            interactive: false,
+            drift: false,
+        },
+        resolver: Arc::new(EnvResolver::new(HashMap::new())) as Arc<dyn SecretResolver>,
+        realm_name: "test",
+        ui: &ui,
+        ws: Arc::new(WorkspaceContext::default()),
+        selection: &Default::default(),
+        changes: Default::default(),
+    };
+
+    let (changed, summary) = plan_service_accounts(&ctx).await.unwrap();
+    assert_eq!(changed, vec![dir.path().join("clients/worker.yaml")]);
+    assert_eq!(summary.updated, 1);
+}
+
+#[tokio::test]
+async fn test_apply_service_accounts_reconciles_assignments() {
+    let mut server = mockito::Server::new_async().await;
+    let client = mock_client(&server);
+    let _mocks = mock_service_account(&mut server).await;
+
+    let _realm_roles = server
+        .mock("GET", "/admin/realms/test/roles")
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            json!([
+                {"id": "r-default", "name": "default-roles-test"},
+                {"id": "r-reader", "name": "reader"},
+                {"id": "r-writer", "name": "writer"},
+                {"id": "r-admin", "name": "admin"}
+            ])
+            .to_string(),
+        )
+        .create_async()
+        .await;
+    let add_realm = server
+        .mock("POST", "/admin/realms/test/users/sa-id/role-mappings/realm")
+        .match_body(Matcher::PartialJson(
+            json!([{"id": "r-writer", "name": "writer"}]),
+        ))
+        .with_status(204)
+        .create_async()
+        .await;
+    // `default-roles-test` is kept even though it is not listed.
+    let remove_realm = server
+        .mock(
+            "DELETE",
+            "/admin/realms/test/users/sa-id/role-mappings/realm",
+        )
+        .match_body(Matcher::PartialJson(
+            json!([{"id": "r-admin", "name": "admin"}]),
+        ))
+        .with_status(204)
+        .create_async()
+        .await;
+
+    let _api_roles = server
+        .mock("GET", "/admin/realms/test/clients/api-uuid/roles")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!([{"id": "a-invoke", "name": "invoke"}]).to_string())
+        .create_async()
+        .await;
+    let add_client = server
+        .mock(
+            "POST",
+            "/admin/realms/test/users/sa-id/role-mappings/clients/api-uuid",
+        )
+        .match_body(Matcher::PartialJson(json!([{"id": "a-invoke"}])))
+        .with_status(204)
+        .create_async()
+        .await;
+    let _legacy_roles = server
+        .mock("GET", "/admin/realms/test/clients/legacy-uuid/roles")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!([{"id": "l-old", "name": "old"}]).to_string())
+        .create_async()
+        .await;
+    let remove_client = server
+        .mock(
+            "DELETE",
+            "/admin/realms/test/users/sa-id/role-mappings/clients/legacy-uuid",
+        )
+        .match_body(Matcher::PartialJson(json!([{"id": "l-old"}])))
+        .with_status(204)
+        .create_async()
+        .await;
+
+    let _group = server
+        .mock("GET", "/admin/realms/test/group-by-path/robots")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!({"id": "g-robots", "name": "robots", "path": "/robots"}).to_string())
+        .create_async()
+        .await;
+    let join = server
+        .mock("PUT", "/admin/realms/test/users/sa-id/groups/g-robots")
+        .with_status(204)
+        .create_async()
+        .await;
+    let leave = server
+        .mock("DELETE", "/admin/realms/test/users/sa-id/groups/g-old")
+        .with_status(204)
+        .create_async()
+        .await;
+
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("clients")).unwrap();
+    fs::write(dir.path().join("clients/worker.yaml"), WORKER).unwrap();
+
+    apply_service_accounts(
+        &client,
+        dir.path(),
+        Arc::new(EnvResolver::new(HashMap::new())),
+        Arc::new(None),
+        "test",
+        Arc::new(WorkspaceContext::default()),
+        &ApplyReport::default(),
+    )
+    .await
+    .unwrap();
+
+    add_realm.assert_async().await;
+    remove_realm.assert_async().await;
+    add_client.assert_async().await;
+    remove_client.assert_async().await;
+    join.assert_async().await;
+    leave.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_apply_service_accounts_unknown_role_fails() {
+    let mut server = mockito::Server::new_async().await;
+    let client = mock_client(&server);
+    let _mocks = mock_service_account(&mut server).await;
+    let _realm_roles = server
+        .mock("GET", "/admin/realms/test/roles")
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body("[]")
+        .create_async()
+        .await;
+
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("clients")).unwrap();
+    fs::write(
+        dir.path().join("clients/worker.yaml"),
+        "clientId: worker\nserviceAccount:\n  realmRoles:\n  - ghost\n",
+    )
+    .unwrap();
+
+    let err = apply_service_accounts(
+        &client,
+        dir.path(),
+        Arc::new(EnvResolver::new(HashMap::new())),
+        Arc::new(None),
+        "test",
+        Arc::new(WorkspaceContext::default()),
+        &ApplyReport::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(format!("{:#}", err).contains("Unknown realm role 'ghost'"));
+}
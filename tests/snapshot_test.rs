@@ -0,0 +1,135 @@
+mod common;
+use common::start_mock_server;
+use kcd::apply;
+use kcd::client::KeycloakClient;
+use kcd::lock::{LocalLock, LockSettings};
+use kcd::snapshot::{self, RealmSnapshot};
+use kcd::utils::secrets::{EnvResolver, SecretResolver};
+use kcd::utils::ui::MockUi;
+use kcd::workspace::WorkspaceContext;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn test_apply_snapshot_and_rollback() {
+    let mock_url = start_mock_server().await;
+    let mut client = KeycloakClient::new(mock_url);
+    client.set_target_realm("test-realm".to_string());
+    client
+        .login("admin-cli", Some("secret"), None, None)
+        .await
+        .expect("Login failed");
+
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().to_path_buf();
+    let realm_dir = workspace_dir.join("test-realm");
+    fs::create_dir_all(realm_dir.join("roles")).unwrap();
+    fs::write(realm_dir.join("realm.yaml"), "realm: test-realm\n").unwrap();
+    fs::write(
+        realm_dir.join("roles/role-1.yaml"),
+        "name: role-1\ndescription: Changed\n",
+    )
+    .unwrap();
+    fs::write(realm_dir.join("roles/new-role.yaml"), "name: new-role\n").unwrap();
+
+    let resolver = Arc::new(EnvResolver::new(HashMap::new())) as Arc<dyn SecretResolver>;
+    let ui = Arc::new(MockUi {
+        inputs: std::sync::Mutex::new(Vec::new()),
+        confirms: std::sync::Mutex::new(Vec::new()),
+        selects: std::sync::Mutex::new(Vec::new()),
+        passwords: std::sync::Mutex::new(Vec::new()),
+    });
+    apply::run(
+        &client,
+        workspace_dir.clone(),
+        &["test-realm".to_string()],
+        true,
+        false,
+        ui.clone(),
+        resolver,
+        Arc::new(WorkspaceContext::default()),
+    )
+    .await
+    .unwrap();
+
+    let ids = snapshot::list(&workspace_dir).await.unwrap();
+    assert_eq!(ids.len(), 1);
+    let content = fs::read_to_string(
+        snapshot::snapshots_dir(&workspace_dir)
+            .join(&ids[0])
+            .join("test-realm.json"),
+    )
+    .unwrap();
+    let snapshot: RealmSnapshot = serde_json::from_str(&content).unwrap();
+    let entry = |identity: &str| {
+        snapshot
+            .resources
+            .iter()
+            .find(|e| e.identity == identity)
+            .unwrap()
+    };
+    assert_eq!(entry("test-realm").kind, "realm");
+    assert_eq!(
+        entry("role-1").before.as_ref().unwrap()["description"],
+        "Role 1"
+    );
+    assert_eq!(entry("new-role").before, None);
+
+    // The snapshot directory is not mistaken for a realm
+    apply::run(
+        &client,
+        workspace_dir.clone(),
+        &[],
+        true,
+        false,
+        ui.clone(),
+        Arc::new(EnvResolver::new(HashMap::new())),
+        Arc::new(WorkspaceContext::default()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(snapshot::list(&workspace_dir).await.unwrap().len(), 2);
+
+    snapshot::rollback(
+        &client,
+        &workspace_dir,
+        &[],
+        Some(&ids[0]),
+        true,
+        ui.clone(),
+        &WorkspaceContext::default(),
+    )
+    .await
+    .unwrap();
+    let err = snapshot::rollback(
+        &client,
+        &workspace_dir,
+        &[],
+        Some("missing"),
+        true,
+        ui.clone(),
+        &WorkspaceContext::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("Snapshot 'missing' not found"));
+
+    // A rollback does not run alongside an apply
+    let _lock = LocalLock::acquire(&workspace_dir, &LockSettings::default())
+        .await
+        .unwrap();
+    let err = snapshot::rollback(
+        &client,
+        &workspace_dir,
+        &[],
+        Some(&ids[0]),
+        true,
+        ui,
+        &WorkspaceContext::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("Workspace is locked"));
+}
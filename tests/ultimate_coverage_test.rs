@@ -3,6 +3,7 @@ mod common;
 use common::start_mock_server;
 use kcd::client::KeycloakClient;
 use kcd::models::RealmRepresentation;
+use kcd::workspace::WorkspaceContext;
 use kcd::{apply, plan};
 use std::fs;
 use tempfile::tempdir;
@@ -56,7 +57,7 @@ async fn test_ultimate_flow() {
         &["test-realm".to_string()],
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -73,7 +74,7 @@ async fn test_ultimate_flow() {
         false,
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -104,7 +105,7 @@ async fn test_ultimate_flow() {
         &["test-realm".to_string()],
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
@@ -135,7 +136,7 @@ async fn test_ultimate_flow() {
         &["test-realm".to_string()],
         ui.clone(),
         resolver.clone(),
-        None,
+        Arc::new(WorkspaceContext::default()),
     )
     .await
     .unwrap();
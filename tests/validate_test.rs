@@ -202,6 +202,7 @@ async fn test_validate_empty_client_id() {
         public_client: None,
         bearer_only: None,
         service_accounts_enabled: None,
+        service_account: None,
         extra: std::collections::HashMap::new(),
     };
     fs::write(
@@ -889,3 +890,90 @@ async fn test_validate_empty_username_edge_case() {
             .contains("User username is missing or empty")
     );
 }
+
+#[tokio::test]
+async fn test_validate_duplicate_client_profile_name() {
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().to_path_buf();
+    let realm_dir = workspace_dir.join("test-realm");
+    std::fs::create_dir_all(realm_dir.join("client-policies")).unwrap();
+
+    fs::write(realm_dir.join("realm.yaml"), "realm: test-realm\n").unwrap();
+    fs::write(
+        realm_dir.join("client-policies/profiles.yaml"),
+        "profiles:\n- name: strict\n- name: strict\n",
+    )
+    .unwrap();
+
+    let result = validate::run(workspace_dir.clone(), &["test-realm".to_string()]).await;
+    assert!(result.is_err());
+    assert!(
+        result
+            .unwrap_err()
+            .to_string()
+            .contains("Duplicate client profile 'strict'")
+    );
+}
+
+#[tokio::test]
+async fn test_validate_empty_organization_name() {
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().to_path_buf();
+    let realm_dir = workspace_dir.join("test-realm");
+    std::fs::create_dir_all(realm_dir.join("organizations")).unwrap();
+
+    fs::write(realm_dir.join("realm.yaml"), "realm: test-realm\n").unwrap();
+    fs::write(realm_dir.join("organizations/acme.yaml"), "name: ''\n").unwrap();
+
+    let result = validate::run(workspace_dir.clone(), &["test-realm".to_string()]).await;
+    assert!(result.is_err());
+    assert!(
+        result
+            .unwrap_err()
+            .to_string()
+            .contains("Organization name is missing or empty")
+    );
+}
+
+#[tokio::test]
+async fn test_validate_dotted_names_and_overlays() {
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().to_path_buf();
+    let realm_dir = workspace_dir.join("test-realm");
+    fs::create_dir_all(realm_dir.join("clients")).unwrap();
+    fs::create_dir_all(workspace_dir.join("profiles")).unwrap();
+    fs::write(
+        workspace_dir.join("profiles/prod.yaml"),
+        "server_url: http://localhost\n",
+    )
+    .unwrap();
+    fs::write(realm_dir.join("realm.yaml"), "realm: test-realm\n").unwrap();
+    fs::write(
+        realm_dir.join("clients/my.app.yaml"),
+        "clientId: my.app\nredirectUris: [\"http://localhost/*\"]\n",
+    )
+    .unwrap();
+    // Overlays hold merge directives that are not valid client representations.
+    fs::write(
+        realm_dir.join("clients/my.app.prod.yaml"),
+        "redirectUris:\n  $append: [\"https://app.example.com/*\"]\n",
+    )
+    .unwrap();
+    // Orphan overlays are only reported.
+    fs::write(realm_dir.join("clients/gone.prod.yaml"), "enabled: false\n").unwrap();
+
+    validate::run(workspace_dir.clone(), &["test-realm".to_string()])
+        .await
+        .unwrap();
+
+    // `my.app.yaml` is a resource, not an overlay of an `app` profile.
+    fs::write(realm_dir.join("clients/my.app.yaml"), "clientId: \"\"\n").unwrap();
+    let err = validate::run(workspace_dir, &["test-realm".to_string()])
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("Client ID is missing or empty"),
+        "{}",
+        err
+    );
+}
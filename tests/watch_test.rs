@@ -0,0 +1,116 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use kcd::client::KeycloakClient;
+use kcd::utils::secrets::EnvResolver;
+use kcd::utils::selection::Selection;
+use kcd::utils::ui::MockUi;
+use kcd::watch;
+use kcd::workspace::WorkspaceContext;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::tempdir;
+use tokio::net::TcpListener;
+
+type Store = Arc<Mutex<HashMap<String, Vec<Value>>>>;
+
+/// A realm admin API keeping whatever is created in it.
+async fn start_store_server(store: Store) -> String {
+    async fn list(
+        State(store): State<Store>,
+        Path((_, rest)): Path<(String, String)>,
+    ) -> Json<Value> {
+        Json(json!(
+            store
+                .lock()
+                .unwrap()
+                .get(&rest)
+                .cloned()
+                .unwrap_or_default()
+        ))
+    }
+    async fn create(
+        State(store): State<Store>,
+        Path((_, rest)): Path<(String, String)>,
+        Json(mut rep): Json<Value>,
+    ) -> StatusCode {
+        let mut store = store.lock().unwrap();
+        let items = store.entry(rest).or_default();
+        rep["id"] = json!(format!("id-{}", items.len() + 1));
+        items.push(rep);
+        StatusCode::CREATED
+    }
+    let app = Router::new()
+        .route("/admin/realms/{realm}/{*rest}", get(list).post(create))
+        .with_state(store);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    format!("http://127.0.0.1:{}", port)
+}
+
+#[tokio::test]
+async fn test_watch_applies_saved_files() {
+    let store = Store::default();
+    let mut client = KeycloakClient::new(start_store_server(store.clone()).await);
+    client.set_target_realm("test".to_string());
+    client.set_token("token".to_string());
+
+    let dir = tempdir().unwrap();
+    let workspace_dir = dir.path().join("workspace");
+    let realm_dir = workspace_dir.join("test");
+    fs::create_dir_all(realm_dir.join("clients")).unwrap();
+    // Present before watching: left alone
+    fs::write(realm_dir.join("clients/old.yaml"), "clientId: old\n").unwrap();
+
+    let ui = Arc::new(MockUi {
+        inputs: Mutex::new(Vec::new()),
+        confirms: Mutex::new(Vec::new()),
+        selects: Mutex::new(Vec::new()),
+        passwords: Mutex::new(Vec::new()),
+    });
+    let watcher = {
+        let workspace_dir = workspace_dir.clone();
+        tokio::spawn(async move {
+            watch::run(
+                &client,
+                &workspace_dir,
+                &["test".to_string()],
+                ui,
+                Arc::new(EnvResolver::new(HashMap::new())),
+                Arc::new(WorkspaceContext::default()),
+                &Selection::default(),
+                Duration::from_millis(100),
+            )
+            .await
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    fs::write(realm_dir.join("clients/app.yaml"), "clientId: app\n").unwrap();
+
+    let mut created = Vec::new();
+    for _ in 0..100 {
+        created = store
+            .lock()
+            .unwrap()
+            .get("clients")
+            .cloned()
+            .unwrap_or_default();
+        if !created.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    watcher.abort();
+
+    let client_ids: Vec<&Value> = created.iter().map(|c| &c["clientId"]).collect();
+    assert_eq!(client_ids, vec!["app"]);
+    // The applied files are no longer planned
+    assert!(!workspace_dir.join(".kcdplan").exists());
+}
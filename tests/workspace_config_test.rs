@@ -0,0 +1,102 @@
+mod common;
+use anyhow::Result;
+use kcd::args::{Cli, Commands};
+use kcd::{load_workspace_config, run_app};
+use std::fs;
+use tempfile::tempdir;
+
+// A single test: the file filter installed by `run_app` is process-wide.
+#[cfg(unix)]
+#[tokio::test]
+async fn test_workspace_config_defaults_filters_and_hooks() -> Result<()> {
+    let mock_url = common::start_mock_server().await;
+    let dir = tempdir()?;
+    let workspace = dir.path().to_path_buf();
+
+    fs::write(
+        workspace.join("kcd.yaml"),
+        r#"
+profile: dev
+realms: [other-realm]
+concurrency: 2
+ignore:
+  - "*/roles/tmp-*"
+managed: [realm, roles]
+protected:
+  - test-realm/roles/locked
+hooks:
+  pre_plan:
+    - echo "$KCD_HOOK $KCD_PROFILE" > pre.out
+  post_plan:
+    - echo "$KCD_HOOK" > post.out
+"#,
+    )?;
+    fs::create_dir_all(workspace.join("profiles"))?;
+    fs::write(
+        workspace.join("profiles/dev.yaml"),
+        format!(
+            "server_url: \"{}\"\nclient_secret: secret\nrealms: [test-realm]\n",
+            mock_url
+        ),
+    )?;
+
+    let realm_dir = workspace.join("test-realm");
+    fs::create_dir_all(realm_dir.join("roles"))?;
+    fs::create_dir_all(realm_dir.join("clients"))?;
+    fs::write(realm_dir.join("realm.yaml"), "realm: test-realm\n")?;
+    for role in ["new-role", "tmp-role", "locked"] {
+        fs::write(
+            realm_dir.join(format!("roles/{}.yaml", role)),
+            format!("name: {}\n", role),
+        )?;
+    }
+    fs::write(
+        realm_dir.join("clients/new-app.yaml"),
+        "clientId: new-app\n",
+    )?;
+
+    // The profile comes from kcd.yaml and its realms win over those of kcd.yaml.
+    let cli = Cli {
+        command: Commands::Plan {
+            workspace: workspace.clone(),
+            changes_only: false,
+            interactive: false,
+            strict: false,
+            graph: None,
+            report: None,
+            selection: Default::default(),
+        },
+        server: None,
+        realms: vec![],
+        user: None,
+        password: None,
+        client_id: None,
+        client_secret: None,
+        profile: None,
+        concurrency: None,
+        vault_addr: None,
+        vault_token: None,
+        vault_namespace: None,
+    };
+    run_app(cli).await?;
+
+    assert_eq!(
+        fs::read_to_string(workspace.join("pre.out"))?,
+        "pre_plan dev\n"
+    );
+    assert_eq!(
+        fs::read_to_string(workspace.join("post.out"))?,
+        "post_plan\n"
+    );
+
+    let planned = fs::read_to_string(workspace.join(".kcdplan"))?;
+    assert!(planned.contains("new-role.yaml"), "{}", planned);
+    assert!(!planned.contains("tmp-role.yaml"), "{}", planned);
+    assert!(!planned.contains("locked.yaml"), "{}", planned);
+    assert!(!planned.contains("new-app.yaml"), "{}", planned);
+
+    // Unknown settings are rejected instead of silently ignored.
+    fs::write(workspace.join("kcd.yaml"), "realm: [test-realm]\n")?;
+    assert!(load_workspace_config(&workspace).await.is_err());
+    Ok(())
+}